@@ -251,12 +251,14 @@ fn set_mute_windows_device(device: &AudioDevice, mute: bool) -> StrResult {
     Ok(())
 }
 
+// Samples are sent as 16 bit interleaved PCM. `on_data` receives them before they are sent
 #[cfg_attr(not(windows), allow(unused_variables))]
 pub async fn record_audio_loop(
     device: AudioDevice,
     channels_count: u16,
     mute: bool,
     mut sender: StreamSender<()>,
+    mut on_data: impl FnMut(&[u8]),
 ) -> StrResult {
     let config = device
         .inner
@@ -363,6 +365,8 @@ pub async fn record_audio_loop(
 
     while let Some(maybe_data) = data_receiver.recv().await {
         let data = maybe_data?;
+        on_data(&data);
+
         let mut buffer = sender.new_buffer(&(), data.len())?;
         buffer.get_mut().extend(data);
         sender.send_buffer(buffer).await.ok();
@@ -594,3 +598,104 @@ pub async fn play_audio_loop(
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_sockets::{simulated_link, GilbertElliott, LinkImpairments, StreamSocket, AUDIO};
+    use std::time::Duration;
+    use tokio::time;
+
+    const CHANNELS_COUNT: usize = 2;
+    const BATCH_FRAMES_COUNT: usize = 32;
+    const AVERAGE_BUFFER_FRAMES_COUNT: usize = 64;
+    const VOLUME: f32 = 0.5;
+
+    // A constant signal is sent over a lossy link and played one batch per packet, like the output
+    // callback does. The only changes in the output must be the fades rendered around the losses.
+    #[tokio::test]
+    async fn packet_loss_recovers_without_clicks() {
+        let (server_send, client_receive) = simulated_link(
+            LinkImpairments {
+                loss: Some(GilbertElliott::bursts(0.05, 2.0)),
+                ..Default::default()
+            },
+            0,
+        );
+        let (client_send, server_receive) = simulated_link(LinkImpairments::default(), 1);
+
+        let server_socket = StreamSocket::new(
+            Arc::clone(&server_send) as _,
+            Box::new(server_receive),
+            1400,
+        );
+        let client_socket = Arc::new(StreamSocket::new(
+            client_send,
+            Box::new(client_receive),
+            1400,
+        ));
+        tokio::spawn({
+            let client_socket = Arc::clone(&client_socket);
+            async move { client_socket.receive_loop().await }
+        });
+
+        let mut sender = server_socket.request_stream::<()>(AUDIO).await.unwrap();
+        let receiver = client_socket.subscribe_to_stream(AUDIO).await.unwrap();
+
+        let sample_buffer = Arc::new(Mutex::new(VecDeque::new()));
+        tokio::spawn(receive_samples_loop(
+            receiver,
+            Arc::clone(&sample_buffer),
+            CHANNELS_COUNT,
+            BATCH_FRAMES_COUNT,
+            AVERAGE_BUFFER_FRAMES_COUNT,
+        ));
+
+        let sample = ((VOLUME * i16::MAX as f32) as i16).to_ne_bytes();
+        let mut output = vec![];
+        for _ in 0..300 {
+            let samples_count = BATCH_FRAMES_COUNT * CHANNELS_COUNT;
+            let mut buffer = sender.new_buffer(&(), samples_count * 2).unwrap();
+            for _ in 0..samples_count {
+                buffer.get_mut().extend_from_slice(&sample);
+            }
+            sender.send_buffer(buffer).await.unwrap();
+
+            time::sleep(Duration::from_millis(1)).await;
+
+            output.extend(get_next_frame_batch(
+                &mut sample_buffer.lock(),
+                CHANNELS_COUNT,
+                BATCH_FRAMES_COUNT,
+            ));
+        }
+
+        assert!(server_send.statistics().lost_packets > 0);
+
+        let max_step = VOLUME / BATCH_FRAMES_COUNT as f32 + 1e-3;
+        for channel in 0..CHANNELS_COUNT {
+            let channel_output = output
+                .iter()
+                .skip(channel)
+                .step_by(CHANNELS_COUNT)
+                .collect::<Vec<_>>();
+
+            assert!(channel_output
+                .windows(2)
+                .all(|pair| (pair[1] - pair[0]).abs() <= max_step));
+        }
+
+        // Playback resumed after the losses
+        let batches = output.chunks(BATCH_FRAMES_COUNT * CHANNELS_COUNT);
+        let silent_batches = batches
+            .clone()
+            .skip_while(|batch| batch.iter().all(|sample| *sample == 0.0))
+            .filter(|batch| batch.iter().all(|sample| *sample == 0.0))
+            .count();
+        assert!(silent_batches > 0);
+        assert!(batches
+            .rev()
+            .take(10)
+            .all(|batch| batch.iter().all(|sample| (sample - VOLUME).abs() < 1e-3)));
+    }
+}
@@ -10,48 +10,26 @@ fn main() {
         .map(|entry| entry.into_path())
         .collect::<Vec<_>>();
 
-    let source_files_paths = if platform_name == "android" {
-        cpp_paths
-            .iter()
-            .filter_map(|path| {
-                path.extension()
-                    .filter(|ext| ext.to_string_lossy() == "cpp")
-                    .is_some()
-                    .then(|| path.clone())
-            })
-            .collect()
-    } else {
-        vec![
-            PathBuf::new().join("cpp/fec.cpp"),
-            PathBuf::new().join("cpp/nal.cpp"),
-        ]
-    };
-
-    let mut builder = &mut cc::Build::new();
-    builder = builder
-        .cpp(true)
-        .files(source_files_paths)
-        .include("cpp")
-        .include("cpp/gl_render_utils");
-    if platform_name == "windows" {
-        builder = builder.flag("/std:c++17")
-    } else {
-        builder = builder
+    // On the other platforms only the declarations are used
+    if platform_name == "android" {
+        let source_files_paths = cpp_paths.iter().filter_map(|path| {
+            path.extension()
+                .filter(|ext| ext.to_string_lossy() == "cpp")
+                .is_some()
+                .then(|| path.clone())
+        });
+
+        cc::Build::new()
+            .cpp(true)
+            .files(source_files_paths)
+            .include("cpp")
+            .include("cpp/gl_render_utils")
             .flag("-std=c++17")
             .flag("-fexceptions")
             .flag("-frtti")
-    }
-    if platform_name == "android" {
-        builder = builder.cpp_link_stdlib("c++_static");
-    }
-    builder.compile("bindings");
-
-    cc::Build::new()
-        .cpp(false)
-        .files(&["cpp/reedsolomon/rs.c"])
-        .compile("bindings_rs_c");
+            .cpp_link_stdlib("c++_static")
+            .compile("bindings");
 
-    if platform_name == "android" {
         println!("cargo:rustc-link-lib=log");
         println!("cargo:rustc-link-lib=EGL");
         println!("cargo:rustc-link-lib=GLESv3");
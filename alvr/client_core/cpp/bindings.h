@@ -9,19 +9,6 @@ struct EyeInput {
     float fovBottom;
 };
 
-struct VideoFrame {
-    unsigned int packetCounter;
-    unsigned long long trackingFrameIndex;
-    // FEC decoder needs some value for identify video frame number to detect new frame.
-    // trackingFrameIndex becomes sometimes same value as previous video frame (in case of low
-    // tracking rate).
-    unsigned long long videoFrameIndex;
-    unsigned long long sentTime;
-    unsigned int frameByteSize;
-    unsigned int fecIndex;
-    unsigned short fecPercentage;
-};
-
 struct OnCreateResult {
     int streamSurfaceHandle;
     int loadingSurfaceHandle;
@@ -58,12 +45,3 @@ extern "C" void streamStartNative(const int *swapchainTextures[2], int swapchain
 extern "C" void updateLobbyHudTexture(const unsigned char *data);
 extern "C" void renderLobbyNative(const EyeInput eyeInputs[2], const int swapchainIndices[2]);
 extern "C" void renderStreamNative(void *streamHardwareBuffer, const int swapchainIndices[2]);
-
-// nal.h
-extern "C" void initializeNalParser(int codec, bool enableFec);
-extern "C" void notifyNewDecoder();
-extern "C" bool processNalPacket(VideoFrame header,
-                                 const unsigned char *payload,
-                                 int payloadSize,
-                                 bool &outHadFecFailure);
-extern "C" void (*pushNal)(const char *buffer, int length, unsigned long long frameIndex);
\ No newline at end of file
@@ -0,0 +1,53 @@
+// Calls from the stream pipeline into the C++ code and the platform decoder. They are behind a
+// trait so the pipeline can run without a headset, against a mock backend.
+
+use crate::{decoder, StreamConfigInput};
+use std::time::Duration;
+
+pub trait ClientBackend: Send + Sync {
+    fn set_stream_config(&self, config: StreamConfigInput);
+    fn create_decoder(&self, config_nal: Vec<u8>);
+    fn push_nal(&self, timestamp: Duration, nal: Vec<u8>);
+}
+
+pub struct CppBackend;
+
+impl ClientBackend for CppBackend {
+    #[allow(unused_variables)]
+    fn set_stream_config(&self, config: StreamConfigInput) {
+        #[cfg(target_os = "android")]
+        unsafe {
+            crate::setStreamConfig(config)
+        };
+    }
+
+    fn create_decoder(&self, config_nal: Vec<u8>) {
+        decoder::create_decoder(config_nal);
+    }
+
+    fn push_nal(&self, timestamp: Duration, nal: Vec<u8>) {
+        decoder::push_nal(timestamp, nal);
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use alvr_common::parking_lot::Mutex;
+
+    #[derive(Default)]
+    pub struct MockBackend {
+        // Timestamp and size of the frames passed to the decoder
+        pub frames: Mutex<Vec<(Duration, usize)>>,
+    }
+
+    impl ClientBackend for MockBackend {
+        fn set_stream_config(&self, _: StreamConfigInput) {}
+
+        fn create_decoder(&self, _: Vec<u8>) {}
+
+        fn push_nal(&self, timestamp: Duration, nal: Vec<u8>) {
+            self.frames.lock().push((timestamp, nal.len()));
+        }
+    }
+}
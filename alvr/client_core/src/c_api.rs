@@ -7,7 +7,7 @@ use alvr_common::{
 };
 use alvr_events::ButtonValue;
 use alvr_session::CodecType;
-use alvr_sockets::{DeviceMotion, Fov, Tracking};
+use alvr_sockets::{DeviceMotion, Fov, HandJoint, HandSkeleton, Tracking};
 use std::{
     collections::VecDeque,
     ffi::{c_char, c_void, CStr},
@@ -26,6 +26,7 @@ static NAL_QUEUE: Lazy<Mutex<VecDeque<ReconstructedNal>>> =
 pub enum AlvrCodec {
     H264 = 0,
     H265 = 1,
+    AV1 = 2,
 }
 
 #[repr(u8)]
@@ -49,6 +50,7 @@ pub enum AlvrEvent {
         codec: AlvrCodec,
     },
     FrameReady,
+    PairingRequested,
 }
 
 #[repr(C)]
@@ -93,6 +95,22 @@ pub struct OculusHand {
     bone_rotations: [AlvrQuat; 19],
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct AlvrHandJoint {
+    orientation: AlvrQuat,
+    position: [f32; 3],
+    radius: f32,
+    orientation_valid: bool,
+    position_valid: bool,
+}
+
+/// Joints indexed like XrHandJointEXT
+#[repr(C)]
+pub struct AlvrHandSkeleton {
+    joints: [AlvrHandJoint; 26],
+}
+
 #[allow(dead_code)]
 #[repr(C)]
 pub enum AlvrButtonValue {
@@ -131,7 +149,7 @@ pub unsafe extern "C" fn alvr_log_time(tag: *const c_char) {
     error!("[ALVR NATIVE] {tag}: {:?}", Instant::now());
 }
 
-/// On non-Android platforms, java_vm and constext should be null.
+/// On non-Android platforms, java_vm and context should be null.
 /// NB: context must be thread safe.
 #[allow(unused_variables)]
 #[no_mangle]
@@ -159,6 +177,16 @@ pub unsafe extern "C" fn alvr_initialize(
     );
 }
 
+/// On non-Android platforms, java_vm and context should be null.
+#[allow(unused_variables)]
+#[no_mangle]
+pub unsafe extern "C" fn alvr_initialize_companion(java_vm: *mut c_void, context: *mut c_void) {
+    #[cfg(target_os = "android")]
+    ndk_context::initialize_android_context(java_vm, context);
+
+    crate::initialize_companion();
+}
+
 #[no_mangle]
 pub extern "C" fn alvr_destroy() {
     crate::destroy();
@@ -174,6 +202,11 @@ pub extern "C" fn alvr_pause() {
     crate::pause();
 }
 
+#[no_mangle]
+pub extern "C" fn alvr_confirm_pairing(accepted: bool) {
+    crate::confirm_pairing(accepted);
+}
+
 /// Returns true if there was a new event
 #[no_mangle]
 pub extern "C" fn alvr_poll_event(out_event: *mut AlvrEvent) -> bool {
@@ -212,10 +245,10 @@ pub extern "C" fn alvr_poll_event(out_event: *mut AlvrEvent) -> bool {
                 });
 
                 AlvrEvent::CreateDecoder {
-                    codec: if matches!(codec, CodecType::H264) {
-                        AlvrCodec::H264
-                    } else {
-                        AlvrCodec::H265
+                    codec: match codec {
+                        CodecType::H264 => AlvrCodec::H264,
+                        CodecType::HEVC => AlvrCodec::H265,
+                        CodecType::AV1 => AlvrCodec::AV1,
                     },
                 }
             }
@@ -227,6 +260,7 @@ pub extern "C" fn alvr_poll_event(out_event: *mut AlvrEvent) -> bool {
 
                 AlvrEvent::FrameReady
             }
+            ClientEvent::PairingRequested => AlvrEvent::PairingRequested,
         };
 
         unsafe { *out_event = event };
@@ -302,34 +336,14 @@ pub extern "C" fn alvr_send_button(path_id: u64, value: AlvrButtonValue) {
     crate::send_button(path_id, value);
 }
 
-#[no_mangle]
-pub extern "C" fn alvr_send_tracking(
-    target_timestamp_ns: u64,
+fn from_tracking_quat(quat: AlvrQuat) -> Quat {
+    Quat::from_xyzw(quat.x, quat.y, quat.z, quat.w)
+}
+
+fn from_device_motions(
     device_motions: *const AlvrDeviceMotion,
     device_motions_count: u64,
-    left_oculus_hand: OculusHand,
-    right_oculus_hand: OculusHand,
-) {
-    fn from_tracking_quat(quat: AlvrQuat) -> Quat {
-        Quat::from_xyzw(quat.x, quat.y, quat.z, quat.w)
-    }
-
-    fn from_oculus_hand(hand: OculusHand) -> Option<[Quat; 19]> {
-        hand.enabled.then(|| {
-            let vec = hand
-                .bone_rotations
-                .iter()
-                .cloned()
-                .map(from_tracking_quat)
-                .collect::<Vec<_>>();
-
-            let mut array = [Quat::IDENTITY; 19];
-            array.copy_from_slice(&vec);
-
-            array
-        })
-    }
-
+) -> Vec<(u64, DeviceMotion)> {
     let mut raw_motions = vec![AlvrDeviceMotion::default(); device_motions_count as _];
     unsafe {
         ptr::copy_nonoverlapping(
@@ -339,7 +353,7 @@ pub extern "C" fn alvr_send_tracking(
         );
     }
 
-    let device_motions = raw_motions
+    raw_motions
         .into_iter()
         .map(|motion| {
             (
@@ -352,11 +366,36 @@ pub extern "C" fn alvr_send_tracking(
                 },
             )
         })
-        .collect::<Vec<_>>();
+        .collect()
+}
+
+#[no_mangle]
+pub extern "C" fn alvr_send_tracking(
+    target_timestamp_ns: u64,
+    device_motions: *const AlvrDeviceMotion,
+    device_motions_count: u64,
+    left_oculus_hand: OculusHand,
+    right_oculus_hand: OculusHand,
+) {
+    fn from_oculus_hand(hand: OculusHand) -> Option<[Quat; 19]> {
+        hand.enabled.then(|| {
+            let vec = hand
+                .bone_rotations
+                .iter()
+                .cloned()
+                .map(from_tracking_quat)
+                .collect::<Vec<_>>();
+
+            let mut array = [Quat::IDENTITY; 19];
+            array.copy_from_slice(&vec);
+
+            array
+        })
+    }
 
     let tracking = Tracking {
         target_timestamp: Duration::from_nanos(target_timestamp_ns),
-        device_motions,
+        device_motions: from_device_motions(device_motions, device_motions_count),
         left_hand_skeleton: from_oculus_hand(left_oculus_hand),
         right_hand_skeleton: from_oculus_hand(right_oculus_hand),
     };
@@ -364,6 +403,50 @@ pub extern "C" fn alvr_send_tracking(
     crate::send_tracking(tracking);
 }
 
+/// The hand skeletons can be null
+#[no_mangle]
+pub unsafe extern "C" fn alvr_send_tracking_with_hand_skeletons(
+    target_timestamp_ns: u64,
+    device_motions: *const AlvrDeviceMotion,
+    device_motions_count: u64,
+    left_hand: *const AlvrHandSkeleton,
+    right_hand: *const AlvrHandSkeleton,
+) {
+    fn from_hand_skeleton(skeleton: &AlvrHandSkeleton) -> HandSkeleton {
+        HandSkeleton {
+            joints: skeleton.joints.map(|joint| HandJoint {
+                orientation: from_tracking_quat(joint.orientation),
+                position: Vec3::from_slice(&joint.position),
+                radius: joint.radius,
+                orientation_valid: joint.orientation_valid,
+                position_valid: joint.position_valid,
+            }),
+        }
+    }
+
+    let tracking = Tracking {
+        target_timestamp: Duration::from_nanos(target_timestamp_ns),
+        device_motions: from_device_motions(device_motions, device_motions_count),
+        left_hand_skeleton: None,
+        right_hand_skeleton: None,
+    };
+
+    crate::send_tracking_with_hand_skeletons(
+        tracking,
+        left_hand.as_ref().map(from_hand_skeleton),
+        right_hand.as_ref().map(from_hand_skeleton),
+    );
+}
+
+/// Returns false if the path is not valid UTF-8 or the recording could not be read
+#[no_mangle]
+pub unsafe extern "C" fn alvr_replay_recording(path: *const c_char) -> bool {
+    let path = CStr::from_ptr(path).to_str().map_err(err!());
+
+    alvr_common::show_err(path.and_then(|path| crate::replay_recording(std::path::Path::new(path))))
+        .is_some()
+}
+
 #[no_mangle]
 pub extern "C" fn alvr_get_head_prediction_offset_ns() -> u64 {
     crate::get_head_prediction_offset().as_nanos() as _
@@ -1,22 +1,27 @@
 #![allow(clippy::if_same_then_else)]
 
 use crate::{
-    decoder::{self, DECODER_INIT_CONFIG},
+    backend::{ClientBackend, CppBackend},
+    decoder::DECODER_INIT_CONFIG,
+    nal::{NalEvent, NalParser},
     platform,
     sockets::AnnouncerSocket,
     statistics::StatisticsManager,
     storage::Config,
-    ClientEvent, VideoFrame, CONTROL_CHANNEL_SENDER, DISCONNECT_NOTIFIER, EVENT_QUEUE, IS_ALIVE,
-    IS_RESUMED, IS_STREAMING, STATISTICS_MANAGER, STATISTICS_SENDER, TRACKING_SENDER,
+    ClientEvent, CONTROL_CHANNEL_SENDER, DISCONNECT_NOTIFIER, EVENT_QUEUE, HAND_SKELETONS_SENDER,
+    IS_ALIVE, IS_RESUMED, IS_STREAMING, PAIRING_CONFIRMATION_SENDER, STATISTICS_MANAGER,
+    STATISTICS_SENDER, TRACKING_SENDER,
 };
 use alvr_audio::{AudioDevice, AudioDeviceType};
-use alvr_common::{glam::UVec2, prelude::*, ALVR_VERSION, HEAD_ID};
-use alvr_session::{AudioDeviceId, CodecType, SessionDesc};
+use alvr_common::{glam::UVec2, prelude::*, RelaxedAtomic, ALVR_VERSION, HEAD_ID};
+use alvr_session::{AudioDeviceId, CodecType, SessionDesc, Settings};
 use alvr_sockets::{
-    spawn_cancelable, BatteryPacket, ClientConnectionResult, ClientControlPacket, Haptics,
-    PeerType, ProtoControlSocket, ServerControlPacket, StreamConfigPacket, StreamSocketBuilder,
-    VideoFrameHeaderPacket, VideoStreamingCapabilities, AUDIO, HAPTICS, STATISTICS, TRACKING,
-    VIDEO,
+    features, spawn_cancelable, BatteryPacket, ClientConnectionResult, ClientControlPacket,
+    ControlSocketReceiver, ControlSocketSender, Haptics, NegotiatedProtocol, PairingRequest,
+    PairingResponse, PeerType, ProtoControlSocket, ProtocolHello, ServerControlPacket,
+    StreamConfigPacket, StreamReceiver, StreamSender, StreamSocketBuilder, Tracking,
+    VideoFrameHeaderPacket, VideoStreamingCapabilities, AUDIO, CONTROL_PORT, HAND_SKELETONS,
+    HAPTICS, STATISTICS, TRACKING, VIDEO,
 };
 use futures::future::BoxFuture;
 use glyph_brush_layout::{
@@ -34,7 +39,7 @@ use std::{
 };
 use tokio::{
     runtime::Runtime,
-    sync::{mpsc as tmpsc, Mutex},
+    sync::{mpsc as tmpsc, oneshot, Mutex},
     time,
 };
 
@@ -45,20 +50,37 @@ use alvr_audio as audio;
 
 const INITIAL_MESSAGE: &str = concat!(
     "Searching for server...\n",
-    "Open ALVR on your PC then click \"Trust\"\n",
+    "Open ALVR on your PC then click \"Pair\"\n",
     "next to the client entry",
 );
 const NETWORK_UNREACHABLE_MESSAGE: &str = "Cannot connect to the internet";
-// const INCOMPATIBLE_VERSIONS_MESSAGE: &str = concat!(
-//     "Server and client have\n",
-//     "incompatible types.\n",
-//     "Please update either the app\n",
-//     "on the PC or on the headset",
-// );
+const INCOMPATIBLE_VERSIONS_MESSAGE: &str = concat!(
+    "Server and client have\n",
+    "incompatible versions.\n",
+    "Please update either the app\n",
+    "on the PC or on the headset",
+);
 const STREAM_STARTING_MESSAGE: &str = "The stream will begin soon\nPlease wait...";
 const SERVER_RESTART_MESSAGE: &str = "The server is restarting\nPlease wait...";
 const SERVER_DISCONNECTED_MESSAGE: &str = "The server has disconnected.";
+const SERVER_NOT_PAIRED_MESSAGE: &str = concat!(
+    "This PC is not paired\n",
+    "with the headset.\n",
+    "Remove the client on the PC\n",
+    "and pair it again",
+);
 
+// Features supported by the client. The ones also supported by the server are used
+fn protocol_features() -> Vec<&'static str> {
+    let mut supported = features::COMMON.to_vec();
+    if platform::decoder_supports_av1() {
+        supported.push(features::CODEC_AV1);
+    }
+
+    supported
+}
+
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
 const DISCOVERY_RETRY_PAUSE: Duration = Duration::from_millis(500);
 const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
 const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
@@ -70,6 +92,11 @@ const HUD_TEXTURE_HEIGHT: usize = 720;
 const FONT_SIZE: f32 = 50_f32;
 
 fn set_hud_message(message: &str) {
+    // The HUD is drawn only on the headset
+    if cfg!(not(target_os = "android")) {
+        return;
+    }
+
     let hostname = Config::load().hostname;
 
     let message = format!(
@@ -121,22 +148,21 @@ fn set_hud_message(message: &str) {
     };
 }
 
-pub fn connection_lifecycle_loop(
-    recommended_view_resolution: UVec2,
-    supported_refresh_rates: Vec<f32>,
-) -> IntResult {
+// The display capabilities are the recommended view resolution and the supported refresh rates.
+// Companion clients have no display.
+pub fn connection_lifecycle_loop(display_capabilities: Option<(UVec2, Vec<f32>)>) -> IntResult {
     set_hud_message(INITIAL_MESSAGE);
 
-    let decoder_guard = Arc::new(Mutex::new(()));
+    let context = StreamContext {
+        backend: Arc::new(CppBackend),
+        decoder_guard: Arc::new(Mutex::new(())),
+        is_resumed: &IS_RESUMED,
+    };
 
     loop {
         check_interrupt!(IS_ALIVE.value());
 
-        match connection_pipeline(
-            recommended_view_resolution,
-            supported_refresh_rates.clone(),
-            Arc::clone(&decoder_guard),
-        ) {
+        match connection_pipeline(&context, display_capabilities.clone()) {
             Ok(()) => continue,
             Err(InterruptibleError::Interrupted) => return Ok(()),
             Err(InterruptibleError::Other(e)) => {
@@ -151,18 +177,57 @@ pub fn connection_lifecycle_loop(
     }
 }
 
+fn pin_server_certificate(config: &mut Config, certificate: Vec<u8>) {
+    if !config.server_certificates.contains(&certificate) {
+        config.server_certificates.push(certificate);
+        config.store();
+    }
+}
+
+// Shows the pairing code until the user accepts or rejects it with confirm_pairing(). The code must
+// match the one shown on the dashboard.
+async fn confirm_pairing_code(pairing_code: &str) -> bool {
+    let (sender, receiver) = oneshot::channel();
+    *PAIRING_CONFIRMATION_SENDER.lock() = Some(sender);
+    EVENT_QUEUE.lock().push_back(ClientEvent::PairingRequested);
+
+    set_hud_message(&format!(
+        "Pairing code: {pairing_code}\n\
+        If the PC shows the same code,\n\
+        press A to confirm or B to reject",
+    ));
+
+    let shutdown_detector = async {
+        while IS_ALIVE.value() {
+            time::sleep(Duration::from_secs(1)).await;
+        }
+    };
+
+    let confirmed = tokio::select! {
+        res = receiver => res.unwrap_or(false),
+        _ = time::sleep(PAIRING_TIMEOUT) => false,
+        _ = shutdown_detector => false,
+    };
+
+    PAIRING_CONFIRMATION_SENDER.lock().take();
+
+    confirmed
+}
+
 fn connection_pipeline(
-    recommended_view_resolution: UVec2,
-    supported_refresh_rates: Vec<f32>,
-    decoder_guard: Arc<Mutex<()>>,
+    context: &StreamContext,
+    display_capabilities: Option<(UVec2, Vec<f32>)>,
 ) -> IntResult {
     let runtime = Runtime::new().map_err(to_int_e!())?;
 
+    let protocol_features = protocol_features();
+
     let (mut proto_control_socket, server_ip) = {
         let config = Config::load();
-        let announcer_socket = AnnouncerSocket::new(&config.hostname).map_err(to_int_e!())?;
+        let announcer_socket =
+            AnnouncerSocket::new(&config.hostname, &protocol_features).map_err(to_int_e!())?;
         let listener_socket = runtime
-            .block_on(alvr_sockets::get_server_listener())
+            .block_on(alvr_sockets::get_server_listener(CONTROL_PORT))
             .map_err(to_int_e!())?;
 
         loop {
@@ -182,7 +247,7 @@ fn connection_pipeline(
 
             let maybe_pair = runtime.block_on(async {
                 tokio::select! {
-                    maybe_pair = ProtoControlSocket::connect_to(PeerType::Server(&listener_socket)) => {
+                    maybe_pair = ProtoControlSocket::connect_to(PeerType::Server(&listener_socket, &config.tls_identity)) => {
                         maybe_pair.map_err(to_int_e!())
                     },
                     _ = time::sleep(DISCOVERY_RETRY_PAUSE) => Err(InterruptibleError::Interrupted)
@@ -195,6 +260,58 @@ fn connection_pipeline(
         }
     };
 
+    let protocol = match runtime
+        .block_on(proto_control_socket.negotiate_protocol(&ProtocolHello::new(&protocol_features)))
+    {
+        Ok(protocol) => protocol,
+        Err(e) => {
+            set_hud_message(INCOMPATIBLE_VERSIONS_MESSAGE);
+            return int_fmt_e!("Protocol negotiation failed: {e}");
+        }
+    };
+
+    let server_certificate = proto_control_socket.peer_certificate().to_vec();
+    let pairing_request = runtime
+        .block_on(proto_control_socket.recv::<PairingRequest>())
+        .map_err(to_int_e!())?;
+    let mut config = Config::load();
+    let is_paired = config.server_certificates.contains(&server_certificate);
+    match pairing_request {
+        PairingRequest::Paired if is_paired => (),
+        PairingRequest::Paired => {
+            set_hud_message(SERVER_NOT_PAIRED_MESSAGE);
+            return int_fmt_e!("The certificate of the server is not pinned");
+        }
+        // Trusting on first use is accepted only by clients that are not paired with another server
+        PairingRequest::TrustOnFirstUse if is_paired || config.server_certificates.is_empty() => {
+            pin_server_certificate(&mut config, server_certificate);
+            runtime
+                .block_on(proto_control_socket.send(&PairingResponse::Confirmed))
+                .map_err(to_int_e!())?;
+        }
+        PairingRequest::ConfirmCode | PairingRequest::TrustOnFirstUse => {
+            let confirmed = IS_RESUMED.value()
+                && runtime.block_on(confirm_pairing_code(proto_control_socket.pairing_code()));
+            set_hud_message(INITIAL_MESSAGE);
+
+            let response = if confirmed {
+                pin_server_certificate(&mut config, server_certificate);
+
+                PairingResponse::Confirmed
+            } else {
+                PairingResponse::Rejected
+            };
+            runtime
+                .block_on(proto_control_socket.send(&response))
+                .map_err(to_int_e!())?;
+
+            // After ConfirmCode, the server connects again once paired
+            if !confirmed || matches!(pairing_request, PairingRequest::ConfirmCode) {
+                return Ok(());
+            }
+        }
+    }
+
     if !IS_RESUMED.value() {
         info!("Not streaming because not resumed");
         return runtime
@@ -202,44 +319,232 @@ fn connection_pipeline(
             .map_err(to_int_e!());
     }
 
-    let microphone_sample_rate =
-        AudioDevice::new(None, &AudioDeviceId::Default, AudioDeviceType::Input)
-            .unwrap()
-            .input_sample_rate()
-            .unwrap();
+    let streaming_capabilities =
+        display_capabilities.map(|(recommended_view_resolution, supported_refresh_rates)| {
+            let microphone_sample_rate =
+                AudioDevice::new(None, &AudioDeviceId::Default, AudioDeviceType::Input)
+                    .unwrap()
+                    .input_sample_rate()
+                    .unwrap();
+
+            VideoStreamingCapabilities {
+                default_view_resolution: recommended_view_resolution,
+                supported_refresh_rates,
+                microphone_sample_rate,
+            }
+        });
+    let is_companion = streaming_capabilities.is_none();
 
     runtime
         .block_on(
             proto_control_socket.send(&ClientConnectionResult::ConnectionAccepted {
                 display_name: platform::device_model(),
                 server_ip,
-                streaming_capabilities: Some(VideoStreamingCapabilities {
-                    default_view_resolution: recommended_view_resolution,
-                    supported_refresh_rates,
-                    microphone_sample_rate,
-                }),
+                streaming_capabilities,
             }),
         )
         .map_err(to_int_e!())?;
+
     let config_packet = runtime
         .block_on(proto_control_socket.recv::<StreamConfigPacket>())
         .map_err(to_int_e!())?;
 
-    runtime
-        .block_on(stream_pipeline(
-            proto_control_socket,
-            config_packet,
-            server_ip,
-            decoder_guard,
+    let settings = stream_settings(&config_packet).map_err(to_int_e!())?;
+    let stream_socket_builder = runtime
+        .block_on(StreamSocketBuilder::listen_for_server(
+            settings.connection.stream_port,
+            settings.connection.stream_protocol,
+            &Config::load().tls_identity,
+            settings.connection.client_send_buffer_bytes,
+            settings.connection.client_recv_buffer_bytes,
         ))
-        .map_err(to_int_e!())
+        .map_err(to_int_e!())?;
+
+    if is_companion {
+        runtime
+            .block_on(companion_pipeline(
+                context,
+                proto_control_socket,
+                config_packet,
+                stream_socket_builder,
+                server_ip,
+            ))
+            .map_err(to_int_e!())
+    } else {
+        runtime
+            .block_on(stream_pipeline(
+                context,
+                protocol,
+                proto_control_socket,
+                config_packet,
+                stream_socket_builder,
+                server_ip,
+            ))
+            .map_err(to_int_e!())
+    }
+}
+
+// The state shared by the streams of the connections. It is passed in, so the stream pipeline can
+// run against a mock backend
+pub struct StreamContext {
+    pub backend: Arc<dyn ClientBackend>,
+    pub decoder_guard: Arc<Mutex<()>>,
+    pub is_resumed: &'static RelaxedAtomic,
+}
+
+fn stream_settings(stream_config: &StreamConfigPacket) -> StrResult<Settings> {
+    let mut session_desc = SessionDesc::default();
+    session_desc.merge_from_json(&json::from_str(&stream_config.session_desc).map_err(err!())?)?;
+
+    Ok(session_desc.to_settings())
 }
 
-async fn stream_pipeline(
+// Forward the video packets to the NAL parser, until the client is paused
+async fn receive_video(
+    receiver: &mut StreamReceiver<VideoFrameHeaderPacket>,
+    backend: &dyn ClientBackend,
+    codec: CodecType,
+    is_resumed: &RelaxedAtomic,
+    control_channel_sender: &tmpsc::UnboundedSender<ClientControlPacket>,
+) -> StrResult {
+    let mut nal_parser = NalParser::new(codec);
+
+    loop {
+        let packet = receiver.recv().await?;
+
+        if !is_resumed.value() {
+            break Ok(());
+        }
+
+        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+            stats.report_video_packet_received(Duration::from_nanos(
+                packet.header.tracking_frame_index,
+            ));
+        }
+
+        // Frames that could not be recovered are lost. Report it to get a new IDR frame
+        if packet.had_packet_loss {
+            control_channel_sender
+                .send(ClientControlPacket::VideoErrorReport)
+                .ok();
+        }
+
+        for event in nal_parser.push_packet(&packet.header, &packet.buffer) {
+            match event {
+                NalEvent::FrameReady { timestamp, nal } => backend.push_nal(timestamp, nal),
+                NalEvent::FrameLost => {
+                    control_channel_sender
+                        .send(ClientControlPacket::VideoErrorReport)
+                        .ok();
+                }
+            }
+        }
+    }
+}
+
+// The loops below are shared by the stream and companion pipelines
+
+async fn send_tracking(mut socket_sender: StreamSender<Tracking>) -> StrResult {
+    let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
+    *TRACKING_SENDER.lock() = Some(data_sender);
+    while let Some(tracking) = data_receiver.recv().await {
+        socket_sender
+            .send_buffer(socket_sender.new_buffer(&tracking, 0)?)
+            .await
+            .ok();
+
+        // Note: this is not the best place to report the acquired input. Instead it should
+        // be done as soon as possible (or even just before polling the input). Instead this
+        // is reported late to partially compensate for lack of network latency measurement,
+        // so the server can just use total_pipeline_latency as the postTimeoffset.
+        // This hack will be removed once poseTimeOffset can be calculated more accurately.
+        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+            stats.report_input_acquired(tracking.target_timestamp);
+        }
+    }
+
+    Ok(())
+}
+
+async fn receive_haptics(mut receiver: StreamReceiver<Haptics>) -> StrResult {
+    loop {
+        let packet = receiver.recv().await?.header;
+
+        EVENT_QUEUE.lock().push_back(ClientEvent::Haptics {
+            device_id: packet.path,
+            duration: packet.duration,
+            frequency: packet.frequency,
+            amplitude: packet.amplitude,
+        });
+    }
+}
+
+async fn send_keepalives(
+    control_sender: &Mutex<ControlSocketSender<ClientControlPacket>>,
+) -> StrResult {
+    loop {
+        let res = control_sender
+            .lock()
+            .await
+            .send(&ClientControlPacket::KeepAlive)
+            .await;
+        if let Err(e) = res {
+            info!("Server disconnected. Cause: {e}");
+            set_hud_message(SERVER_DISCONNECTED_MESSAGE);
+            break Ok(());
+        }
+
+        time::sleep(NETWORK_KEEPALIVE_INTERVAL).await;
+    }
+}
+
+async fn send_control_packets(
+    control_sender: Arc<Mutex<ControlSocketSender<ClientControlPacket>>>,
+    mut control_channel_receiver: tmpsc::UnboundedReceiver<ClientControlPacket>,
+) -> StrResult {
+    while let Some(packet) = control_channel_receiver.recv().await {
+        control_sender.lock().await.send(&packet).await.ok();
+    }
+
+    Ok(())
+}
+
+async fn receive_control(
+    mut control_receiver: ControlSocketReceiver<ServerControlPacket>,
+    backend: &dyn ClientBackend,
+) -> StrResult {
+    loop {
+        match control_receiver.recv().await {
+            Ok(ServerControlPacket::InitializeDecoder { config_buffer }) => {
+                backend.create_decoder(config_buffer);
+            }
+            Ok(ServerControlPacket::Restarting) => {
+                info!("{SERVER_RESTART_MESSAGE}");
+                set_hud_message(SERVER_RESTART_MESSAGE);
+                break Ok(());
+            }
+            Ok(ServerControlPacket::ServerPredictionAverage(interval)) => {
+                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    stats.report_server_prediction_average(interval);
+                }
+            }
+            Ok(_) => (),
+            Err(e) => {
+                info!("{SERVER_DISCONNECTED_MESSAGE} Cause: {e}");
+                set_hud_message(SERVER_DISCONNECTED_MESSAGE);
+                break Ok(());
+            }
+        }
+    }
+}
+
+pub async fn stream_pipeline(
+    context: &StreamContext,
+    protocol: NegotiatedProtocol,
     proto_socket: ProtoControlSocket,
     stream_config: StreamConfigPacket,
+    stream_socket_builder: StreamSocketBuilder,
     server_ip: IpAddr,
-    decoder_guard: Arc<Mutex<()>>,
 ) -> StrResult {
     let (control_sender, mut control_receiver) = proto_socket.split();
     let control_sender = Arc::new(Mutex::new(control_sender));
@@ -266,25 +571,12 @@ async fn stream_pipeline(
         }
     }
 
-    let settings = {
-        let mut session_desc = SessionDesc::default();
-        session_desc
-            .merge_from_json(&json::from_str(&stream_config.session_desc).map_err(err!())?)?;
-        session_desc.to_settings()
-    };
+    let settings = stream_settings(&stream_config)?;
 
     *STATISTICS_MANAGER.lock() = Some(StatisticsManager::new(
         settings.connection.statistics_history_size as _,
     ));
 
-    let stream_socket_builder = StreamSocketBuilder::listen_for_server(
-        settings.connection.stream_port,
-        settings.connection.stream_protocol,
-        settings.connection.client_send_buffer_bytes,
-        settings.connection.client_recv_buffer_bytes,
-    )
-    .await?;
-
     if let Err(e) = control_sender
         .lock()
         .await
@@ -300,6 +592,7 @@ async fn stream_pipeline(
         res = stream_socket_builder.accept_from_server(
             server_ip,
             settings.connection.stream_port,
+            settings.connection.packet_size as _,
         ) => res?,
         _ = time::sleep(Duration::from_secs(5)) => {
             return fmt_e!("Timeout while setting up streams");
@@ -310,8 +603,10 @@ async fn stream_pipeline(
     info!("Connected to server");
 
     // create this before initializing the stream on cpp side
-    let (control_channel_sender, mut control_channel_receiver) = tmpsc::unbounded_channel();
-    *CONTROL_CHANNEL_SENDER.lock() = Some(control_channel_sender);
+    let (control_channel_sender, control_channel_receiver) = tmpsc::unbounded_channel();
+    *CONTROL_CHANNEL_SENDER.lock() = Some(control_channel_sender.clone());
+
+    let backend = Arc::clone(&context.backend);
 
     {
         let config = &mut *DECODER_INIT_CONFIG.lock();
@@ -326,8 +621,8 @@ async fn stream_pipeline(
     }
 
     #[cfg(target_os = "android")]
-    unsafe {
-        crate::setStreamConfig(crate::StreamConfigInput {
+    {
+        backend.set_stream_config(crate::StreamConfigInput {
             viewWidth: stream_config.view_resolution.x,
             viewHeight: stream_config.view_resolution.y,
             enableFoveation: matches!(settings.video.foveated_rendering, Switch::Enabled(_)),
@@ -376,30 +671,27 @@ async fn stream_pipeline(
         });
     }
 
-    let tracking_send_loop = {
-        let mut socket_sender = stream_socket.request_stream(TRACKING).await?;
-        async move {
-            let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
-            *TRACKING_SENDER.lock() = Some(data_sender);
-            while let Some(tracking) = data_receiver.recv().await {
-                socket_sender
-                    .send_buffer(socket_sender.new_buffer(&tracking, 0)?)
-                    .await
-                    .ok();
-
-                // Note: this is not the best place to report the acquired input. Instead it should
-                // be done as soon as possible (or even just before polling the input). Instead this
-                // is reported late to partially compensate for lack of network latency measurement,
-                // so the server can just use total_pipeline_latency as the postTimeoffset.
-                // This hack will be removed once poseTimeOffset can be calculated more accurately.
-                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                    stats.report_input_acquired(tracking.target_timestamp);
+    let tracking_send_loop = send_tracking(stream_socket.request_stream(TRACKING).await?);
+
+    // Without this stream the skeletons are sent in the legacy format, in the tracking packets
+    let hand_skeletons_send_loop: BoxFuture<_> =
+        if protocol.supports(features::HAND_TRACKING_OPENXR) {
+            let mut socket_sender = stream_socket.request_stream(HAND_SKELETONS).await?;
+            Box::pin(async move {
+                let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
+                *HAND_SKELETONS_SENDER.lock() = Some(data_sender);
+                while let Some(skeletons) = data_receiver.recv().await {
+                    socket_sender
+                        .send_buffer(socket_sender.new_buffer(&skeletons, 0)?)
+                        .await
+                        .ok();
                 }
-            }
 
-            Ok(())
-        }
-    };
+                Ok(())
+            })
+        } else {
+            Box::pin(future::pending())
+        };
 
     let statistics_send_loop = {
         let mut socket_sender = stream_socket.request_stream(STATISTICS).await?;
@@ -431,8 +723,13 @@ async fn stream_pipeline(
         let mut receiver = stream_socket
             .subscribe_to_stream::<VideoFrameHeaderPacket>(VIDEO)
             .await?;
+        if settings.connection.enable_nack && protocol.supports(features::NACK) {
+            receiver.enable_nack(Duration::from_secs_f32(1.0 / stream_config.fps));
+        }
         let codec = settings.video.codec;
-        let enable_fec = settings.connection.enable_fec;
+        let backend = Arc::clone(&backend);
+        let decoder_guard = Arc::clone(&context.decoder_guard);
+        let is_resumed = context.is_resumed;
         async move {
             let _decoder_guard = decoder_guard.lock().await;
 
@@ -455,70 +752,20 @@ async fn stream_pipeline(
 
             let _stream_guard = StreamCloseGuard;
 
-            unsafe {
-                crate::initializeNalParser(matches!(codec, CodecType::HEVC) as _, enable_fec)
-            };
-
             EVENT_QUEUE.lock().push_back(streaming_start_event);
 
-            loop {
-                let packet = receiver.recv().await?;
-
-                if !IS_RESUMED.value() {
-                    break Ok(());
-                }
-
-                let header = VideoFrame {
-                    packetCounter: packet.header.packet_counter,
-                    trackingFrameIndex: packet.header.tracking_frame_index,
-                    videoFrameIndex: packet.header.video_frame_index,
-                    sentTime: packet.header.sent_time,
-                    frameByteSize: packet.header.frame_byte_size,
-                    fecIndex: packet.header.fec_index,
-                    fecPercentage: packet.header.fec_percentage,
-                };
-
-                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                    stats.report_video_packet_received(Duration::from_nanos(
-                        packet.header.tracking_frame_index,
-                    ));
-                }
-
-                let mut fec_failure = false;
-                unsafe {
-                    crate::processNalPacket(
-                        header,
-                        packet.buffer.as_ptr(),
-                        packet.buffer.len() as _,
-                        &mut fec_failure,
-                    )
-                };
-                if fec_failure {
-                    if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
-                        sender.send(ClientControlPacket::VideoErrorReport).ok();
-                    }
-                }
-            }
+            receive_video(
+                &mut receiver,
+                &*backend,
+                codec,
+                is_resumed,
+                &control_channel_sender,
+            )
+            .await
         }
     };
 
-    let haptics_receive_loop = {
-        let mut receiver = stream_socket
-            .subscribe_to_stream::<Haptics>(HAPTICS)
-            .await?;
-        async move {
-            loop {
-                let packet = receiver.recv().await?.header;
-
-                EVENT_QUEUE.lock().push_back(ClientEvent::Haptics {
-                    device_id: packet.path,
-                    duration: packet.duration,
-                    frequency: packet.frequency,
-                    amplitude: packet.amplitude,
-                });
-            }
-        }
-    };
+    let haptics_receive_loop = receive_haptics(stream_socket.subscribe_to_stream(HAPTICS).await?);
 
     let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.game_audio {
         let device = AudioDevice::new(None, &AudioDeviceId::Default, AudioDeviceType::Output)
@@ -586,59 +833,10 @@ async fn stream_pipeline(
         }
     });
 
-    let keepalive_sender_loop = {
-        let control_sender = Arc::clone(&control_sender);
-        async move {
-            loop {
-                let res = control_sender
-                    .lock()
-                    .await
-                    .send(&ClientControlPacket::KeepAlive)
-                    .await;
-                if let Err(e) = res {
-                    info!("Server disconnected. Cause: {e}");
-                    set_hud_message(SERVER_DISCONNECTED_MESSAGE);
-                    break Ok(());
-                }
-
-                time::sleep(NETWORK_KEEPALIVE_INTERVAL).await;
-            }
-        }
-    };
-
-    let control_send_loop = async move {
-        while let Some(packet) = control_channel_receiver.recv().await {
-            control_sender.lock().await.send(&packet).await.ok();
-        }
-
-        Ok(())
-    };
-
-    let control_receive_loop = async move {
-        loop {
-            match control_receiver.recv().await {
-                Ok(ServerControlPacket::InitializeDecoder { config_buffer }) => {
-                    decoder::create_decoder(config_buffer);
-                }
-                Ok(ServerControlPacket::Restarting) => {
-                    info!("{SERVER_RESTART_MESSAGE}");
-                    set_hud_message(SERVER_RESTART_MESSAGE);
-                    break Ok(());
-                }
-                Ok(ServerControlPacket::ServerPredictionAverage(interval)) => {
-                    if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                        stats.report_server_prediction_average(interval);
-                    }
-                }
-                Ok(_) => (),
-                Err(e) => {
-                    info!("{SERVER_DISCONNECTED_MESSAGE} Cause: {e}");
-                    set_hud_message(SERVER_DISCONNECTED_MESSAGE);
-                    break Ok(());
-                }
-            }
-        }
-    };
+    let keepalive_sender_loop = send_keepalives(&control_sender);
+    let control_send_loop =
+        send_control_packets(Arc::clone(&control_sender), control_channel_receiver);
+    let control_receive_loop = receive_control(control_receiver, &*backend);
 
     let receive_loop = async move { stream_socket.receive_loop().await };
 
@@ -657,6 +855,7 @@ async fn stream_pipeline(
         res = spawn_cancelable(game_audio_loop) => res,
         res = spawn_cancelable(microphone_loop) => res,
         res = spawn_cancelable(tracking_send_loop) => res,
+        res = spawn_cancelable(hand_skeletons_send_loop) => res,
         res = spawn_cancelable(statistics_send_loop) => res,
         res = spawn_cancelable(video_receive_loop) => res,
         res = spawn_cancelable(haptics_receive_loop) => res,
@@ -669,3 +868,185 @@ async fn stream_pipeline(
         _ = DISCONNECT_NOTIFIER.notified() => Ok(()),
     }
 }
+
+// Companion clients only send tracking, input and battery status, and receive haptics
+async fn companion_pipeline(
+    context: &StreamContext,
+    proto_socket: ProtoControlSocket,
+    stream_config: StreamConfigPacket,
+    stream_socket_builder: StreamSocketBuilder,
+    server_ip: IpAddr,
+) -> StrResult {
+    let (control_sender, mut control_receiver) = proto_socket.split();
+    let control_sender = Arc::new(Mutex::new(control_sender));
+
+    match control_receiver.recv().await {
+        Ok(ServerControlPacket::StartStream) => {
+            info!("Companion stream starting");
+        }
+        Ok(ServerControlPacket::Restarting) => {
+            info!("Server restarting");
+            return Ok(());
+        }
+        Err(e) => {
+            info!("Server disconnected. Cause: {e}");
+            return Ok(());
+        }
+        _ => {
+            info!("Unexpected packet");
+            return Ok(());
+        }
+    }
+
+    let settings = stream_settings(&stream_config)?;
+
+    if let Err(e) = control_sender
+        .lock()
+        .await
+        .send(&ClientControlPacket::StreamReady)
+        .await
+    {
+        info!("Server disconnected. Cause: {e}");
+        return Ok(());
+    }
+
+    let stream_socket = tokio::select! {
+        res = stream_socket_builder.accept_from_server(
+            server_ip,
+            settings.connection.stream_port,
+            settings.connection.packet_size as _,
+        ) => res?,
+        _ = time::sleep(Duration::from_secs(5)) => {
+            return fmt_e!("Timeout while setting up streams");
+        }
+    };
+    let stream_socket = Arc::new(stream_socket);
+
+    info!("Connected to server as companion");
+
+    let (control_channel_sender, control_channel_receiver) = tmpsc::unbounded_channel();
+    *CONTROL_CHANNEL_SENDER.lock() = Some(control_channel_sender);
+
+    let tracking_send_loop = send_tracking(stream_socket.request_stream(TRACKING).await?);
+
+    let haptics_receive_loop = receive_haptics(stream_socket.subscribe_to_stream(HAPTICS).await?);
+
+    let keepalive_sender_loop = send_keepalives(&control_sender);
+    let control_send_loop =
+        send_control_packets(Arc::clone(&control_sender), control_channel_receiver);
+    // The server does not send decoder configurations to companion clients
+    let control_receive_loop = receive_control(control_receiver, &*context.backend);
+
+    let receive_loop = async move { stream_socket.receive_loop().await };
+
+    tokio::select! {
+        res = spawn_cancelable(receive_loop) => {
+            if let Err(e) = res {
+                info!("Server disconnected. Cause: {e}");
+            }
+
+            Ok(())
+        },
+        res = spawn_cancelable(tracking_send_loop) => res,
+        res = spawn_cancelable(haptics_receive_loop) => res,
+        res = spawn_cancelable(control_send_loop) => res,
+
+        // keep these loops on the current task
+        res = keepalive_sender_loop => res,
+        res = control_receive_loop => res,
+
+        _ = DISCONNECT_NOTIFIER.notified() => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use alvr_sockets::{simulated_link, GilbertElliott, LinkImpairments, StreamSocket};
+
+    const PACKET_SIZE: usize = 1400;
+    const FRAMES_COUNT: u64 = 100;
+    const FRAME_SIZE: usize = 3000;
+
+    #[tokio::test]
+    async fn video_frames_reach_decoder() {
+        let is_resumed = RelaxedAtomic::new(true);
+        let (control_channel_sender, mut control_channel_receiver) = tmpsc::unbounded_channel();
+
+        let (server_send_socket, client_receive_socket) = simulated_link(
+            LinkImpairments {
+                loss: Some(GilbertElliott::bursts(0.05, 2.0)),
+                ..Default::default()
+            },
+            0,
+        );
+        let (client_send_socket, server_receive_socket) =
+            simulated_link(LinkImpairments::default(), 1);
+
+        let server_socket = StreamSocket::new(
+            server_send_socket,
+            Box::new(server_receive_socket),
+            PACKET_SIZE,
+        );
+        let client_socket = StreamSocket::new(
+            client_send_socket,
+            Box::new(client_receive_socket),
+            PACKET_SIZE,
+        );
+
+        let mut sender = server_socket
+            .request_stream::<VideoFrameHeaderPacket>(VIDEO)
+            .await
+            .unwrap();
+        let mut receiver = client_socket.subscribe_to_stream(VIDEO).await.unwrap();
+
+        for video_frame_index in 0..FRAMES_COUNT {
+            let header = VideoFrameHeaderPacket {
+                packet_counter: video_frame_index as _,
+                tracking_frame_index: video_frame_index,
+                video_frame_index,
+                sent_time: 0,
+                frame_byte_size: FRAME_SIZE as _,
+                fec_index: 0,
+                fec_percentage: 0,
+            };
+            // IDR slice
+            let mut buffer = sender.new_buffer(&header, FRAME_SIZE).unwrap();
+            buffer.get_mut().extend([0, 0, 0, 1, 0x65]);
+            buffer.get_mut().extend(vec![0xAA; FRAME_SIZE - 5]);
+            sender.send_buffer(buffer).await.unwrap();
+        }
+
+        // The receiver fails once the server disconnects and all the packets are received
+        drop(sender);
+        drop(server_socket);
+
+        let backend = MockBackend::default();
+        let (_, res) = tokio::join!(
+            client_socket.receive_loop(),
+            receive_video(
+                &mut receiver,
+                &backend,
+                CodecType::H264,
+                &is_resumed,
+                &control_channel_sender,
+            )
+        );
+        assert!(res.is_err());
+
+        let frames = backend.frames.lock();
+        assert!(!frames.is_empty());
+        assert!(frames.len() < FRAMES_COUNT as usize);
+        assert!(frames.iter().all(|(_, size)| *size == FRAME_SIZE));
+        assert!(frames.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        let mut error_reports = 0;
+        while let Ok(packet) = control_channel_receiver.try_recv() {
+            if matches!(packet, ClientControlPacket::VideoErrorReport) {
+                error_reports += 1;
+            }
+        }
+        assert!(error_reports > 0);
+    }
+}
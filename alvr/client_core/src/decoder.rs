@@ -1,7 +1,7 @@
 use crate::{ClientEvent, EVENT_QUEUE};
 use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex, RelaxedAtomic};
 use alvr_session::{CodecType, MediacodecDataType};
-use std::{ffi::c_char, ptr, time::Duration};
+use std::time::Duration;
 
 #[cfg(target_os = "android")]
 use alvr_common::prelude::*;
@@ -61,18 +61,11 @@ pub fn create_decoder(config_nal: Vec<u8>) {
                     .send(alvr_sockets::ClientControlPacket::RequestIdr)
                     .ok();
             }
-
-            unsafe { crate::notifyNewDecoder() };
         }
     }
 }
 
-pub extern "C" fn push_nal(buffer: *const c_char, length: i32, timestamp_ns: u64) {
-    let timestamp = Duration::from_nanos(timestamp_ns);
-
-    let mut nal = vec![0; length as _];
-    unsafe { ptr::copy_nonoverlapping(buffer, nal.as_mut_ptr() as _, length as _) }
-
+pub fn push_nal(timestamp: Duration, nal: Vec<u8>) {
     if EXTERNAL_DECODER.value() {
         EVENT_QUEUE
             .lock()
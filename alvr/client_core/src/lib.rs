@@ -5,10 +5,12 @@
     clippy::unseparated_literal_suffix
 )]
 
+mod backend;
 mod c_api;
 mod connection;
 mod decoder;
 mod logging_backend;
+mod nal;
 mod platform;
 mod sockets;
 mod statistics;
@@ -19,6 +21,12 @@ mod audio;
 
 pub use logging_backend::init_logging;
 
+// Used by the loopback test of the server, that runs both pipelines in the same process
+#[doc(hidden)]
+pub use backend::ClientBackend;
+#[doc(hidden)]
+pub use connection::{stream_pipeline, StreamContext};
+
 include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 
 use alvr_common::{
@@ -31,23 +39,27 @@ use alvr_common::{
 use alvr_events::ButtonValue;
 use alvr_session::{CodecType, OculusFovetionLevel};
 use alvr_sockets::{
-    BatteryPacket, ClientControlPacket, ClientStatistics, Fov, Tracking, ViewsConfig,
+    BatteryPacket, ClientControlPacket, ClientStatistics, Fov, HandSkeleton, HandSkeletons,
+    RecordedEvent, Tracking, ViewsConfig,
 };
 use decoder::EXTERNAL_DECODER;
 use serde::{Deserialize, Serialize};
 use statistics::StatisticsManager;
 use std::{
     collections::VecDeque,
+    path::Path,
     thread::{self, JoinHandle},
-    time::Duration,
+    time::{Duration, Instant},
 };
 use storage::Config;
-use tokio::{sync::mpsc, sync::Notify};
+use tokio::{sync::mpsc, sync::oneshot, sync::Notify};
 
 static STATISTICS_MANAGER: Lazy<Mutex<Option<StatisticsManager>>> = Lazy::new(|| Mutex::new(None));
 
 static TRACKING_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<Tracking>>>> =
     Lazy::new(|| Mutex::new(None));
+static HAND_SKELETONS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<HandSkeletons>>>> =
+    Lazy::new(|| Mutex::new(None));
 static STATISTICS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<ClientStatistics>>>> =
     Lazy::new(|| Mutex::new(None));
 static CONTROL_CHANNEL_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<ClientControlPacket>>>> =
@@ -61,6 +73,13 @@ static IS_RESUMED: RelaxedAtomic = RelaxedAtomic::new(false);
 static IS_STREAMING: RelaxedAtomic = RelaxedAtomic::new(false);
 
 static CONNECTION_THREAD: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
+// Set while the pairing code is shown on the HUD
+static PAIRING_CONFIRMATION_SENDER: Lazy<Mutex<Option<oneshot::Sender<bool>>>> =
+    Lazy::new(|| Mutex::new(None));
+
+// Time base of the target timestamps of the replayed tracking
+static REPLAY_EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+static REPLAY_THREAD: Lazy<Mutex<Option<JoinHandle<()>>>> = Lazy::new(|| Mutex::new(None));
 
 #[derive(Serialize, Deserialize)]
 pub enum ClientEvent {
@@ -86,6 +105,24 @@ pub enum ClientEvent {
         timestamp: Duration,
         nal: Vec<u8>,
     },
+    // The HUD shows the pairing code. The user must accept or reject it with confirm_pairing().
+    PairingRequested,
+}
+
+// Make sure to reset config in case of version compat mismatch. The hostname, the identity and the
+// pairings are kept: the servers know the client by them.
+fn reset_outdated_config() {
+    let config = Config::load();
+    if config.protocol_id != alvr_common::protocol_id() {
+        // NB: Config::default() sets the current protocol ID
+        Config {
+            hostname: config.hostname,
+            tls_identity: config.tls_identity,
+            server_certificates: config.server_certificates,
+            ..Config::default()
+        }
+        .store();
+    }
 }
 
 pub fn initialize(
@@ -95,15 +132,7 @@ pub fn initialize(
 ) {
     logging_backend::init_logging();
 
-    unsafe {
-        pushNal = Some(decoder::push_nal);
-    }
-
-    // Make sure to reset config in case of version compat mismatch.
-    if Config::load().protocol_id != alvr_common::protocol_id() {
-        // NB: Config::default() sets the current protocol ID
-        Config::default().store();
-    }
+    reset_outdated_config();
 
     #[cfg(target_os = "android")]
     platform::try_get_microphone_permission();
@@ -113,8 +142,26 @@ pub fn initialize(
     EXTERNAL_DECODER.set(external_decoder);
 
     *CONNECTION_THREAD.lock() = Some(thread::spawn(move || {
-        connection::connection_lifecycle_loop(recommended_view_resolution, supported_refresh_rates)
-            .ok();
+        connection::connection_lifecycle_loop(Some((
+            recommended_view_resolution,
+            supported_refresh_rates,
+        )))
+        .ok();
+    }));
+}
+
+// Companion clients, like a phone or a second headset, send only tracking, input and battery
+// status. They do not receive video nor audio.
+pub fn initialize_companion() {
+    logging_backend::init_logging();
+
+    reset_outdated_config();
+
+    #[cfg(target_os = "android")]
+    platform::acquire_wifi_lock();
+
+    *CONNECTION_THREAD.lock() = Some(thread::spawn(|| {
+        connection::connection_lifecycle_loop(None).ok();
     }));
 }
 
@@ -137,6 +184,14 @@ pub fn pause() {
     IS_RESUMED.set(false);
 }
 
+// Answer to ClientEvent::PairingRequested, once the user has compared the pairing code with the one
+// shown on the PC
+pub fn confirm_pairing(accepted: bool) {
+    if let Some(sender) = PAIRING_CONFIRMATION_SENDER.lock().take() {
+        sender.send(accepted).ok();
+    }
+}
+
 pub fn poll_event() -> Option<ClientEvent> {
     EVENT_QUEUE.lock().pop_front()
 }
@@ -181,6 +236,82 @@ pub fn send_tracking(tracking: Tracking) {
     }
 }
 
+// Skeletons in the OpenXR format. If the server does not support them, they are converted to the
+// legacy format, and the motion of each tracked hand is replaced by the pose of its wrist.
+pub fn send_tracking_with_hand_skeletons(
+    mut tracking: Tracking,
+    left_hand: Option<HandSkeleton>,
+    right_hand: Option<HandSkeleton>,
+) {
+    let skeletons = HandSkeletons {
+        target_timestamp: tracking.target_timestamp,
+        left_hand,
+        right_hand,
+    };
+
+    // The sender is closed if the last server supporting the new format disconnected
+    let res = if let Some(sender) = &*HAND_SKELETONS_SENDER.lock() {
+        sender.send(skeletons).map_err(|e| e.0)
+    } else {
+        Err(skeletons)
+    };
+
+    if let Err(skeletons) = res {
+        alvr_sockets::merge_legacy_hand_skeletons(
+            &mut tracking,
+            skeletons.left_hand.as_ref(),
+            skeletons.right_hand.as_ref(),
+        );
+    }
+
+    send_tracking(tracking);
+}
+
+// Sends a tracking recording made by the server in place of a headset, with the original timing.
+// The target timestamps are shifted to keep increasing when a recording is replayed more than once.
+// The recorded haptics are skipped. Only one recording can be replayed at a time.
+pub fn replay_recording(path: &Path) -> StrResult {
+    let mut replay_thread = REPLAY_THREAD.lock();
+    if matches!(&*replay_thread, Some(thread) if !thread.is_finished()) {
+        return fmt_e!("A tracking recording is already being replayed");
+    }
+
+    let entries = alvr_sockets::read_recording(path)?;
+    let first_timestamp = entries.iter().find_map(|entry| match &entry.event {
+        RecordedEvent::Tracking(tracking) => Some(tracking.target_timestamp),
+        _ => None,
+    });
+
+    *replay_thread = Some(thread::spawn(move || {
+        let start_instant = Instant::now();
+        let timestamp_offset = start_instant.saturating_duration_since(*REPLAY_EPOCH);
+
+        for entry in entries {
+            thread::sleep((start_instant + entry.time).saturating_duration_since(Instant::now()));
+
+            if !IS_ALIVE.value() {
+                break;
+            }
+
+            match entry.event {
+                RecordedEvent::Tracking(mut tracking) => {
+                    tracking.target_timestamp = tracking
+                        .target_timestamp
+                        .saturating_sub(first_timestamp.unwrap_or_default())
+                        + timestamp_offset;
+                    send_tracking(*tracking);
+                }
+                RecordedEvent::Button { path_id, value } => send_button(path_id, value),
+                RecordedEvent::Haptics(_) => (),
+            }
+        }
+
+        info!("Tracking replay finished");
+    }));
+
+    Ok(())
+}
+
 pub fn get_head_prediction_offset() -> Duration {
     if let Some(stats) = &*STATISTICS_MANAGER.lock() {
         stats.average_total_pipeline_latency()
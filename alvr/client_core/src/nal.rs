@@ -0,0 +1,315 @@
+// Reassembles the video frames from the packets of the video stream and splits them into NAL
+// units. The frames are held back until the first IDR frame, as the previous ones cannot be
+// decoded. The parameter sets are left out: the decoder is created from the ones the server sends
+// with InitializeDecoder. AV1 frames are forwarded as they are.
+
+use alvr_session::CodecType;
+use alvr_sockets::{annex_b_nals, is_idr, is_parameter_set, nal_type, VideoFrameHeaderPacket};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum NalEvent {
+    FrameReady { timestamp: Duration, nal: Vec<u8> },
+    // A frame was not received in full
+    FrameLost,
+}
+
+struct PartialFrame {
+    video_frame_index: u64,
+    timestamp: Duration,
+    frame_byte_size: usize,
+    buffer: Vec<u8>,
+}
+
+pub struct NalParser {
+    codec: CodecType,
+    partial_frame: Option<PartialFrame>,
+    last_video_frame_index: Option<u64>,
+    idr_received: bool,
+}
+
+impl NalParser {
+    pub fn new(codec: CodecType) -> Self {
+        Self {
+            codec,
+            partial_frame: None,
+            last_video_frame_index: None,
+            idr_received: false,
+        }
+    }
+
+    // A frame can be split across several packets with the same video frame index. It is complete
+    // once frame_byte_size bytes are received.
+    pub fn push_packet(&mut self, header: &VideoFrameHeaderPacket, shard: &[u8]) -> Vec<NalEvent> {
+        let mut events = vec![];
+
+        // Packets of frames that were already completed or dropped
+        if matches!(self.last_video_frame_index, Some(index) if header.video_frame_index <= index) {
+            return events;
+        }
+
+        if matches!(
+            &self.partial_frame,
+            Some(frame) if frame.video_frame_index != header.video_frame_index
+        ) {
+            self.partial_frame = None;
+            events.push(NalEvent::FrameLost);
+        }
+
+        let frame = self.partial_frame.get_or_insert_with(|| PartialFrame {
+            video_frame_index: header.video_frame_index,
+            timestamp: Duration::from_nanos(header.tracking_frame_index),
+            frame_byte_size: header.frame_byte_size as _,
+            buffer: vec![],
+        });
+        frame.buffer.extend_from_slice(shard);
+
+        if frame.buffer.len() >= frame.frame_byte_size {
+            if let Some(frame) = self.partial_frame.take() {
+                self.last_video_frame_index = Some(frame.video_frame_index);
+                self.process_frame(frame.timestamp, frame.buffer, &mut events);
+            }
+        }
+
+        events
+    }
+
+    fn process_frame(&mut self, timestamp: Duration, buffer: Vec<u8>, events: &mut Vec<NalEvent>) {
+        if matches!(self.codec, CodecType::AV1) {
+            events.push(NalEvent::FrameReady {
+                timestamp,
+                nal: buffer,
+            });
+
+            return;
+        }
+
+        let mut nal = vec![];
+        let mut frame_is_idr = false;
+        for unit in annex_b_nals(&buffer) {
+            if let Some(nal_type) = nal_type(self.codec, unit) {
+                if !is_parameter_set(self.codec, nal_type) {
+                    frame_is_idr |= is_idr(self.codec, nal_type);
+                    nal.extend_from_slice(unit);
+                }
+            }
+        }
+
+        self.idr_received |= frame_is_idr;
+
+        if self.idr_received && !nal.is_empty() {
+            events.push(NalEvent::FrameReady { timestamp, nal });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn header(video_frame_index: u64, frame_byte_size: usize) -> VideoFrameHeaderPacket {
+        VideoFrameHeaderPacket {
+            packet_counter: 0,
+            tracking_frame_index: video_frame_index * 1000,
+            video_frame_index,
+            sent_time: 0,
+            frame_byte_size: frame_byte_size as _,
+            fec_index: 0,
+            fec_percentage: 0,
+        }
+    }
+
+    // NAL unit with a 3 or 4 byte start code. The payload has no zero bytes, so it never contains a
+    // start code.
+    fn nal_unit(codec: CodecType, nal_type: u8, long_start_code: bool, payload: &[u8]) -> Vec<u8> {
+        let mut nal = if long_start_code {
+            vec![0, 0, 0, 1]
+        } else {
+            vec![0, 0, 1]
+        };
+        match codec {
+            CodecType::H264 => nal.push(0x60 | nal_type),
+            _ => nal.extend([nal_type << 1, 1]),
+        }
+        nal.extend_from_slice(payload);
+
+        nal
+    }
+
+    fn nal_units_strategy(codec: CodecType) -> impl Strategy<Value = Vec<Vec<u8>>> {
+        let nal_types = match codec {
+            CodecType::H264 => vec![1, 5, 6, 7, 8, 9],
+            _ => vec![0, 1, 19, 21, 32, 33, 34, 35, 39],
+        };
+
+        prop::collection::vec(
+            (
+                prop::sample::select(nal_types),
+                any::<bool>(),
+                prop::collection::vec(1..=u8::MAX, 0..64),
+            )
+                .prop_map(move |(nal_type, long_start_code, payload)| {
+                    nal_unit(codec, nal_type, long_start_code, &payload)
+                }),
+            1..16,
+        )
+    }
+
+    fn codec_strategy() -> impl Strategy<Value = CodecType> {
+        prop_oneof![Just(CodecType::H264), Just(CodecType::HEVC)]
+    }
+
+    fn codec_and_nal_units_strategy() -> impl Strategy<Value = (CodecType, Vec<Vec<u8>>)> {
+        codec_strategy().prop_flat_map(|codec| (Just(codec), nal_units_strategy(codec)))
+    }
+
+    // Splits the frame at the given fractions of its size
+    fn shards(frame: &[u8], split_points: &[f32]) -> Vec<Vec<u8>> {
+        let mut indices = split_points
+            .iter()
+            .map(|point| (point * frame.len() as f32) as usize)
+            .collect::<Vec<_>>();
+        indices.push(0);
+        indices.push(frame.len());
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices
+            .windows(2)
+            .map(|range| frame[range[0]..range[1]].to_vec())
+            .collect()
+    }
+
+    fn idr_unit(codec: CodecType) -> Vec<u8> {
+        match codec {
+            CodecType::H264 => nal_unit(codec, 5, true, &[0x88]),
+            _ => nal_unit(codec, 19, true, &[0xAF]),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn frames_are_reassembled_from_any_shards(
+            (codec, units) in codec_and_nal_units_strategy(),
+            split_points in prop::collection::vec(0_f32..1.0, 0..8),
+        ) {
+            let mut parser = NalParser::new(codec);
+            // Make sure the frame is not held back waiting for an IDR
+            let idr = idr_unit(codec);
+            parser.push_packet(&header(0, idr.len()), &idr);
+
+            let frame = units.concat();
+            let mut events = vec![];
+            for shard in shards(&frame, &split_points) {
+                events.extend(parser.push_packet(&header(1, frame.len()), &shard));
+            }
+
+            let nals = units
+                .iter()
+                .filter(|unit| !is_parameter_set(codec, nal_type(codec, unit).unwrap()))
+                .collect::<Vec<_>>();
+            let mut expected_events = vec![];
+            if !nals.is_empty() {
+                expected_events.push(NalEvent::FrameReady {
+                    timestamp: Duration::from_micros(1),
+                    nal: nals.into_iter().flatten().copied().collect(),
+                });
+            }
+            prop_assert_eq!(events, expected_events);
+        }
+
+        #[test]
+        fn incomplete_frames_are_lost(
+            (codec, units) in codec_and_nal_units_strategy(),
+            received_fraction in 0_f32..1.0,
+        ) {
+            let mut parser = NalParser::new(codec);
+
+            let frame = units.concat();
+            let received_size = (received_fraction * frame.len() as f32) as usize;
+            let mut events = parser.push_packet(&header(0, frame.len()), &frame[..received_size]);
+
+            let idr = idr_unit(codec);
+            events.extend(parser.push_packet(&header(1, idr.len()), &idr));
+            // Late packet of the lost frame
+            events.extend(parser.push_packet(&header(0, frame.len()), &frame[received_size..]));
+
+            prop_assert_eq!(
+                events,
+                vec![
+                    NalEvent::FrameLost,
+                    NalEvent::FrameReady {
+                        timestamp: Duration::from_micros(1),
+                        nal: idr,
+                    }
+                ]
+            );
+        }
+
+        #[test]
+        fn frames_before_idr_are_dropped(
+            (codec, units) in codec_and_nal_units_strategy(),
+        ) {
+            let mut parser = NalParser::new(codec);
+
+            let frame = units.concat();
+            let events = parser.push_packet(&header(0, frame.len()), &frame);
+
+            let has_idr = units
+                .iter()
+                .any(|unit| is_idr(codec, nal_type(codec, unit).unwrap()));
+            let has_frame = events
+                .iter()
+                .any(|event| matches!(event, NalEvent::FrameReady { .. }));
+            prop_assert!(has_idr || !has_frame);
+        }
+
+        #[test]
+        fn arbitrary_data_does_not_panic(
+            codec in codec_strategy(),
+            packets in prop::collection::vec(
+                (0_u64..4, 0_usize..256, prop::collection::vec(any::<u8>(), 0..256)),
+                0..8,
+            ),
+        ) {
+            let mut parser = NalParser::new(codec);
+            for (video_frame_index, frame_byte_size, shard) in packets {
+                parser.push_packet(&header(video_frame_index, frame_byte_size), &shard);
+            }
+        }
+    }
+
+    #[test]
+    fn parameter_sets_are_left_out() {
+        let codec = CodecType::H264;
+        let sps = nal_unit(codec, 7, true, &[0x64, 0x00, 0x1F]);
+        let pps = nal_unit(codec, 8, true, &[0xEE]);
+        let frame = [sps, pps, idr_unit(codec)].concat();
+
+        let mut parser = NalParser::new(codec);
+        let events = parser.push_packet(&header(0, frame.len()), &frame);
+        assert_eq!(
+            events,
+            vec![NalEvent::FrameReady {
+                timestamp: Duration::ZERO,
+                nal: idr_unit(codec),
+            }]
+        );
+    }
+
+    #[test]
+    fn av1_frames_are_forwarded() {
+        let mut parser = NalParser::new(CodecType::AV1);
+        let frame = [0x12, 0, 0x32, 1, 0x10];
+
+        let events = parser.push_packet(&header(0, frame.len()), &frame);
+        assert_eq!(
+            events,
+            vec![NalEvent::FrameReady {
+                timestamp: Duration::ZERO,
+                nal: frame.to_vec(),
+            }]
+        );
+    }
+}
@@ -337,6 +337,91 @@ impl Drop for VideoDecoderDequeuer {
 
 // Create a enqueuer/dequeuer pair. To preserve the state of internal variables, use
 // `enqueuer.recreate_decoder()` instead of dropping the pair and calling this function again.
+const AV1_MIME: &str = "video/av01";
+
+// Hardware AV1 decoders are only found on recent headsets. The software decoders that ship with
+// Android (c2.android.av1.decoder) are too slow for streaming and are not counted.
+fn has_av1_hardware_decoder() -> StrResult<bool> {
+    let vm = vm();
+    let env = vm.attach_current_thread().map_err(err!())?;
+
+    const ALL_CODECS: i32 = 1;
+
+    let codec_list = env
+        .new_object("android/media/MediaCodecList", "(I)V", &[ALL_CODECS.into()])
+        .map_err(err!())?;
+    let codec_infos = env
+        .call_method(
+            codec_list,
+            "getCodecInfos",
+            "()[Landroid/media/MediaCodecInfo;",
+            &[],
+        )
+        .and_then(|value| value.l())
+        .map_err(err!())?
+        .into_raw();
+
+    for index in 0..env.get_array_length(codec_infos).map_err(err!())? {
+        let codec_info = env
+            .get_object_array_element(codec_infos, index)
+            .map_err(err!())?;
+
+        let is_hardware_decoder = !env
+            .call_method(codec_info, "isEncoder", "()Z", &[])
+            .and_then(|value| value.z())
+            .map_err(err!())?
+            && env
+                .call_method(codec_info, "isHardwareAccelerated", "()Z", &[])
+                .and_then(|value| value.z())
+                .map_err(err!())?;
+
+        let mut supports_av1 = false;
+        if is_hardware_decoder {
+            let mime_types = env
+                .call_method(
+                    codec_info,
+                    "getSupportedTypes",
+                    "()[Ljava/lang/String;",
+                    &[],
+                )
+                .and_then(|value| value.l())
+                .map_err(err!())?
+                .into_raw();
+
+            for index in 0..env.get_array_length(mime_types).map_err(err!())? {
+                let mime_type = env
+                    .get_object_array_element(mime_types, index)
+                    .map_err(err!())?;
+                supports_av1 |= env
+                    .get_string(mime_type.into())
+                    .map_err(err!())?
+                    .to_string_lossy()
+                    .eq_ignore_ascii_case(AV1_MIME);
+                env.delete_local_ref(mime_type).ok();
+            }
+        }
+
+        // The list can be longer than the local reference table
+        env.delete_local_ref(codec_info).ok();
+
+        if supports_av1 {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+pub fn decoder_supports_av1() -> bool {
+    // MediaCodecInfo.isHardwareAccelerated() is available from API level 29
+    if get_api_level() < 29 {
+        return false;
+    }
+
+    // AV1 is not advertised if the codec list cannot be read
+    alvr_common::show_err(has_av1_hardware_decoder()).unwrap_or(false)
+}
+
 pub fn video_decoder_split(
     config: DecoderInitConfig,
     csd_0: Vec<u8>,
@@ -362,13 +447,22 @@ pub fn video_decoder_split(
             let mime = match config.codec {
                 CodecType::H264 => "video/avc",
                 CodecType::HEVC => "video/hevc",
+                CodecType::AV1 => AV1_MIME,
             };
 
             let format = MediaFormat::new();
             format.set_str("mime", mime);
             format.set_i32("width", 512);
             format.set_i32("height", 1024);
-            format.set_buffer("csd-0", &csd_0);
+            if matches!(config.codec, CodecType::AV1) {
+                // The server sends the bare sequence header OBU
+                match alvr_sockets::av1_codec_configuration_record(&csd_0) {
+                    Ok(record) => format.set_buffer("csd-0", &record),
+                    Err(e) => error!("Invalid AV1 sequence header: {e}"),
+                }
+            } else {
+                format.set_buffer("csd-0", &csd_0);
+            }
 
             for (key, value) in &config.options {
                 match value {
@@ -3,9 +3,9 @@ pub mod android;
 
 #[cfg(target_os = "android")]
 pub use android::{
-    acquire_wifi_lock, battery_status, context, device_model, local_ip, release_wifi_lock,
-    try_get_microphone_permission, video_decoder_split, vm, VideoDecoderDequeuer,
-    VideoDecoderEnqueuer,
+    acquire_wifi_lock, battery_status, context, decoder_supports_av1, device_model, local_ip,
+    release_wifi_lock, try_get_microphone_permission, video_decoder_split, vm,
+    VideoDecoderDequeuer, VideoDecoderEnqueuer,
 };
 
 #[cfg(not(target_os = "android"))]
@@ -25,6 +25,12 @@ pub fn local_ip() -> std::net::IpAddr {
     std::net::IpAddr::V4(std::net::Ipv4Addr::UNSPECIFIED)
 }
 
+// The decoders of the other platforms are external, and the ones in use are not known
+#[cfg(not(target_os = "android"))]
+pub fn decoder_supports_av1() -> bool {
+    false
+}
+
 // Return (percentage, is plugged)
 #[cfg(not(target_os = "android"))]
 pub fn battery_status() -> (f32, bool) {
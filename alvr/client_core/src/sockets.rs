@@ -1,15 +1,22 @@
-use alvr_common::{StrResult, *};
-use alvr_sockets::{CONTROL_PORT, LOCAL_IP};
-use std::net::{Ipv4Addr, UdpSocket};
+use alvr_common::{prelude::*, ALVR_NAME};
+use alvr_sockets::{
+    bind_dual_stack, socket2, to_local_family, CONTROL_PORT, DISCOVERY_MULTICAST_IP,
+    MDNS_FEATURES_KEY, MDNS_HOSTNAME_KEY, MDNS_PROTOCOL_KEY, MDNS_SERVICE_TYPE,
+};
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use std::net::{Ipv4Addr, SocketAddr, UdpSocket};
 
+// The client is announced with IPv4 broadcast, IPv6 multicast and mDNS, since some networks drop
+// one or the other
 pub struct AnnouncerSocket {
     socket: UdpSocket,
     packet: [u8; 56],
+    mdns: Option<(ServiceDaemon, String)>,
 }
 
 impl AnnouncerSocket {
-    pub fn new(hostname: &str) -> StrResult<Self> {
-        let socket = UdpSocket::bind((LOCAL_IP, CONTROL_PORT)).map_err(err!())?;
+    pub fn new(hostname: &str, features: &[&str]) -> StrResult<Self> {
+        let socket: UdpSocket = bind_dual_stack(socket2::Type::DGRAM, CONTROL_PORT)?.into();
         socket.set_broadcast(true).map_err(err!())?;
 
         let mut packet = [0; 56];
@@ -17,13 +24,80 @@ impl AnnouncerSocket {
         packet[16..24].copy_from_slice(&alvr_common::protocol_id().to_le_bytes());
         packet[24..24 + hostname.len()].copy_from_slice(hostname.as_bytes());
 
-        Ok(Self { socket, packet })
+        let mdns = register_mdns_service(hostname, features)
+            .map_err(|e| warn!("mDNS advertising not available: {e}"))
+            .ok();
+
+        Ok(Self {
+            socket,
+            packet,
+            mdns,
+        })
     }
 
     pub fn broadcast(&self) -> StrResult {
-        self.socket
-            .send_to(&self.packet, (Ipv4Addr::BROADCAST, CONTROL_PORT))
-            .map_err(err!())?;
+        let local_address = self.socket.local_addr().map_err(err!())?;
+
+        let broadcast_res = self.socket.send_to(
+            &self.packet,
+            to_local_family(
+                local_address,
+                SocketAddr::new(Ipv4Addr::BROADCAST.into(), CONTROL_PORT),
+            ),
+        );
+        let res = if local_address.is_ipv6() {
+            let multicast_res = self
+                .socket
+                .send_to(&self.packet, (DISCOVERY_MULTICAST_IP, CONTROL_PORT));
+
+            // On IPv4-only or IPv6-only networks one of the two fails
+            broadcast_res.or(multicast_res)
+        } else {
+            broadcast_res
+        };
+        res.map_err(err!())?;
+
         Ok(())
     }
 }
+
+impl Drop for AnnouncerSocket {
+    fn drop(&mut self) {
+        if let Some((daemon, fullname)) = &self.mdns {
+            daemon.unregister(fullname).ok();
+            daemon.shutdown().ok();
+        }
+    }
+}
+
+// Returns the daemon that answers the queries and the full name of the service
+fn register_mdns_service(hostname: &str, features: &[&str]) -> StrResult<(ServiceDaemon, String)> {
+    let daemon = ServiceDaemon::new().map_err(err!())?;
+
+    let protocol_id = alvr_common::protocol_id().to_string();
+    let features = features.join(",");
+    let properties = [
+        (MDNS_HOSTNAME_KEY, hostname),
+        (MDNS_PROTOCOL_KEY, protocol_id.as_str()),
+        (MDNS_FEATURES_KEY, features.as_str()),
+    ];
+
+    // Dots are not allowed in the instance name. The addresses are filled in by the daemon, for all
+    // network interfaces.
+    let instance_name = hostname.replace('.', "-");
+    let service = ServiceInfo::new(
+        MDNS_SERVICE_TYPE,
+        &instance_name,
+        &format!("{instance_name}.local."),
+        (),
+        CONTROL_PORT,
+        &properties[..],
+    )
+    .map_err(err!())?
+    .enable_addr_auto();
+    let fullname = service.get_fullname().to_owned();
+
+    daemon.register(service).map_err(err!())?;
+
+    Ok((daemon, fullname))
+}
@@ -1,4 +1,5 @@
 use alvr_common::prelude::*;
+use alvr_sockets::TlsIdentity;
 use app_dirs2::{AppDataType, AppInfo};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
@@ -25,41 +26,71 @@ fn config_path() -> PathBuf {
 pub struct Config {
     pub protocol_id: u64,
     pub hostname: String,
+    // Missing from the configs stored by older versions
+    #[serde(default)]
+    pub tls_identity: TlsIdentity,
+    // DER encoded certificates of the servers the client is paired with
+    #[serde(default)]
+    pub server_certificates: Vec<Vec<u8>>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         let mut rng = rand::thread_rng();
 
+        let hostname = format!(
+            "{}{}{}{}.client.alvr",
+            rng.gen_range(0..10),
+            rng.gen_range(0..10),
+            rng.gen_range(0..10),
+            rng.gen_range(0..10),
+        );
+
         Self {
             protocol_id: alvr_common::protocol_id(),
-            hostname: format!(
-                "{}{}{}{}.client.alvr",
-                rng.gen_range(0..10),
-                rng.gen_range(0..10),
-                rng.gen_range(0..10),
-                rng.gen_range(0..10),
-            ),
+            hostname,
+            // Generated by load()
+            tls_identity: TlsIdentity::default(),
+            server_certificates: vec![],
         }
     }
 }
 
 impl Config {
     pub fn load() -> Self {
-        if let Ok(config_string) = fs::read_to_string(config_path()) {
+        let mut config = if let Ok(config_string) = fs::read_to_string(config_path()) {
             // Failure happens if the Config signature changed between versions.
             // todo: recover data from mismatched Config signature. low priority
-            if let Ok(config) = serde_json::from_str(&config_string) {
-                return config;
+            if let Ok(config) = serde_json::from_str::<Config>(&config_string) {
+                config
             } else {
                 info!("Error parsing ALVR config. Using default");
+
+                let config = Config::default();
+                config.store();
+
+                config
             }
         } else {
             info!("Error reading ALVR config. Using default");
-        }
 
-        let config = Config::default();
-        config.store();
+            let config = Config::default();
+            config.store();
+
+            config
+        };
+
+        // Generated on first use. If this fails, the connection fails without the identity and
+        // the generation is tried again on the next load.
+        if config.tls_identity.certificate.is_empty() {
+            match TlsIdentity::generate(&config.hostname) {
+                Ok(identity) => {
+                    config.tls_identity = identity;
+                    config.store();
+                }
+                Err(e) => error!("Error generating the TLS identity: {e}"),
+            }
+        }
 
         config
     }
@@ -6,6 +6,19 @@ pub const LEFT_HAND_PATH: &str = "/user/hand/left";
 pub const RIGHT_HAND_PATH: &str = "/user/hand/right";
 pub const LEFT_CONTROLLER_HAPTIC_PATH: &str = "/user/hand/left/output/haptic";
 pub const RIGHT_CONTROLLER_HAPTIC_PATH: &str = "/user/hand/right/output/haptic";
+pub const WAIST_PATH: &str = "/user/waist";
+pub const LEFT_FOOT_PATH: &str = "/user/foot/left";
+pub const RIGHT_FOOT_PATH: &str = "/user/foot/right";
+
+// The hands of companion clients are exposed as generic trackers, with paths like
+// "/companion/phone/user/hand/left" for the left hand of the client "phone"
+pub fn companion_device_path(hostname: &str, device_path: &str) -> String {
+    format!("/companion/{hostname}{device_path}")
+}
+
+pub fn is_companion_device_path(path: &str) -> bool {
+    path.starts_with("/companion/")
+}
 
 pub static HEAD_ID: Lazy<u64> = Lazy::new(|| hash_string(HEAD_PATH));
 pub static LEFT_HAND_ID: Lazy<u64> = Lazy::new(|| hash_string(LEFT_HAND_PATH));
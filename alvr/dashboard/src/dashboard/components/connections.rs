@@ -1,9 +1,10 @@
 use crate::{dashboard::ConnectionsResponse, dashboard::DashboardResponse, theme};
+use alvr_events::PairingCodeEvent;
 use alvr_session::{ClientConnectionDesc, SessionDesc};
 use egui::{Align, Frame, Layout, Resize, RichText, Ui, Window};
 use std::{
-    collections::HashSet,
-    net::{IpAddr, Ipv4Addr},
+    collections::{HashMap, HashSet},
+    net::IpAddr,
 };
 
 struct EditPopupState {
@@ -14,12 +15,23 @@ struct EditPopupState {
 
 pub struct ConnectionsTab {
     edit_popup_state: Option<EditPopupState>,
+    // Codes of the pairings in progress, by hostname
+    pairing_codes: HashMap<String, String>,
 }
 
 impl ConnectionsTab {
     pub fn new() -> Self {
         Self {
             edit_popup_state: None,
+            pairing_codes: HashMap::new(),
+        }
+    }
+
+    pub fn update_pairing_code(&mut self, event: PairingCodeEvent) {
+        if let Some(code) = event.code {
+            self.pairing_codes.insert(event.hostname, code);
+        } else {
+            self.pairing_codes.remove(&event.hostname);
         }
     }
 
@@ -81,19 +93,19 @@ impl ConnectionsTab {
             .fill(theme::SECTION_BG)
             .show(ui, |ui| {
                 ui.label(RichText::new("New clients").size(18.0));
-                for (name, client_desc) in new {
+                for (name, _) in new {
                     ui.horizontal(|ui| {
                         ui.label(name);
-                        if ui.button("Trust").clicked() {
-                            let mut client_desc = client_desc.clone();
-                            client_desc.trusted = true;
+                        // The client is connected to only after the user asks to pair it. The
+                        // pairing is then confirmed on the headset.
+                        if let Some(code) = self.pairing_codes.get(name) {
+                            ui.label(format!("Pairing code: {code}"));
+                            ui.label("Confirm it on the headset");
+                        } else if ui.button("Pair").clicked() {
                             response = Some(DashboardResponse::Connections(
-                                ConnectionsResponse::AddOrUpdate {
-                                    name: name.clone(),
-                                    client_desc: client_desc.clone(),
-                                },
+                                ConnectionsResponse::Trust(name.clone()),
                             ));
-                        };
+                        }
                     });
                 }
             });
@@ -133,22 +145,12 @@ impl ConnectionsTab {
                             if ui[0].button("Ok").clicked() {
                                 let mut ip_addresses = HashSet::new();
 
+                                // Both IPv4 and IPv6 addresses are accepted. Invalid ones are
+                                // discarded.
                                 for address in &state.ip_addresses {
-                                    let parts: Vec<&str> = address.splitn(4, ".").collect();
-                                    let mut raw_addr: [u8; 4] = [0, 0, 0, 0];
-
-                                    for i in 0..4 {
-                                        match parts.get(i) {
-                                            Some(num) => {
-                                                raw_addr[i] = num.parse::<u8>().unwrap_or(0);
-                                            }
-                                            None => (),
-                                        }
+                                    if let Ok(addr) = address.trim().parse::<IpAddr>() {
+                                        ip_addresses.insert(addr);
                                     }
-
-                                    let addr = IpAddr::V4(Ipv4Addr::from(raw_addr));
-
-                                    ip_addresses.insert(addr);
                                 }
 
                                 response = Some(DashboardResponse::Connections(
@@ -159,6 +161,11 @@ impl ConnectionsTab {
                                             current_ip: None,
                                             manual_ips: ip_addresses,
                                             trusted: true,
+                                            // Keep the pairing of an edited client
+                                            certificate: session
+                                                .client_connections
+                                                .get(&state.hostname)
+                                                .and_then(|client| client.certificate.clone()),
                                         },
                                     },
                                 ));
@@ -38,6 +38,8 @@ pub enum ConnectionsResponse {
         client_desc: ClientConnectionDesc,
     },
     RemoveEntry(String),
+    // Starts the pairing of a new client
+    Trust(String),
 }
 
 #[derive(Debug)]
@@ -143,6 +145,9 @@ impl Dashboard {
             EventType::Session(session) => {
                 self.session = session.to_owned();
             }
+            EventType::PairingCode(pairing_code) => self
+                .connections_tab
+                .update_pairing_code(pairing_code.clone()),
             _ => {
                 self.logs_tab.update_logs(event.clone());
                 // Create a notification based on the notification level in the settings
@@ -332,6 +337,7 @@ impl Dashboard {
                         self.session.client_connections.remove(name);
                         response = Some(DashboardResponse::SessionUpdated(self.session.to_owned()));
                     }
+                    ConnectionsResponse::Trust(_) => (),
                 },
 
                 DashboardResponse::SessionUpdated(session) => self.session = session.to_owned(),
@@ -1,4 +1,7 @@
-use alvr_dashboard::dashboard::{DashboardResponse, DriverResponse, FirewallRulesResponse};
+use alvr_dashboard::dashboard::{
+    ConnectionsResponse, DashboardResponse, DriverResponse, FirewallRulesResponse,
+};
+use std::net::IpAddr;
 
 use crate::{GuiMsg, WorkerMsg};
 
@@ -44,6 +47,14 @@ pub async fn handle_msg(
                     .await?;
                 false
             }
+            DashboardResponse::Connections(ConnectionsResponse::Trust(hostname)) => {
+                client
+                    .get(format!("{}/api/client/trust", BASE_URL))
+                    .body(serde_json::to_string(&(hostname, None::<IpAddr>)).unwrap())
+                    .send()
+                    .await?;
+                false
+            }
             DashboardResponse::RestartSteamVR => {
                 client
                     .get(format!("{}/restart-steamvr", BASE_URL))
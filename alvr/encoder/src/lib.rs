@@ -0,0 +1,371 @@
+// Video encoders implemented in Rust. They receive the frames composed by the C++ renderer of the
+// server and pass the encoded frames to an EncoderSink, which sends them to the clients through the
+// same path as the C++ encoders.
+
+mod software;
+
+pub use software::SoftwareEncoder;
+
+use alvr_common::prelude::*;
+use alvr_session::CodecType;
+use alvr_sockets::{
+    VideoFrameHeaderPacket, OBU_FRAME, OBU_FRAME_HEADER, OBU_PADDING, OBU_SEQUENCE_HEADER,
+    OBU_TEMPORAL_DELIMITER,
+};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// 8 bit YUV 4:2:0 planar frame
+pub struct RawFrame<'a> {
+    pub width: u32,
+    pub height: u32,
+    pub y: &'a [u8],
+    pub u: &'a [u8],
+    pub v: &'a [u8],
+    pub y_stride: usize,
+    pub uv_stride: usize,
+}
+
+pub struct EncodedFrame {
+    // SPS and PPS (and VPS for HEVC) in Annex B format, or the sequence header OBU for AV1. Set on
+    // IDR frames.
+    pub config_buffer: Option<Vec<u8>>,
+    // Slice NAL units in Annex B format, or the frame OBUs for AV1
+    pub nals: Vec<u8>,
+}
+
+pub trait VideoEncoder: Send {
+    // Returns None if the encoder skipped the frame
+    fn encode(&mut self, frame: &RawFrame, force_idr: bool) -> StrResult<Option<EncodedFrame>>;
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> StrResult;
+}
+
+// Receives the output of an EncoderPipeline
+pub trait EncoderSink: Send {
+    fn report_frame_encoded(&self, target_timestamp: Duration);
+    fn send_decoder_config(&self, config_buffer: Vec<u8>);
+    fn send_video_packet(&self, header: VideoFrameHeaderPacket, payload: Vec<u8>);
+}
+
+// Returns each NAL unit with whether it is part of the decoder configuration and whether it should
+// be dropped, according to its type
+fn classify_nals(codec: CodecType, data: &[u8]) -> Vec<(&[u8], bool, bool)> {
+    alvr_sockets::annex_b_nals(data)
+        .into_iter()
+        .filter_map(|nal| {
+            let nal_type = alvr_sockets::nal_type(codec, nal)?;
+
+            Some((
+                nal,
+                alvr_sockets::is_parameter_set(codec, nal_type),
+                alvr_sockets::is_sei_or_delimiter(codec, nal_type),
+            ))
+        })
+        .collect()
+}
+
+fn classify_obus(data: &[u8]) -> Vec<(&[u8], bool, bool)> {
+    match alvr_sockets::parse_obus(data) {
+        Ok(obus) => obus
+            .into_iter()
+            .map(|obu| {
+                (
+                    obu.data,
+                    obu.obu_type == OBU_SEQUENCE_HEADER,
+                    matches!(obu.obu_type, OBU_TEMPORAL_DELIMITER | OBU_PADDING),
+                )
+            })
+            .collect(),
+        Err(e) => {
+            // Let the decoder deal with it
+            warn!("Invalid AV1 temporal unit: {e}");
+
+            vec![(data, false, false)]
+        }
+    }
+}
+
+// Separates the decoder configuration from the slices, and drops the SEI and access unit delimiter
+// NAL units like the C++ encoders do. For AV1, temporal delimiters and padding are dropped.
+pub fn split_access_unit(codec: CodecType, data: &[u8]) -> EncodedFrame {
+    let units = match codec {
+        CodecType::H264 | CodecType::HEVC => classify_nals(codec, data),
+        CodecType::AV1 => classify_obus(data),
+    };
+
+    let mut config_buffer = vec![];
+    let mut nals = vec![];
+    for (unit, is_config, is_dropped) in units {
+        if is_config {
+            config_buffer.extend_from_slice(unit);
+        } else if !is_dropped {
+            nals.extend_from_slice(unit);
+        }
+    }
+
+    EncodedFrame {
+        config_buffer: (!config_buffer.is_empty()).then_some(config_buffer),
+        nals,
+    }
+}
+
+// Whether the frame can be decoded without the previous ones. The decoder configuration is sent
+// separately, so it is not required here.
+pub fn is_keyframe(codec: CodecType, data: &[u8]) -> bool {
+    match codec {
+        CodecType::H264 | CodecType::HEVC => alvr_sockets::annex_b_nals(data)
+            .into_iter()
+            .filter_map(|nal| alvr_sockets::nal_type(codec, nal))
+            .any(|nal_type| alvr_sockets::is_idr(codec, nal_type)),
+        // The first bits of the frame header are show_existing_frame and frame_type, which is 0 for
+        // key frames
+        CodecType::AV1 => alvr_sockets::parse_obus(data)
+            .map(|obus| {
+                obus.iter().any(|obu| {
+                    matches!(obu.obu_type, OBU_FRAME_HEADER | OBU_FRAME)
+                        && matches!(obu.payload.first(), Some(byte) if byte >> 5 == 0)
+                })
+            })
+            .unwrap_or(false),
+    }
+}
+
+pub struct EncoderPipeline {
+    encoder: Box<dyn VideoEncoder>,
+    sink: Box<dyn EncoderSink>,
+    width: u32,
+    height: u32,
+    packet_counter: u32,
+    video_frame_index: u64,
+}
+
+impl EncoderPipeline {
+    pub fn new(
+        encoder: Box<dyn VideoEncoder>,
+        sink: Box<dyn EncoderSink>,
+        width: u32,
+        height: u32,
+    ) -> Self {
+        Self {
+            encoder,
+            sink,
+            width,
+            height,
+            packet_counter: 0,
+            video_frame_index: 0,
+        }
+    }
+
+    // The encoder must be recreated for frames of a different size
+    pub fn frame_size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn set_bitrate(&mut self, bitrate_bps: u64) -> StrResult {
+        self.encoder.set_bitrate(bitrate_bps)
+    }
+
+    pub fn push_frame(
+        &mut self,
+        frame: &RawFrame,
+        target_timestamp: Duration,
+        force_idr: bool,
+    ) -> StrResult {
+        let encoded = match self.encoder.encode(frame, force_idr)? {
+            Some(encoded) => encoded,
+            None => return Ok(()),
+        };
+
+        self.sink.report_frame_encoded(target_timestamp);
+
+        if let Some(config_buffer) = encoded.config_buffer {
+            self.sink.send_decoder_config(config_buffer);
+        }
+
+        if !encoded.nals.is_empty() {
+            let header = VideoFrameHeaderPacket {
+                packet_counter: self.packet_counter,
+                tracking_frame_index: target_timestamp.as_nanos() as _,
+                video_frame_index: self.video_frame_index,
+                sent_time: SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_err(err!())?
+                    .as_micros() as _,
+                frame_byte_size: encoded.nals.len() as _,
+                fec_index: 0,
+                fec_percentage: 0,
+            };
+            self.sink.send_video_packet(header, encoded.nals);
+
+            self.packet_counter += 1;
+        }
+
+        self.video_frame_index += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::parking_lot::Mutex;
+    use std::sync::Arc;
+
+    const WIDTH: u32 = 128;
+    const HEIGHT: u32 = 96;
+
+    #[derive(Default)]
+    struct SinkOutput {
+        encoded_timestamps: Vec<Duration>,
+        decoder_configs: Vec<Vec<u8>>,
+        video_packets: Vec<(VideoFrameHeaderPacket, Vec<u8>)>,
+    }
+
+    struct MockSink(Arc<Mutex<SinkOutput>>);
+
+    impl EncoderSink for MockSink {
+        fn report_frame_encoded(&self, target_timestamp: Duration) {
+            self.0.lock().encoded_timestamps.push(target_timestamp);
+        }
+
+        fn send_decoder_config(&self, config_buffer: Vec<u8>) {
+            self.0.lock().decoder_configs.push(config_buffer);
+        }
+
+        fn send_video_packet(&self, header: VideoFrameHeaderPacket, payload: Vec<u8>) {
+            self.0.lock().video_packets.push((header, payload));
+        }
+    }
+
+    fn test_frame(index: u8) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        let y = (0..WIDTH * HEIGHT)
+            .map(|i| ((i % WIDTH) as u8).wrapping_add(index.wrapping_mul(8)))
+            .collect();
+        let chroma = vec![128; (WIDTH * HEIGHT / 4) as usize];
+
+        (y, chroma.clone(), chroma)
+    }
+
+    #[test]
+    fn split_h264_access_unit() {
+        let data = [
+            0, 0, 0, 1, 0x67, 1, 2, // SPS
+            0, 0, 0, 1, 0x68, 3, // PPS
+            0, 0, 1, 0x06, 4, // SEI
+            0, 0, 1, 0x65, 5, 6, // IDR slice
+        ];
+
+        let frame = split_access_unit(CodecType::H264, &data);
+
+        assert_eq!(
+            frame.config_buffer.unwrap(),
+            [0, 0, 0, 1, 0x67, 1, 2, 0, 0, 0, 1, 0x68, 3]
+        );
+        assert_eq!(frame.nals, [0, 0, 1, 0x65, 5, 6]);
+    }
+
+    #[test]
+    fn split_hevc_access_unit() {
+        let data = [
+            0, 0, 0, 1, 0x40, 1, 1, // VPS
+            0, 0, 0, 1, 0x42, 1, 2, // SPS
+            0, 0, 0, 1, 0x44, 1, 3, // PPS
+            0, 0, 0, 1, 0x02, 1, 4, // TRAIL_R slice
+        ];
+
+        let frame = split_access_unit(CodecType::HEVC, &data);
+
+        assert_eq!(frame.config_buffer.unwrap().len(), 21);
+        assert_eq!(frame.nals, [0, 0, 0, 1, 0x02, 1, 4]);
+    }
+
+    #[test]
+    fn split_av1_temporal_unit() {
+        let data = [
+            0x12, 0, // temporal delimiter
+            0x0A, 2, 1, 2, // sequence header
+            0x7A, 1, 0, // padding
+            0x32, 3, 3, 4, 5, // frame
+        ];
+
+        let frame = split_access_unit(CodecType::AV1, &data);
+
+        assert_eq!(frame.config_buffer.unwrap(), [0x0A, 2, 1, 2]);
+        assert_eq!(frame.nals, [0x32, 3, 3, 4, 5]);
+    }
+
+    #[test]
+    fn detect_keyframes() {
+        assert!(is_keyframe(CodecType::H264, &[0, 0, 0, 1, 0x65, 1]));
+        assert!(!is_keyframe(CodecType::H264, &[0, 0, 0, 1, 0x41, 1]));
+        // IDR_W_RADL
+        assert!(is_keyframe(CodecType::HEVC, &[0, 0, 0, 1, 0x26, 1, 1]));
+        assert!(!is_keyframe(CodecType::HEVC, &[0, 0, 0, 1, 0x02, 1, 1]));
+        // Frame OBUs with frame_type KEY_FRAME and INTER_FRAME
+        assert!(is_keyframe(CodecType::AV1, &[0x32, 2, 0x10, 0]));
+        assert!(!is_keyframe(CodecType::AV1, &[0x32, 2, 0x30, 0]));
+    }
+
+    // Encodes a few frames with the software encoder and checks what is sent to the clients
+    #[test]
+    fn software_encoder_to_stream() {
+        let output = Arc::new(Mutex::new(SinkOutput::default()));
+
+        let encoder = SoftwareEncoder::new(WIDTH, HEIGHT, 5_000_000, 72.0, 1).unwrap();
+        let mut pipeline = EncoderPipeline::new(
+            Box::new(encoder),
+            Box::new(MockSink(Arc::clone(&output))),
+            WIDTH,
+            HEIGHT,
+        );
+
+        for index in 0..3 {
+            let (y, u, v) = test_frame(index);
+            let frame = RawFrame {
+                width: WIDTH,
+                height: HEIGHT,
+                y: &y,
+                u: &u,
+                v: &v,
+                y_stride: WIDTH as _,
+                uv_stride: (WIDTH / 2) as _,
+            };
+
+            if index == 2 {
+                pipeline.set_bitrate(2_000_000).unwrap();
+            }
+
+            pipeline
+                .push_frame(&frame, Duration::from_millis(index as u64 * 14), index == 0)
+                .unwrap();
+        }
+
+        let output = output.lock();
+
+        assert_eq!(
+            output.encoded_timestamps,
+            [0, 14, 28].map(Duration::from_millis)
+        );
+
+        // Starts with the SPS
+        assert_eq!(output.decoder_configs[0][..5], [0, 0, 0, 1, 0x67]);
+
+        let (first_header, first_payload) = &output.video_packets[0];
+        assert_eq!(first_header.video_frame_index, 0);
+        assert_eq!(first_header.frame_byte_size as usize, first_payload.len());
+        let first_frame = split_access_unit(CodecType::H264, first_payload);
+        assert!(first_frame.config_buffer.is_none());
+        assert!(is_keyframe(CodecType::H264, &first_frame.nals));
+
+        for (index, (header, _)) in output.video_packets.iter().enumerate() {
+            assert_eq!(header.packet_counter, index as u32);
+            assert_eq!(header.video_frame_index, index as u64);
+            assert_eq!(
+                header.tracking_frame_index,
+                Duration::from_millis(index as u64 * 14).as_nanos() as u64
+            );
+            assert!(header.sent_time > 0);
+        }
+        assert_eq!(output.video_packets.len(), 3);
+    }
+}
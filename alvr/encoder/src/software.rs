@@ -0,0 +1,111 @@
+use super::{split_access_unit, EncodedFrame, RawFrame, VideoEncoder};
+use alvr_common::prelude::*;
+use alvr_session::CodecType;
+use openh264::{
+    encoder::{Encoder, EncoderConfig},
+    formats::YUVSource,
+};
+use openh264_sys2::{SBitrateInfo, ENCODER_OPTION_BITRATE, SPATIAL_LAYER_ALL};
+use std::ptr;
+
+impl YUVSource for RawFrame<'_> {
+    fn width(&self) -> i32 {
+        self.width as _
+    }
+
+    fn height(&self) -> i32 {
+        self.height as _
+    }
+
+    fn y(&self) -> &[u8] {
+        self.y
+    }
+
+    fn u(&self) -> &[u8] {
+        self.u
+    }
+
+    fn v(&self) -> &[u8] {
+        self.v
+    }
+
+    fn y_stride(&self) -> i32 {
+        self.y_stride as _
+    }
+
+    fn u_stride(&self) -> i32 {
+        self.uv_stride as _
+    }
+
+    fn v_stride(&self) -> i32 {
+        self.uv_stride as _
+    }
+}
+
+// H264 encoder running on the CPU, based on OpenH264. It does not need a GPU, so it is also used to
+// test the streaming path.
+pub struct SoftwareEncoder {
+    encoder: Encoder,
+}
+
+// SAFETY: Encoder is not Send only because it holds the raw ISVCEncoder pointer. OpenH264 encoders
+// have no thread affinity: they can be created on one thread and used on another, as long as they
+// are never used by two threads at once. SoftwareEncoder is not Sync and every call takes
+// `&mut self`, so the borrow checker already rules out concurrent use.
+unsafe impl Send for SoftwareEncoder {}
+
+impl SoftwareEncoder {
+    // A thread count of 0 lets OpenH264 choose
+    pub fn new(
+        width: u32,
+        height: u32,
+        bitrate_bps: u64,
+        frame_rate: f32,
+        thread_count: u32,
+    ) -> StrResult<Self> {
+        let config = EncoderConfig::new(width, height)
+            .set_bitrate_bps(bitrate_bps.min(u32::MAX as u64) as _)
+            .max_frame_rate(frame_rate)
+            .enable_skip_frame(false)
+            .set_multiple_thread_count(thread_count as _);
+
+        Ok(Self {
+            encoder: Encoder::with_config(config).map_err(err!())?,
+        })
+    }
+}
+
+impl VideoEncoder for SoftwareEncoder {
+    fn encode(&mut self, frame: &RawFrame, force_idr: bool) -> StrResult<Option<EncodedFrame>> {
+        if force_idr {
+            // The IDR frame also contains the SPS and PPS
+            unsafe { self.encoder.raw_api().force_intra_frame(true) };
+        }
+
+        let data = self.encoder.encode(frame).map_err(err!())?.to_vec();
+        if data.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(split_access_unit(CodecType::H264, &data)))
+    }
+
+    fn set_bitrate(&mut self, bitrate_bps: u64) -> StrResult {
+        let mut bitrate_info = SBitrateInfo {
+            iLayer: SPATIAL_LAYER_ALL,
+            iBitrate: bitrate_bps.min(i32::MAX as u64) as _,
+        };
+
+        let res = unsafe {
+            self.encoder.raw_api().set_option(
+                ENCODER_OPTION_BITRATE,
+                ptr::addr_of_mut!(bitrate_info).cast(),
+            )
+        };
+        if res != 0 {
+            return fmt_e!("Failed to set the bitrate: error {res}");
+        }
+
+        Ok(())
+    }
+}
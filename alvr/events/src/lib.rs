@@ -54,7 +54,7 @@ pub struct LogEvent {
     pub content: String,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
 pub enum ButtonValue {
     Binary(bool),
     Scalar(f32),
@@ -66,6 +66,13 @@ pub struct ButtonEvent {
     pub value: ButtonValue,
 }
 
+// The pairing code is not stored in the session. It is None once the pairing ends.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct PairingCodeEvent {
+    pub hostname: String,
+    pub code: Option<String>,
+}
+
 // Event is serialized as #{ "id": "..." [, "data": ...] }#
 // Pound signs are used to identify start and finish of json
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -83,6 +90,7 @@ pub enum EventType {
     Statistics(Statistics),
     GraphStatistics(GraphStatistics),
     Button(ButtonEvent),
+    PairingCode(PairingCodeEvent),
     ServerQuitting,
     Log(LogEvent),
 }
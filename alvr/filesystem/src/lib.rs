@@ -185,6 +185,11 @@ impl Layout {
         self.config_dir.join("session.json")
     }
 
+    // Identity of the server, whose certificate is pinned by the paired clients
+    pub fn tls_identity(&self) -> PathBuf {
+        self.config_dir.join("tls_identity.json")
+    }
+
     pub fn session_log(&self) -> PathBuf {
         if cfg!(target_os = "linux") {
             self.log_dir.join("alvr_session_log.txt")
@@ -197,6 +202,10 @@ impl Layout {
         self.log_dir.join("crash_log.txt")
     }
 
+    pub fn tracking_recording(&self) -> PathBuf {
+        self.log_dir.join("tracking_recording.bin")
+    }
+
     pub fn openvr_driver_lib_dir(&self) -> PathBuf {
         let platform = if cfg!(windows) {
             "win64"
@@ -8,6 +8,7 @@
 enum ALVR_CODEC {
 	ALVR_CODEC_H264 = 0,
 	ALVR_CODEC_H265 = 1,
+	ALVR_CODEC_AV1 = 2,
 };
 
 enum ALVR_RATE_CONTROL_METHOD {
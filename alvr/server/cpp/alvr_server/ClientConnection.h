@@ -17,7 +17,6 @@ public:
 
 	ClientConnection();
 
-	void FECSend(uint8_t *buf, int len, uint64_t targetTimestampNs, uint64_t videoFrameIndex);
 	void SendVideo(uint8_t *buf, int len, uint64_t targetTimestampNs);
  	void ReportNetworkLatency(uint64_t latencyUs);
 	void OnFecFailure();
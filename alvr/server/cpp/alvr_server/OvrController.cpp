@@ -9,33 +9,12 @@
 #include <string_view>
 
 OvrController::OvrController(uint64_t deviceID) : TrackedDevice(deviceID) {
-    double rightHandSignFlip = deviceID == LEFT_HAND_ID ? 1. : -1.;
-
     m_pose = vr::DriverPose_t{};
     m_pose.poseIsValid = true;
     m_pose.result = vr::TrackingResult_Running_OK;
     m_pose.deviceIsConnected = true;
 
-    // controller is rotated and translated, prepare pose
-    double rotation[3] = {
-        Settings::Instance().m_leftControllerRotationOffset[1] * DEG_TO_RAD * rightHandSignFlip,
-        Settings::Instance().m_leftControllerRotationOffset[2] * DEG_TO_RAD * rightHandSignFlip,
-        Settings::Instance().m_leftControllerRotationOffset[0] * DEG_TO_RAD,
-    };
-    m_pose.qDriverFromHeadRotation = EulerAngleToQuaternion(rotation);
-
-    vr::HmdVector3d_t offset;
-    offset.v[0] = Settings::Instance().m_leftControllerPositionOffset[0] * rightHandSignFlip;
-    offset.v[1] = Settings::Instance().m_leftControllerPositionOffset[1];
-    offset.v[2] = Settings::Instance().m_leftControllerPositionOffset[2];
-
-    vr::HmdVector3d_t offetRes =
-        vrmath::quaternionRotateVector(m_pose.qDriverFromHeadRotation, offset, false);
-
-    m_pose.vecDriverFromHeadTranslation[0] = offetRes.v[0];
-    m_pose.vecDriverFromHeadTranslation[1] = offetRes.v[1];
-    m_pose.vecDriverFromHeadTranslation[2] = offetRes.v[2];
-
+    m_pose.qDriverFromHeadRotation = HmdQuaternion_Init(1, 0, 0, 0);
     m_pose.qWorldFromDriverRotation = HmdQuaternion_Init(1, 0, 0, 0);
 
     m_pose.qRotation = HmdQuaternion_Init(1, 0, 0, 0);
@@ -660,13 +639,9 @@ void OvrController::SetButton(uint64_t id, AlvrButtonValue value) {
     }
 }
 
-inline float Magnitude(const float v[3]) { return v[0] * v[0] + v[1] * v[1] + v[2] * v[2]; }
-// Magnitude already squared
-inline float Shape(float x, float a) { return (x > a * a ? 1 - (a * a / x) : 0.); }
-
-bool OvrController::onPoseUpdate(float predictionS,
-                                 AlvrDeviceMotion motion,
-                                 const OculusHand &hand) {
+// The offsets, the velocity cutoffs and the prediction are applied to the motion by the
+// TrackingManager in Rust, which also converts the angular velocity to the device space
+bool OvrController::onPoseUpdate(AlvrDeviceMotion motion, const OculusHand &hand) {
     if (this->object_id == vr::k_unTrackedDeviceIndexInvalid) {
         return false;
     }
@@ -679,90 +654,24 @@ bool OvrController::onPoseUpdate(float predictionS,
     pose.result = vr::TrackingResult_Running_OK;
     pose.deviceIsConnected = true;
 
-    double rightHandSignFlip = this->device_id == LEFT_HAND_ID ? 1. : -1.;
-
-    // controller is rotated and translated, prepare pose
-    double rotation[3] = {
-        Settings::Instance().m_leftControllerRotationOffset[1] * DEG_TO_RAD * rightHandSignFlip,
-        Settings::Instance().m_leftControllerRotationOffset[2] * DEG_TO_RAD * rightHandSignFlip,
-        Settings::Instance().m_leftControllerRotationOffset[0] * DEG_TO_RAD,
-    };
-    pose.qDriverFromHeadRotation = EulerAngleToQuaternion(rotation);
-
-    vr::HmdVector3d_t offset;
-    offset.v[0] = Settings::Instance().m_leftControllerPositionOffset[0] * rightHandSignFlip;
-    offset.v[1] = Settings::Instance().m_leftControllerPositionOffset[1];
-    offset.v[2] = Settings::Instance().m_leftControllerPositionOffset[2];
-
-    vr::HmdVector3d_t offsetRes =
-        vrmath::quaternionRotateVector(pose.qDriverFromHeadRotation, offset, false);
-
-    pose.vecDriverFromHeadTranslation[0] = offsetRes.v[0];
-    pose.vecDriverFromHeadTranslation[1] = offsetRes.v[1];
-    pose.vecDriverFromHeadTranslation[2] = offsetRes.v[2];
-
+    pose.qDriverFromHeadRotation = HmdQuaternion_Init(1, 0, 0, 0);
     pose.qWorldFromDriverRotation = HmdQuaternion_Init(1, 0, 0, 0);
 
-    if (hand.enabled) {
-        vr::HmdQuaternion_t rootBoneRot = HmdQuaternion_Init(
-            motion.orientation.w, motion.orientation.x, motion.orientation.y, motion.orientation.z);
-        vr::HmdQuaternion_t boneFixer = this->device_id == LEFT_HAND_ID
-                                            ? HmdQuaternion_Init(-0.5, 0.5, 0.5, -0.5)
-                                            : HmdQuaternion_Init(0.5, 0.5, 0.5, 0.5);
-        pose.qRotation = QuatMultiply(&rootBoneRot, &boneFixer);
-        pose.vecPosition[0] = motion.position[0];
-        pose.vecPosition[1] = motion.position[1];
-        pose.vecPosition[2] = motion.position[2];
+    pose.qRotation = HmdQuaternion_Init(
+        motion.orientation.w, motion.orientation.x, motion.orientation.y, motion.orientation.z);
 
-        if (this->device_id == LEFT_HAND_ID) {
-            double bonePosFixer[3] = {0.0, 0.05, -0.05};
-            vr::HmdVector3d_t posFix = vrmath::quaternionRotateVector(pose.qRotation, bonePosFixer);
-            pose.vecPosition[0] = motion.position[0] + posFix.v[0];
-            pose.vecPosition[1] = motion.position[1] + posFix.v[1];
-            pose.vecPosition[2] = motion.position[2] + posFix.v[2];
-        } else {
-            double bonePosFixer[3] = {0.0, 0.05, -0.05};
-            vr::HmdVector3d_t posFix = vrmath::quaternionRotateVector(pose.qRotation, bonePosFixer);
-            pose.vecPosition[0] = motion.position[0] + posFix.v[0];
-            pose.vecPosition[1] = motion.position[1] + posFix.v[1];
-            pose.vecPosition[2] = motion.position[2] + posFix.v[2];
-        }
-    } else {
-        pose.qRotation = HmdQuaternion_Init(motion.orientation.w,
-                                            motion.orientation.x,
-                                            motion.orientation.y,
-                                            motion.orientation.z); // controllerRotation;
-
-        pose.vecPosition[0] = motion.position[0];
-        pose.vecPosition[1] = motion.position[1];
-        pose.vecPosition[2] = motion.position[2];
-    }
+    pose.vecPosition[0] = motion.position[0];
+    pose.vecPosition[1] = motion.position[1];
+    pose.vecPosition[2] = motion.position[2];
 
-    // use cutoffs for velocity to stop jitter when there is not a lot of movement
-    float LinearVelocityMultiplier =
-        Shape(Magnitude(motion.linearVelocity), Settings::Instance().m_linearVelocityCutoff);
-    float AngularVelocityMultiplier =
-        Shape(Magnitude(motion.angularVelocity),
-              Settings::Instance().m_angularVelocityCutoff * DEG_TO_RAD);
-
-    pose.vecVelocity[0] = motion.linearVelocity[0] * LinearVelocityMultiplier;
-    pose.vecVelocity[1] = motion.linearVelocity[1] * LinearVelocityMultiplier;
-    pose.vecVelocity[2] = motion.linearVelocity[2] * LinearVelocityMultiplier;
-    pose.vecAngularVelocity[0] = motion.angularVelocity[0] * AngularVelocityMultiplier;
-    pose.vecAngularVelocity[1] = motion.angularVelocity[1] * AngularVelocityMultiplier;
-    pose.vecAngularVelocity[2] = motion.angularVelocity[2] * AngularVelocityMultiplier;
-
-    // correct direction of velocities
-    vr::HmdVector3d_t angVel;
-    angVel.v[0] = pose.vecAngularVelocity[0];
-    angVel.v[1] = pose.vecAngularVelocity[1];
-    angVel.v[2] = pose.vecAngularVelocity[2];
-    vr::HmdVector3d_t angVelRes = vrmath::quaternionRotateVector(pose.qRotation, angVel, true);
-    pose.vecAngularVelocity[0] = angVelRes.v[0];
-    pose.vecAngularVelocity[1] = angVelRes.v[1];
-    pose.vecAngularVelocity[2] = angVelRes.v[2];
-
-    pose.poseTimeOffset = predictionS;
+    pose.vecVelocity[0] = motion.linearVelocity[0];
+    pose.vecVelocity[1] = motion.linearVelocity[1];
+    pose.vecVelocity[2] = motion.linearVelocity[2];
+
+    // Already in the local space of the device, without the offsets
+    pose.vecAngularVelocity[0] = motion.angularVelocity[0];
+    pose.vecAngularVelocity[1] = motion.angularVelocity[1];
+    pose.vecAngularVelocity[2] = motion.angularVelocity[2];
 
     m_pose = pose;
 
@@ -792,107 +701,109 @@ bool OvrController::onPoseUpdate(float predictionS,
             0.67f;
         float grip = std::min({rotMiddle, rotRing, rotPinky}) * 4.0f - 3.0f;
 
-        switch (Settings::Instance().m_controllerMode) {
-        case 1: // Oculus Rift
-        case 7: // Oculus Quest
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_APPLICATION_MENU_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_GRIP_CLICK], grip > 0.9f, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_GRIP_VALUE], grip, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_GRIP_TOUCH], grip > 0.7f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_THUMB_REST_TOUCH], false, 0.0);
-            if (this->device_id == RIGHT_HAND_ID) {
+        if (!hand.gestureButtons) {
+            switch (Settings::Instance().m_controllerMode) {
+            case 1: // Oculus Rift
+            case 7: // Oculus Quest
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_A_CLICK], false, 0.0);
+                    m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_A_TOUCH], false, 0.0);
+                    m_handles[ALVR_INPUT_APPLICATION_MENU_CLICK], false, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_B_CLICK], false, 0.0);
+                    m_handles[ALVR_INPUT_GRIP_CLICK], grip > 0.9f, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_GRIP_VALUE], grip, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_B_TOUCH], false, 0.0);
-            } else {
+                    m_handles[ALVR_INPUT_GRIP_TOUCH], grip > 0.7f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_THUMB_REST_TOUCH], false, 0.0);
+                if (this->device_id == RIGHT_HAND_ID) {
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_A_CLICK], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_A_TOUCH], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_B_CLICK], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_B_TOUCH], false, 0.0);
+                } else {
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_X_CLICK], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_X_TOUCH], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_Y_CLICK], false, 0.0);
+                    vr_driver_input->UpdateBooleanComponent(
+                        m_handles[ALVR_INPUT_Y_TOUCH], false, 0.0);
+                }
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_JOYSTICK_CLICK], false, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_X], 0.0f, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_Y], 0.0f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_JOYSTICK_TOUCH], rotThumb > 0.7f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_BACK_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_GUIDE_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_START_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
+                vr_driver_input->UpdateScalarComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_TOUCH], rotIndex > 0.7f, 0.0);
+                break;
+            case 3:
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_GRIP_TOUCH], grip > 0.7f, 0.0);
+                vr_driver_input->UpdateScalarComponent(
+                    m_handles[ALVR_INPUT_GRIP_FORCE], (grip * 1.1f - 1.f) * 10.f, 0.0);
+                vr_driver_input->UpdateScalarComponent(
+                    m_handles[ALVR_INPUT_GRIP_VALUE], grip * 1.1f, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_X], 0, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_Y], 0, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_X_CLICK], false, 0.0);
+                    m_handles[ALVR_INPUT_TRACKPAD_TOUCH], false, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_X], 0, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_Y], 0, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_X_TOUCH], false, 0.0);
+                    m_handles[ALVR_INPUT_JOYSTICK_CLICK], false, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_Y_CLICK], false, 0.0);
+                    m_handles[ALVR_INPUT_JOYSTICK_TOUCH], rotThumb > 0.7f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_A_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_A_TOUCH], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_B_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_B_TOUCH], false, 0.0);
                 vr_driver_input->UpdateBooleanComponent(
-                    m_handles[ALVR_INPUT_Y_TOUCH], false, 0.0);
+                    m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_TOUCH], rotIndex > 0.7f, 0.0);
+                vr_driver_input->UpdateScalarComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
+                break;
+            case 5:
+            case 9: // vive tracker
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRACKPAD_TOUCH], rotThumb > 0.7f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRACKPAD_CLICK], rotThumb > 0.9f, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_X], 0, 0.0);
+                vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_Y], 0, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
+                vr_driver_input->UpdateScalarComponent(
+                    m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_GRIP_CLICK], grip > 0.9f, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_APPLICATION_MENU_CLICK], false, 0.0);
+                vr_driver_input->UpdateBooleanComponent(
+                    m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
+                break;
             }
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_JOYSTICK_CLICK], false, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_X], 0.0f, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_Y], 0.0f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_JOYSTICK_TOUCH], rotThumb > 0.7f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_BACK_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_GUIDE_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_START_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
-            vr_driver_input->UpdateScalarComponent(
-                m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRIGGER_TOUCH], rotIndex > 0.7f, 0.0);
-            break;
-        case 3:
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_GRIP_TOUCH], grip > 0.7f, 0.0);
-            vr_driver_input->UpdateScalarComponent(
-                m_handles[ALVR_INPUT_GRIP_FORCE], (grip * 1.1f - 1.f) * 10.f, 0.0);
-            vr_driver_input->UpdateScalarComponent(
-                m_handles[ALVR_INPUT_GRIP_VALUE], grip * 1.1f, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_X], 0, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_Y], 0, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRACKPAD_TOUCH], false, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_X], 0, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_JOYSTICK_Y], 0, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_JOYSTICK_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_JOYSTICK_TOUCH], rotThumb > 0.7f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_A_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_A_TOUCH], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_B_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(m_handles[ALVR_INPUT_B_TOUCH], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRIGGER_TOUCH], rotIndex > 0.7f, 0.0);
-            vr_driver_input->UpdateScalarComponent(
-                m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
-            break;
-        case 5:
-        case 9: // vive tracker
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRACKPAD_TOUCH], rotThumb > 0.7f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRACKPAD_CLICK], rotThumb > 0.9f, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_X], 0, 0.0);
-            vr_driver_input->UpdateScalarComponent(m_handles[ALVR_INPUT_TRACKPAD_Y], 0, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_TRIGGER_CLICK], rotIndex > 0.9f, 0.0);
-            vr_driver_input->UpdateScalarComponent(
-                m_handles[ALVR_INPUT_TRIGGER_VALUE], rotIndex, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_GRIP_CLICK], grip > 0.9f, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_APPLICATION_MENU_CLICK], false, 0.0);
-            vr_driver_input->UpdateBooleanComponent(
-                m_handles[ALVR_INPUT_SYSTEM_CLICK], false, 0.0);
-            break;
         }
         // Hand
         const vr::VRBoneTransform_t handRestPose = {{0, 0, 0, 1}, {1, 0, 0, 0}};
@@ -1059,6 +970,17 @@ bool OvrController::onPoseUpdate(float predictionS,
                 QuatMultiply(&fixer, &m_boneTransform[HSB_PinkyFinger0].orientation);
         }
 
+        // The bones converted from the OpenXR joints replace the estimate from the rotations
+        if (hand.skeletonValid) {
+            for (size_t i = 0U; i < HSB_Count; i++) {
+                auto &bone = hand.skeleton[i];
+                m_boneTransform[i].position = {
+                    bone.position[0], bone.position[1], bone.position[2], 1.f};
+                m_boneTransform[i].orientation = {
+                    bone.orientation.w, bone.orientation.x, bone.orientation.y, bone.orientation.z};
+            }
+        }
+
         vr_driver_input->UpdateSkeletonComponent(
             m_compSkeleton, vr::VRSkeletalMotionRange_WithController, m_boneTransform, HSB_Count);
         vr_driver_input->UpdateSkeletonComponent(m_compSkeleton,
@@ -1146,9 +1068,7 @@ bool OvrController::onPoseUpdate(float predictionS,
             }
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_TRIGGER_CLICK],
-                Settings::Instance().m_overrideTriggerThreshold ?
-                    m_triggerValue >= Settings::Instance().m_triggerThreshold :
-                    (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
+                (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
                 0.0);
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_TRIGGER_TOUCH],
@@ -1195,17 +1115,13 @@ bool OvrController::onPoseUpdate(float predictionS,
                 m_handles[ALVR_INPUT_TRACKPAD_Y], m_joystickY, 0.0);
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_TRIGGER_CLICK],
-                Settings::Instance().m_overrideTriggerThreshold ?
-                    m_triggerValue >= Settings::Instance().m_triggerThreshold :
-                    (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
+                (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
                 0.0);
             vr_driver_input->UpdateScalarComponent(
                 m_handles[ALVR_INPUT_TRIGGER_VALUE], m_triggerValue, 0.0);
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_GRIP_CLICK],
-                Settings::Instance().m_overrideGripThreshold ?
-                    m_gripValue >= Settings::Instance().m_gripThreshold :
-                    (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_GRIP_CLICK)) != 0,
+                (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_GRIP_CLICK)) != 0,
                 0.0);
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_SYSTEM_CLICK],
@@ -1236,9 +1152,7 @@ bool OvrController::onPoseUpdate(float predictionS,
                 0.0);
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_GRIP_CLICK],
-                Settings::Instance().m_overrideGripThreshold ?
-                    m_gripValue >= Settings::Instance().m_gripThreshold :
-                    (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_GRIP_CLICK)) != 0,
+                (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_GRIP_CLICK)) != 0,
                 0.0);
             vr_driver_input->UpdateScalarComponent(
                 m_handles[ALVR_INPUT_GRIP_VALUE], m_gripValue, 0.0);
@@ -1318,9 +1232,7 @@ bool OvrController::onPoseUpdate(float predictionS,
 
             vr_driver_input->UpdateBooleanComponent(
                 m_handles[ALVR_INPUT_TRIGGER_CLICK],
-                Settings::Instance().m_overrideTriggerThreshold ?
-                    m_triggerValue >= Settings::Instance().m_triggerThreshold :
-                    (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
+                (m_buttons & ALVR_BUTTON_FLAG(ALVR_INPUT_TRIGGER_CLICK)) != 0,
                 0.0);
             vr_driver_input->UpdateScalarComponent(
                 m_handles[ALVR_INPUT_TRIGGER_VALUE], m_triggerValue, 0.0);
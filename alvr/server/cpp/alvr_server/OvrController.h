@@ -37,7 +37,7 @@ class OvrController : public TrackedDevice, public vr::ITrackedDeviceServerDrive
 
     void SetButton(uint64_t id, AlvrButtonValue value);
 
-    bool onPoseUpdate(float predictionS, AlvrDeviceMotion motion, const OculusHand &hand);
+    bool onPoseUpdate(AlvrDeviceMotion motion, const OculusHand &hand);
     std::string GetSerialNumber();
 
     void GetBoneTransform(bool withController,
@@ -0,0 +1,104 @@
+#include "OvrGenericTracker.h"
+#include "Logger.h"
+#include "Utils.h"
+
+OvrGenericTracker::OvrGenericTracker(uint64_t deviceID, GenericTrackerConfig config)
+    : TrackedDevice(deviceID), m_config(config) {
+    m_pose = vr::DriverPose_t{};
+    m_pose.poseIsValid = false;
+    m_pose.result = vr::TrackingResult_Uninitialized;
+    m_pose.deviceIsConnected = true;
+
+    m_pose.qWorldFromDriverRotation = HmdQuaternion_Init(1, 0, 0, 0);
+    m_pose.qDriverFromHeadRotation = HmdQuaternion_Init(1, 0, 0, 0);
+    m_pose.qRotation = HmdQuaternion_Init(1, 0, 0, 0);
+}
+
+//
+// ITrackedDeviceServerDriver
+//
+
+vr::EVRInitError OvrGenericTracker::Activate(vr::TrackedDeviceIndex_t unObjectId) {
+    Debug("OvrGenericTracker::Activate. path=%s objectId=%d\n", m_config.path.c_str(), unObjectId);
+
+    auto vr_properties = vr::VRProperties();
+
+    this->object_id = unObjectId;
+    this->prop_container = vr_properties->TrackedDeviceToPropertyContainer(this->object_id);
+
+    vr_properties->SetStringProperty(this->prop_container,
+                                     vr::Prop_TrackingSystemName_String,
+                                     m_config.trackingSystemName.c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_ManufacturerName_String, m_config.manufacturerName.c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_ModelNumber_String, m_config.modelNumber.c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_SerialNumber_String, GetSerialNumber().c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_RenderModelName_String, m_config.renderModelName.c_str());
+    vr_properties->SetStringProperty(this->prop_container,
+                                     vr::Prop_RegisteredDeviceType_String,
+                                     m_config.registeredDeviceType.c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_ControllerType_String, m_config.controllerType.c_str());
+    vr_properties->SetStringProperty(
+        this->prop_container, vr::Prop_InputProfilePath_String, m_config.inputProfilePath.c_str());
+
+    // Trackers must not be picked up as hands
+    vr_properties->SetInt32Property(
+        this->prop_container, vr::Prop_ControllerRoleHint_Int32, vr::TrackedControllerRole_OptOut);
+    vr_properties->SetInt32Property(
+        this->prop_container, vr::Prop_DeviceClass_Int32, vr::TrackedDeviceClass_GenericTracker);
+
+    vr_properties->SetBoolProperty(this->prop_container, vr::Prop_WillDriftInYaw_Bool, false);
+    vr_properties->SetBoolProperty(this->prop_container, vr::Prop_DeviceIsWireless_Bool, true);
+    vr_properties->SetBoolProperty(
+        this->prop_container, vr::Prop_DeviceProvidesBatteryStatus_Bool, true);
+    vr_properties->SetBoolProperty(this->prop_container, vr::Prop_Identifiable_Bool, false);
+    vr_properties->SetBoolProperty(
+        this->prop_container, vr::Prop_Firmware_UpdateAvailable_Bool, false);
+
+    return vr::VRInitError_None;
+}
+
+void OvrGenericTracker::Deactivate() {
+    Debug("OvrGenericTracker::Deactivate. path=%s\n", m_config.path.c_str());
+    this->object_id = vr::k_unTrackedDeviceIndexInvalid;
+}
+
+void OvrGenericTracker::DebugRequest(const char *request,
+                                     char *response_buffer,
+                                     uint32_t response_buffer_size) {
+    if (response_buffer_size >= 1)
+        response_buffer[0] = 0;
+}
+
+vr::DriverPose_t OvrGenericTracker::GetPose() { return m_pose; }
+
+void OvrGenericTracker::onPoseUpdate(AlvrDeviceMotion motion) {
+    if (this->object_id == vr::k_unTrackedDeviceIndexInvalid) {
+        return;
+    }
+
+    auto pose = m_pose;
+
+    pose.poseIsValid = true;
+    pose.result = vr::TrackingResult_Running_OK;
+
+    pose.qRotation = HmdQuaternion_Init(
+        motion.orientation.w, motion.orientation.x, motion.orientation.y, motion.orientation.z);
+
+    for (int i = 0; i < 3; i++) {
+        pose.vecPosition[i] = motion.position[i];
+        pose.vecVelocity[i] = motion.linearVelocity[i];
+        pose.vecAngularVelocity[i] = motion.angularVelocity[i];
+    }
+
+    m_pose = pose;
+
+    vr::VRServerDriverHost()->TrackedDevicePoseUpdated(
+        this->object_id, pose, sizeof(vr::DriverPose_t));
+}
+
+std::string OvrGenericTracker::GetSerialNumber() { return m_config.serialNumber; }
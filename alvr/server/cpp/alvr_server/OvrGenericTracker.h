@@ -0,0 +1,39 @@
+#pragma once
+
+#include "Settings.h"
+#include "TrackedDevice.h"
+#include "openvr_driver.h"
+
+class OvrGenericTracker : public TrackedDevice, public vr::ITrackedDeviceServerDriver {
+  public:
+    OvrGenericTracker(uint64_t deviceID, GenericTrackerConfig config);
+
+    virtual ~OvrGenericTracker(){};
+
+    //
+    // ITrackedDeviceServerDriver
+    //
+
+    virtual vr::EVRInitError Activate(vr::TrackedDeviceIndex_t unObjectId);
+
+    virtual void Deactivate();
+
+    virtual void EnterStandby(){};
+
+    void *GetComponent(const char *pchComponentNameAndVersion) { return nullptr; };
+
+    virtual void PowerOff(){};
+
+    /** debug request from a client */
+    virtual void
+    DebugRequest(const char *pchRequest, char *pchResponseBuffer, uint32_t unResponseBufferSize);
+
+    virtual vr::DriverPose_t GetPose();
+
+    void onPoseUpdate(AlvrDeviceMotion motion);
+    std::string GetSerialNumber();
+
+  private:
+    GenericTrackerConfig m_config;
+    vr::DriverPose_t m_pose;
+};
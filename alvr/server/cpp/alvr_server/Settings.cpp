@@ -16,11 +16,8 @@ extern uint64_t g_DriverTestMode;
 Settings Settings::m_Instance;
 
 Settings::Settings()
-	: m_loaded(false), m_EnableOffsetPos(false)
+	: m_loaded(false)
 {
-	m_OffsetPos[0] = 0.0f;
-	m_OffsetPos[1] = 0.0f;
-	m_OffsetPos[2] = 0.0f;
 }
 
 Settings::~Settings()
@@ -105,6 +102,7 @@ void Settings::Load()
 		m_encoderQualityPreset = (uint32_t)config.get("encoder_quality_preset").get<int64_t>();
 		m_force_sw_encoding = config.get("force_sw_encoding").get<bool>();
 		m_swThreadCount = (int32_t)config.get("sw_thread_count").get<int64_t>();
+		m_useOpenh264 = config.get("use_openh264").get<bool>();
 
 		m_controllerTrackingSystemName = config.get("controllers_tracking_system_name").get<std::string>();
 		m_controllerManufacturerName = config.get("controllers_manufacturer_name").get<std::string>();
@@ -121,30 +119,6 @@ void Settings::Load()
 
 		m_disableController = !config.get("controllers_enabled").get<bool>();
 
-		m_EnableOffsetPos = true;
-		auto headsetPositionOffset = config.get("position_offset").get<picojson::array>();
-		m_OffsetPos[0] = (float)headsetPositionOffset[0].get<double>();
-		m_OffsetPos[1] = (float)headsetPositionOffset[1].get<double>();
-		m_OffsetPos[2] = (float)headsetPositionOffset[2].get<double>();
-
-		m_linearVelocityCutoff = (float)config.get("linear_velocity_cutoff").get<double>();
-		m_angularVelocityCutoff = (float)config.get("angular_velocity_cutoff").get<double>();
-
-		auto leftControllerPositionOffset = config.get("position_offset_left").get<picojson::array>();
-		m_leftControllerPositionOffset[0] = leftControllerPositionOffset[0].get<double>();
-		m_leftControllerPositionOffset[1] = leftControllerPositionOffset[1].get<double>();
-		m_leftControllerPositionOffset[2] = leftControllerPositionOffset[2].get<double>();
-
-		auto leftControllerRotationOffset = config.get("rotation_offset_left").get<picojson::array>();
-		m_leftControllerRotationOffset[0] = leftControllerRotationOffset[0].get<double>();
-		m_leftControllerRotationOffset[1] = leftControllerRotationOffset[1].get<double>();
-		m_leftControllerRotationOffset[2] = leftControllerRotationOffset[2].get<double>();
-
-		m_overrideTriggerThreshold = config.get("override_trigger_threshold").get<bool>();
-		m_triggerThreshold = config.get("trigger_threshold").get<double>();
-		m_overrideGripThreshold = config.get("override_grip_threshold").get<bool>();
-		m_gripThreshold = config.get("grip_threshold").get<double>();
-
 		m_hapticsIntensity = config.get("haptics_intensity").get<double>();
 		m_hapticsAmplitudeCurve = config.get("haptics_amplitude_curve").get<double>();
 		m_hapticsMinDuration = config.get("haptics_min_duration").get<double>();
@@ -168,8 +142,6 @@ void Settings::Load()
 		m_gamma = (float)config.get("gamma").get<double>();
 		m_sharpening = (float)config.get("sharpening").get<double>();
 
-		m_enableFec = config.get("enable_fec").get<bool>();
-
 		m_enableLinuxVulkanAsync = config.get("linux_async_reprojection").get<bool>();
 
 		m_nvencPreset = config.get("nvenc_preset").get<int64_t>();
@@ -189,6 +161,26 @@ void Settings::Load()
 
 		m_captureFrameDir = config.get("capture_frame_dir").get<std::string>();
 
+		m_genericTrackers.clear();
+		// Missing from the configurations saved by older versions
+		if (config.contains("generic_trackers"))
+		{
+			for (auto &tracker : config.get("generic_trackers").get<picojson::array>())
+			{
+				GenericTrackerConfig trackerConfig;
+				trackerConfig.path = tracker.get("path").get<std::string>();
+				trackerConfig.serialNumber = tracker.get("serial_number").get<std::string>();
+				trackerConfig.trackingSystemName = tracker.get("tracking_system_name").get<std::string>();
+				trackerConfig.manufacturerName = tracker.get("manufacturer_name").get<std::string>();
+				trackerConfig.modelNumber = tracker.get("model_number").get<std::string>();
+				trackerConfig.renderModelName = tracker.get("render_model_name").get<std::string>();
+				trackerConfig.registeredDeviceType = tracker.get("registered_device_type").get<std::string>();
+				trackerConfig.controllerType = tracker.get("controller_type").get<std::string>();
+				trackerConfig.inputProfilePath = tracker.get("input_profile_path").get<std::string>();
+				m_genericTrackers.push_back(trackerConfig);
+			}
+		}
+
 		Debug("Config JSON: %hs\n", json.c_str());
 		Info("Serial Number: %hs\n", mSerialNumber.c_str());
 		Info("Model Number: %hs\n", mModelNumber.c_str());
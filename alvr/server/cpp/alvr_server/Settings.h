@@ -1,8 +1,22 @@
 #pragma once
 
 #include <string>
+#include <vector>
 #include "ALVR-common/packet_types.h"
 
+struct GenericTrackerConfig
+{
+	std::string path;
+	std::string serialNumber;
+	std::string trackingSystemName;
+	std::string manufacturerName;
+	std::string modelNumber;
+	std::string renderModelName;
+	std::string registeredDeviceType;
+	std::string controllerType;
+	std::string inputProfilePath;
+};
+
 class Settings
 {
 	static Settings m_Instance;
@@ -81,6 +95,7 @@ public:
 	uint32_t m_rateControlMode;
 	bool m_force_sw_encoding;
 	uint32_t m_swThreadCount;
+	bool m_useOpenh264;
 
 	// Controller configs
 	std::string m_controllerTrackingSystemName;
@@ -95,19 +110,6 @@ public:
 	std::string m_controllerInputProfilePath;
 	bool m_disableController;
 
-	float m_linearVelocityCutoff;
-	float m_angularVelocityCutoff;
-
-	float m_OffsetPos[3];
-	bool m_EnableOffsetPos;
-
-	double m_leftControllerPositionOffset[3];
-	double m_leftControllerRotationOffset[3];
-
-	bool m_overrideTriggerThreshold;
-	float m_triggerThreshold;
-	bool m_overrideGripThreshold;
-	float m_gripThreshold;
 
 	float m_hapticsIntensity;
 	float m_hapticsAmplitudeCurve;
@@ -135,8 +137,6 @@ public:
 
 	bool m_useHeadsetTrackingSystem = false;
 	
-	bool m_enableFec;
-
 	bool m_enableLinuxVulkanAsync;
 
 	int64_t m_nvencPreset;
@@ -155,4 +155,6 @@ public:
 	int64_t m_nvencEnableAQ;
 
 	std::string m_captureFrameDir;
+
+	std::vector<GenericTrackerConfig> m_genericTrackers;
 };
@@ -9,6 +9,7 @@
 #include "ClientConnection.h"
 #include "Logger.h"
 #include "OvrController.h"
+#include "OvrGenericTracker.h"
 #include "OvrHMD.h"
 #include "Paths.h"
 #include "Settings.h"
@@ -60,7 +61,7 @@ class DriverProvider : public vr::IServerTrackedDeviceProvider {
   public:
     std::shared_ptr<OvrHmd> hmd;
     std::shared_ptr<OvrController> left_controller, right_controller;
-    // std::vector<OvrViveTrackerProxy> generic_trackers;
+    std::map<uint64_t, std::shared_ptr<OvrGenericTracker>> generic_trackers;
 
     std::map<uint64_t, TrackedDevice *> tracked_devices;
 
@@ -79,9 +80,25 @@ class DriverProvider : public vr::IServerTrackedDeviceProvider {
                 {RIGHT_HAND_ID, (TrackedDevice *)&*this->right_controller});
         }
 
+        for (auto &config : Settings::Instance().m_genericTrackers) {
+            auto device_id = PathStringToHash(config.path.c_str());
+            auto tracker = std::make_shared<OvrGenericTracker>(device_id, config);
+            if (!vr::VRServerDriverHost()->TrackedDeviceAdded(tracker->GetSerialNumber().c_str(),
+                                                              vr::TrackedDeviceClass_GenericTracker,
+                                                              tracker.get())) {
+                Warn("Failed to register tracker %s", config.path.c_str());
+                continue;
+            }
+
+            this->generic_trackers.insert({device_id, tracker});
+            this->tracked_devices.insert({device_id, (TrackedDevice *)&*tracker});
+        }
+
         return vr::VRInitError_None;
     }
     virtual void Cleanup() override {
+        this->tracked_devices.clear();
+        this->generic_trackers.clear();
         this->left_controller.reset();
         this->right_controller.reset();
         this->hmd.reset();
@@ -135,6 +152,8 @@ class DriverProvider : public vr::IServerTrackedDeviceProvider {
                     HapticsSend(
                         RIGHT_CONTROLLER_HAPTIC_ID, duration, haptics_info.fFrequency, amplitude);
                 }
+            } else if (event.eventType == vr::VREvent_SceneApplicationChanged) {
+                SceneApplicationChanged(event.data.process.pid);
             }
         }
     }
@@ -176,6 +195,9 @@ void (*LogPeriodically)(const char *tag, const char *stringPtr);
 void (*DriverReadyIdle)(bool setDefaultChaprone);
 void (*InitializeDecoder)(const unsigned char *configBuffer, int len);
 void (*VideoSend)(VideoFrame header, unsigned char *buf, int len);
+void (*EncodeFrameSoftware)(RawVideoFrame frame, unsigned long long targetTimestampNs, bool idr);
+void (*SetSoftwareEncoderBitrate)(unsigned long long bitrateBps);
+void (*SceneApplicationChanged)(unsigned int pid);
 void (*HapticsSend)(unsigned long long path, float duration_s, float frequency, float amplitude);
 void (*ShutdownRuntime)();
 unsigned long long (*PathStringToHash)(const char *path);
@@ -230,7 +252,6 @@ void RequestIDR() {
 }
 
 void SetTracking(unsigned long long targetTimestampNs,
-                 float controllerPoseTimeOffsetS,
                  const AlvrDeviceMotion *deviceMotions,
                  int motionsCount,
                  OculusHand leftHand,
@@ -240,12 +261,16 @@ void SetTracking(unsigned long long targetTimestampNs,
             g_driver_provider.hmd->OnPoseUpdated(targetTimestampNs, deviceMotions[i]);
         } else {
             if (deviceMotions[i].deviceID == LEFT_HAND_ID && g_driver_provider.left_controller) {
-                g_driver_provider.left_controller->onPoseUpdate(
-                    controllerPoseTimeOffsetS, deviceMotions[i], leftHand);
+                g_driver_provider.left_controller->onPoseUpdate(deviceMotions[i], leftHand);
             } else if (deviceMotions[i].deviceID == RIGHT_HAND_ID &&
                        g_driver_provider.right_controller) {
-                g_driver_provider.right_controller->onPoseUpdate(
-                    controllerPoseTimeOffsetS, deviceMotions[i], rightHand);
+                g_driver_provider.right_controller->onPoseUpdate(deviceMotions[i], rightHand);
+            } else {
+                auto tracker_it =
+                    g_driver_provider.generic_trackers.find(deviceMotions[i].deviceID);
+                if (tracker_it != g_driver_provider.generic_trackers.end()) {
+                    tracker_it->second->onPoseUpdate(deviceMotions[i]);
+                }
             }
         }
     }
@@ -14,9 +14,19 @@ struct AlvrQuat {
     float w;
 };
 
+struct AlvrBoneTransform {
+    AlvrQuat orientation;
+    float position[3];
+};
+
 struct OculusHand {
     bool enabled;
     AlvrQuat boneRotations[19];
+    // The buttons are emulated from the OpenXR skeleton in Rust, see gestures.rs
+    bool gestureButtons;
+    // Bones of the SteamVR skeleton converted from the OpenXR joints, in place of boneRotations
+    bool skeletonValid;
+    AlvrBoneTransform skeleton[31];
 };
 
 struct AlvrDeviceMotion {
@@ -47,6 +57,16 @@ struct VideoFrame {
     unsigned short fecPercentage;
     // char frameBuffer[];
 };
+// 8 bit YUV 4:2:0 planar frame
+struct RawVideoFrame {
+    const unsigned char *y;
+    const unsigned char *u;
+    const unsigned char *v;
+    int yStride;
+    int uvStride;
+    unsigned int width;
+    unsigned int height;
+};
 enum OpenvrPropertyType {
     Bool,
     Float,
@@ -122,6 +142,11 @@ extern "C" void (*LogPeriodically)(const char *tag, const char *stringPtr);
 extern "C" void (*DriverReadyIdle)(bool setDefaultChaprone);
 extern "C" void (*InitializeDecoder)(const unsigned char *configBuffer, int len);
 extern "C" void (*VideoSend)(VideoFrame header, unsigned char *buf, int len);
+extern "C" void (*EncodeFrameSoftware)(RawVideoFrame frame,
+                                       unsigned long long targetTimestampNs,
+                                       bool idr);
+extern "C" void (*SetSoftwareEncoderBitrate)(unsigned long long bitrateBps);
+extern "C" void (*SceneApplicationChanged)(unsigned int pid);
 extern "C" void (*HapticsSend)(unsigned long long path,
                                float duration_s,
                                float frequency,
@@ -139,7 +164,6 @@ extern "C" void DeinitializeStreaming();
 extern "C" void SendVSync(float frameIntervalS);
 extern "C" void RequestIDR();
 extern "C" void SetTracking(unsigned long long targetTimestampNs,
-                            float controllerPoseTimeOffsetS,
                             const AlvrDeviceMotion *deviceMotions,
                             int motionsCount,
                             OculusHand leftHand,
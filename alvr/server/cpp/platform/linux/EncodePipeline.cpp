@@ -45,6 +45,12 @@ void filter_NAL(const uint8_t* input, size_t input_size, std::vector<uint8_t> &o
   if (input_size < 4)
     return;
   auto codec = Settings::Instance().m_codec;
+  // AV1 packets are made of OBUs, they are split in Rust
+  if (codec == ALVR_CODEC_AV1)
+  {
+    out.insert(out.end(), input, input + input_size);
+    return;
+  }
   std::array<uint8_t, 3> header = {{0, 0, 1}};
   auto end = input + input_size;
   auto header_start = input;
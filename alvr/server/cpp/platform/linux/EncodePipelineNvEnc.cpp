@@ -18,6 +18,8 @@ const char *encoder(ALVR_CODEC codec) {
         return "h264_nvenc";
     case ALVR_CODEC_H265:
         return "hevc_nvenc";
+    case ALVR_CODEC_AV1:
+        return "av1_nvenc";
     }
     throw std::runtime_error("invalid codec " + std::to_string(codec));
 }
@@ -56,6 +58,12 @@ alvr::EncodePipelineNvEnc::EncodePipelineNvEnc(VkFrame &input_frame,
         AVUTIL.av_opt_set(encoder_ctx, "preset", "llhq", 0);
         AVUTIL.av_opt_set(encoder_ctx, "zerolatency", "1", 0);
         break;
+    case ALVR_CODEC_AV1:
+        // The legacy presets are not available for AV1
+        AVUTIL.av_opt_set(encoder_ctx, "preset", "p1", 0);
+        AVUTIL.av_opt_set(encoder_ctx, "tune", "ull", 0);
+        AVUTIL.av_opt_set(encoder_ctx, "zerolatency", "1", 0);
+        break;
     }
 
     /**
@@ -4,6 +4,7 @@
 #include <chrono>
 
 #include "alvr_server/Settings.h"
+#include "alvr_server/bindings.h"
 #include "ffmpeg_helper.h"
 
 extern "C" {
@@ -23,6 +24,8 @@ const char * encoder(ALVR_CODEC codec)
       return "libx264";
     case ALVR_CODEC_H265:
       return "libx265";
+    case ALVR_CODEC_AV1:
+      return "libsvtav1";
   }
   throw std::runtime_error("invalid codec " + std::to_string(codec));
 }
@@ -36,6 +39,27 @@ alvr::EncodePipelineSW::EncodePipelineSW(VkFrame &input_frame, VkFrameCtx& vk_fr
 
   const auto& settings = Settings::Instance();
 
+  transferred_frame = AVUTIL.av_frame_alloc();
+  encoder_frame = AVUTIL.av_frame_alloc();
+  encoder_frame->width = settings.m_renderWidth;
+  encoder_frame->height = settings.m_renderHeight;
+
+  // The frames are encoded by OpenH264 in Rust, and sent from there
+  if (settings.m_useOpenh264)
+  {
+    encoder_frame->width = width;
+    encoder_frame->height = height;
+    encoder_frame->format = AV_PIX_FMT_YUV420P;
+    AVUTIL.av_frame_get_buffer(encoder_frame, 0);
+
+    scaler_ctx = SWSCALE.sws_getContext(
+            vk_frame->width, vk_frame->height, ((AVHWFramesContext*)vk_frame->hw_frames_ctx->data)->sw_format,
+            width, height, AV_PIX_FMT_YUV420P,
+            SWS_BILINEAR,
+            NULL, NULL, NULL);
+    return;
+  }
+
   auto codec_id = ALVR_CODEC(settings.m_codec);
   const char * encoder_name = encoder(codec_id);
   const AVCodec *codec = AVCODEC.avcodec_find_encoder_by_name(encoder_name);
@@ -65,6 +89,13 @@ alvr::EncodePipelineSW::EncodePipelineSW(VkFrame &input_frame, VkFrameCtx& vk_fr
       AVUTIL.av_dict_set(&opt, "tune", "zerolatency", 0);
       encoder_ctx->gop_size = 72;
       break;
+    case ALVR_CODEC_AV1:
+      encoder_ctx->profile = FF_PROFILE_AV1_MAIN;
+      // Fastest preset, with the low delay prediction structure
+      AVUTIL.av_dict_set(&opt, "preset", "12", 0);
+      AVUTIL.av_dict_set(&opt, "svtav1-params", "pred-struct=1", 0);
+      encoder_ctx->gop_size = 72;
+      break;
   }
 
 
@@ -83,10 +114,6 @@ alvr::EncodePipelineSW::EncodePipelineSW(VkFrame &input_frame, VkFrameCtx& vk_fr
     throw alvr::AvException("Cannot open video encoder codec:", err);
   }
 
-  transferred_frame = AVUTIL.av_frame_alloc();
-  encoder_frame = AVUTIL.av_frame_alloc();
-  encoder_frame->width = settings.m_renderWidth;
-  encoder_frame->height = settings.m_renderHeight;
   encoder_frame->format = encoder_ctx->pix_fmt;
   AVUTIL.av_frame_get_buffer(encoder_frame, 0);
 
@@ -115,6 +142,20 @@ void alvr::EncodePipelineSW::PushFrame(uint64_t targetTimestampNs, bool idr)
   if (err == 0)
     throw alvr::AvException("sws_scale failed:", err);
 
+  if (not encoder_ctx)
+  {
+    RawVideoFrame frame = {};
+    frame.y = encoder_frame->data[0];
+    frame.u = encoder_frame->data[1];
+    frame.v = encoder_frame->data[2];
+    frame.yStride = encoder_frame->linesize[0];
+    frame.uvStride = encoder_frame->linesize[1];
+    frame.width = encoder_frame->width;
+    frame.height = encoder_frame->height;
+    EncodeFrameSoftware(frame, targetTimestampNs, idr);
+    return;
+  }
+
   encoder_frame->pict_type = idr ? AV_PICTURE_TYPE_I : AV_PICTURE_TYPE_NONE;
   encoder_frame->pts = targetTimestampNs;
 
@@ -122,3 +163,19 @@ void alvr::EncodePipelineSW::PushFrame(uint64_t targetTimestampNs, bool idr)
     throw alvr::AvException("avcodec_send_frame failed:", err);
   }
 }
+
+bool alvr::EncodePipelineSW::GetEncoded(std::vector<uint8_t> &out, uint64_t *pts)
+{
+  // The frames encoded in Rust are not returned
+  if (not encoder_ctx)
+    return false;
+  return EncodePipeline::GetEncoded(out, pts);
+}
+
+void alvr::EncodePipelineSW::SetBitrate(int64_t bitrate)
+{
+  if (encoder_ctx)
+    EncodePipeline::SetBitrate(bitrate);
+  else
+    SetSoftwareEncoderBitrate(bitrate);
+}
@@ -15,6 +15,8 @@ public:
   EncodePipelineSW(VkFrame &input_frame, VkFrameCtx& vk_frame_ctx, uint32_t width, uint32_t height);
 
   void PushFrame(uint64_t targetTimestampNs, bool idr) override;
+  bool GetEncoded(std::vector<uint8_t> & out, uint64_t *pts) override;
+  void SetBitrate(int64_t bitrate) override;
 
 private:
   AVFrame *vk_frame;
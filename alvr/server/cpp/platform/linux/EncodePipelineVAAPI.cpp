@@ -24,6 +24,8 @@ const char * encoder(ALVR_CODEC codec)
       return "h264_vaapi";
     case ALVR_CODEC_H265:
       return "hevc_vaapi";
+    case ALVR_CODEC_AV1:
+      return "av1_vaapi";
   }
   throw std::runtime_error("invalid codec " + std::to_string(codec));
 }
@@ -129,6 +131,9 @@ alvr::EncodePipelineVAAPI::EncodePipelineVAAPI(VkFrame &input_frame, VkFrameCtx&
     case ALVR_CODEC_H265:
       encoder_ctx->profile = Settings::Instance().m_use10bitEncoder ? FF_PROFILE_HEVC_MAIN_10 : FF_PROFILE_HEVC_MAIN;
       break;
+    case ALVR_CODEC_AV1:
+      encoder_ctx->profile = FF_PROFILE_AV1_MAIN;
+      break;
   }
 
   encoder_ctx->width = width;
@@ -0,0 +1,169 @@
+// Calls from the connection pipeline into the C++ driver and encoder. They are behind a trait so
+// the pipeline can run without SteamVR, against a mock backend.
+
+use crate::{AlvrButtonValue, AlvrDeviceMotion, OculusHand, OpenvrProperty, ViewsConfigData};
+use alvr_common::glam::Vec2;
+use std::time::Duration;
+
+pub trait ServerBackend: Send + Sync {
+    fn initialize_streaming(&self);
+    fn deinitialize_streaming(&self);
+    fn send_vsync(&self, frame_interval: Duration);
+    fn set_chaperone(&self, area: Vec2);
+    fn set_tracking(
+        &self,
+        target_timestamp: Duration,
+        device_motions: &[AlvrDeviceMotion],
+        left_hand: OculusHand,
+        right_hand: OculusHand,
+    );
+    fn report_network_latency(&self, latency: Duration);
+    fn set_bitrate_parameters(
+        &self,
+        bitrate_mbs: u64,
+        adaptive_bitrate_enabled: bool,
+        bitrate_maximum: u64,
+    );
+    fn request_idr(&self);
+    fn report_video_error(&self);
+    fn set_views_config(&self, config: ViewsConfigData);
+    fn set_battery(&self, device_id: u64, gauge_value: f32, is_plugged: bool);
+    fn set_button(&self, path_id: u64, value: AlvrButtonValue);
+    fn set_openvr_property(&self, device_id: u64, prop: OpenvrProperty);
+}
+
+pub struct CppBackend;
+
+impl ServerBackend for CppBackend {
+    fn initialize_streaming(&self) {
+        unsafe { crate::InitializeStreaming() };
+    }
+
+    fn deinitialize_streaming(&self) {
+        unsafe { crate::DeinitializeStreaming() };
+    }
+
+    fn send_vsync(&self, frame_interval: Duration) {
+        unsafe { crate::SendVSync(frame_interval.as_secs_f32()) };
+    }
+
+    fn set_chaperone(&self, area: Vec2) {
+        unsafe { crate::SetChaperone(area.x, area.y) };
+    }
+
+    fn set_tracking(
+        &self,
+        target_timestamp: Duration,
+        device_motions: &[AlvrDeviceMotion],
+        left_hand: OculusHand,
+        right_hand: OculusHand,
+    ) {
+        unsafe {
+            crate::SetTracking(
+                target_timestamp.as_nanos() as _,
+                device_motions.as_ptr(),
+                device_motions.len() as _,
+                left_hand,
+                right_hand,
+            )
+        };
+    }
+
+    fn report_network_latency(&self, latency: Duration) {
+        unsafe { crate::ReportNetworkLatency(latency.as_micros() as _) };
+    }
+
+    fn set_bitrate_parameters(
+        &self,
+        bitrate_mbs: u64,
+        adaptive_bitrate_enabled: bool,
+        bitrate_maximum: u64,
+    ) {
+        unsafe {
+            crate::SetBitrateParameters(bitrate_mbs, adaptive_bitrate_enabled, bitrate_maximum)
+        };
+    }
+
+    fn request_idr(&self) {
+        unsafe { crate::RequestIDR() };
+    }
+
+    fn report_video_error(&self) {
+        unsafe { crate::VideoErrorReportReceive() };
+    }
+
+    fn set_views_config(&self, config: ViewsConfigData) {
+        unsafe { crate::SetViewsConfig(config) };
+    }
+
+    fn set_battery(&self, device_id: u64, gauge_value: f32, is_plugged: bool) {
+        unsafe { crate::SetBattery(device_id, gauge_value, is_plugged) };
+    }
+
+    fn set_button(&self, path_id: u64, value: AlvrButtonValue) {
+        unsafe { crate::SetButton(path_id, value) };
+    }
+
+    fn set_openvr_property(&self, device_id: u64, prop: OpenvrProperty) {
+        unsafe { crate::SetOpenvrProperty(device_id, prop) };
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use super::*;
+    use alvr_common::parking_lot::Mutex;
+
+    // Records the calls checked by the tests
+    #[derive(Default)]
+    pub struct MockBackend {
+        pub is_streaming: Mutex<bool>,
+        pub tracking_timestamps: Mutex<Vec<Duration>>,
+        pub video_error_reports: Mutex<usize>,
+        pub idr_requests: Mutex<usize>,
+    }
+
+    impl ServerBackend for MockBackend {
+        fn initialize_streaming(&self) {
+            *self.is_streaming.lock() = true;
+        }
+
+        fn deinitialize_streaming(&self) {
+            *self.is_streaming.lock() = false;
+        }
+
+        fn send_vsync(&self, _: Duration) {}
+
+        fn set_chaperone(&self, _: Vec2) {}
+
+        fn set_tracking(
+            &self,
+            target_timestamp: Duration,
+            _: &[AlvrDeviceMotion],
+            _: OculusHand,
+            _: OculusHand,
+        ) {
+            self.tracking_timestamps.lock().push(target_timestamp);
+        }
+
+        fn report_network_latency(&self, _: Duration) {}
+
+        fn set_bitrate_parameters(&self, _: u64, _: bool, _: u64) {}
+
+        fn request_idr(&self) {
+            *self.idr_requests.lock() += 1;
+        }
+
+        fn report_video_error(&self) {
+            *self.video_error_reports.lock() += 1;
+        }
+
+        fn set_views_config(&self, _: ViewsConfigData) {}
+
+        fn set_battery(&self, _: u64, _: f32, _: bool) {}
+
+        fn set_button(&self, _: u64, _: AlvrButtonValue) {}
+
+        fn set_openvr_property(&self, _: u64, _: OpenvrProperty) {}
+    }
+}
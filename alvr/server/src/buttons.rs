@@ -1,5 +1,10 @@
 use alvr_common::{once_cell::sync::Lazy, *};
-use std::collections::HashMap;
+use alvr_events::ButtonValue;
+use alvr_session::{ButtonMappingDesc, InputProfileDesc};
+use std::{collections::HashMap, mem};
+
+// Scalar inputs are considered pressed above this value when used in a chord
+const CHORD_PRESS_THRESHOLD: f32 = 0.5;
 
 pub static BUTTON_PATH_FROM_ID: Lazy<HashMap<u64, String>> = Lazy::new(|| {
     [
@@ -43,3 +48,441 @@ pub static BUTTON_PATH_FROM_ID: Lazy<HashMap<u64, String>> = Lazy::new(|| {
     .into_iter()
     .collect()
 });
+
+fn is_binary_path(path: &str) -> bool {
+    path.ends_with("/click") || path.ends_with("/touch")
+}
+
+fn is_pressed(value: &ButtonValue) -> bool {
+    match value {
+        ButtonValue::Binary(value) => *value,
+        ButtonValue::Scalar(value) => value.abs() >= CHORD_PRESS_THRESHOLD,
+    }
+}
+
+fn released(value: &ButtonValue) -> ButtonValue {
+    match value {
+        ButtonValue::Binary(_) => ButtonValue::Binary(false),
+        ButtonValue::Scalar(_) => ButtonValue::Scalar(0.0),
+    }
+}
+
+struct ButtonMapping {
+    target_id: u64,
+    target_is_binary: bool,
+    binary_threshold: f32,
+    invert: bool,
+    deadzone: f32,
+}
+
+impl ButtonMapping {
+    fn new(config: &ButtonMappingDesc) -> Self {
+        Self {
+            target_id: hash_string(&config.target),
+            target_is_binary: is_binary_path(&config.target),
+            binary_threshold: config.binary_threshold,
+            invert: config.invert,
+            deadzone: config.deadzone.clamp(0.0, 1.0),
+        }
+    }
+
+    fn map(&self, value: &ButtonValue) -> ButtonValue {
+        match *value {
+            ButtonValue::Binary(value) => {
+                let value = value != self.invert;
+                if self.target_is_binary {
+                    ButtonValue::Binary(value)
+                } else {
+                    ButtonValue::Scalar(if value { 1.0 } else { 0.0 })
+                }
+            }
+            ButtonValue::Scalar(value) => {
+                // A deadzone of 1 leaves nothing to rescale
+                let magnitude = if value.abs() <= self.deadzone || self.deadzone >= 1.0 {
+                    0.0
+                } else {
+                    (value.abs() - self.deadzone) / (1.0 - self.deadzone)
+                };
+                let value = magnitude.copysign(value) * if self.invert { -1.0 } else { 1.0 };
+
+                if self.target_is_binary {
+                    ButtonValue::Binary(value >= self.binary_threshold)
+                } else {
+                    ButtonValue::Scalar(value)
+                }
+            }
+        }
+    }
+}
+
+fn map_value(
+    mappings: &HashMap<u64, Vec<ButtonMapping>>,
+    path_id: u64,
+    value: &ButtonValue,
+) -> Vec<(u64, ButtonValue)> {
+    if let Some(mappings) = mappings.get(&path_id) {
+        mappings
+            .iter()
+            .map(|mapping| (mapping.target_id, mapping.map(value)))
+            .collect()
+    } else {
+        vec![(path_id, value.clone())]
+    }
+}
+
+struct ButtonChord {
+    target_id: u64,
+    first_id: u64,
+    second_id: u64,
+    active: bool,
+}
+
+#[derive(Clone, PartialEq, Default)]
+pub struct RemapperConfig {
+    pub profile: Option<InputProfileDesc>,
+    // Legacy overrides of the click of the trigger and the grip, set from their value
+    pub trigger_threshold: Option<f32>,
+    pub grip_threshold: Option<f32>,
+}
+
+// Applies an input profile to the buttons received from the client. The inputs without a mapping
+// are forwarded unchanged.
+#[derive(Default)]
+pub struct ButtonRemapper {
+    config: RemapperConfig,
+    // An empty list drops the input
+    mappings: HashMap<u64, Vec<ButtonMapping>>,
+    chords: Vec<ButtonChord>,
+    // Last value received for each input, before the remapping
+    source_values: HashMap<u64, ButtonValue>,
+}
+
+impl ButtonRemapper {
+    pub fn new(config: RemapperConfig) -> Self {
+        let mut mappings = HashMap::<_, Vec<_>>::new();
+        let mut chords = vec![];
+        if let Some(profile) = &config.profile {
+            for (source, config) in &profile.mappings {
+                mappings
+                    .entry(hash_string(source))
+                    .or_default()
+                    .push(ButtonMapping::new(config));
+            }
+
+            chords = profile
+                .chords
+                .iter()
+                .map(|(target, config)| ButtonChord {
+                    target_id: hash_string(target),
+                    first_id: hash_string(&config.first),
+                    second_id: hash_string(&config.second),
+                    active: false,
+                })
+                .collect();
+        }
+
+        // The click sent by the client is replaced by the value compared to the threshold, unless
+        // the profile remaps these inputs
+        for (threshold, value_path, click_path) in [
+            (
+                config.trigger_threshold,
+                LEFT_TRIGGER_VALUE_PATH,
+                LEFT_TRIGGER_CLICK_PATH,
+            ),
+            (
+                config.trigger_threshold,
+                RIGHT_TRIGGER_VALUE_PATH,
+                RIGHT_TRIGGER_CLICK_PATH,
+            ),
+            (
+                config.grip_threshold,
+                LEFT_SQUEEZE_VALUE_PATH,
+                LEFT_SQUEEZE_CLICK_PATH,
+            ),
+            (
+                config.grip_threshold,
+                RIGHT_SQUEEZE_VALUE_PATH,
+                RIGHT_SQUEEZE_CLICK_PATH,
+            ),
+        ] {
+            let threshold = match threshold {
+                Some(threshold) => threshold,
+                None => continue,
+            };
+            let value_id = hash_string(value_path);
+            let click_id = hash_string(click_path);
+            if mappings.contains_key(&value_id) || mappings.contains_key(&click_id) {
+                continue;
+            }
+
+            let mapping = |target: &str, binary_threshold| {
+                ButtonMapping::new(&ButtonMappingDesc {
+                    target: target.into(),
+                    binary_threshold,
+                    invert: false,
+                    deadzone: 0.0,
+                })
+            };
+            mappings.insert(
+                value_id,
+                vec![mapping(value_path, 0.0), mapping(click_path, threshold)],
+            );
+            mappings.insert(click_id, vec![]);
+        }
+
+        Self {
+            config,
+            mappings,
+            chords,
+            source_values: HashMap::new(),
+        }
+    }
+
+    // The outputs of the previous configuration are released, then the last values of the inputs
+    // are applied to the new one
+    pub fn set_config(&mut self, config: RemapperConfig) -> Vec<(u64, ButtonValue)> {
+        if config == self.config {
+            return vec![];
+        }
+
+        let mut outputs = vec![];
+        for chord in &self.chords {
+            if chord.active {
+                outputs.push((chord.target_id, ButtonValue::Binary(false)));
+            }
+        }
+        for (path_id, value) in &self.source_values {
+            for (target_id, value) in map_value(&self.mappings, *path_id, value) {
+                outputs.push((target_id, released(&value)));
+            }
+        }
+
+        let source_values = mem::take(&mut self.source_values);
+        *self = Self::new(config);
+        for (path_id, value) in source_values {
+            outputs.extend(self.process(path_id, value));
+        }
+
+        outputs
+    }
+
+    pub fn process(&mut self, path_id: u64, value: ButtonValue) -> Vec<(u64, ButtonValue)> {
+        self.source_values.insert(path_id, value.clone());
+
+        let mappings = &self.mappings;
+        let source_values = &self.source_values;
+        let map = |path_id: u64, value: &ButtonValue| map_value(mappings, path_id, value);
+        let pressed = |path_id| source_values.get(&path_id).map_or(false, is_pressed);
+
+        let mut outputs = vec![];
+        let mut suppressed = false;
+        for chord in &mut self.chords {
+            if chord.first_id != path_id && chord.second_id != path_id {
+                continue;
+            }
+
+            let active = pressed(chord.first_id) && pressed(chord.second_id);
+            if active != chord.active {
+                outputs.push((chord.target_id, ButtonValue::Binary(active)));
+
+                for source_id in [chord.first_id, chord.second_id] {
+                    if let Some(source_value) = source_values.get(&source_id) {
+                        if active {
+                            // The buttons of the chord are released while it is held
+                            outputs.extend(map(source_id, &released(source_value)));
+                        } else if source_id != path_id {
+                            // The button still held is restored
+                            outputs.extend(map(source_id, source_value));
+                        }
+                    }
+                }
+
+                chord.active = active;
+            }
+
+            suppressed |= active;
+        }
+
+        if !suppressed {
+            outputs.extend(map(path_id, &value));
+        }
+
+        outputs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_session::ButtonChordDesc;
+
+    fn remapper(profile: InputProfileDesc) -> ButtonRemapper {
+        ButtonRemapper::new(RemapperConfig {
+            profile: Some(profile),
+            ..Default::default()
+        })
+    }
+
+    fn mapping(target: &str, invert: bool, deadzone: f32) -> ButtonMappingDesc {
+        ButtonMappingDesc {
+            target: target.into(),
+            binary_threshold: 0.8,
+            invert,
+            deadzone,
+        }
+    }
+
+    #[test]
+    fn unmapped_buttons_pass_through() {
+        let mut remapper = ButtonRemapper::default();
+
+        assert_eq!(
+            remapper.process(*A_CLICK_ID, ButtonValue::Binary(true)),
+            vec![(*A_CLICK_ID, ButtonValue::Binary(true))]
+        );
+    }
+
+    #[test]
+    fn scalar_to_binary_threshold() {
+        let mut remapper = remapper(InputProfileDesc {
+            mappings: vec![(
+                RIGHT_TRIGGER_VALUE_PATH.into(),
+                mapping(B_CLICK_PATH, false, 0.0),
+            )],
+            chords: vec![],
+        });
+
+        assert_eq!(
+            remapper.process(*RIGHT_TRIGGER_VALUE_ID, ButtonValue::Scalar(0.7)),
+            vec![(*B_CLICK_ID, ButtonValue::Binary(false))]
+        );
+        assert_eq!(
+            remapper.process(*RIGHT_TRIGGER_VALUE_ID, ButtonValue::Scalar(0.9)),
+            vec![(*B_CLICK_ID, ButtonValue::Binary(true))]
+        );
+    }
+
+    #[test]
+    fn axis_inversion_and_deadzone() {
+        let mut remapper = remapper(InputProfileDesc {
+            mappings: vec![(
+                LEFT_THUMBSTICK_Y_PATH.into(),
+                mapping(LEFT_THUMBSTICK_Y_PATH, true, 0.5),
+            )],
+            chords: vec![],
+        });
+
+        assert_eq!(
+            remapper.process(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(0.3)),
+            vec![(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(0.0))]
+        );
+        assert_eq!(
+            remapper.process(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(-0.75)),
+            vec![(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(0.5))]
+        );
+        assert_eq!(
+            remapper.process(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(1.0)),
+            vec![(*LEFT_THUMBSTICK_Y_ID, ButtonValue::Scalar(-1.0))]
+        );
+    }
+
+    #[test]
+    fn chord_replaces_its_buttons() {
+        let mut remapper = remapper(InputProfileDesc {
+            mappings: vec![],
+            chords: vec![(
+                MENU_CLICK_PATH.into(),
+                ButtonChordDesc {
+                    first: A_CLICK_PATH.into(),
+                    second: B_CLICK_PATH.into(),
+                },
+            )],
+        });
+
+        assert_eq!(
+            remapper.process(*A_CLICK_ID, ButtonValue::Binary(true)),
+            vec![(*A_CLICK_ID, ButtonValue::Binary(true))]
+        );
+
+        let outputs = remapper.process(*B_CLICK_ID, ButtonValue::Binary(true));
+        assert!(outputs.contains(&(*MENU_CLICK_ID, ButtonValue::Binary(true))));
+        assert!(outputs.contains(&(*A_CLICK_ID, ButtonValue::Binary(false))));
+        assert!(outputs.contains(&(*B_CLICK_ID, ButtonValue::Binary(false))));
+
+        // Releasing one button ends the chord and restores the other one
+        let outputs = remapper.process(*B_CLICK_ID, ButtonValue::Binary(false));
+        assert!(outputs.contains(&(*MENU_CLICK_ID, ButtonValue::Binary(false))));
+        assert!(outputs.contains(&(*A_CLICK_ID, ButtonValue::Binary(true))));
+        assert!(outputs.contains(&(*B_CLICK_ID, ButtonValue::Binary(false))));
+    }
+
+    #[test]
+    fn full_deadzone_is_zero() {
+        let mut remapper = remapper(InputProfileDesc {
+            mappings: vec![(
+                LEFT_THUMBSTICK_X_PATH.into(),
+                mapping(LEFT_THUMBSTICK_X_PATH, false, 1.0),
+            )],
+            chords: vec![],
+        });
+
+        for value in [0.5, 1.0, -1.0] {
+            assert_eq!(
+                remapper.process(*LEFT_THUMBSTICK_X_ID, ButtonValue::Scalar(value)),
+                vec![(*LEFT_THUMBSTICK_X_ID, ButtonValue::Scalar(0.0))]
+            );
+        }
+    }
+
+    #[test]
+    fn threshold_override_replaces_click() {
+        let mut remapper = ButtonRemapper::new(RemapperConfig {
+            trigger_threshold: Some(0.3),
+            ..Default::default()
+        });
+
+        assert!(remapper
+            .process(*RIGHT_TRIGGER_CLICK_ID, ButtonValue::Binary(true))
+            .is_empty());
+        assert_eq!(
+            remapper.process(*RIGHT_TRIGGER_VALUE_ID, ButtonValue::Scalar(0.4)),
+            vec![
+                (*RIGHT_TRIGGER_VALUE_ID, ButtonValue::Scalar(0.4)),
+                (*RIGHT_TRIGGER_CLICK_ID, ButtonValue::Binary(true))
+            ]
+        );
+        // Other inputs are not affected
+        assert_eq!(
+            remapper.process(*LEFT_SQUEEZE_CLICK_ID, ButtonValue::Binary(true)),
+            vec![(*LEFT_SQUEEZE_CLICK_ID, ButtonValue::Binary(true))]
+        );
+    }
+
+    #[test]
+    fn switching_profile_releases_outputs() {
+        let profile = InputProfileDesc {
+            mappings: vec![(A_CLICK_PATH.into(), mapping(B_CLICK_PATH, false, 0.0))],
+            chords: vec![],
+        };
+        let mut remapper = remapper(profile.clone());
+
+        remapper.process(*A_CLICK_ID, ButtonValue::Binary(true));
+
+        // Same configuration, nothing to do
+        assert!(remapper
+            .set_config(RemapperConfig {
+                profile: Some(profile),
+                ..Default::default()
+            })
+            .is_empty());
+
+        // The held button is forwarded unchanged by the new configuration
+        assert_eq!(
+            remapper.set_config(RemapperConfig::default()),
+            vec![
+                (*B_CLICK_ID, ButtonValue::Binary(false)),
+                (*A_CLICK_ID, ButtonValue::Binary(true))
+            ]
+        );
+    }
+}
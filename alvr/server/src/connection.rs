@@ -1,33 +1,48 @@
 use crate::{
-    buttons::BUTTON_PATH_FROM_ID, sockets::WelcomeSocket, statistics::StatisticsManager,
-    tracking::TrackingManager, AlvrButtonType_BUTTON_TYPE_BINARY,
-    AlvrButtonType_BUTTON_TYPE_SCALAR, AlvrButtonValue, AlvrButtonValue__bindgen_ty_1,
-    AlvrDeviceMotion, AlvrQuat, EyeFov, OculusHand, VideoPacket, CONTROL_CHANNEL_SENDER,
-    DISCONNECT_CLIENT_NOTIFIER, HAPTICS_SENDER, IS_ALIVE, RESTART_NOTIFIER, SERVER_DATA_MANAGER,
-    STATISTICS_MANAGER, VIDEO_SENDER,
+    backend::{CppBackend, ServerBackend},
+    buttons::{ButtonRemapper, RemapperConfig, BUTTON_PATH_FROM_ID},
+    gestures::GestureRecognizer,
+    hand_skeleton::{to_steamvr_bones, BoneTransform, STEAMVR_HAND_BONE_COUNT},
+    sockets::WelcomeSocket,
+    statistics::StatisticsManager,
+    tracking::{hand_skeleton_wrist, TrackingManager},
+    video_recording::VideoRecorder,
+    AlvrBoneTransform, AlvrButtonType_BUTTON_TYPE_BINARY, AlvrButtonType_BUTTON_TYPE_SCALAR,
+    AlvrButtonValue, AlvrButtonValue__bindgen_ty_1, AlvrDeviceMotion, AlvrQuat, ClientRole,
+    ClientSession, EyeFov, OculusHand, VideoPacket, CLIENT_SESSIONS, DECODER_CONFIG,
+    DISCONNECT_CLIENT_NOTIFIER, FILESYSTEM_LAYOUT, IS_ALIVE, PAIRING_CLIENTS,
+    PLAYSPACE_RECENTERING, RESTART_NOTIFIER, RUNNING_GAME, SERVER_DATA_MANAGER,
 };
 use alvr_audio::{AudioDevice, AudioDeviceType};
 use alvr_common::{
+    companion_device_path,
     glam::{Quat, UVec2, Vec2},
-    once_cell::sync::Lazy,
-    parking_lot,
+    hash_string, is_companion_device_path, parking_lot,
     prelude::*,
-    RelaxedAtomic, HEAD_ID,
+    RelaxedAtomic, HEAD_ID, LEFT_HAND_ID, LEFT_HAND_PATH, RIGHT_HAND_ID, RIGHT_HAND_PATH,
+};
+use alvr_events::{ButtonEvent, ButtonValue, EventType, PairingCodeEvent};
+use alvr_server_data::ServerDataManager;
+use alvr_session::{
+    CodecType, CodecTypeDefaultVariant, ConnectionDesc, ControllersDesc, FrameSize,
+    GenericTrackerConfig, OpenvrConfig, Settings, VideoRecordingDesc,
 };
-use alvr_events::{ButtonEvent, ButtonValue, EventType};
-use alvr_session::{CodecType, FrameSize, OpenvrConfig};
 use alvr_sockets::{
-    spawn_cancelable, ClientConnectionResult, ClientControlPacket, ClientListAction,
-    ClientStatistics, ControlSocketReceiver, ControlSocketSender, PeerType, ProtoControlSocket,
-    ServerControlPacket, StreamConfigPacket, StreamSocketBuilder, Tracking, AUDIO, HAPTICS,
+    features, merge_legacy_hand_skeletons, spawn_cancelable, ClientConnectionResult,
+    ClientControlPacket, ClientListAction, ClientStatistics, ControlSocketReceiver,
+    ControlSocketSender, DeviceMotion, HandSkeletons, NegotiatedProtocol, PairingRequest,
+    PairingResponse, PeerType, ProtoControlSocket, ProtocolHello, RecordedEvent,
+    ServerControlPacket, StreamConfigPacket, StreamSocketConnector, TlsIdentity, Tracking,
+    TrackingRecorder, VideoFrameHeaderPacket, AUDIO, CONTROL_PORT, HAND_SKELETONS, HAPTICS,
     KEEPALIVE_INTERVAL, STATISTICS, TRACKING, VIDEO,
 };
 use futures::future::BoxFuture;
 use settings_schema::Switch;
 use std::{
-    collections::{HashMap, HashSet},
-    future,
+    collections::{HashMap, VecDeque},
+    fs, future,
     net::IpAddr,
+    path::PathBuf,
     process::Command,
     sync::{mpsc as smpsc, Arc},
     thread,
@@ -40,11 +55,10 @@ use tokio::{
 };
 
 const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
-
-static CONNECTED_CLIENT_HOSTNAMES: Lazy<parking_lot::Mutex<HashSet<String>>> =
-    Lazy::new(|| parking_lot::Mutex::new(HashSet::new()));
-static STREAMING_CLIENT_HOSTNAME: Lazy<parking_lot::Mutex<Option<String>>> =
-    Lazy::new(|| parking_lot::Mutex::new(None));
+const PAIRING_TIMEOUT: Duration = Duration::from_secs(60);
+// Hand skeletons waiting for the tracking packet with the same target timestamp
+const MAX_PENDING_HAND_SKELETONS: usize = 8;
+const SPECTATOR_IDR_MIN_INTERVAL: Duration = Duration::from_secs(2);
 
 fn align32(value: f32) -> u32 {
     ((value / 32.).floor() * 32.) as u32
@@ -54,6 +68,174 @@ fn mbits_to_bytes(value: u64) -> u32 {
     (value * 1024 * 1024 / 8) as u32
 }
 
+type SharedRecorder = Arc<parking_lot::Mutex<Option<TrackingRecorder>>>;
+
+// The event is built only if the recording is enabled
+fn record_event(recorder: &SharedRecorder, event: impl FnOnce() -> RecordedEvent) {
+    if let Some(recorder) = &mut *recorder.lock() {
+        recorder.record(event()).ok();
+    }
+}
+
+type SharedVideoRecorder = Arc<parking_lot::Mutex<Option<VideoRecorder>>>;
+
+// The recording is saved in the log directory if no directory is set. The file is named after the
+// time of the connection
+fn new_video_recorder(
+    data_manager: &parking_lot::RwLock<ServerDataManager>,
+    desc: &VideoRecordingDesc,
+    game_audio_sample_rate: Option<u32>,
+) -> StrResult<VideoRecorder> {
+    let directory = if desc.output_directory.is_empty() {
+        FILESYSTEM_LAYOUT.log_dir.clone()
+    } else {
+        PathBuf::from(&desc.output_directory)
+    };
+    fs::create_dir_all(&directory).map_err(err!())?;
+    let path = directory.join(
+        chrono::Local::now()
+            .format("recording_%Y-%m-%d_%H-%M-%S.mkv")
+            .to_string(),
+    );
+
+    // The encoded frame contains both views side by side
+    let (codec, frame_size) = {
+        let config = &data_manager.read().session().openvr_config;
+        (
+            codec_from_config(config.codec),
+            UVec2::new(
+                config.eye_resolution_width * 2,
+                config.eye_resolution_height,
+            ),
+        )
+    };
+    let audio = game_audio_sample_rate
+        .filter(|_| desc.include_game_audio)
+        .map(|sample_rate| (sample_rate, 2));
+
+    info!("Recording the video stream to {}", path.display());
+
+    VideoRecorder::new(&path, codec, frame_size, audio)
+}
+
+// The spectators can only ask for a keyframe once in a while, the primary client is never limited
+fn spectator_idr_allowed(last_request: &mut Option<Instant>, now: Instant) -> bool {
+    let allowed = last_request.map_or(true, |last| {
+        now.saturating_duration_since(last) >= SPECTATOR_IDR_MIN_INTERVAL
+    });
+    if allowed {
+        *last_request = Some(now);
+    }
+
+    allowed
+}
+
+// The game audio is captured once, by the primary client connection, and shared with the others
+fn send_game_audio(data: &[u8]) {
+    for session in CLIENT_SESSIONS.lock().values() {
+        if let Some(sender) = &session.audio_sender {
+            sender.send(data.to_vec()).ok();
+        }
+    }
+}
+
+// The recording is stopped at the first error
+fn record_video_packet(
+    recorder: &SharedVideoRecorder,
+    header: &VideoFrameHeaderPacket,
+    payload: &[u8],
+) {
+    let mut recorder_lock = recorder.lock();
+    if let Some(recorder) = &mut *recorder_lock {
+        // The tracking frame index is the target timestamp of the frame, in nanoseconds
+        if let Err(e) = recorder.record_video(
+            Duration::from_nanos(header.tracking_frame_index),
+            payload,
+            DECODER_CONFIG.lock().as_deref(),
+        ) {
+            warn!("Video recording stopped: {e}");
+            *recorder_lock = None;
+        }
+    }
+}
+
+// The remapper is rebuilt only when the settings or the running game change
+#[derive(Default)]
+struct RemapperCache {
+    settings_revision: Option<u64>,
+    running_game: Option<String>,
+    remapper: ButtonRemapper,
+}
+
+type SharedRemapper = Arc<parking_lot::Mutex<RemapperCache>>;
+
+fn new_remapper() -> SharedRemapper {
+    Arc::new(parking_lot::Mutex::new(RemapperCache::default()))
+}
+
+// The profile of the running game has priority over the active profile
+fn remapper_config(settings: &Settings, running_game: Option<&str>) -> RemapperConfig {
+    let config = if let Switch::Enabled(config) = &settings.headset.controllers {
+        config
+    } else {
+        return RemapperConfig::default();
+    };
+
+    let profile_name = running_game
+        .and_then(|game| {
+            config
+                .game_input_profiles
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(game))
+        })
+        .map(|(_, profile_name)| profile_name)
+        .unwrap_or(&config.active_input_profile);
+
+    RemapperConfig {
+        profile: config
+            .input_profiles
+            .iter()
+            .find(|(name, _)| name == profile_name)
+            .map(|(_, profile)| profile.clone()),
+        trigger_threshold: match &config.override_trigger_threshold {
+            Switch::Enabled(config) => Some(config.trigger_threshold),
+            Switch::Disabled => None,
+        },
+        grip_threshold: match &config.override_grip_threshold {
+            Switch::Enabled(config) => Some(config.grip_threshold),
+            Switch::Disabled => None,
+        },
+    }
+}
+
+// When the profile is switched, the outputs of the previous one are released first
+fn remap_button(
+    data_manager: &parking_lot::RwLock<ServerDataManager>,
+    remapper: &SharedRemapper,
+    path_id: u64,
+    value: ButtonValue,
+) -> Vec<(u64, ButtonValue)> {
+    let mut cache = remapper.lock();
+
+    let mut outputs = vec![];
+    {
+        let data_manager = data_manager.read();
+        let settings_revision = Some(data_manager.settings_revision());
+        let running_game = RUNNING_GAME.lock().clone();
+        if cache.settings_revision != settings_revision || cache.running_game != running_game {
+            let config = remapper_config(data_manager.settings(), running_game.as_deref());
+            outputs = cache.remapper.set_config(config);
+
+            cache.settings_revision = settings_revision;
+            cache.running_game = running_game;
+        }
+    }
+
+    outputs.extend(cache.remapper.process(path_id, value));
+
+    outputs
+}
+
 // Alternate connection trials with manual IPs and clients discovered on the local network
 pub fn handshake_loop() -> IntResult {
     let mut welcome_socket = WelcomeSocket::new().map_err(to_int_e!())?;
@@ -63,6 +245,9 @@ pub fn handshake_loop() -> IntResult {
 
         let mut manual_client_ips = HashMap::new();
         for (hostname, connection_info) in SERVER_DATA_MANAGER.read().client_list() {
+            if !connection_info.trusted || PAIRING_CLIENTS.lock().contains(hostname) {
+                continue;
+            }
             for ip in &connection_info.manual_ips {
                 manual_client_ips.insert(*ip, hostname.clone());
             }
@@ -79,7 +264,7 @@ pub fn handshake_loop() -> IntResult {
             .connection
             .client_discovery
             .clone();
-        if let Switch::Enabled(config) = discovery_config {
+        if let Switch::Enabled(discovery_config) = discovery_config {
             let (client_hostname, client_ip) = match welcome_socket.recv_non_blocking() {
                 Ok(pair) => pair,
                 Err(e) => {
@@ -91,26 +276,25 @@ pub fn handshake_loop() -> IntResult {
                 }
             };
 
-            let trusted = {
+            // New clients are connected to only once the user asks to pair them, or right away
+            // if they are trusted on first use
+            let can_connect = {
                 let mut data_manager = SERVER_DATA_MANAGER.write();
 
                 data_manager
                     .update_client_list(client_hostname.clone(), ClientListAction::AddIfMissing);
 
-                if config.auto_trust_clients {
-                    data_manager
-                        .update_client_list(client_hostname.clone(), ClientListAction::Trust);
-                }
+                let client = data_manager.client_list().get(&client_hostname).unwrap();
 
-                data_manager
-                    .client_list()
-                    .get(&client_hostname)
-                    .unwrap()
-                    .trusted
+                client.trusted
+                    || (discovery_config.auto_trust_clients && client.certificate.is_none())
             };
 
-            // do not attempt connection if the client is already connected
-            if trusted && !CONNECTED_CLIENT_HOSTNAMES.lock().contains(&client_hostname) {
+            // do not attempt connection if the client is already connected or being paired
+            if can_connect
+                && !CLIENT_SESSIONS.lock().contains_key(&client_hostname)
+                && !PAIRING_CLIENTS.lock().contains(&client_hostname)
+            {
                 match try_connect([(client_ip, client_hostname.clone())].into_iter().collect()) {
                     Ok(()) => continue,
                     // use error!(): usually errors should not happen here
@@ -123,12 +307,185 @@ pub fn handshake_loop() -> IntResult {
     }
 }
 
+fn pin_certificate(client_hostname: &str, certificate: Vec<u8>) {
+    let mut data_manager = SERVER_DATA_MANAGER.write();
+    data_manager.update_client_list(
+        client_hostname.to_owned(),
+        ClientListAction::SetCertificate(certificate),
+    );
+    data_manager.update_client_list(client_hostname.to_owned(), ClientListAction::Trust);
+}
+
+// The identity is generated the first time a client connects. Regenerating it would break the
+// pairing with all clients.
+fn server_identity() -> StrResult<TlsIdentity> {
+    let path = FILESYSTEM_LAYOUT.tls_identity();
+    if let Ok(identity_json) = fs::read_to_string(&path) {
+        return serde_json::from_str(&identity_json).map_err(err!());
+    }
+
+    let identity = TlsIdentity::generate("server.alvr")?;
+    fs::write(&path, serde_json::to_string(&identity).map_err(err!())?).map_err(err!())?;
+
+    Ok(identity)
+}
+
+// Wait on a separate thread for the user to confirm on the headset that the pairing code is the one
+// shown on the dashboard, then pin the certificate of the client. The client pins the certificate
+// of the server when confirming. Once paired, the client is connected to again by the handshake
+// loop.
+fn spawn_pairing(runtime: Runtime, mut proto_socket: ProtoControlSocket, client_hostname: String) {
+    PAIRING_CLIENTS.lock().insert(client_hostname.clone());
+    SERVER_DATA_MANAGER
+        .write()
+        .update_client_list(client_hostname.clone(), ClientListAction::RevokeTrust);
+    alvr_events::send_event(EventType::PairingCode(PairingCodeEvent {
+        hostname: client_hostname.clone(),
+        code: Some(proto_socket.pairing_code().to_owned()),
+    }));
+
+    thread::spawn(move || {
+        let certificate = proto_socket.peer_certificate().to_vec();
+
+        let res = runtime.block_on(async {
+            proto_socket.send(&PairingRequest::ConfirmCode).await?;
+
+            let shutdown_detector = async {
+                while IS_ALIVE.value() {
+                    time::sleep(Duration::from_secs(1)).await;
+                }
+            };
+
+            tokio::select! {
+                res = proto_socket.recv::<PairingResponse>() => res,
+                _ = time::sleep(PAIRING_TIMEOUT) => fmt_e!("Timeout"),
+                _ = shutdown_detector => fmt_e!("Server shutting down"),
+            }
+        });
+
+        PAIRING_CLIENTS.lock().remove(&client_hostname);
+        alvr_events::send_event(EventType::PairingCode(PairingCodeEvent {
+            hostname: client_hostname.clone(),
+            code: None,
+        }));
+
+        match res {
+            Ok(PairingResponse::Confirmed) => pin_certificate(&client_hostname, certificate),
+            Ok(PairingResponse::Rejected) => {
+                warn!("Pairing with client {client_hostname} was rejected on the headset")
+            }
+            Err(e) => warn!("Pairing with client {client_hostname} was not confirmed: {e}"),
+        }
+    });
+}
+
+// Features supported by the server. The ones also supported by the client are used
+fn protocol_hello() -> ProtocolHello {
+    let mut supported = features::COMMON.to_vec();
+    // The NVENC SDK used on Windows has no AV1 support
+    if !cfg!(windows) {
+        supported.push(features::CODEC_AV1);
+    }
+
+    ProtocolHello::new(&supported)
+}
+
+fn codec_feature(codec: CodecType) -> &'static str {
+    match codec {
+        CodecType::H264 => features::CODEC_H264,
+        CodecType::HEVC => features::CODEC_HEVC,
+        CodecType::AV1 => features::CODEC_AV1,
+    }
+}
+
+// Inverse of the `codec` field of OpenvrConfig
+pub fn codec_from_config(codec: u32) -> CodecType {
+    match codec {
+        1 => CodecType::HEVC,
+        2 => CodecType::AV1,
+        _ => CodecType::H264,
+    }
+}
+
+// The first streaming client drives the stream. The next ones join as spectators. Companion
+// clients do not stream video. All clients other than the primary use their own stream port.
+fn client_role(
+    sessions: &HashMap<String, ClientSession>,
+    config: &ConnectionDesc,
+    is_companion: bool,
+) -> StrResult<(ClientRole, u16)> {
+    let count = |role| {
+        sessions
+            .values()
+            .filter(|session| session.role == role)
+            .count()
+    };
+    let free_stream_port = || {
+        (config.stream_port..=u16::MAX)
+            .skip(1)
+            .find(|port| {
+                sessions
+                    .values()
+                    .all(|session| session.stream_port != *port)
+            })
+            .ok_or_else(|| "no free stream port".to_owned())
+    };
+
+    if is_companion {
+        if count(ClientRole::Companion) < config.max_companions as usize {
+            Ok((ClientRole::Companion, free_stream_port()?))
+        } else {
+            fmt_e!("too many companions")
+        }
+    } else if count(ClientRole::Primary) == 0 {
+        Ok((ClientRole::Primary, config.stream_port))
+    } else if count(ClientRole::Spectator) < config.max_spectators as usize {
+        Ok((ClientRole::Spectator, free_stream_port()?))
+    } else {
+        fmt_e!("too many spectators")
+    }
+}
+
+// SteamVR creates the tracker devices only when the driver starts, so the hands of a companion are
+// added to the OpenVR config the first time it connects. Returns false if they were already there.
+fn register_companion_trackers(
+    generic_trackers: &mut Vec<GenericTrackerConfig>,
+    hostname: &str,
+) -> bool {
+    let mut changed = false;
+    for (device_path, side) in [(LEFT_HAND_PATH, "Left"), (RIGHT_HAND_PATH, "Right")] {
+        let path = companion_device_path(hostname, device_path);
+        if generic_trackers.iter().any(|tracker| tracker.path == path) {
+            continue;
+        }
+
+        let serial_number = format!("ALVR_Companion_{hostname}_{side}");
+        generic_trackers.push(GenericTrackerConfig {
+            path,
+            registered_device_type: format!("htc/{serial_number}"),
+            serial_number,
+            tracking_system_name: "oculus".into(),
+            manufacturer_name: "HTC".into(),
+            model_number: "Vive Tracker Pro MV".into(),
+            render_model_name: "{htc}vr_tracker_vive_1_0".into(),
+            controller_type: "vive_tracker_handed".into(),
+            input_profile_path: "{htc}/input/vive_tracker_profile.json".into(),
+        });
+        changed = true;
+    }
+
+    changed
+}
+
 fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
     let runtime = Runtime::new().map_err(to_int_e!())?;
 
+    let identity = server_identity().map_err(to_int_e!())?;
     let (mut proto_socket, client_ip) = runtime
         .block_on(ProtoControlSocket::connect_to(PeerType::AnyClient(
             client_ips.keys().cloned().collect(),
+            CONTROL_PORT,
+            &identity,
         )))
         .map_err(to_int_e!())?;
 
@@ -140,6 +497,67 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         ClientListAction::UpdateCurrentIp(Some(client_ip)),
     );
 
+    let protocol = match runtime.block_on(proto_socket.negotiate_protocol(&protocol_hello())) {
+        Ok(protocol) => protocol,
+        Err(e) => {
+            alvr_events::send_event(EventType::ClientFoundWrongVersion(format!(
+                "{client_hostname}: {e}"
+            )));
+            return int_fmt_e!("Protocol negotiation with {client_hostname} failed: {e}");
+        }
+    };
+
+    let certificate = proto_socket.peer_certificate().to_vec();
+    let (maybe_pinned_certificate, trusted) = {
+        let data_manager = SERVER_DATA_MANAGER.read();
+        let client = data_manager
+            .client_list()
+            .get(&client_hostname)
+            .ok_or_else(enone!())
+            .map_err(to_int_e!())?;
+
+        (client.certificate.clone(), client.trusted)
+    };
+
+    // A different certificate means that the client was reinstalled or that another device is
+    // impersonating it, in which case the user must remove the client and pair it again.
+    let pairing_request = match maybe_pinned_certificate {
+        Some(pinned_certificate) if pinned_certificate != certificate => {
+            return int_fmt_e!("Certificate of client {client_hostname} has changed!");
+        }
+        Some(_) if !trusted => {
+            return int_fmt_e!("Client {client_hostname} is not trusted");
+        }
+        Some(_) => PairingRequest::Paired,
+        None => {
+            let auto_trust = matches!(
+                &SERVER_DATA_MANAGER.read().settings().connection.client_discovery,
+                Switch::Enabled(config) if config.auto_trust_clients
+            );
+            if !auto_trust {
+                spawn_pairing(runtime, proto_socket, client_hostname);
+
+                return Ok(());
+            }
+
+            PairingRequest::TrustOnFirstUse
+        }
+    };
+    let trust_on_first_use = matches!(pairing_request, PairingRequest::TrustOnFirstUse);
+    runtime
+        .block_on(proto_socket.send(&pairing_request))
+        .map_err(to_int_e!())?;
+
+    if trust_on_first_use {
+        match runtime.block_on(proto_socket.recv()).map_err(to_int_e!())? {
+            PairingResponse::Confirmed => pin_certificate(&client_hostname, certificate),
+            PairingResponse::Rejected => {
+                debug!("Client {client_hostname} did not accept the pairing. Retrying");
+                return Ok(());
+            }
+        }
+    }
+
     let maybe_streaming_caps = if let ClientConnectionResult::ConnectionAccepted {
         display_name,
         streaming_capabilities,
@@ -157,17 +575,78 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         return Ok(());
     };
 
+    let settings = SERVER_DATA_MANAGER.read().settings().clone();
+
+    let (role, stream_port) = match client_role(
+        &CLIENT_SESSIONS.lock(),
+        &settings.connection,
+        maybe_streaming_caps.is_none(),
+    ) {
+        Ok(res) => res,
+        Err(e) => return int_fmt_e!("Cannot connect client {client_hostname}: {e}"),
+    };
+
     let streaming_caps = if let Some(streaming_caps) = maybe_streaming_caps {
-        if let Some(hostname) = &*STREAMING_CLIENT_HOSTNAME.lock() {
-            return int_fmt_e!("Streaming client {hostname} is already connected!");
-        } else {
-            streaming_caps
-        }
+        streaming_caps
     } else {
-        return int_fmt_e!("Only streaming clients are supported for now");
+        let client_config = StreamConfigPacket {
+            session_desc: client_session_desc(stream_port, settings.video.codec)?,
+            view_resolution: UVec2::ZERO,
+            fps: 0.0,
+            game_audio_sample_rate: 0,
+        };
+        runtime
+            .block_on(proto_socket.send(&client_config))
+            .map_err(to_int_e!())?;
+
+        let stream_socket_connector =
+            bind_stream_socket(&runtime, &client_hostname, stream_port, &settings)?;
+        let (control_sender, control_receiver) = proto_socket.split();
+
+        let params = ConnectionParams {
+            protocol,
+            client_hostname: client_hostname.clone(),
+            client_ip,
+            role,
+            stream_port,
+            microphone_sample_rate: 0,
+            game_audio_sample_rate: 0,
+            refresh_rate: 0.0,
+        };
+        spawn_client_pipeline(runtime, client_hostname, role, stream_port, move || {
+            companion_pipeline(
+                Arc::new(CppBackend),
+                &SERVER_DATA_MANAGER,
+                params,
+                stream_socket_connector,
+                control_sender,
+                control_receiver,
+            )
+        });
+
+        return Ok(());
     };
 
-    let settings = SERVER_DATA_MANAGER.read().settings().clone();
+    // Spectators receive the stream of the primary client, which is not reconfigured
+    let codec = if role == ClientRole::Spectator {
+        codec_from_config(SERVER_DATA_MANAGER.read().session().openvr_config.codec)
+    } else if matches!(settings.video.codec, CodecType::AV1)
+        && !protocol.supports(features::CODEC_AV1)
+    {
+        let fallback = if protocol.supports(features::CODEC_HEVC) {
+            CodecType::HEVC
+        } else {
+            CodecType::H264
+        };
+        warn!("AV1 is not available with client {client_hostname}. Using {fallback:?}");
+
+        fallback
+    } else {
+        settings.video.codec
+    };
+    if !protocol.supports(codec_feature(codec)) {
+        return int_fmt_e!("Client {client_hostname} does not support the {codec:?} codec");
+    }
 
     let stream_view_resolution = match settings.video.render_resolution {
         FrameSize::Scale(scale) => streaming_caps.default_view_resolution.as_vec2() * scale,
@@ -234,11 +713,23 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         0
     };
 
+    // Spectators receive the stream of the primary client, which is not reconfigured
+    let (stream_view_resolution, fps) = if role == ClientRole::Spectator {
+        let openvr_config = &SERVER_DATA_MANAGER.read().session().openvr_config;
+
+        (
+            UVec2::new(
+                openvr_config.eye_resolution_width,
+                openvr_config.eye_resolution_height,
+            ),
+            openvr_config.refresh_rate as f32,
+        )
+    } else {
+        (stream_view_resolution, fps)
+    };
+
     let client_config = StreamConfigPacket {
-        session_desc: {
-            let session = SERVER_DATA_MANAGER.read().session().clone();
-            serde_json::to_string(&session).map_err(to_int_e!())?
-        },
+        session_desc: client_session_desc(stream_port, codec)?,
         view_resolution: stream_view_resolution,
         fps,
         game_audio_sample_rate,
@@ -292,14 +783,6 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
     let mut controllers_type_right = "".into();
     let mut controllers_registered_device_type = "".into();
     let mut controllers_input_profile_path = "".into();
-    let mut linear_velocity_cutoff = 0.0;
-    let mut angular_velocity_cutoff = 0.0;
-    let mut position_offset_left = [0.0; 3];
-    let mut rotation_offset_left = [0.0; 3];
-    let mut override_trigger_threshold = false;
-    let mut trigger_threshold = 0.0;
-    let mut override_grip_threshold = false;
-    let mut grip_threshold = 0.0;
     let mut haptics_intensity = 0.0;
     let mut haptics_amplitude_curve = 0.0;
     let mut haptics_min_duration = 0.0;
@@ -318,23 +801,6 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         controllers_type_right = config.ctrl_type_right.clone();
         controllers_registered_device_type = config.registered_device_type.clone();
         controllers_input_profile_path = config.input_profile_path.clone();
-        linear_velocity_cutoff = config.linear_velocity_cutoff;
-        angular_velocity_cutoff = config.angular_velocity_cutoff;
-        position_offset_left = config.position_offset_left;
-        rotation_offset_left = config.rotation_offset_left;
-        override_trigger_threshold =
-            if let Switch::Enabled(config) = config.override_trigger_threshold {
-                trigger_threshold = config.trigger_threshold;
-                true
-            } else {
-                false
-            };
-        override_grip_threshold = if let Switch::Enabled(config) = config.override_grip_threshold {
-            grip_threshold = config.grip_threshold;
-            true
-        } else {
-            false
-        };
         haptics_intensity = config.haptics_intensity;
         haptics_amplitude_curve = config.haptics_amplitude_curve;
         haptics_min_duration = config.haptics_min_duration;
@@ -383,6 +849,41 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         false
     };
 
+    let mut generic_trackers = if let Switch::Enabled(trackers) = settings.headset.trackers {
+        trackers
+            .into_iter()
+            .map(|(path, config)| GenericTrackerConfig {
+                path,
+                serial_number: config.serial_number,
+                tracking_system_name: config.tracking_system_name,
+                manufacturer_name: config.manufacturer_name,
+                model_number: config.model_number,
+                render_model_name: config.render_model_name,
+                registered_device_type: config.registered_device_type,
+                controller_type: config.controller_type,
+                input_profile_path: config.input_profile_path,
+            })
+            .collect::<Vec<_>>()
+    } else {
+        vec![]
+    };
+    // The trackers of the companions registered so far are kept
+    let companion_trackers = SERVER_DATA_MANAGER
+        .read()
+        .session()
+        .openvr_config
+        .generic_trackers
+        .iter()
+        .filter(|tracker| {
+            is_companion_device_path(&tracker.path)
+                && generic_trackers
+                    .iter()
+                    .all(|other| other.path != tracker.path)
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+    generic_trackers.extend(companion_trackers);
+
     let nvenc_overrides = settings.video.advanced_codec_options.nvenc_overrides;
     let amf_controls = settings.video.advanced_codec_options.amf_controls;
 
@@ -405,7 +906,7 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         enable_vive_tracker_proxy: settings.headset.enable_vive_tracker_proxy,
         aggressive_keyframe_resend: settings.connection.aggressive_keyframe_resend,
         adapter_index: settings.video.adapter_index,
-        codec: matches!(settings.video.codec, CodecType::HEVC) as _,
+        codec: codec as _,
         rate_control_mode: settings.video.rate_control_mode as u32,
         refresh_rate: fps as _,
         use_10bit_encoder: settings.video.use_10bit_encoder,
@@ -415,6 +916,7 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         encoder_quality_preset: amf_controls.encoder_quality_preset as u32,
         force_sw_encoding: settings.video.force_sw_encoding,
         sw_thread_count: settings.video.sw_thread_count,
+        use_openh264: settings.video.use_openh264 && matches!(codec, CodecType::H264),
         encode_bitrate_mbs: settings.video.encode_bitrate_mbs,
         enable_adaptive_bitrate,
         bitrate_maximum,
@@ -426,7 +928,6 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         bitrate_up_rate,
         bitrate_down_rate,
         bitrate_light_load_threshold,
-        position_offset: settings.headset.position_offset,
         controllers_enabled,
         controllers_mode_idx,
         controllers_tracking_system_name,
@@ -439,14 +940,6 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         controllers_type_right,
         controllers_registered_device_type,
         controllers_input_profile_path,
-        linear_velocity_cutoff,
-        angular_velocity_cutoff,
-        position_offset_left,
-        rotation_offset_left,
-        override_trigger_threshold,
-        trigger_threshold,
-        override_grip_threshold,
-        grip_threshold,
         haptics_intensity,
         haptics_amplitude_curve,
         haptics_min_duration,
@@ -466,7 +959,6 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         saturation,
         gamma,
         sharpening,
-        enable_fec: settings.connection.enable_fec,
         linux_async_reprojection: settings.extra.patches.linux_async_reprojection,
         nvenc_preset: nvenc_overrides.preset as i64,
         nvenc_refresh_rate: nvenc_overrides.refresh_rate,
@@ -483,10 +975,23 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         rc_average_bitrate: nvenc_overrides.rc_average_bitrate,
         enable_aq: nvenc_overrides.enable_aq,
         capture_frame_dir: settings.extra.capture_frame_dir,
+        generic_trackers,
     };
 
-    if SERVER_DATA_MANAGER.read().session().openvr_config != new_openvr_config {
-        SERVER_DATA_MANAGER.write().session_mut().openvr_config = new_openvr_config;
+    let old_openvr_config = SERVER_DATA_MANAGER.read().session().openvr_config.clone();
+    let restart_openvr_config = match role {
+        ClientRole::Primary => {
+            (old_openvr_config != new_openvr_config).then_some(new_openvr_config)
+        }
+        ClientRole::Companion => {
+            let mut openvr_config = old_openvr_config;
+            register_companion_trackers(&mut openvr_config.generic_trackers, &client_hostname)
+                .then_some(openvr_config)
+        }
+        ClientRole::Spectator => None,
+    };
+    if let Some(openvr_config) = restart_openvr_config {
+        SERVER_DATA_MANAGER.write().session_mut().openvr_config = openvr_config;
 
         runtime
             .block_on(control_sender.send(&ServerControlPacket::Restarting))
@@ -495,65 +1000,237 @@ fn try_connect(mut client_ips: HashMap<IpAddr, String>) -> IntResult {
         crate::notify_restart_driver();
     }
 
-    CONNECTED_CLIENT_HOSTNAMES
-        .lock()
-        .insert(client_hostname.clone());
+    let stream_socket_connector =
+        bind_stream_socket(&runtime, &client_hostname, stream_port, &settings)?;
 
-    *STREAMING_CLIENT_HOSTNAME.lock() = Some(client_hostname.clone());
+    let params = ConnectionParams {
+        protocol,
+        client_hostname: client_hostname.clone(),
+        client_ip,
+        role,
+        stream_port,
+        microphone_sample_rate: streaming_caps.microphone_sample_rate,
+        game_audio_sample_rate,
+        refresh_rate: fps,
+    };
+    spawn_client_pipeline(runtime, client_hostname, role, stream_port, move || {
+        connection_pipeline(
+            Arc::new(CppBackend),
+            &SERVER_DATA_MANAGER,
+            params,
+            stream_socket_connector,
+            control_sender,
+            control_receiver,
+        )
+    });
 
-    thread::spawn(move || {
-        runtime.block_on({
-            let client_hostname = client_hostname.clone();
-            async move {
-                // this is a bridge between sync and async, skips the needs for a notifier
-                let shutdown_detector = async {
-                    while IS_ALIVE.value() {
-                        time::sleep(Duration::from_secs(1)).await;
-                    }
-                };
+    Ok(())
+}
 
-                tokio::select! {
-                    res = connection_pipeline(
-                        client_hostname,
-                        client_ip,
-                        control_sender,
-                        control_receiver,
-                        streaming_caps.microphone_sample_rate,
-                        fps,
-                    ) => {
-                        show_warn(res);
-                    },
-                    _ = DISCONNECT_CLIENT_NOTIFIER.notified() => (),
-                    _ = shutdown_detector => (),
-                };
-            }
-        });
+// The socket is bound before the client is told to start the stream, so that the client can
+// connect as soon as it is ready
+fn bind_stream_socket(
+    runtime: &Runtime,
+    client_hostname: &str,
+    stream_port: u16,
+    settings: &Settings,
+) -> IntResult<StreamSocketConnector> {
+    let client_certificate = SERVER_DATA_MANAGER
+        .read()
+        .client_list()
+        .get(client_hostname)
+        .and_then(|client| client.certificate.clone());
 
-        {
-            let mut streaming_hostname_mut = STREAMING_CLIENT_HOSTNAME.lock();
-            if let Some(hostname) = streaming_hostname_mut.clone() {
-                if hostname == client_hostname {
-                    *streaming_hostname_mut = None
+    runtime
+        .block_on(StreamSocketConnector::bind_for_client(
+            stream_port,
+            settings.connection.stream_protocol,
+            client_certificate,
+            mbits_to_bytes(settings.video.encode_bitrate_mbs),
+            settings.connection.server_send_buffer_bytes,
+            settings.connection.server_recv_buffer_bytes,
+        ))
+        .map_err(to_int_e!())
+}
+
+// The session is sent as a string to allow for extrapolation. Each client gets its own stream port,
+// and the codec is the one negotiated with the client, which can differ from the settings
+fn client_session_desc(stream_port: u16, codec: CodecType) -> IntResult<String> {
+    let mut session = SERVER_DATA_MANAGER.read().session().clone();
+    session.session_settings.connection.stream_port = stream_port;
+    session.session_settings.video.codec.variant = match codec {
+        CodecType::H264 => CodecTypeDefaultVariant::H264,
+        CodecType::HEVC => CodecTypeDefaultVariant::HEVC,
+        CodecType::AV1 => CodecTypeDefaultVariant::AV1,
+    };
+
+    serde_json::to_string(&session).map_err(to_int_e!())
+}
+
+// Run the pipeline on a new thread, until the client disconnects, it is disconnected from the
+// dashboard or the driver shuts down
+fn spawn_client_pipeline<F, Fut>(
+    runtime: Runtime,
+    client_hostname: String,
+    role: ClientRole,
+    stream_port: u16,
+    pipeline: F,
+) where
+    F: FnOnce() -> Fut + Send + 'static,
+    Fut: future::Future<Output = StrResult>,
+{
+    CLIENT_SESSIONS.lock().insert(
+        client_hostname.clone(),
+        ClientSession {
+            role,
+            stream_port,
+            control_channel_sender: None,
+            video_sender: None,
+            audio_sender: None,
+            haptics_sender: None,
+            statistics: None,
+        },
+    );
+
+    thread::spawn(move || {
+        runtime.block_on(async move {
+            // this is a bridge between sync and async, skips the needs for a notifier
+            let shutdown_detector = async {
+                while IS_ALIVE.value() {
+                    time::sleep(Duration::from_secs(1)).await;
                 }
-            }
-        }
+            };
+
+            tokio::select! {
+                res = pipeline() => {
+                    show_warn(res);
+                },
+                _ = DISCONNECT_CLIENT_NOTIFIER.notified() => (),
+                _ = shutdown_detector => (),
+            };
+        });
 
-        CONNECTED_CLIENT_HOSTNAMES.lock().remove(&client_hostname);
+        remove_client_session(&mut CLIENT_SESSIONS.lock(), &client_hostname);
     });
+}
 
-    Ok(())
+// When the primary client disconnects, the spectator with the lowest stream port is asked to
+// reconnect. Since no primary client is left, it gets the primary role and the stream restarts with
+// its own settings.
+fn remove_client_session(sessions: &mut HashMap<String, ClientSession>, client_hostname: &str) {
+    if !matches!(
+        sessions.remove(client_hostname),
+        Some(ClientSession {
+            role: ClientRole::Primary,
+            ..
+        })
+    ) {
+        return;
+    }
+
+    let maybe_sender = sessions
+        .values()
+        .filter(|session| session.role == ClientRole::Spectator)
+        .min_by_key(|session| session.stream_port)
+        .and_then(|session| session.control_channel_sender.as_ref());
+    if let Some(sender) = maybe_sender {
+        sender.send(ServerControlPacket::Restarting).ok();
+    }
+}
+
+fn to_tracking_quat(quat: Quat) -> AlvrQuat {
+    AlvrQuat {
+        x: quat.x,
+        y: quat.y,
+        z: quat.z,
+        w: quat.w,
+    }
+}
+
+fn to_raw_motions(
+    tracking_manager: &mut TrackingManager,
+    timestamp: Duration,
+    device_motions: Vec<(u64, DeviceMotion)>,
+    left_hand_tracked: bool,
+    right_hand_tracked: bool,
+) -> Vec<AlvrDeviceMotion> {
+    tracking_manager
+        .map_motions(
+            timestamp,
+            device_motions,
+            left_hand_tracked,
+            right_hand_tracked,
+        )
+        .into_iter()
+        .map(|(id, motion)| AlvrDeviceMotion {
+            deviceID: id,
+            orientation: to_tracking_quat(motion.orientation),
+            position: motion.position.to_array(),
+            linearVelocity: motion.linear_velocity.to_array(),
+            angularVelocity: motion.angular_velocity.to_array(),
+        })
+        .collect()
+}
+
+fn to_alvr_button_value(value: ButtonValue) -> AlvrButtonValue {
+    match value {
+        ButtonValue::Binary(value) => AlvrButtonValue {
+            type_: AlvrButtonType_BUTTON_TYPE_BINARY,
+            __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { binary: value },
+        },
+        ButtonValue::Scalar(value) => AlvrButtonValue {
+            type_: AlvrButtonType_BUTTON_TYPE_SCALAR,
+            __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { scalar: value },
+        },
+    }
+}
+
+// The SteamVR bones are used by the driver in place of the legacy skeleton when available
+fn to_oculus_hand(
+    maybe_skeleton: Option<[Quat; 19]>,
+    maybe_bones: Option<[BoneTransform; STEAMVR_HAND_BONE_COUNT]>,
+    gesture_buttons: bool,
+) -> OculusHand {
+    if let Some(skeleton) = maybe_skeleton {
+        OculusHand {
+            enabled: true,
+            boneRotations: skeleton.map(to_tracking_quat),
+            gestureButtons: gesture_buttons,
+            skeletonValid: maybe_bones.is_some(),
+            skeleton: maybe_bones
+                .map(|bones| {
+                    bones.map(|bone| AlvrBoneTransform {
+                        orientation: to_tracking_quat(bone.orientation),
+                        position: bone.position.to_array(),
+                    })
+                })
+                .unwrap_or_default(),
+        }
+    } else {
+        OculusHand {
+            enabled: false,
+            ..Default::default()
+        }
+    }
 }
 
 // close stream on Drop (manual disconnection or execution canceling)
-struct StreamCloseGuard(Arc<RelaxedAtomic>);
+struct StreamCloseGuard {
+    is_streaming: Arc<RelaxedAtomic>,
+    backend: Arc<dyn ServerBackend>,
+    data_manager: &'static parking_lot::RwLock<ServerDataManager>,
+}
 
 impl Drop for StreamCloseGuard {
     fn drop(&mut self) {
-        self.0.set(false);
+        self.is_streaming.set(false);
+
+        self.backend.deinitialize_streaming();
 
-        unsafe { crate::DeinitializeStreaming() };
+        *DECODER_CONFIG.lock() = None;
 
-        let on_disconnect_script = SERVER_DATA_MANAGER
+        let on_disconnect_script = self
+            .data_manager
             .read()
             .settings()
             .connection
@@ -571,14 +1248,37 @@ impl Drop for StreamCloseGuard {
     }
 }
 
-async fn connection_pipeline(
+// Negotiated during the handshake with the client
+struct ConnectionParams {
+    protocol: NegotiatedProtocol,
     client_hostname: String,
     client_ip: IpAddr,
-    control_sender: ControlSocketSender<ServerControlPacket>,
-    mut control_receiver: ControlSocketReceiver<ClientControlPacket>,
+    role: ClientRole,
+    stream_port: u16,
     microphone_sample_rate: u32,
+    game_audio_sample_rate: u32,
     refresh_rate: f32,
+}
+
+async fn connection_pipeline(
+    backend: Arc<dyn ServerBackend>,
+    data_manager: &'static parking_lot::RwLock<ServerDataManager>,
+    params: ConnectionParams,
+    stream_socket_connector: StreamSocketConnector,
+    control_sender: ControlSocketSender<ServerControlPacket>,
+    mut control_receiver: ControlSocketReceiver<ClientControlPacket>,
 ) -> StrResult {
+    let ConnectionParams {
+        protocol,
+        client_hostname,
+        client_ip,
+        role,
+        stream_port,
+        microphone_sample_rate,
+        game_audio_sample_rate,
+        refresh_rate,
+    } = params;
+    let is_primary = role == ClientRole::Primary;
     let control_sender = Arc::new(Mutex::new(control_sender));
 
     control_sender
@@ -597,31 +1297,65 @@ async fn connection_pipeline(
         }
     }
 
-    let settings = SERVER_DATA_MANAGER.read().settings().clone();
+    let settings = data_manager.read().settings().clone();
 
     let stream_socket = tokio::select! {
-        res = StreamSocketBuilder::connect_to_client(
+        res = stream_socket_connector.connect_to_client(
             client_ip,
-            settings.connection.stream_port,
-            settings.connection.stream_protocol,
-            mbits_to_bytes(settings.video.encode_bitrate_mbs),
-            settings.connection.server_send_buffer_bytes,
-            settings.connection.server_recv_buffer_bytes,
+            stream_port,
+            settings.connection.packet_size as _,
         ) => res?,
         _ = time::sleep(Duration::from_secs(5)) => {
             return fmt_e!("Timeout while setting up streams");
         }
     };
+    if settings.connection.enable_fec && protocol.supports(features::FEC) {
+        stream_socket.enable_fec(VIDEO).await;
+    }
+    if settings.connection.enable_nack && protocol.supports(features::NACK) {
+        stream_socket
+            .enable_nack(VIDEO, Duration::from_secs_f32(1.0 / refresh_rate))
+            .await;
+    }
     let stream_socket = Arc::new(stream_socket);
 
-    *STATISTICS_MANAGER.lock() = Some(StatisticsManager::new(
-        settings.connection.statistics_history_size as _,
-        Duration::from_secs_f32(1.0 / refresh_rate),
+    // The file is closed when the client disconnects and the pipeline is dropped
+    let tracking_recorder: SharedRecorder = Arc::new(parking_lot::Mutex::new(
+        if is_primary && settings.extra.record_tracking {
+            alvr_common::show_warn(TrackingRecorder::new(
+                &FILESYSTEM_LAYOUT.tracking_recording(),
+            ))
+        } else {
+            None
+        },
     ));
+    let video_recorder: SharedVideoRecorder = Arc::new(parking_lot::Mutex::new(
+        if let (Switch::Enabled(desc), true) = (&settings.extra.record_video, is_primary) {
+            let game_audio_sample_rate = matches!(settings.audio.game_audio, Switch::Enabled(_))
+                .then_some(game_audio_sample_rate);
+
+            alvr_common::show_warn(new_video_recorder(
+                data_manager,
+                desc,
+                game_audio_sample_rate,
+            ))
+        } else {
+            None
+        },
+    ));
+    let button_remapper = new_remapper();
+
+    if let Some(session) = CLIENT_SESSIONS.lock().get_mut(&client_hostname) {
+        session.statistics = Some(StatisticsManager::new(
+            settings.connection.statistics_history_size as _,
+            Duration::from_secs_f32(1.0 / refresh_rate),
+            is_primary,
+        ));
+    }
 
     alvr_events::send_event(EventType::ClientConnected);
 
-    {
+    if is_primary {
         let on_connect_script = settings.connection.on_connect_script;
 
         if !on_connect_script.is_empty() {
@@ -635,68 +1369,110 @@ async fn connection_pipeline(
         }
     }
 
-    unsafe { crate::InitializeStreaming() };
-
+    // Spectators join the stream that the primary client started
     let is_streaming = Arc::new(RelaxedAtomic::new(true));
-    let _stream_guard = StreamCloseGuard(Arc::clone(&is_streaming));
+    let _stream_guard = if is_primary {
+        backend.initialize_streaming();
 
-    let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.game_audio {
-        let sender = stream_socket.request_stream(AUDIO).await?;
-        Box::pin(async move {
-            loop {
-                let device = match AudioDevice::new(
-                    Some(settings.audio.linux_backend),
-                    &desc.device_id,
-                    AudioDeviceType::Output,
-                ) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        warn!("New audio device Failed : {e}");
-                        time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
-                        continue;
-                    }
-                };
-                let mute_when_streaming = desc.mute_when_streaming;
+        Some(StreamCloseGuard {
+            is_streaming: Arc::clone(&is_streaming),
+            backend: Arc::clone(&backend),
+            data_manager,
+        })
+    } else {
+        None
+    };
 
-                #[cfg(windows)]
-                unsafe {
-                    let device_id = match alvr_audio::get_windows_device_id(&device) {
-                        Ok(data) => data,
-                        Err(_) => continue,
-                    };
-                    crate::SetOpenvrProperty(
-                        *HEAD_ID,
-                        crate::to_cpp_openvr_prop(
-                            alvr_session::OpenvrPropertyKey::AudioDefaultPlaybackDeviceId,
-                            alvr_session::OpenvrPropValue::String(device_id),
-                        ),
-                    )
+    // The spectators and the companions receive the audio captured for the primary client
+    let game_audio_loop: BoxFuture<_> =
+        if let (Switch::Enabled(_), false) = (&settings.audio.game_audio, is_primary) {
+            let mut sender = stream_socket.request_stream(AUDIO).await?;
+            let client_hostname = client_hostname.clone();
+            Box::pin(async move {
+                let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
+                if let Some(session) = CLIENT_SESSIONS.lock().get_mut(&client_hostname) {
+                    session.audio_sender = Some(data_sender);
                 }
-                let new_sender = sender.clone();
-                match alvr_audio::record_audio_loop(device, 2, mute_when_streaming, new_sender)
-                    .await
-                {
-                    Ok(_) => (),
-                    Err(e) => warn!("Audio task exit with error : {e}"),
-                };
 
-                #[cfg(windows)]
-                {
-                    let default_device = match AudioDevice::new(
-                        None,
-                        &alvr_session::AudioDeviceId::Default,
+                while let Some(data) = data_receiver.recv().await {
+                    let mut buffer = sender.new_buffer(&(), data.len())?;
+                    buffer.get_mut().extend(data);
+                    sender.send_buffer(buffer).await.ok();
+                }
+
+                Ok(())
+            })
+        } else if let Switch::Enabled(desc) = settings.audio.game_audio {
+            let sender = stream_socket.request_stream(AUDIO).await?;
+            #[cfg(windows)]
+            let backend = Arc::clone(&backend);
+            let video_recorder = Arc::clone(&video_recorder);
+            Box::pin(async move {
+                loop {
+                    let device = match AudioDevice::new(
+                        Some(settings.audio.linux_backend),
+                        &desc.device_id,
                         AudioDeviceType::Output,
                     ) {
                         Ok(data) => data,
-                        Err(_) => continue,
+                        Err(e) => {
+                            warn!("New audio device Failed : {e}");
+                            time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
+                            continue;
+                        }
                     };
-                    let default_device_id = match alvr_audio::get_windows_device_id(&default_device)
+                    let mute_when_streaming = desc.mute_when_streaming;
+
+                    #[cfg(windows)]
                     {
-                        Ok(data) => data,
-                        Err(_) => continue,
+                        let device_id = match alvr_audio::get_windows_device_id(&device) {
+                            Ok(data) => data,
+                            Err(_) => continue,
+                        };
+                        backend.set_openvr_property(
+                            *HEAD_ID,
+                            crate::to_cpp_openvr_prop(
+                                alvr_session::OpenvrPropertyKey::AudioDefaultPlaybackDeviceId,
+                                alvr_session::OpenvrPropValue::String(device_id),
+                            ),
+                        )
+                    }
+                    let new_sender = sender.clone();
+                    let video_recorder = Arc::clone(&video_recorder);
+                    match alvr_audio::record_audio_loop(
+                        device,
+                        2,
+                        mute_when_streaming,
+                        new_sender,
+                        move |data| {
+                            if let Some(recorder) = &mut *video_recorder.lock() {
+                                recorder.record_audio(data).ok();
+                            }
+                            send_game_audio(data);
+                        },
+                    )
+                    .await
+                    {
+                        Ok(_) => (),
+                        Err(e) => warn!("Audio task exit with error : {e}"),
                     };
-                    unsafe {
-                        crate::SetOpenvrProperty(
+
+                    #[cfg(windows)]
+                    {
+                        let default_device = match AudioDevice::new(
+                            None,
+                            &alvr_session::AudioDeviceId::Default,
+                            AudioDeviceType::Output,
+                        ) {
+                            Ok(data) => data,
+                            Err(_) => continue,
+                        };
+                        let default_device_id =
+                            match alvr_audio::get_windows_device_id(&default_device) {
+                                Ok(data) => data,
+                                Err(_) => continue,
+                            };
+                        backend.set_openvr_property(
                             *HEAD_ID,
                             crate::to_cpp_openvr_prop(
                                 alvr_session::OpenvrPropertyKey::AudioDefaultPlaybackDeviceId,
@@ -705,31 +1481,31 @@ async fn connection_pipeline(
                         )
                     }
                 }
-            }
-        })
-    } else {
-        Box::pin(future::pending())
-    };
-    let microphone_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.microphone {
-        let input_device = AudioDevice::new(
-            Some(settings.audio.linux_backend),
-            &desc.input_device_id,
-            AudioDeviceType::VirtualMicrophoneInput,
-        )?;
-        let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
-
-        #[cfg(windows)]
-        {
-            let microphone_device = AudioDevice::new(
-                None,
-                &desc.output_device_id,
-                AudioDeviceType::VirtualMicrophoneOutput {
-                    matching_input_device_name: input_device.name()?,
-                },
+            })
+        } else {
+            Box::pin(future::pending())
+        };
+    // The virtual microphone is fed only by the primary client
+    let microphone_loop: BoxFuture<_> =
+        if let (Switch::Enabled(desc), true) = (settings.audio.microphone, is_primary) {
+            let input_device = AudioDevice::new(
+                Some(settings.audio.linux_backend),
+                &desc.input_device_id,
+                AudioDeviceType::VirtualMicrophoneInput,
             )?;
-            let microphone_device_id = alvr_audio::get_windows_device_id(&microphone_device)?;
-            unsafe {
-                crate::SetOpenvrProperty(
+            let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
+
+            #[cfg(windows)]
+            {
+                let microphone_device = AudioDevice::new(
+                    None,
+                    &desc.output_device_id,
+                    AudioDeviceType::VirtualMicrophoneOutput {
+                        matching_input_device_name: input_device.name()?,
+                    },
+                )?;
+                let microphone_device_id = alvr_audio::get_windows_device_id(&microphone_device)?;
+                backend.set_openvr_property(
                     *HEAD_ID,
                     crate::to_cpp_openvr_prop(
                         alvr_session::OpenvrPropertyKey::AudioDefaultRecordingDeviceId,
@@ -737,26 +1513,42 @@ async fn connection_pipeline(
                     ),
                 )
             }
-        }
 
-        Box::pin(alvr_audio::play_audio_loop(
-            input_device,
-            1,
-            microphone_sample_rate,
-            desc.buffering_config,
-            receiver,
-        ))
-    } else {
-        Box::pin(future::pending())
-    };
+            Box::pin(alvr_audio::play_audio_loop(
+                input_device,
+                1,
+                microphone_sample_rate,
+                desc.buffering_config,
+                receiver,
+            ))
+        } else {
+            Box::pin(future::pending())
+        };
 
     let video_send_loop = {
         let mut socket_sender = stream_socket.request_stream(VIDEO).await?;
+        let client_hostname = client_hostname.clone();
+        let backend = Arc::clone(&backend);
+        let video_recorder = Arc::clone(&video_recorder);
         async move {
             let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
-            *VIDEO_SENDER.lock() = Some(data_sender);
+            if let Some(session) = CLIENT_SESSIONS.lock().get_mut(&client_hostname) {
+                session.video_sender = Some(data_sender);
+            }
+
+            // A spectator cannot decode the stream before the next keyframe
+            if !is_primary
+                && spectator_idr_allowed(
+                    &mut crate::LAST_SPECTATOR_IDR_REQUEST.lock(),
+                    Instant::now(),
+                )
+            {
+                backend.request_idr();
+            }
 
             while let Some(VideoPacket { header, payload }) = data_receiver.recv().await {
+                record_video_packet(&video_recorder, &header, &payload);
+
                 let mut buffer = socket_sender.new_buffer(&header, payload.len())?;
                 buffer.get_mut().extend(payload);
                 socket_sender.send_buffer(buffer).await.ok();
@@ -767,13 +1559,14 @@ async fn connection_pipeline(
     };
 
     // Vsync thread
-    if cfg!(windows) {
+    if cfg!(windows) && is_primary {
         let frame_interval = Duration::from_secs_f32(1.0 / refresh_rate);
+        let backend = Arc::clone(&backend);
         thread::spawn(move || {
             let mut deadline = Instant::now();
 
             while is_streaming.value() {
-                unsafe { crate::SendVSync(frame_interval.as_secs_f32()) };
+                backend.send_vsync(frame_interval);
 
                 deadline += frame_interval;
                 std::thread::sleep(deadline.saturating_duration_since(Instant::now()));
@@ -783,11 +1576,19 @@ async fn connection_pipeline(
 
     let haptics_send_loop = {
         let mut socket_sender = stream_socket.request_stream(HAPTICS).await?;
+        let client_hostname = client_hostname.clone();
+        let tracking_recorder = Arc::clone(&tracking_recorder);
         async move {
             let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
-            *HAPTICS_SENDER.lock() = Some(data_sender);
+            if let Some(session) = CLIENT_SESSIONS.lock().get_mut(&client_hostname) {
+                session.haptics_sender = Some(data_sender);
+            }
 
             while let Some(haptics) = data_receiver.recv().await {
+                record_event(&tracking_recorder, || {
+                    RecordedEvent::Haptics(haptics.clone())
+                });
+
                 socket_sender
                     .send_buffer(socket_sender.new_buffer(&haptics, 0)?)
                     .await
@@ -800,122 +1601,170 @@ async fn connection_pipeline(
 
     let (playspace_sync_sender, playspace_sync_receiver) = smpsc::channel::<Vec2>();
 
-    let is_tracking_ref_only = settings.headset.tracking_ref_only;
-    if !is_tracking_ref_only {
+    // The playspace of the spectators is ignored
+    let sync_playspace = is_primary && !settings.headset.tracking_ref_only;
+    if sync_playspace {
         // use a separate thread because SetChaperone() is blocking
+        let backend = Arc::clone(&backend);
         thread::spawn(move || {
             while let Ok(packet) = playspace_sync_receiver.recv() {
-                let width = f32::max(packet.x, 2.0);
-                let height = f32::max(packet.y, 2.0);
-                unsafe { crate::SetChaperone(width, height) };
+                backend.set_chaperone(packet.max(Vec2::new(2.0, 2.0)));
             }
         });
     }
 
-    fn to_tracking_quat(quat: Quat) -> AlvrQuat {
-        AlvrQuat {
-            x: quat.x,
-            y: quat.y,
-            z: quat.z,
-            w: quat.w,
-        }
-    }
+    // Skeletons in the OpenXR format, if negotiated. The client sends them before the tracking
+    // packet with the same target timestamp, into which they are merged.
+    let hand_skeletons: Arc<parking_lot::Mutex<VecDeque<HandSkeletons>>> =
+        Arc::new(parking_lot::Mutex::new(VecDeque::new()));
+
+    let hand_skeletons_receive_loop: BoxFuture<_> =
+        if is_primary && protocol.supports(features::HAND_TRACKING_OPENXR) {
+            let mut receiver = stream_socket
+                .subscribe_to_stream::<HandSkeletons>(HAND_SKELETONS)
+                .await?;
+            let hand_skeletons = Arc::clone(&hand_skeletons);
+            Box::pin(async move {
+                loop {
+                    let skeletons = receiver.recv().await?.header;
+
+                    let mut pending = hand_skeletons.lock();
+                    pending.push_back(skeletons);
+                    if pending.len() > MAX_PENDING_HAND_SKELETONS {
+                        pending.pop_front();
+                    }
+                }
+            })
+        } else {
+            Box::pin(future::pending())
+        };
 
     let tracking_receive_loop = {
         let mut receiver = stream_socket
             .subscribe_to_stream::<Tracking>(TRACKING)
             .await?;
         let control_sender = Arc::clone(&control_sender);
+        let backend = Arc::clone(&backend);
+        let client_hostname = client_hostname.clone();
+        let tracking_recorder = Arc::clone(&tracking_recorder);
+        let button_remapper = Arc::clone(&button_remapper);
         async move {
-            let tracking_latency_offset_s =
-                if let Switch::Enabled(controllers) = &settings.headset.controllers {
-                    controllers.pose_time_offset_ms
-                } else {
-                    0
-                } as f32
-                    / 1000.;
-
-            let tracking_manager = TrackingManager::new(settings.headset);
+            let mut gesture_recognizers = match &settings.headset.controllers {
+                Switch::Enabled(ControllersDesc {
+                    hand_gestures: Switch::Enabled(config),
+                    ..
+                }) => Some([
+                    GestureRecognizer::new(config.clone(), true),
+                    GestureRecognizer::new(config.clone(), false),
+                ]),
+                _ => None,
+            };
+            let mut tracking_manager = TrackingManager::new(settings.headset);
             loop {
-                let tracking = receiver.recv().await?.header;
+                let mut tracking = receiver.recv().await?.header;
 
-                let mut device_motions = vec![];
-                for (id, motion) in tracking.device_motions {
-                    let motion = if id == *HEAD_ID {
-                        tracking_manager.map_head(motion)
-                    } else if let Some(motion) = tracking_manager.map_controller(motion) {
-                        motion
-                    } else {
-                        warn!("Unrecognized device ID. Trackers are not supported");
-                        continue;
-                    };
-                    device_motions.push((id, motion));
+                // Only the primary client moves the headset and the controllers
+                if !is_primary {
+                    continue;
                 }
 
-                let raw_motions = device_motions
-                    .into_iter()
-                    .map(|(id, motion)| AlvrDeviceMotion {
-                        deviceID: id,
-                        orientation: to_tracking_quat(motion.orientation),
-                        position: motion.position.to_array(),
-                        linearVelocity: motion.linear_velocity.to_array(),
-                        angularVelocity: motion.angular_velocity.to_array(),
-                    })
-                    .collect::<Vec<_>>();
-
-                let left_oculus_hand = if let Some(arr) = tracking.left_hand_skeleton {
-                    let vec = arr.into_iter().map(to_tracking_quat).collect::<Vec<_>>();
-                    let mut array = [AlvrQuat::default(); 19];
-                    array.copy_from_slice(&vec);
-
-                    OculusHand {
-                        enabled: true,
-                        boneRotations: array,
-                    }
-                } else {
-                    OculusHand {
-                        enabled: false,
-                        ..Default::default()
+                let mut gesture_buttons = vec![];
+                // The OpenXR skeleton of a hand is received only while the hand is tracked in
+                // place of the controller. The legacy emulation of the buttons is skipped for the
+                // hands handled by the gesture recognizers.
+                let mut gesture_hands = (false, false);
+                let steamvr_bones = {
+                    // The skeletons of older packets will not be matched anymore
+                    let skeletons = {
+                        let mut pending = hand_skeletons.lock();
+                        pending.retain(|skeletons| {
+                            skeletons.target_timestamp >= tracking.target_timestamp
+                        });
+                        pending
+                            .iter()
+                            .position(|skeletons| {
+                                skeletons.target_timestamp == tracking.target_timestamp
+                            })
+                            .and_then(|index| pending.remove(index))
+                    };
+                    let left_hand = skeletons
+                        .as_ref()
+                        .and_then(|skeletons| skeletons.left_hand.as_ref());
+                    let right_hand = skeletons
+                        .as_ref()
+                        .and_then(|skeletons| skeletons.right_hand.as_ref());
+
+                    merge_legacy_hand_skeletons(&mut tracking, left_hand, right_hand);
+
+                    if let Some([left_recognizer, right_recognizer]) = &mut gesture_recognizers {
+                        gesture_hands = (left_hand.is_some(), right_hand.is_some());
+                        gesture_buttons.extend(left_recognizer.update(left_hand));
+                        gesture_buttons.extend(right_recognizer.update(right_hand));
                     }
-                };
 
-                let right_oculus_hand = if let Some(arr) = tracking.right_hand_skeleton {
-                    let vec = arr.into_iter().map(to_tracking_quat).collect::<Vec<_>>();
-                    let mut array = [AlvrQuat::default(); 19];
-                    array.copy_from_slice(&vec);
-
-                    OculusHand {
-                        enabled: true,
-                        boneRotations: array,
-                    }
-                } else {
-                    OculusHand {
-                        enabled: false,
-                        ..Default::default()
-                    }
+                    (
+                        left_hand.and_then(|skeleton| {
+                            to_steamvr_bones(skeleton, true, hand_skeleton_wrist(true))
+                        }),
+                        right_hand.and_then(|skeleton| {
+                            to_steamvr_bones(skeleton, false, hand_skeleton_wrist(false))
+                        }),
+                    )
                 };
+                for (path_id, value) in gesture_buttons {
+                    for (path_id, value) in
+                        remap_button(data_manager, &button_remapper, path_id, value)
+                    {
+                        backend.set_button(path_id, to_alvr_button_value(value));
+                    }
+                }
 
-                let maybe_server_prediction_average =
-                    if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                        stats.report_tracking_received(tracking.target_timestamp);
-
-                        unsafe {
-                            crate::SetTracking(
-                                tracking.target_timestamp.as_nanos() as _,
-                                tracking_latency_offset_s,
-                                raw_motions.as_ptr(),
-                                raw_motions.len() as _,
-                                left_oculus_hand,
-                                right_oculus_hand,
-                            )
-                        };
+                record_event(&tracking_recorder, || {
+                    RecordedEvent::Tracking(Box::new(tracking.clone()))
+                });
+
+                let raw_motions = to_raw_motions(
+                    &mut tracking_manager,
+                    tracking.target_timestamp,
+                    tracking.device_motions,
+                    tracking.left_hand_skeleton.is_some(),
+                    tracking.right_hand_skeleton.is_some(),
+                );
+                *PLAYSPACE_RECENTERING.lock() = tracking_manager.recentering();
+                let left_oculus_hand = to_oculus_hand(
+                    tracking.left_hand_skeleton,
+                    steamvr_bones.0,
+                    gesture_hands.0,
+                );
+                let right_oculus_hand = to_oculus_hand(
+                    tracking.right_hand_skeleton,
+                    steamvr_bones.1,
+                    gesture_hands.1,
+                );
+
+                // The frames rendered for this pose are streamed to the spectators too
+                let maybe_server_prediction_average = {
+                    let mut sessions = CLIENT_SESSIONS.lock();
+                    for session in sessions.values_mut() {
+                        if let Some(stats) = &mut session.statistics {
+                            stats.report_tracking_received(tracking.target_timestamp);
+                        }
+                    }
 
-                        Some(stats.get_server_prediction_average())
-                    } else {
-                        None
-                    };
+                    sessions
+                        .get(&client_hostname)
+                        .and_then(|session| session.statistics.as_ref())
+                        .map(|stats| stats.get_server_prediction_average())
+                };
 
                 if let Some(server_prediction_average) = maybe_server_prediction_average {
+                    backend.set_tracking(
+                        tracking.target_timestamp,
+                        &raw_motions,
+                        left_oculus_hand,
+                        right_oculus_hand,
+                    );
+
                     control_sender
                         .lock()
                         .await
@@ -933,13 +1782,23 @@ async fn connection_pipeline(
         let mut receiver = stream_socket
             .subscribe_to_stream::<ClientStatistics>(STATISTICS)
             .await?;
+        let backend = Arc::clone(&backend);
+        let client_hostname = client_hostname.clone();
         async move {
             loop {
                 let client_stats = receiver.recv().await?.header;
 
-                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                if let Some(stats) = CLIENT_SESSIONS
+                    .lock()
+                    .get_mut(&client_hostname)
+                    .and_then(|session| session.statistics.as_mut())
+                {
                     let network_latency = stats.report_statistics(client_stats);
-                    unsafe { crate::ReportNetworkLatency(network_latency.as_micros() as _) };
+
+                    // The bitrate adapts to the network of the primary client
+                    if is_primary {
+                        backend.report_network_latency(network_latency);
+                    }
                 }
             }
         }
@@ -947,6 +1806,7 @@ async fn connection_pipeline(
 
     let keepalive_loop = {
         let control_sender = Arc::clone(&control_sender);
+        let backend = Arc::clone(&backend);
         async move {
             loop {
                 let res = control_sender
@@ -961,34 +1821,48 @@ async fn connection_pipeline(
                 }
                 time::sleep(KEEPALIVE_INTERVAL).await;
 
+                if !is_primary {
+                    continue;
+                }
+
                 // copy some settings periodically into c++
-                let data_manager = SERVER_DATA_MANAGER.read();
+                let data_manager = data_manager.read();
                 let settings = data_manager.settings();
 
                 let mut bitrate_maximum = 0;
-                let adaptive_bitrate_enabled = if let Switch::Enabled(config) =
-                    &SERVER_DATA_MANAGER.read().settings().video.adaptive_bitrate
-                {
-                    bitrate_maximum = config.bitrate_maximum;
+                let adaptive_bitrate_enabled =
+                    if let Switch::Enabled(config) = &settings.video.adaptive_bitrate {
+                        bitrate_maximum = config.bitrate_maximum;
 
-                    true
-                } else {
-                    false
-                };
+                        true
+                    } else {
+                        false
+                    };
 
-                unsafe {
-                    crate::SetBitrateParameters(
-                        settings.video.encode_bitrate_mbs,
-                        adaptive_bitrate_enabled,
-                        bitrate_maximum,
-                    )
-                };
+                backend.set_bitrate_parameters(
+                    settings.video.encode_bitrate_mbs,
+                    adaptive_bitrate_enabled,
+                    bitrate_maximum,
+                );
             }
         }
     };
 
     let (control_channel_sender, mut control_channel_receiver) = tmpsc::unbounded_channel();
-    *CONTROL_CHANNEL_SENDER.lock() = Some(control_channel_sender);
+    {
+        // The decoder configuration is broadcast only when it changes
+        let decoder_config = DECODER_CONFIG.lock();
+        if !is_primary {
+            if let Some(config_buffer) = decoder_config.clone() {
+                control_channel_sender
+                    .send(ServerControlPacket::InitializeDecoder { config_buffer })
+                    .ok();
+            }
+        }
+        if let Some(session) = CLIENT_SESSIONS.lock().get_mut(&client_hostname) {
+            session.control_channel_sender = Some(control_channel_sender);
+        }
+    }
 
     let control_send_loop = {
         let control_sender = Arc::clone(&control_sender);
@@ -1005,16 +1879,26 @@ async fn connection_pipeline(
         loop {
             match control_receiver.recv().await {
                 Ok(ClientControlPacket::PlayspaceSync(packet)) => {
-                    if !is_tracking_ref_only {
+                    if sync_playspace {
                         playspace_sync_sender.send(packet).ok();
                     }
                 }
-                Ok(ClientControlPacket::RequestIdr) => unsafe { crate::RequestIDR() },
-                Ok(ClientControlPacket::VideoErrorReport) => unsafe {
-                    crate::VideoErrorReportReceive()
-                },
-                Ok(ClientControlPacket::ViewsConfig(config)) => unsafe {
-                    crate::SetViewsConfig(crate::ViewsConfigData {
+                Ok(ClientControlPacket::RequestIdr) => {
+                    if is_primary
+                        || spectator_idr_allowed(
+                            &mut crate::LAST_SPECTATOR_IDR_REQUEST.lock(),
+                            Instant::now(),
+                        )
+                    {
+                        backend.request_idr();
+                    }
+                }
+                // The errors of a spectator must not change the encoder settings of the primary
+                Ok(ClientControlPacket::VideoErrorReport) if is_primary => {
+                    backend.report_video_error()
+                }
+                Ok(ClientControlPacket::ViewsConfig(config)) if is_primary => {
+                    backend.set_views_config(crate::ViewsConfigData {
                         fov: [
                             EyeFov {
                                 left: config.fov[0].left,
@@ -1031,15 +1915,30 @@ async fn connection_pipeline(
                         ],
                         ipd_m: config.ipd_m,
                     });
-                },
-                Ok(ClientControlPacket::Battery(packet)) => unsafe {
-                    crate::SetBattery(packet.device_id, packet.gauge_value, packet.is_plugged);
+                }
+                Ok(ClientControlPacket::Battery(packet)) => {
+                    if is_primary {
+                        backend.set_battery(
+                            packet.device_id,
+                            packet.gauge_value,
+                            packet.is_plugged,
+                        );
+                    }
 
-                    if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    if let Some(stats) = CLIENT_SESSIONS
+                        .lock()
+                        .get_mut(&client_hostname)
+                        .and_then(|session| session.statistics.as_mut())
+                    {
                         stats.report_battery(packet.device_id, packet.gauge_value);
                     }
-                },
-                Ok(ClientControlPacket::Button { path_id, value }) => {
+                }
+                Ok(ClientControlPacket::Button { path_id, value }) if is_primary => {
+                    record_event(&tracking_recorder, || RecordedEvent::Button {
+                        path_id,
+                        value: value.clone(),
+                    });
+
                     if settings.extra.log_button_presses {
                         alvr_events::send_event(EventType::Button(ButtonEvent {
                             path: BUTTON_PATH_FROM_ID
@@ -1050,19 +1949,11 @@ async fn connection_pipeline(
                         }));
                     }
 
-                    let value = match value {
-                        ButtonValue::Binary(value) => AlvrButtonValue {
-                            type_: AlvrButtonType_BUTTON_TYPE_BINARY,
-                            __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { binary: value },
-                        },
-
-                        ButtonValue::Scalar(value) => AlvrButtonValue {
-                            type_: AlvrButtonType_BUTTON_TYPE_SCALAR,
-                            __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { scalar: value },
-                        },
-                    };
-
-                    unsafe { crate::SetButton(path_id, value) };
+                    for (path_id, value) in
+                        remap_button(data_manager, &button_remapper, path_id, value)
+                    {
+                        backend.set_button(path_id, to_alvr_button_value(value));
+                    }
                 }
                 Ok(ClientControlPacket::Log { level, message }) => {
                     info!("Client {client_hostname}: [{level:?}] {message}")
@@ -1097,6 +1988,7 @@ async fn connection_pipeline(
         res = spawn_cancelable(statistics_receive_loop) => res,
         res = spawn_cancelable(haptics_send_loop) => res,
         res = spawn_cancelable(tracking_receive_loop) => res,
+        res = spawn_cancelable(hand_skeletons_receive_loop) => res,
 
         // Leave these loops on the current task
         res = keepalive_loop => res,
@@ -1115,3 +2007,612 @@ async fn connection_pipeline(
         }
     }
 }
+
+// Companion clients do not receive video nor audio. Their devices are forwarded to SteamVR together
+// with the ones of the primary client, except for the head which belongs to the primary client.
+async fn companion_pipeline(
+    backend: Arc<dyn ServerBackend>,
+    data_manager: &'static parking_lot::RwLock<ServerDataManager>,
+    params: ConnectionParams,
+    stream_socket_connector: StreamSocketConnector,
+    control_sender: ControlSocketSender<ServerControlPacket>,
+    mut control_receiver: ControlSocketReceiver<ClientControlPacket>,
+) -> StrResult {
+    let ConnectionParams {
+        client_hostname,
+        client_ip,
+        stream_port,
+        ..
+    } = params;
+    let control_sender = Arc::new(Mutex::new(control_sender));
+
+    control_sender
+        .lock()
+        .await
+        .send(&ServerControlPacket::StartStream)
+        .await?;
+
+    match control_receiver.recv().await {
+        Ok(ClientControlPacket::StreamReady) => {}
+        Ok(_) => {
+            return fmt_e!("Got unexpected packet waiting for stream ack");
+        }
+        Err(e) => {
+            return fmt_e!("Error while waiting for stream ack: {e}");
+        }
+    }
+
+    let settings = data_manager.read().settings().clone();
+
+    let stream_socket = tokio::select! {
+        res = stream_socket_connector.connect_to_client(
+            client_ip,
+            stream_port,
+            settings.connection.packet_size as _,
+        ) => res?,
+        _ = time::sleep(Duration::from_secs(5)) => {
+            return fmt_e!("Timeout while setting up streams");
+        }
+    };
+    let stream_socket = Arc::new(stream_socket);
+
+    info!("Companion client {client_hostname} connected");
+
+    // The hands would take over the controllers of the primary client, they are exposed as generic
+    // trackers instead. Any other device would collide with the ones of the primary client and is
+    // ignored.
+    let [left_hand_tracker_id, right_hand_tracker_id] = [LEFT_HAND_PATH, RIGHT_HAND_PATH]
+        .map(|path| hash_string(&companion_device_path(&client_hostname, path)));
+    let companion_device_id = move |id: u64| {
+        if id == *LEFT_HAND_ID {
+            Some(left_hand_tracker_id)
+        } else if id == *RIGHT_HAND_ID {
+            Some(right_hand_tracker_id)
+        } else {
+            None
+        }
+    };
+
+    let tracking_receive_loop = {
+        let mut receiver = stream_socket
+            .subscribe_to_stream::<Tracking>(TRACKING)
+            .await?;
+        let backend = Arc::clone(&backend);
+        async move {
+            let mut tracking_manager = TrackingManager::new(settings.headset);
+            tracking_manager.add_trackers([left_hand_tracker_id, right_hand_tracker_id]);
+
+            loop {
+                let tracking = receiver.recv().await?.header;
+
+                let device_motions = tracking
+                    .device_motions
+                    .into_iter()
+                    .filter_map(|(id, motion)| Some((companion_device_id(id)?, motion)))
+                    .collect();
+
+                tracking_manager.set_recentering(*PLAYSPACE_RECENTERING.lock());
+                let raw_motions = to_raw_motions(
+                    &mut tracking_manager,
+                    tracking.target_timestamp,
+                    device_motions,
+                    false,
+                    false,
+                );
+                backend.set_tracking(
+                    tracking.target_timestamp,
+                    &raw_motions,
+                    to_oculus_hand(None, None, false),
+                    to_oculus_hand(None, None, false),
+                );
+            }
+        }
+    };
+
+    let keepalive_loop = {
+        let control_sender = Arc::clone(&control_sender);
+        let client_hostname = client_hostname.clone();
+        async move {
+            loop {
+                let res = control_sender
+                    .lock()
+                    .await
+                    .send(&ServerControlPacket::KeepAlive)
+                    .await;
+                if let Err(e) = res {
+                    info!("Companion client {client_hostname} disconnected. Cause: {e}");
+                    break Ok(());
+                }
+
+                time::sleep(KEEPALIVE_INTERVAL).await;
+            }
+        }
+    };
+
+    // The buttons are ignored, generic trackers have no input profile to bind them to
+    let control_loop = async move {
+        loop {
+            match control_receiver.recv().await {
+                Ok(ClientControlPacket::Battery(packet)) => {
+                    if let Some(device_id) = companion_device_id(packet.device_id) {
+                        backend.set_battery(device_id, packet.gauge_value, packet.is_plugged);
+                    }
+                }
+                Ok(ClientControlPacket::Log { level, message }) => {
+                    info!("Client {client_hostname}: [{level:?}] {message}")
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    info!("Companion client {client_hostname} disconnected. Cause: {e}");
+                    break Ok(());
+                }
+            }
+        }
+    };
+
+    let receive_loop = async move { stream_socket.receive_loop().await };
+
+    tokio::select! {
+        res = spawn_cancelable(receive_loop) => {
+            if let Err(e) = res {
+                info!("Companion client disconnected. Cause: {e}");
+            }
+
+            Ok(())
+        },
+        res = spawn_cancelable(tracking_receive_loop) => res,
+
+        res = keepalive_loop => res,
+        res = control_loop => res,
+
+        _ = RESTART_NOTIFIER.notified() => {
+            control_sender
+                .lock()
+                .await
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use alvr_client_core::{ClientBackend, StreamConfigInput, StreamContext};
+    use alvr_common::once_cell::sync::Lazy;
+    use alvr_sockets::{
+        simulated_transport_pair, LinkImpairments, SimulatedStreamPeer, StreamSocketBuilder,
+        VideoFrameHeaderPacket,
+    };
+    use std::net::Ipv4Addr;
+
+    const PACKET_SIZE: usize = 1400;
+    const REFRESH_RATE: f32 = 72.0;
+    const FRAMES_COUNT: u64 = 20;
+    const FRAME_SIZE: usize = 3000;
+
+    // Default session, without audio so that no audio device is needed
+    static DATA_MANAGER: Lazy<parking_lot::RwLock<ServerDataManager>> = Lazy::new(|| {
+        let session_path = std::env::temp_dir()
+            .join(format!("alvr_test_{}", std::process::id()))
+            .join("session.json");
+        let mut data_manager = ServerDataManager::new(&session_path);
+        {
+            let mut session = data_manager.session_mut();
+            session.session_settings.audio.game_audio.enabled = false;
+            session.session_settings.audio.microphone.enabled = false;
+        }
+
+        parking_lot::RwLock::new(data_manager)
+    });
+
+    #[derive(Default)]
+    struct MockDecoder {
+        frames: parking_lot::Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl ClientBackend for MockDecoder {
+        fn set_stream_config(&self, _: StreamConfigInput) {}
+
+        fn create_decoder(&self, _: Vec<u8>) {}
+
+        fn push_nal(&self, _: Duration, nal: Vec<u8>) {
+            self.frames.lock().push(nal);
+        }
+    }
+
+    // Returns the server and the client sides of a control connection over localhost
+    async fn connect(
+        client_features: &[&str],
+    ) -> (
+        (ProtoControlSocket, NegotiatedProtocol),
+        (ProtoControlSocket, NegotiatedProtocol),
+    ) {
+        let client_identity = TlsIdentity::generate("client.alvr").unwrap();
+        let server_identity = TlsIdentity::generate("server.alvr").unwrap();
+        let listener = alvr_sockets::get_server_listener(0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let ((mut client_socket, _), (mut server_socket, _)) = tokio::try_join!(
+            ProtoControlSocket::connect_to(PeerType::Server(&listener, &client_identity)),
+            ProtoControlSocket::connect_to(PeerType::AnyClient(
+                vec![Ipv4Addr::LOCALHOST.into()],
+                port,
+                &server_identity,
+            )),
+        )
+        .unwrap();
+
+        let (server_protocol, client_protocol) = tokio::try_join!(
+            server_socket.negotiate_protocol(&protocol_hello()),
+            client_socket.negotiate_protocol(&ProtocolHello::new(client_features)),
+        )
+        .unwrap();
+
+        (
+            (server_socket, server_protocol),
+            (client_socket, client_protocol),
+        )
+    }
+
+    fn new_session(role: ClientRole, stream_port: u16) -> ClientSession {
+        ClientSession {
+            role,
+            stream_port,
+            control_channel_sender: None,
+            video_sender: None,
+            audio_sender: None,
+            haptics_sender: None,
+            statistics: None,
+        }
+    }
+
+    // The session is normally registered by the handshake
+    fn new_params(client_hostname: &str, protocol: NegotiatedProtocol) -> ConnectionParams {
+        CLIENT_SESSIONS.lock().insert(
+            client_hostname.to_owned(),
+            new_session(ClientRole::Primary, 9944),
+        );
+
+        ConnectionParams {
+            protocol,
+            client_hostname: client_hostname.to_owned(),
+            client_ip: Ipv4Addr::LOCALHOST.into(),
+            role: ClientRole::Primary,
+            stream_port: 9944,
+            microphone_sample_rate: 0,
+            game_audio_sample_rate: 0,
+            refresh_rate: REFRESH_RATE,
+        }
+    }
+
+    async fn wait_until(condition: impl Fn() -> bool) {
+        while !condition() {
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    // The video sender is registered once the pipeline is running
+    async fn video_sender(client_hostname: &str) -> tmpsc::UnboundedSender<VideoPacket> {
+        loop {
+            let maybe_sender = CLIENT_SESSIONS
+                .lock()
+                .get(client_hostname)
+                .and_then(|session| session.video_sender.clone());
+            if let Some(sender) = maybe_sender {
+                return sender;
+            }
+
+            time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    fn video_packet(video_frame_index: u64) -> VideoPacket {
+        // IDR slice
+        let mut payload = vec![0, 0, 0, 1, 0x65];
+        payload.extend(vec![video_frame_index as u8; FRAME_SIZE - 5]);
+
+        VideoPacket {
+            header: VideoFrameHeaderPacket {
+                packet_counter: video_frame_index as _,
+                tracking_frame_index: video_frame_index,
+                video_frame_index,
+                sent_time: 0,
+                frame_byte_size: FRAME_SIZE as _,
+                fec_index: 0,
+                fec_percentage: 0,
+            },
+            payload,
+        }
+    }
+
+    // The test plays the client, over a simulated stream transport
+    #[tokio::test]
+    async fn connection_pipeline_serves_client() {
+        let client_hostname = "pipeline.client.alvr";
+        let ((server_control, protocol), (client_control, _)) =
+            connect(&[features::CODEC_H264]).await;
+        let (server_transport, client_transport) =
+            simulated_transport_pair(LinkImpairments::default(), LinkImpairments::default(), 0);
+
+        let backend = Arc::new(MockBackend::default());
+        let (control_sender, control_receiver) = server_control.split();
+        let server_pipeline = connection_pipeline(
+            Arc::clone(&backend) as Arc<dyn ServerBackend>,
+            &DATA_MANAGER,
+            new_params(client_hostname, protocol),
+            StreamSocketConnector::new(Box::new(SimulatedStreamPeer(server_transport))),
+            control_sender,
+            control_receiver,
+        );
+
+        let client = async {
+            let (mut control_sender, mut control_receiver) = client_control.split();
+            assert!(matches!(
+                control_receiver.recv().await.unwrap(),
+                ServerControlPacket::StartStream
+            ));
+
+            let stream_socket =
+                StreamSocketBuilder::new(Box::new(SimulatedStreamPeer(client_transport)))
+                    .accept_from_server(Ipv4Addr::LOCALHOST.into(), 9944, PACKET_SIZE)
+                    .await
+                    .unwrap();
+            let stream_socket = Arc::new(stream_socket);
+            let mut video_receiver = stream_socket
+                .subscribe_to_stream::<VideoFrameHeaderPacket>(VIDEO)
+                .await
+                .unwrap();
+            let mut tracking_sender = stream_socket.request_stream(TRACKING).await.unwrap();
+            tokio::spawn({
+                let stream_socket = Arc::clone(&stream_socket);
+                async move { stream_socket.receive_loop().await }
+            });
+
+            control_sender
+                .send(&ClientControlPacket::StreamReady)
+                .await
+                .unwrap();
+
+            let tracking = Tracking {
+                target_timestamp: Duration::from_millis(10),
+                device_motions: vec![],
+                left_hand_skeleton: None,
+                right_hand_skeleton: None,
+            };
+            tracking_sender
+                .send_buffer(tracking_sender.new_buffer(&tracking, 0).unwrap())
+                .await
+                .unwrap();
+            wait_until(|| !backend.tracking_timestamps.lock().is_empty()).await;
+            assert_eq!(
+                *backend.tracking_timestamps.lock(),
+                [Duration::from_millis(10)]
+            );
+            assert!(*backend.is_streaming.lock());
+
+            let packet = video_packet(0);
+            let payload = packet.payload.clone();
+            video_sender(client_hostname).await.send(packet).unwrap();
+            let received = video_receiver.recv().await.unwrap();
+            assert_eq!(received.header.video_frame_index, 0);
+            assert_eq!(&received.buffer[..], &payload[..]);
+
+            control_sender
+                .send(&ClientControlPacket::VideoErrorReport)
+                .await
+                .unwrap();
+            wait_until(|| *backend.video_error_reports.lock() == 1).await;
+        };
+
+        tokio::select! {
+            res = server_pipeline => panic!("Server pipeline ended: {res:?}"),
+            _ = client => (),
+        }
+
+        // The stream is closed when the pipeline is dropped
+        assert!(!*backend.is_streaming.lock());
+        CLIENT_SESSIONS.lock().remove(client_hostname);
+    }
+
+    // Both pipelines run in the same process, connected by a simulated stream transport
+    #[tokio::test]
+    async fn loopback_video_reaches_client_decoder() {
+        static IS_RESUMED: RelaxedAtomic = RelaxedAtomic::new(true);
+
+        let client_hostname = "loopback.client.alvr";
+        let ((server_control, server_protocol), (client_control, client_protocol)) =
+            connect(&[features::CODEC_H264, features::FEC, features::NACK]).await;
+        let (server_transport, client_transport) =
+            simulated_transport_pair(LinkImpairments::default(), LinkImpairments::default(), 0);
+
+        let (control_sender, control_receiver) = server_control.split();
+        let server_pipeline = connection_pipeline(
+            Arc::new(MockBackend::default()),
+            &DATA_MANAGER,
+            new_params(client_hostname, server_protocol),
+            StreamSocketConnector::new(Box::new(SimulatedStreamPeer(server_transport))),
+            control_sender,
+            control_receiver,
+        );
+
+        let decoder = Arc::new(MockDecoder::default());
+        let context = StreamContext {
+            backend: Arc::clone(&decoder) as Arc<dyn ClientBackend>,
+            decoder_guard: Arc::new(Mutex::new(())),
+            is_resumed: &IS_RESUMED,
+        };
+        let stream_config = StreamConfigPacket {
+            session_desc: serde_json::to_string(DATA_MANAGER.read().session()).unwrap(),
+            view_resolution: UVec2::new(1024, 1024),
+            fps: REFRESH_RATE,
+            game_audio_sample_rate: 0,
+        };
+        let client_pipeline = alvr_client_core::stream_pipeline(
+            &context,
+            client_protocol,
+            client_control,
+            stream_config,
+            StreamSocketBuilder::new(Box::new(SimulatedStreamPeer(client_transport))),
+            Ipv4Addr::LOCALHOST.into(),
+        );
+
+        let frames_check = async {
+            let video_sender = video_sender(client_hostname).await;
+            for video_frame_index in 0..FRAMES_COUNT {
+                video_sender.send(video_packet(video_frame_index)).unwrap();
+            }
+
+            wait_until(|| decoder.frames.lock().len() == FRAMES_COUNT as usize).await;
+        };
+
+        tokio::select! {
+            res = server_pipeline => panic!("Server pipeline ended: {res:?}"),
+            res = client_pipeline => panic!("Client pipeline ended: {res:?}"),
+            _ = frames_check => (),
+        }
+
+        let frames = decoder.frames.lock();
+        for (video_frame_index, frame) in frames.iter().enumerate() {
+            assert_eq!(*frame, video_packet(video_frame_index as _).payload);
+        }
+        CLIENT_SESSIONS.lock().remove(client_hostname);
+    }
+
+    #[test]
+    fn client_roles_and_stream_ports() {
+        let mut config = DATA_MANAGER.read().settings().connection.clone();
+        config.stream_port = 9944;
+        config.max_spectators = 1;
+        config.max_companions = 2;
+
+        let mut sessions = HashMap::new();
+
+        // Companions never drive the stream, even if they connect first
+        let (role, port) = client_role(&sessions, &config, true).unwrap();
+        assert!(role == ClientRole::Companion && port == 9945);
+        sessions.insert("companion1".into(), new_session(role, port));
+
+        let (role, port) = client_role(&sessions, &config, false).unwrap();
+        assert!(role == ClientRole::Primary && port == 9944);
+        sessions.insert("primary".into(), new_session(role, port));
+
+        // The ports in use are skipped
+        let (role, port) = client_role(&sessions, &config, false).unwrap();
+        assert!(role == ClientRole::Spectator && port == 9946);
+        sessions.insert("spectator".into(), new_session(role, port));
+
+        assert!(client_role(&sessions, &config, false).is_err());
+
+        let (role, port) = client_role(&sessions, &config, true).unwrap();
+        assert!(role == ClientRole::Companion && port == 9947);
+        sessions.insert("companion2".into(), new_session(role, port));
+
+        assert!(client_role(&sessions, &config, true).is_err());
+
+        // The port of a disconnected client is reused
+        sessions.remove("companion1");
+        let (role, port) = client_role(&sessions, &config, true).unwrap();
+        assert!(role == ClientRole::Companion && port == 9945);
+    }
+
+    #[test]
+    fn no_free_stream_port() {
+        let mut config = DATA_MANAGER.read().settings().connection.clone();
+        config.stream_port = u16::MAX;
+
+        let sessions = HashMap::from([(
+            "primary".to_owned(),
+            new_session(ClientRole::Primary, u16::MAX),
+        )]);
+        assert!(client_role(&sessions, &config, true).is_err());
+    }
+
+    #[test]
+    fn companion_trackers_registered_once() {
+        let mut generic_trackers = vec![];
+        assert!(register_companion_trackers(&mut generic_trackers, "phone"));
+        assert_eq!(
+            generic_trackers
+                .iter()
+                .map(|tracker| tracker.path.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "/companion/phone/user/hand/left",
+                "/companion/phone/user/hand/right"
+            ]
+        );
+        assert!(generic_trackers
+            .iter()
+            .all(|tracker| is_companion_device_path(&tracker.path)));
+
+        // A reconnection does not restart SteamVR
+        assert!(!register_companion_trackers(&mut generic_trackers, "phone"));
+        assert_eq!(generic_trackers.len(), 2);
+
+        assert!(register_companion_trackers(&mut generic_trackers, "tablet"));
+        assert_eq!(generic_trackers.len(), 4);
+    }
+
+    #[test]
+    fn spectator_idr_requests_rate_limited() {
+        let epoch = Instant::now();
+        let mut last_request = None;
+
+        assert!(spectator_idr_allowed(&mut last_request, epoch));
+        assert!(!spectator_idr_allowed(
+            &mut last_request,
+            epoch + SPECTATOR_IDR_MIN_INTERVAL / 2
+        ));
+        // The rejected request does not delay the next one
+        assert!(spectator_idr_allowed(
+            &mut last_request,
+            epoch + SPECTATOR_IDR_MIN_INTERVAL
+        ));
+    }
+
+    #[test]
+    fn spectator_promoted_when_primary_disconnects() {
+        let mut sessions = HashMap::new();
+        let mut control_receivers = HashMap::new();
+        for (hostname, role, stream_port) in [
+            ("primary", ClientRole::Primary, 9944),
+            ("spectator1", ClientRole::Spectator, 9945),
+            ("companion", ClientRole::Companion, 9946),
+            ("spectator2", ClientRole::Spectator, 9947),
+        ] {
+            let (sender, receiver) = tmpsc::unbounded_channel();
+            let mut session = new_session(role, stream_port);
+            session.control_channel_sender = Some(sender);
+            sessions.insert(hostname.to_owned(), session);
+            control_receivers.insert(hostname, receiver);
+        }
+
+        // The spectator with the lowest port is asked to reconnect
+        remove_client_session(&mut sessions, "primary");
+        assert_eq!(sessions.len(), 3);
+        assert!(matches!(
+            control_receivers.get_mut("spectator1").unwrap().try_recv(),
+            Ok(ServerControlPacket::Restarting)
+        ));
+        assert!(control_receivers
+            .values_mut()
+            .all(|receiver| receiver.try_recv().is_err()));
+
+        // Nobody is promoted when a spectator leaves
+        remove_client_session(&mut sessions, "spectator2");
+        assert!(control_receivers
+            .values_mut()
+            .all(|receiver| receiver.try_recv().is_err()));
+
+        // The promoted spectator takes the primary role once it reconnects
+        remove_client_session(&mut sessions, "spectator1");
+        let config = DATA_MANAGER.read().settings().connection.clone();
+        let (role, port) = client_role(&sessions, &config, false).unwrap();
+        assert!(role == ClientRole::Primary && port == config.stream_port);
+    }
+}
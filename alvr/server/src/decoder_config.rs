@@ -0,0 +1,263 @@
+// Decoder configuration records and length prefixed NAL units of H264 and HEVC, as defined by
+// ISO/IEC 14496-15. They are used by the video recordings and by the video mirror.
+
+use alvr_common::prelude::*;
+use alvr_session::CodecType;
+use alvr_sockets::{annex_b_nals, strip_start_code};
+
+// Removes the 0x03 bytes inserted after two zero bytes to avoid start code emulation
+fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(nal.len());
+    let mut zeros_count = 0;
+    for &byte in nal {
+        if zeros_count >= 2 && byte == 3 {
+            zeros_count = 0;
+            continue;
+        }
+
+        zeros_count = if byte == 0 { zeros_count + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+
+    rbsp
+}
+
+// Matroska and WebCodecs, when given a description, expect H264 and HEVC NAL units with a 4 byte
+// length instead of a start code
+pub fn write_length_prefixed(annex_b: &[u8], buffer: &mut Vec<u8>) {
+    for nal in annex_b_nals(annex_b) {
+        let nal = strip_start_code(nal);
+        buffer.extend((nal.len() as u32).to_be_bytes());
+        buffer.extend_from_slice(nal);
+    }
+}
+
+// NAL units of the configuration with their type, without the start code
+fn config_nals(codec: CodecType, config: &[u8]) -> Vec<(u8, &[u8])> {
+    annex_b_nals(config)
+        .into_iter()
+        .filter_map(|nal| Some((alvr_sockets::nal_type(codec, nal)?, strip_start_code(nal))))
+        .collect()
+}
+
+// AVCDecoderConfigurationRecord, built from the SPS and PPS
+pub fn avc_configuration_record(config: &[u8]) -> StrResult<Vec<u8>> {
+    let nals = config_nals(CodecType::H264, config);
+    let sps = nals
+        .iter()
+        .filter(|(nal_type, _)| *nal_type == 7)
+        .map(|(_, nal)| *nal)
+        .collect::<Vec<_>>();
+    let pps = nals
+        .iter()
+        .filter(|(nal_type, _)| *nal_type == 8)
+        .map(|(_, nal)| *nal)
+        .collect::<Vec<_>>();
+
+    // Profile, constraint flags and level
+    let first_sps = sps
+        .first()
+        .filter(|sps| sps.len() >= 4)
+        .ok_or_else(enone!())?;
+    let mut record = vec![1, first_sps[1], first_sps[2], first_sps[3], 0xFF];
+
+    record.push(0xE0 | sps.len() as u8);
+    for nal in sps {
+        record.extend((nal.len() as u16).to_be_bytes());
+        record.extend_from_slice(nal);
+    }
+
+    record.push(pps.len() as u8);
+    for nal in pps {
+        record.extend((nal.len() as u16).to_be_bytes());
+        record.extend_from_slice(nal);
+    }
+
+    Ok(record)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> StrResult<u32> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.position / 8).ok_or_else(enone!())?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+
+        Ok(value)
+    }
+
+    fn read_flag(&mut self) -> StrResult<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    fn skip(&mut self, bits: u32) -> StrResult {
+        self.read(bits).map(|_| ())
+    }
+
+    // Exp-Golomb coded unsigned integer
+    fn read_ue(&mut self) -> StrResult<u32> {
+        let mut leading_zeros = 0;
+        while !self.read_flag()? {
+            leading_zeros += 1;
+            if leading_zeros >= 32 {
+                return fmt_e!("Invalid exp-Golomb code");
+            }
+        }
+
+        Ok((1 << leading_zeros) - 1 + self.read(leading_zeros)?)
+    }
+}
+
+// Fields of the SPS that are repeated in the HEVCDecoderConfigurationRecord
+struct HevcSpsInfo {
+    max_sub_layers_minus1: u8,
+    temporal_id_nesting: bool,
+    chroma_format_idc: u8,
+    bit_depth_luma_minus8: u8,
+    bit_depth_chroma_minus8: u8,
+}
+
+// `sps` is the RBSP, including the NAL header
+fn parse_hevc_sps(sps: &[u8]) -> StrResult<HevcSpsInfo> {
+    let mut reader = BitReader {
+        data: sps.get(2..).ok_or_else(enone!())?,
+        position: 0,
+    };
+
+    let _sps_video_parameter_set_id = reader.read(4)?;
+    let max_sub_layers_minus1 = reader.read(3)? as u8;
+    let temporal_id_nesting = reader.read_flag()?;
+
+    // profile_tier_level: general fields, then the optional sub-layer fields
+    reader.skip(96)?;
+    let mut sub_layers_present = vec![];
+    for _ in 0..max_sub_layers_minus1 {
+        let profile_present = reader.read_flag()?;
+        let level_present = reader.read_flag()?;
+        sub_layers_present.push((profile_present, level_present));
+    }
+    if max_sub_layers_minus1 > 0 {
+        // reserved_zero_2bits
+        reader.skip(2 * (8 - max_sub_layers_minus1 as u32))?;
+    }
+    for (profile_present, level_present) in sub_layers_present {
+        if profile_present {
+            reader.skip(88)?;
+        }
+        if level_present {
+            reader.skip(8)?;
+        }
+    }
+
+    let _sps_seq_parameter_set_id = reader.read_ue()?;
+    let chroma_format_idc = reader.read_ue()? as u8;
+    if chroma_format_idc == 3 {
+        let _separate_colour_plane_flag = reader.read_flag()?;
+    }
+    let _pic_width_in_luma_samples = reader.read_ue()?;
+    let _pic_height_in_luma_samples = reader.read_ue()?;
+    if reader.read_flag()? {
+        // conformance window offsets
+        for _ in 0..4 {
+            reader.read_ue()?;
+        }
+    }
+    let bit_depth_luma_minus8 = reader.read_ue()? as u8;
+    let bit_depth_chroma_minus8 = reader.read_ue()? as u8;
+
+    Ok(HevcSpsInfo {
+        max_sub_layers_minus1,
+        temporal_id_nesting,
+        chroma_format_idc,
+        bit_depth_luma_minus8,
+        bit_depth_chroma_minus8,
+    })
+}
+
+// HEVCDecoderConfigurationRecord, built from the VPS, SPS and PPS. The profile, tier and level, the
+// chroma format, the bit depths and the temporal layers are read from the first SPS.
+pub fn hevc_configuration_record(config: &[u8]) -> StrResult<Vec<u8>> {
+    let nals = config_nals(CodecType::HEVC, config);
+
+    let sps = nals
+        .iter()
+        .find(|(nal_type, _)| *nal_type == 33)
+        .map(|(_, nal)| remove_emulation_prevention(nal))
+        .ok_or_else(enone!())?;
+    // From general_profile_space to general_level_idc, after the NAL header and the first byte
+    let profile_tier_level = sps.get(3..15).ok_or_else(enone!())?;
+    let sps_info = parse_hevc_sps(&sps)?;
+
+    let mut record = vec![1];
+    record.extend_from_slice(profile_tier_level);
+    // min_spatial_segmentation_idc and parallelismType
+    record.extend([0xF0, 0x00, 0xFC]);
+    record.push(0xFC | (sps_info.chroma_format_idc & 0x03));
+    record.push(0xF8 | (sps_info.bit_depth_luma_minus8 & 0x07));
+    record.push(0xF8 | (sps_info.bit_depth_chroma_minus8 & 0x07));
+    // avgFrameRate
+    record.extend([0x00, 0x00]);
+    // numTemporalLayers, temporalIdNested and 4 byte NAL unit lengths
+    record.push(
+        ((sps_info.max_sub_layers_minus1 + 1) << 3)
+            | ((sps_info.temporal_id_nesting as u8) << 2)
+            | 0x03,
+    );
+
+    // VPS, SPS and PPS arrays
+    let array_types = [32, 33, 34];
+    record.push(array_types.len() as u8);
+    for array_type in array_types {
+        let array = nals
+            .iter()
+            .filter(|(nal_type, _)| *nal_type == array_type)
+            .map(|(_, nal)| *nal)
+            .collect::<Vec<_>>();
+
+        record.push(0x80 | array_type);
+        record.extend((array.len() as u16).to_be_bytes());
+        for nal in array {
+            record.extend((nal.len() as u16).to_be_bytes());
+            record.extend_from_slice(nal);
+        }
+    }
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hevc_record_from_parameter_sets() {
+        let config = [
+            0, 0, 0, 1, 0x40, 0x01, 0x0C, // VPS
+            0, 0, 0, 1, 0x42, 0x01, 0x01, 0x01, 0x60, 0x00, 0x00, 0x03, 0x00, 0x90, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x03, 0x00, 0x5D, 0xA0, 0x20, 0x82, 0x13,
+            0x70, // SPS, with emulation prevention bytes
+            0, 0, 0, 1, 0x44, 0x01, 0xC1, // PPS
+        ];
+
+        let record = hevc_configuration_record(&config).unwrap();
+
+        // Main profile, level 3.1
+        assert_eq!(
+            record[..13],
+            [1, 0x01, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 0x5D]
+        );
+        // 4:2:0, 10 bit luma and chroma, one nested temporal layer
+        assert_eq!(record[16..19], [0xFD, 0xFA, 0xFA]);
+        assert_eq!(record[21], 0x0F);
+        // Three arrays, starting with the VPS
+        assert_eq!(record[22..29], [3, 0xA0, 0, 1, 0, 3, 0x40]);
+    }
+}
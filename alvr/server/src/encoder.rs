@@ -0,0 +1,69 @@
+// Glue between the C++ software encoding pipeline and the encoders of alvr_encoder
+
+use crate::{send_decoder_config, send_video_packet, CLIENT_SESSIONS, SERVER_DATA_MANAGER};
+use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex};
+use alvr_encoder::{EncoderPipeline, EncoderSink, RawFrame, SoftwareEncoder};
+use alvr_sockets::VideoFrameHeaderPacket;
+use std::time::Duration;
+
+// Sends the frames to the clients and to the video mirror, like the C++ encoders
+struct ClientsSink;
+
+impl EncoderSink for ClientsSink {
+    fn report_frame_encoded(&self, target_timestamp: Duration) {
+        for session in CLIENT_SESSIONS.lock().values_mut() {
+            if let Some(stats) = &mut session.statistics {
+                stats.report_frame_encoded(target_timestamp);
+            }
+        }
+    }
+
+    fn send_decoder_config(&self, config_buffer: Vec<u8>) {
+        send_decoder_config(config_buffer);
+    }
+
+    fn send_video_packet(&self, header: VideoFrameHeaderPacket, payload: Vec<u8>) {
+        send_video_packet(header, payload);
+    }
+}
+
+static SOFTWARE_PIPELINE: Lazy<Mutex<Option<EncoderPipeline>>> = Lazy::new(|| Mutex::new(None));
+
+// Called by the C++ software encoding pipeline when use_openh264 is set. The encoder is recreated
+// when the frame size changes.
+pub fn encode_frame_software(frame: &RawFrame, target_timestamp: Duration, force_idr: bool) {
+    let mut pipeline = SOFTWARE_PIPELINE.lock();
+
+    let size_changed = pipeline.as_ref().map_or(true, |pipeline| {
+        pipeline.frame_size() != (frame.width, frame.height)
+    });
+    if size_changed {
+        let config = SERVER_DATA_MANAGER.read().session().openvr_config.clone();
+        *pipeline = alvr_common::show_err(SoftwareEncoder::new(
+            frame.width,
+            frame.height,
+            config.encode_bitrate_mbs * 1_000_000,
+            config.refresh_rate as _,
+            config.sw_thread_count,
+        ))
+        .map(|encoder| {
+            EncoderPipeline::new(
+                Box::new(encoder),
+                Box::new(ClientsSink),
+                frame.width,
+                frame.height,
+            )
+        });
+    }
+
+    if let Some(pipeline) = &mut *pipeline {
+        alvr_common::show_err(pipeline.push_frame(frame, target_timestamp, force_idr));
+    }
+}
+
+// Called by the C++ software encoding pipeline when the bitrate adapts to the network
+pub fn set_software_encoder_bitrate(bitrate_bps: u64) {
+    if let Some(pipeline) = &mut *SOFTWARE_PIPELINE.lock() {
+        alvr_common::show_err(pipeline.set_bitrate(bitrate_bps));
+    }
+}
@@ -0,0 +1,305 @@
+use alvr_common::*;
+use alvr_events::ButtonValue;
+use alvr_session::HandGesturesDesc;
+use alvr_sockets::HandSkeleton;
+
+// XrHandJointEXT indices
+const PALM: usize = 0;
+const THUMB_TIP: usize = 5;
+const INDEX_TIP: usize = 10;
+const MIDDLE_TIP: usize = 15;
+const RING_TIP: usize = 20;
+const LITTLE_TIP: usize = 25;
+
+// 0 at start_distance, 1 at end_distance
+fn gesture_value(distance: f32, start_distance: f32, end_distance: f32) -> f32 {
+    ((start_distance - distance) / (start_distance - end_distance)).clamp(0.0, 1.0)
+}
+
+struct ButtonIds {
+    trigger_value: u64,
+    trigger_click: u64,
+    trigger_touch: u64,
+    squeeze_value: u64,
+    squeeze_click: u64,
+    // Menu button on the left hand, A button on the right hand
+    face_click: u64,
+}
+
+// Derives the buttons of one hand from its skeleton. Only the buttons that changed since the last
+// update are returned, starting from all buttons released, so that the buttons of the controller
+// are not overridden while the hand is not tracked.
+pub struct GestureRecognizer {
+    config: HandGesturesDesc,
+    ids: ButtonIds,
+    trigger_pressed: bool,
+    squeeze_pressed: bool,
+    face_pressed: bool,
+    last_values: Vec<(u64, ButtonValue)>,
+}
+
+impl GestureRecognizer {
+    pub fn new(config: HandGesturesDesc, is_left: bool) -> Self {
+        let ids = if is_left {
+            ButtonIds {
+                trigger_value: *LEFT_TRIGGER_VALUE_ID,
+                trigger_click: *LEFT_TRIGGER_CLICK_ID,
+                trigger_touch: *LEFT_TRIGGER_TOUCH_ID,
+                squeeze_value: *LEFT_SQUEEZE_VALUE_ID,
+                squeeze_click: *LEFT_SQUEEZE_CLICK_ID,
+                face_click: *MENU_CLICK_ID,
+            }
+        } else {
+            ButtonIds {
+                trigger_value: *RIGHT_TRIGGER_VALUE_ID,
+                trigger_click: *RIGHT_TRIGGER_CLICK_ID,
+                trigger_touch: *RIGHT_TRIGGER_TOUCH_ID,
+                squeeze_value: *RIGHT_SQUEEZE_VALUE_ID,
+                squeeze_click: *RIGHT_SQUEEZE_CLICK_ID,
+                face_click: *A_CLICK_ID,
+            }
+        };
+
+        let mut recognizer = Self {
+            config,
+            ids,
+            trigger_pressed: false,
+            squeeze_pressed: false,
+            face_pressed: false,
+            last_values: vec![],
+        };
+        recognizer.last_values = recognizer.values(0.0, 0.0);
+
+        recognizer
+    }
+
+    fn values(&self, trigger: f32, squeeze: f32) -> Vec<(u64, ButtonValue)> {
+        vec![
+            (self.ids.trigger_value, ButtonValue::Scalar(trigger)),
+            (
+                self.ids.trigger_click,
+                ButtonValue::Binary(self.trigger_pressed),
+            ),
+            (self.ids.trigger_touch, ButtonValue::Binary(trigger > 0.0)),
+            (self.ids.squeeze_value, ButtonValue::Scalar(squeeze)),
+            (
+                self.ids.squeeze_click,
+                ButtonValue::Binary(self.squeeze_pressed),
+            ),
+            (self.ids.face_click, ButtonValue::Binary(self.face_pressed)),
+        ]
+    }
+
+    fn update_click(&self, pressed: bool, value: f32) -> bool {
+        if pressed {
+            value > self.config.click_release_threshold
+        } else {
+            value >= self.config.click_press_threshold
+        }
+    }
+
+    // With no skeleton, or with untracked fingertips, the buttons pressed by the gestures are
+    // released
+    pub fn update(&mut self, skeleton: Option<&HandSkeleton>) -> Vec<(u64, ButtonValue)> {
+        let joints = skeleton.map(|skeleton| &skeleton.joints).filter(|joints| {
+            [PALM, THUMB_TIP, INDEX_TIP, MIDDLE_TIP, RING_TIP, LITTLE_TIP]
+                .iter()
+                .all(|&joint| joints[joint].position_valid)
+        });
+
+        let (trigger, squeeze, face) = if let Some(joints) = joints {
+            let thumb_tip = joints[THUMB_TIP].position;
+            let pinch = |tip: usize| {
+                gesture_value(
+                    thumb_tip.distance(joints[tip].position),
+                    self.config.pinch_start_distance,
+                    self.config.pinch_end_distance,
+                )
+            };
+
+            let grab_distance = [MIDDLE_TIP, RING_TIP, LITTLE_TIP]
+                .iter()
+                .map(|&tip| joints[PALM].position.distance(joints[tip].position))
+                .sum::<f32>()
+                / 3.0;
+            let squeeze = gesture_value(
+                grab_distance,
+                self.config.grab_start_distance,
+                self.config.grab_end_distance,
+            );
+
+            (pinch(INDEX_TIP), squeeze, pinch(MIDDLE_TIP))
+        } else {
+            (0.0, 0.0, 0.0)
+        };
+
+        self.trigger_pressed = self.update_click(self.trigger_pressed, trigger);
+        self.squeeze_pressed = self.update_click(self.squeeze_pressed, squeeze);
+        // In a fist the thumb rests near the middle finger
+        self.face_pressed = self.update_click(self.face_pressed, face) && !self.squeeze_pressed;
+
+        let values = self.values(trigger, squeeze);
+
+        let changed = values
+            .iter()
+            .filter(|value| !self.last_values.contains(value))
+            .cloned()
+            .collect();
+        self.last_values = values;
+
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::glam::{Quat, Vec3};
+    use alvr_sockets::{HandJoint, HAND_JOINT_COUNT};
+
+    fn config() -> HandGesturesDesc {
+        HandGesturesDesc {
+            pinch_start_distance: 0.05,
+            pinch_end_distance: 0.015,
+            grab_start_distance: 0.08,
+            grab_end_distance: 0.04,
+            click_press_threshold: 0.9,
+            click_release_threshold: 0.7,
+        }
+    }
+
+    // Flat hand with the fingertips 9cm away from the palm. The thumb is pinch_distance away from
+    // the tip of the index finger, and the other fingertips are grab_distance away from the palm.
+    fn skeleton(pinch_distance: f32, grab_distance: f32) -> HandSkeleton {
+        let mut joints = [HandJoint {
+            orientation: Quat::IDENTITY,
+            position: Vec3::ZERO,
+            radius: 0.01,
+            orientation_valid: true,
+            position_valid: true,
+        }; HAND_JOINT_COUNT];
+
+        joints[INDEX_TIP].position = Vec3::new(-0.03, 0.0, -0.09);
+        joints[THUMB_TIP].position =
+            joints[INDEX_TIP].position + Vec3::new(-pinch_distance, 0.0, 0.0);
+        joints[MIDDLE_TIP].position = Vec3::new(0.0, 0.0, -grab_distance);
+        joints[RING_TIP].position = Vec3::new(0.02, 0.0, -grab_distance);
+        joints[LITTLE_TIP].position = Vec3::new(0.04, 0.0, -grab_distance);
+
+        HandSkeleton { joints }
+    }
+
+    fn value(events: &[(u64, ButtonValue)], id: u64) -> Option<ButtonValue> {
+        events
+            .iter()
+            .find(|(event_id, _)| *event_id == id)
+            .map(|(_, value)| value.clone())
+    }
+
+    #[test]
+    fn pinch_presses_trigger() {
+        let mut recognizer = GestureRecognizer::new(config(), true);
+        recognizer.update(Some(&skeleton(0.1, 0.09)));
+
+        let events = recognizer.update(Some(&skeleton(0.01, 0.09)));
+        assert_eq!(
+            value(&events, *LEFT_TRIGGER_VALUE_ID),
+            Some(ButtonValue::Scalar(1.0))
+        );
+        assert_eq!(
+            value(&events, *LEFT_TRIGGER_CLICK_ID),
+            Some(ButtonValue::Binary(true))
+        );
+        assert_eq!(
+            value(&events, *LEFT_TRIGGER_TOUCH_ID),
+            Some(ButtonValue::Binary(true))
+        );
+        // The grip did not change
+        assert_eq!(value(&events, *LEFT_SQUEEZE_VALUE_ID), None);
+    }
+
+    #[test]
+    fn trigger_click_has_hysteresis() {
+        let mut recognizer = GestureRecognizer::new(config(), false);
+
+        // Distance for a trigger value of 0.8, between the release and the press thresholds
+        let middle_distance = 0.05 - 0.8 * 0.035;
+
+        recognizer.update(Some(&skeleton(middle_distance, 0.09)));
+        assert!(!recognizer.trigger_pressed);
+
+        recognizer.update(Some(&skeleton(0.01, 0.09)));
+        assert!(recognizer.trigger_pressed);
+
+        let events = recognizer.update(Some(&skeleton(middle_distance, 0.09)));
+        assert!(recognizer.trigger_pressed);
+        assert_eq!(value(&events, *RIGHT_TRIGGER_CLICK_ID), None);
+
+        let events = recognizer.update(Some(&skeleton(0.05, 0.09)));
+        assert_eq!(
+            value(&events, *RIGHT_TRIGGER_CLICK_ID),
+            Some(ButtonValue::Binary(false))
+        );
+    }
+
+    #[test]
+    fn grab_presses_grip_and_blocks_face_button() {
+        let mut recognizer = GestureRecognizer::new(config(), false);
+        recognizer.update(Some(&skeleton(0.1, 0.09)));
+
+        // Fist, with the thumb resting on the middle finger
+        let mut fist = skeleton(0.1, 0.03);
+        fist.joints[THUMB_TIP].position = fist.joints[MIDDLE_TIP].position;
+
+        let events = recognizer.update(Some(&fist));
+        assert_eq!(
+            value(&events, *RIGHT_SQUEEZE_CLICK_ID),
+            Some(ButtonValue::Binary(true))
+        );
+        assert_eq!(value(&events, *A_CLICK_ID), None);
+    }
+
+    #[test]
+    fn middle_pinch_presses_menu() {
+        let mut recognizer = GestureRecognizer::new(config(), true);
+
+        let mut menu = skeleton(0.1, 0.09);
+        menu.joints[THUMB_TIP].position = menu.joints[MIDDLE_TIP].position;
+
+        let events = recognizer.update(Some(&menu));
+        assert_eq!(
+            value(&events, *MENU_CLICK_ID),
+            Some(ButtonValue::Binary(true))
+        );
+        assert_eq!(value(&events, *LEFT_TRIGGER_CLICK_ID), None);
+    }
+
+    #[test]
+    fn no_events_before_hand_is_tracked() {
+        let mut recognizer = GestureRecognizer::new(config(), true);
+        assert!(recognizer.update(None).is_empty());
+
+        // Open hand, nothing is pressed
+        let events = recognizer.update(Some(&skeleton(0.1, 0.09)));
+        assert!(events.is_empty());
+    }
+
+    #[test]
+    fn lost_hand_releases_buttons() {
+        let mut recognizer = GestureRecognizer::new(config(), true);
+        recognizer.update(Some(&skeleton(0.01, 0.03)));
+
+        let events = recognizer.update(None);
+        assert_eq!(
+            value(&events, *LEFT_TRIGGER_VALUE_ID),
+            Some(ButtonValue::Scalar(0.0))
+        );
+        assert_eq!(
+            value(&events, *LEFT_SQUEEZE_CLICK_ID),
+            Some(ButtonValue::Binary(false))
+        );
+
+        // Nothing changes while the hand stays lost
+        assert!(recognizer.update(None).is_empty());
+    }
+}
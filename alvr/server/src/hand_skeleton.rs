@@ -0,0 +1,204 @@
+// Conversion of the OpenXR hand skeletons received from the client to the bones of the SteamVR
+// skeletal input
+
+use alvr_common::glam::{Quat, Vec3};
+use alvr_sockets::{HandSkeleton, HAND_JOINT_COUNT};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+pub const STEAMVR_HAND_BONE_COUNT: usize = 31;
+
+// XrHandJointEXT indices
+const WRIST: usize = 1;
+const THUMB_DISTAL: usize = 4;
+const INDEX_DISTAL: usize = 9;
+const MIDDLE_DISTAL: usize = 14;
+const RING_DISTAL: usize = 19;
+const LITTLE_DISTAL: usize = 24;
+
+// The SteamVR bones from the wrist to the little fingertip have the index of the OpenXR joint. The
+// first joint of each finger is attached to the wrist, the others to the previous joint.
+const FINGER_ROOTS: [usize; 5] = [2, 6, 11, 16, 21];
+const FINGER_TIPS: [usize; 5] = [5, 10, 15, 20, 25];
+// The auxiliary bones 26 to 30 repeat the distal joints in the space of the root
+const AUX_JOINTS: [usize; 5] = [
+    THUMB_DISTAL,
+    INDEX_DISTAL,
+    MIDDLE_DISTAL,
+    RING_DISTAL,
+    LITTLE_DISTAL,
+];
+
+// OpenXR joints point -Z towards the fingertip, with +Y out of the back of the hand. SteamVR bones
+// point +X towards the fingertip on the left hand and -X on the right hand, and curl around +Z.
+const LEFT_BASIS: Quat = Quat::from_xyzw(FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2, 0.0);
+const RIGHT_BASIS: Quat = Quat::from_xyzw(0.0, -FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2);
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoneTransform {
+    pub orientation: Quat,
+    pub position: Vec3,
+}
+
+impl BoneTransform {
+    pub const IDENTITY: Self = Self {
+        orientation: Quat::IDENTITY,
+        position: Vec3::ZERO,
+    };
+
+    fn mul(&self, other: &BoneTransform) -> BoneTransform {
+        BoneTransform {
+            orientation: (self.orientation * other.orientation).normalize(),
+            position: self.position + self.orientation * other.position,
+        }
+    }
+}
+
+// Bones of the SteamVR skeletal input, relative to their parent bone. The root bone is the pose of
+// the device and `wrist` is the pose of the wrist relative to it. The tips are moved out by their
+// radius to the end of the fingers. None if any joint is not tracked.
+pub fn to_steamvr_bones(
+    skeleton: &HandSkeleton,
+    is_left: bool,
+    wrist: BoneTransform,
+) -> Option<[BoneTransform; STEAMVR_HAND_BONE_COUNT]> {
+    let basis = if is_left { LEFT_BASIS } else { RIGHT_BASIS };
+
+    if skeleton
+        .joints
+        .iter()
+        .skip(WRIST)
+        .any(|joint| !joint.orientation_valid || !joint.position_valid)
+    {
+        return None;
+    }
+
+    // Pose of a joint relative to another one, in the bone space of SteamVR
+    let relative = |joint: usize, parent: usize| {
+        let joint = &skeleton.joints[joint];
+        let parent_orientation = skeleton.joints[parent].orientation * basis;
+        BoneTransform {
+            orientation: (parent_orientation.inverse() * joint.orientation * basis).normalize(),
+            position: parent_orientation.inverse()
+                * (joint.position - skeleton.joints[parent].position),
+        }
+    };
+
+    let mut bones = [BoneTransform::IDENTITY; STEAMVR_HAND_BONE_COUNT];
+    bones[WRIST] = wrist;
+    for (joint, bone) in bones
+        .iter_mut()
+        .enumerate()
+        .take(HAND_JOINT_COUNT)
+        .skip(WRIST + 1)
+    {
+        let parent = if FINGER_ROOTS.contains(&joint) {
+            WRIST
+        } else {
+            joint - 1
+        };
+        *bone = relative(joint, parent);
+
+        if FINGER_TIPS.contains(&joint) {
+            let length = bone.position.length();
+            if length > f32::EPSILON {
+                bone.position *= (length + skeleton.joints[joint].radius) / length;
+            }
+        }
+    }
+    for (bone, joint) in AUX_JOINTS.into_iter().enumerate() {
+        bones[HAND_JOINT_COUNT + bone] = wrist.mul(&relative(joint, WRIST));
+    }
+
+    Some(bones)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_sockets::HandJoint;
+
+    const INDEX_PROXIMAL: usize = 7;
+    const INDEX_INTERMEDIATE: usize = 8;
+
+    // Flat hand with the palm facing down and the fingers pointing forward, with the joints of each
+    // finger 2 cm apart
+    fn stretched_hand() -> HandSkeleton {
+        let mut skeleton = HandSkeleton {
+            joints: [HandJoint {
+                orientation: Quat::IDENTITY,
+                position: Vec3::new(0.0, 1.0, -0.3),
+                radius: 0.01,
+                orientation_valid: true,
+                position_valid: true,
+            }; HAND_JOINT_COUNT],
+        };
+        for (finger, root) in FINGER_ROOTS.into_iter().enumerate() {
+            let tip = FINGER_TIPS[finger];
+            for joint in root..=tip {
+                skeleton.joints[joint].position = Vec3::new(
+                    finger as f32 * 0.02,
+                    1.0,
+                    -0.3 - (joint - root + 1) as f32 * 0.02,
+                );
+            }
+        }
+
+        skeleton
+    }
+
+    #[test]
+    fn steamvr_bones_follow_the_fingers() {
+        let wrist = BoneTransform {
+            orientation: Quat::from_rotation_y(0.3),
+            position: Vec3::new(0.0, 0.05, -0.05),
+        };
+
+        for (is_left, direction) in [(true, Vec3::X), (false, -Vec3::X)] {
+            let bones = to_steamvr_bones(&stretched_hand(), is_left, wrist).unwrap();
+
+            assert_eq!(bones[0], BoneTransform::IDENTITY);
+            assert_eq!(bones[WRIST], wrist);
+            for bone in &bones[WRIST + 1..HAND_JOINT_COUNT] {
+                assert!(bone.orientation.abs_diff_eq(Quat::IDENTITY, 1e-6));
+            }
+            // Phalanges are 2 cm long, the tips are extended by their 1 cm radius
+            assert!(bones[INDEX_DISTAL]
+                .position
+                .abs_diff_eq(direction * 0.02, 1e-6));
+            assert!(bones[INDEX_DISTAL + 1]
+                .position
+                .abs_diff_eq(direction * 0.03, 1e-6));
+        }
+    }
+
+    #[test]
+    fn aux_bones_are_distal_joints_in_root_space() {
+        let wrist = BoneTransform {
+            orientation: Quat::from_rotation_y(0.3),
+            position: Vec3::new(0.0, 0.05, -0.05),
+        };
+        let mut skeleton = stretched_hand();
+        // The index fingertip moves towards the palm
+        for joint in [INDEX_PROXIMAL, INDEX_INTERMEDIATE, INDEX_DISTAL] {
+            skeleton.joints[joint].orientation = Quat::from_rotation_x(-0.5);
+        }
+
+        let bones = to_steamvr_bones(&skeleton, true, wrist).unwrap();
+
+        let mut distal = bones[WRIST];
+        for bone in &bones[6..=INDEX_DISTAL] {
+            distal = distal.mul(bone);
+        }
+        let aux = bones[HAND_JOINT_COUNT + 1];
+        assert!(aux.orientation.abs_diff_eq(distal.orientation, 1e-5));
+        assert!(aux.position.abs_diff_eq(distal.position, 1e-5));
+    }
+
+    #[test]
+    fn untracked_joint_has_no_steamvr_bones() {
+        let mut skeleton = stretched_hand();
+        skeleton.joints[RING_DISTAL].position_valid = false;
+
+        assert!(to_steamvr_bones(&skeleton, true, BoneTransform::IDENTITY).is_none());
+    }
+}
@@ -1,10 +1,18 @@
+mod backend;
 mod buttons;
 mod connection;
 mod dashboard;
+mod decoder_config;
+mod encoder;
+mod gestures;
+mod hand_skeleton;
 mod logging_backend;
+mod prediction;
 mod sockets;
 mod statistics;
 mod tracking;
+mod video_mirror;
+mod video_recording;
 mod web_server;
 
 #[allow(
@@ -29,15 +37,15 @@ use alvr_common::{
 use alvr_events::EventType;
 use alvr_filesystem::{self as afs, Layout};
 use alvr_server_data::ServerDataManager;
-use alvr_session::{OpenvrPropValue, OpenvrPropertyKey};
+use alvr_session::{CodecType, OpenvrPropValue, OpenvrPropertyKey};
 use alvr_sockets::{
     ClientListAction, GpuVendor, Haptics, ServerControlPacket, VideoFrameHeaderPacket,
 };
 use statistics::StatisticsManager;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ffi::{c_char, c_void, CStr, CString},
-    ptr,
+    ptr, slice,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc, Once,
@@ -45,10 +53,12 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessRefreshKind, System, SystemExt};
 use tokio::{
     runtime::Runtime,
     sync::{broadcast, mpsc, Notify},
 };
+use tracking::TransformStage;
 
 static FILESYSTEM_LAYOUT: Lazy<Layout> = Lazy::new(|| {
     afs::filesystem_layout_from_openvr_driver_root_dir(&alvr_commands::get_driver_dir().unwrap())
@@ -59,19 +69,48 @@ static WEBSERVER_RUNTIME: Lazy<Mutex<Option<Runtime>>> =
     Lazy::new(|| Mutex::new(Runtime::new().ok()));
 static WINDOW: Lazy<Mutex<Option<Arc<WindowType>>>> = Lazy::new(|| Mutex::new(None));
 
-static STATISTICS_MANAGER: Lazy<Mutex<Option<StatisticsManager>>> = Lazy::new(|| Mutex::new(None));
-
 pub struct VideoPacket {
     pub header: VideoFrameHeaderPacket,
     pub payload: Vec<u8>,
 }
 
-static CONTROL_CHANNEL_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<ServerControlPacket>>>> =
-    Lazy::new(|| Mutex::new(None));
-static VIDEO_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<VideoPacket>>>> =
-    Lazy::new(|| Mutex::new(None));
-static HAPTICS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<Haptics>>>> =
-    Lazy::new(|| Mutex::new(None));
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ClientRole {
+    // Drives the headset pose, the input and the encoder settings
+    Primary,
+    // Receives the video rendered for the primary client
+    Spectator,
+    // Sends only tracking and input, does not receive video
+    Companion,
+}
+
+// State of a connected client. The senders and the statistics are set when the stream starts
+pub struct ClientSession {
+    pub role: ClientRole,
+    pub stream_port: u16,
+    pub control_channel_sender: Option<mpsc::UnboundedSender<ServerControlPacket>>,
+    pub video_sender: Option<mpsc::UnboundedSender<VideoPacket>>,
+    // Game audio captured by the primary client connection. The primary sends its own copy.
+    pub audio_sender: Option<mpsc::UnboundedSender<Vec<u8>>>,
+    pub haptics_sender: Option<mpsc::UnboundedSender<Haptics>>,
+    pub statistics: Option<StatisticsManager>,
+}
+
+// Indexed by client hostname
+static CLIENT_SESSIONS: Lazy<Mutex<HashMap<String, ClientSession>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+// Hostnames of the clients waiting for the user to confirm the pairing code on the headset. The
+// codes are sent to the dashboard only through events, they are never stored in the session.
+static PAIRING_CLIENTS: Lazy<Mutex<HashSet<String>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+// Kept for the spectators that connect while the encoder is already running
+static DECODER_CONFIG: Lazy<Mutex<Option<Vec<u8>>>> = Lazy::new(|| Mutex::new(None));
+// Shared by all the spectators, so they cannot flood the encoder with keyframes
+static LAST_SPECTATOR_IDR_REQUEST: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+// Executable name of the application rendering in SteamVR, used to select the input profile
+static RUNNING_GAME: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+// Set by the primary client, for the companions which send no head pose
+static PLAYSPACE_RECENTERING: Lazy<Mutex<Option<TransformStage>>> = Lazy::new(|| Mutex::new(None));
+// Messages encoded by video_mirror, for the /api/video-mirror websockets
 static VIDEO_MIRROR_SENDER: Lazy<Mutex<Option<broadcast::Sender<Vec<u8>>>>> =
     Lazy::new(|| Mutex::new(None));
 
@@ -180,6 +219,83 @@ pub fn notify_application_update() {
     alvr_commands::invoke_application_update(&FILESYSTEM_LAYOUT.launcher_exe()).ok();
 }
 
+fn video_mirror_codec() -> CodecType {
+    connection::codec_from_config(SERVER_DATA_MANAGER.read().session().openvr_config.codec)
+}
+
+// Used by the websocket of new video mirror clients, before they receive the next frames
+fn video_mirror_config_message() -> Option<Vec<u8>> {
+    let config_buffer = DECODER_CONFIG.lock().clone()?;
+
+    video_mirror::config_message(video_mirror_codec(), &config_buffer)
+        .map_err(|e| warn!("Failed to create video mirror configuration: {e}"))
+        .ok()
+}
+
+// Used by both the C++ encoders and the encoders in Rust
+fn send_decoder_config(config_buffer: Vec<u8>) {
+    // The AV1 sequence header is sent with every keyframe, the decoders are reconfigured only if it
+    // changes. The lock is held so that clients that join meanwhile get the new configuration.
+    let mut decoder_config = DECODER_CONFIG.lock();
+    if decoder_config.as_ref() == Some(&config_buffer) {
+        return;
+    }
+
+    if let Some(sender) = &*VIDEO_MIRROR_SENDER.lock() {
+        match video_mirror::config_message(video_mirror_codec(), &config_buffer) {
+            Ok(message) => {
+                sender.send(message).ok();
+            }
+            Err(e) => warn!("Failed to create video mirror configuration: {e}"),
+        }
+    }
+
+    for session in CLIENT_SESSIONS.lock().values() {
+        if let Some(sender) = &session.control_channel_sender {
+            sender
+                .send(ServerControlPacket::InitializeDecoder {
+                    config_buffer: config_buffer.clone(),
+                })
+                .ok();
+        }
+    }
+
+    *decoder_config = Some(config_buffer);
+}
+
+fn send_video_packet(header: VideoFrameHeaderPacket, payload: Vec<u8>) {
+    // The frames are converted only while a dashboard is watching the mirror
+    if let Some(sender) = VIDEO_MIRROR_SENDER
+        .lock()
+        .as_ref()
+        .filter(|sender| sender.receiver_count() > 0)
+    {
+        sender
+            .send(video_mirror::frame_message(
+                video_mirror_codec(),
+                Duration::from_nanos(header.tracking_frame_index),
+                &payload,
+            ))
+            .ok();
+    }
+
+    // The primary client and the spectators receive the same frames
+    for session in CLIENT_SESSIONS.lock().values_mut() {
+        if let Some(sender) = &session.video_sender {
+            sender
+                .send(VideoPacket {
+                    header: header.clone(),
+                    payload: payload.clone(),
+                })
+                .ok();
+
+            if let Some(stats) = &mut session.statistics {
+                stats.report_video_packet(payload.len());
+            }
+        }
+    }
+}
+
 fn init() {
     let (log_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
     let (legacy_events_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
@@ -323,61 +439,94 @@ pub unsafe extern "C" fn HmdDriverFactory(
     }
 
     extern "C" fn initialize_decoder(buffer_ptr: *const u8, len: i32) {
-        if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
-            let mut config_buffer = vec![0; len as usize];
+        let mut config_buffer = vec![0; len as usize];
 
-            unsafe {
-                ptr::copy_nonoverlapping(buffer_ptr, config_buffer.as_mut_ptr(), len as usize)
-            };
+        unsafe { ptr::copy_nonoverlapping(buffer_ptr, config_buffer.as_mut_ptr(), len as usize) };
 
-            if let Some(sender) = &*VIDEO_MIRROR_SENDER.lock() {
-                sender.send(config_buffer.clone()).ok();
-            }
-
-            sender
-                .send(ServerControlPacket::InitializeDecoder { config_buffer })
-                .ok();
-        }
+        send_decoder_config(config_buffer);
     }
 
     extern "C" fn video_send(header: VideoFrame, buffer_ptr: *mut u8, len: i32) {
-        if let Some(sender) = &*VIDEO_SENDER.lock() {
-            let header = VideoFrameHeaderPacket {
-                packet_counter: header.packetCounter,
-                tracking_frame_index: header.trackingFrameIndex,
-                video_frame_index: header.videoFrameIndex,
-                sent_time: header.sentTime,
-                frame_byte_size: header.frameByteSize,
-                fec_index: header.fecIndex,
-                fec_percentage: header.fecPercentage,
-            };
-
-            let mut vec_buffer = vec![0; len as _];
+        let mut header = VideoFrameHeaderPacket {
+            packet_counter: header.packetCounter,
+            tracking_frame_index: header.trackingFrameIndex,
+            video_frame_index: header.videoFrameIndex,
+            sent_time: header.sentTime,
+            frame_byte_size: header.frameByteSize,
+            fec_index: header.fecIndex,
+            fec_percentage: header.fecPercentage,
+        };
+
+        let mut vec_buffer = vec![0; len as _];
+
+        // use copy_nonoverlapping (aka memcpy) to avoid freeing memory allocated by C++
+        unsafe {
+            ptr::copy_nonoverlapping(buffer_ptr, vec_buffer.as_mut_ptr(), len as _);
+        }
 
-            // use copy_nonoverlapping (aka memcpy) to avoid freeing memory allocated by C++
-            unsafe {
-                ptr::copy_nonoverlapping(buffer_ptr, vec_buffer.as_mut_ptr(), len as _);
+        // The C++ encoders only split the NAL units of H264 and HEVC. For AV1 the sequence header
+        // is extracted here
+        if SERVER_DATA_MANAGER.read().session().openvr_config.codec == CodecType::AV1 as u32 {
+            let frame = alvr_encoder::split_access_unit(CodecType::AV1, &vec_buffer);
+            if let Some(config_buffer) = frame.config_buffer {
+                send_decoder_config(config_buffer);
             }
 
-            if let Some(sender) = &*VIDEO_MIRROR_SENDER.lock() {
-                sender.send(vec_buffer.clone()).ok();
-            }
+            header.frame_byte_size = frame.nals.len() as _;
+            vec_buffer = frame.nals;
+        }
 
-            sender
-                .send(VideoPacket {
-                    header,
-                    payload: vec_buffer,
-                })
-                .ok();
+        send_video_packet(header, vec_buffer);
+    }
 
-            if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                stats.report_video_packet(len as _);
-            }
+    unsafe extern "C" fn encode_frame_software(
+        frame: RawVideoFrame,
+        target_timestamp_ns: u64,
+        idr: bool,
+    ) {
+        let uv_height = (frame.height as usize + 1) / 2;
+        let frame = alvr_encoder::RawFrame {
+            width: frame.width,
+            height: frame.height,
+            y: slice::from_raw_parts(frame.y, frame.yStride as usize * frame.height as usize),
+            u: slice::from_raw_parts(frame.u, frame.uvStride as usize * uv_height),
+            v: slice::from_raw_parts(frame.v, frame.uvStride as usize * uv_height),
+            y_stride: frame.yStride as _,
+            uv_stride: frame.uvStride as _,
+        };
+
+        encoder::encode_frame_software(&frame, Duration::from_nanos(target_timestamp_ns), idr);
+    }
+
+    extern "C" fn set_software_encoder_bitrate(bitrate_bps: u64) {
+        encoder::set_software_encoder_bitrate(bitrate_bps);
+    }
+
+    extern "C" fn scene_application_changed(pid: u32) {
+        // No process is reported while the compositor renders the scene
+        let game = if pid != 0 {
+            let pid = Pid::from_u32(pid);
+            let mut system = System::new();
+            system.refresh_process_specifics(pid, ProcessRefreshKind::new());
+            system.process(pid).map(|process| process.name().to_owned())
+        } else {
+            None
+        };
+
+        if let Some(game) = &game {
+            info!("Scene application changed: {game}");
         }
+        *RUNNING_GAME.lock() = game;
     }
 
     extern "C" fn haptics_send(path: u64, duration_s: f32, frequency: f32, amplitude: f32) {
-        if let Some(sender) = &*HAPTICS_SENDER.lock() {
+        // Only the primary client has controllers registered in SteamVR
+        if let Some(sender) = CLIENT_SESSIONS
+            .lock()
+            .values()
+            .find(|session| session.role == ClientRole::Primary)
+            .and_then(|session| session.haptics_sender.as_ref())
+        {
             let haptics = Haptics {
                 path,
                 duration: Duration::from_secs_f32(duration_s),
@@ -418,26 +567,34 @@ pub unsafe extern "C" fn HmdDriverFactory(
     }
 
     extern "C" fn report_present(timestamp_ns: u64) {
-        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-            stats.report_frame_present(Duration::from_nanos(timestamp_ns));
+        for session in CLIENT_SESSIONS.lock().values_mut() {
+            if let Some(stats) = &mut session.statistics {
+                stats.report_frame_present(Duration::from_nanos(timestamp_ns));
+            }
         }
     }
 
     extern "C" fn report_composed(timestamp_ns: u64) {
-        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-            stats.report_frame_composed(Duration::from_nanos(timestamp_ns));
+        for session in CLIENT_SESSIONS.lock().values_mut() {
+            if let Some(stats) = &mut session.statistics {
+                stats.report_frame_composed(Duration::from_nanos(timestamp_ns));
+            }
         }
     }
 
     extern "C" fn report_encoded(timestamp_ns: u64) {
-        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-            stats.report_frame_encoded(Duration::from_nanos(timestamp_ns));
+        for session in CLIENT_SESSIONS.lock().values_mut() {
+            if let Some(stats) = &mut session.statistics {
+                stats.report_frame_encoded(Duration::from_nanos(timestamp_ns));
+            }
         }
     }
 
     extern "C" fn report_fec_failure(percentage: i32) {
-        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-            stats.report_fec_failure(percentage as u32);
+        for session in CLIENT_SESSIONS.lock().values_mut() {
+            if let Some(stats) = &mut session.statistics {
+                stats.report_fec_failure(percentage as u32);
+            }
         }
     }
 
@@ -449,6 +606,9 @@ pub unsafe extern "C" fn HmdDriverFactory(
     DriverReadyIdle = Some(driver_ready_idle);
     InitializeDecoder = Some(initialize_decoder);
     VideoSend = Some(video_send);
+    EncodeFrameSoftware = Some(encode_frame_software);
+    SetSoftwareEncoderBitrate = Some(set_software_encoder_bitrate);
+    SceneApplicationChanged = Some(scene_application_changed);
     HapticsSend = Some(haptics_send);
     ShutdownRuntime = Some(_shutdown_runtime);
     PathStringToHash = Some(path_string_to_hash);
@@ -0,0 +1,246 @@
+use crate::tracking::TransformStage;
+use alvr_common::glam::{Quat, Vec3};
+use alvr_session::PredictionModel;
+use alvr_sockets::DeviceMotion;
+use std::{f32::consts::PI, time::Duration};
+
+// Weight of the new sample of a low pass filter with the given cutoff frequency
+fn smoothing_factor(dt_s: f32, cutoff_hz: f32) -> f32 {
+    let tau = 1.0 / (2.0 * PI * cutoff_hz);
+    1.0 / (1.0 + tau / dt_s)
+}
+
+// Extrapolates the motion of a device forward in time. The models other than ConstantVelocity
+// keep the previous sample, so there must be one predictor per device.
+pub struct Predictor {
+    model: PredictionModel,
+    offset_s: f32,
+    // Timestamp and motion of the previous sample. For OneEuro the motion is the filtered one.
+    last_sample: Option<(Duration, DeviceMotion)>,
+    // Linear and angular acceleration, for ConstantAcceleration
+    acceleration: (Vec3, Vec3),
+    // Low passed linear and angular speed, for OneEuro
+    speed: (f32, f32),
+}
+
+impl Predictor {
+    pub fn new(model: PredictionModel, offset_s: f32) -> Self {
+        Self {
+            model,
+            offset_s,
+            last_sample: None,
+            acceleration: (Vec3::ZERO, Vec3::ZERO),
+            speed: (0.0, 0.0),
+        }
+    }
+
+    pub fn predict(&mut self, timestamp: Duration, motion: DeviceMotion) -> DeviceMotion {
+        // Zero if this is the first sample, or if the packet is repeated or out of order
+        let dt_s = self
+            .last_sample
+            .as_ref()
+            .map(|(last_timestamp, _)| timestamp.saturating_sub(*last_timestamp).as_secs_f32())
+            .unwrap_or(0.0);
+
+        match self.model {
+            PredictionModel::ConstantVelocity => {
+                TransformStage::Prediction(self.offset_s).apply(motion)
+            }
+            PredictionModel::ConstantAcceleration => {
+                if let Some((_, last_motion)) = &self.last_sample {
+                    if dt_s > 0.0 {
+                        self.acceleration = (
+                            (motion.linear_velocity - last_motion.linear_velocity) / dt_s,
+                            (motion.angular_velocity - last_motion.angular_velocity) / dt_s,
+                        );
+                    }
+                }
+                if dt_s > 0.0 || self.last_sample.is_none() {
+                    self.last_sample = Some((timestamp, motion.clone()));
+                }
+
+                let t = self.offset_s;
+                let (linear_acceleration, angular_acceleration) = self.acceleration;
+                DeviceMotion {
+                    orientation: (Quat::from_scaled_axis(
+                        motion.angular_velocity * t + angular_acceleration * (0.5 * t * t),
+                    ) * motion.orientation)
+                        .normalize(),
+                    position: motion.position
+                        + motion.linear_velocity * t
+                        + linear_acceleration * (0.5 * t * t),
+                    linear_velocity: motion.linear_velocity + linear_acceleration * t,
+                    angular_velocity: motion.angular_velocity + angular_acceleration * t,
+                }
+            }
+            PredictionModel::OneEuro {
+                min_cutoff,
+                beta,
+                derivative_cutoff,
+            } => {
+                let filtered = match &self.last_sample {
+                    Some((_, last)) if dt_s > 0.0 => {
+                        let derivative_factor = smoothing_factor(dt_s, derivative_cutoff);
+
+                        let linear_speed = (motion.position - last.position).length() / dt_s;
+                        let angular_speed =
+                            last.orientation.angle_between(motion.orientation) / dt_s;
+                        self.speed.0 += derivative_factor * (linear_speed - self.speed.0);
+                        self.speed.1 += derivative_factor * (angular_speed - self.speed.1);
+
+                        let position_factor =
+                            smoothing_factor(dt_s, min_cutoff + beta * self.speed.0);
+                        let orientation_factor =
+                            smoothing_factor(dt_s, min_cutoff + beta * self.speed.1);
+
+                        DeviceMotion {
+                            position: last.position.lerp(motion.position, position_factor),
+                            orientation: last
+                                .orientation
+                                .slerp(motion.orientation, orientation_factor),
+                            ..motion
+                        }
+                    }
+                    Some((_, last)) => DeviceMotion {
+                        position: last.position,
+                        orientation: last.orientation,
+                        ..motion
+                    },
+                    None => motion,
+                };
+                if dt_s > 0.0 || self.last_sample.is_none() {
+                    self.last_sample = Some((timestamp, filtered.clone()));
+                }
+
+                TransformStage::Prediction(self.offset_s).apply(filtered)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_sockets::Tracking;
+
+    // Synthetic recording of a left controller swinging at 72 Hz, with noise added to the pose and
+    // to the velocities
+    const CONTROLLER_SWING: &str = include_str!("../resources/controller_swing.json");
+
+    // The pose is predicted two frames ahead, about 28ms
+    const LOOKAHEAD_FRAMES: usize = 2;
+    const WARMUP_FRAMES: usize = 10;
+
+    const ONE_EURO: PredictionModel = PredictionModel::OneEuro {
+        min_cutoff: 1.0,
+        beta: 5.0,
+        derivative_cutoff: 1.0,
+    };
+
+    fn load_recording() -> Vec<(Duration, DeviceMotion)> {
+        let packets: Vec<Tracking> = serde_json::from_str(CONTROLLER_SWING).unwrap();
+
+        packets
+            .into_iter()
+            .map(|mut packet| (packet.target_timestamp, packet.device_motions.remove(0).1))
+            .collect()
+    }
+
+    // Replays the recording and returns the mean position (m) and orientation (rad) errors between
+    // the predicted poses and the poses recorded later
+    fn prediction_error(model: PredictionModel, predict: bool) -> (f32, f32) {
+        let recording = load_recording();
+        let offset_s = if predict {
+            (recording[LOOKAHEAD_FRAMES].0 - recording[0].0).as_secs_f32()
+        } else {
+            0.0
+        };
+        let mut predictor = Predictor::new(model, offset_s);
+
+        let mut position_error = 0.0;
+        let mut orientation_error = 0.0;
+        let mut count = 0;
+        for (index, (timestamp, motion)) in recording.iter().enumerate() {
+            let predicted = predictor.predict(*timestamp, motion.clone());
+
+            if let Some((_, future)) = recording.get(index + LOOKAHEAD_FRAMES) {
+                if index >= WARMUP_FRAMES {
+                    position_error += predicted.position.distance(future.position);
+                    orientation_error += predicted.orientation.angle_between(future.orientation);
+                    count += 1;
+                }
+            }
+        }
+
+        (
+            position_error / count as f32,
+            orientation_error / count as f32,
+        )
+    }
+
+    // max_ratio: maximum error relative to the error without prediction
+    fn assert_error_ratio(model: PredictionModel, max_ratio: f32) {
+        let (baseline_position, baseline_orientation) =
+            prediction_error(PredictionModel::ConstantVelocity, false);
+        let (position, orientation) = prediction_error(model, true);
+
+        assert!(position < baseline_position * max_ratio);
+        assert!(orientation < baseline_orientation * max_ratio);
+    }
+
+    #[test]
+    fn constant_velocity_reduces_error() {
+        assert_error_ratio(PredictionModel::ConstantVelocity, 0.25);
+    }
+
+    #[test]
+    fn constant_acceleration_reduces_error() {
+        assert_error_ratio(PredictionModel::ConstantAcceleration, 0.25);
+    }
+
+    // The smoothing adds some lag, which is partly compensated by the prediction
+    #[test]
+    fn one_euro_reduces_error() {
+        assert_error_ratio(ONE_EURO, 0.75);
+    }
+
+    #[test]
+    fn one_euro_removes_jitter() {
+        let mut predictor = Predictor::new(ONE_EURO, 0.0);
+
+        // A still device with the position alternating by 2mm
+        let mut max_jitter: f32 = 0.0;
+        for index in 0..100 {
+            let offset = if index % 2 == 0 { 0.001 } else { -0.001 };
+            let predicted = predictor.predict(
+                Duration::from_secs_f32(index as f32 / 72.0),
+                DeviceMotion {
+                    position: Vec3::new(offset, 1.0, 0.0),
+                    ..Default::default()
+                },
+            );
+
+            if index >= WARMUP_FRAMES {
+                max_jitter = max_jitter.max(predicted.position.x.abs());
+            }
+        }
+
+        assert!(max_jitter < 0.0005);
+    }
+
+    #[test]
+    fn repeated_packet_keeps_state() {
+        let mut predictor = Predictor::new(PredictionModel::ConstantAcceleration, 0.1);
+        let timestamp = Duration::from_millis(100);
+
+        predictor.predict(Duration::ZERO, DeviceMotion::default());
+        let motion = DeviceMotion {
+            linear_velocity: Vec3::X,
+            ..Default::default()
+        };
+        let first = predictor.predict(timestamp, motion.clone());
+        let repeated = predictor.predict(timestamp, motion);
+
+        assert_eq!(first.position, repeated.position);
+    }
+}
@@ -1,29 +1,111 @@
-use alvr_common::{StrResult, *};
+use alvr_common::{prelude::*, ALVR_NAME};
 use alvr_events::EventType;
-use alvr_sockets::{CONTROL_PORT, HANDSHAKE_PACKET_SIZE_BYTES, LOCAL_IP};
+use alvr_sockets::{
+    bind_dual_stack, canonical_ip, is_link_local, register_peer_address, socket2, CONTROL_PORT,
+    HANDSHAKE_PACKET_SIZE_BYTES, MDNS_HOSTNAME_KEY, MDNS_SERVICE_TYPE,
+};
+use mdns_sd::{Receiver, ServiceDaemon, ServiceEvent};
 use std::{
+    collections::HashMap,
     io::ErrorKind,
     net::{IpAddr, UdpSocket},
+    time::{Duration, Instant},
 };
 
+// mDNS resolves a client only when it appears or changes, so the resolved clients are kept and
+// reported again periodically, like the clients that broadcast the UDP handshake packet
+const MDNS_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+struct MdnsClient {
+    hostname: String,
+    ip: IpAddr,
+    next_report: Instant,
+}
+
 pub struct WelcomeSocket {
     socket: UdpSocket,
     buffer: [u8; HANDSHAKE_PACKET_SIZE_BYTES],
+    mdns: Option<(ServiceDaemon, Receiver<ServiceEvent>)>,
+    // Indexed by the full name of the service
+    mdns_clients: HashMap<String, MdnsClient>,
 }
 
 impl WelcomeSocket {
     pub fn new() -> StrResult<Self> {
-        let socket = UdpSocket::bind((LOCAL_IP, CONTROL_PORT)).map_err(err!())?;
+        // Receives both the IPv4 broadcast and the IPv6 multicast packets
+        let socket: UdpSocket = bind_dual_stack(socket2::Type::DGRAM, CONTROL_PORT)?.into();
         socket.set_nonblocking(true).map_err(err!())?;
 
+        let mdns = ServiceDaemon::new()
+            .and_then(|daemon| {
+                let receiver = daemon.browse(MDNS_SERVICE_TYPE)?;
+                Ok((daemon, receiver))
+            })
+            .map_err(|e| warn!("mDNS discovery not available: {e}"))
+            .ok();
+
         Ok(Self {
             socket,
             buffer: [0; HANDSHAKE_PACKET_SIZE_BYTES],
+            mdns,
+            mdns_clients: HashMap::new(),
         })
     }
 
+    fn poll_mdns(&mut self) -> Option<(String, IpAddr)> {
+        let (_, receiver) = self.mdns.as_ref()?;
+
+        for event in receiver.try_iter() {
+            match event {
+                ServiceEvent::ServiceResolved(info) => {
+                    let hostname = info.get_property_val_str(MDNS_HOSTNAME_KEY);
+                    // mDNS does not report the scope ID needed for link-local IPv6 addresses
+                    let ip = info
+                        .get_addresses_v4()
+                        .into_iter()
+                        .next()
+                        .map(|ip| IpAddr::V4(*ip))
+                        .or_else(|| {
+                            info.get_addresses()
+                                .iter()
+                                .copied()
+                                .find(|ip| matches!(ip, IpAddr::V6(ip) if !is_link_local(ip)))
+                        });
+
+                    if let (Some(hostname), Some(ip)) = (hostname, ip) {
+                        self.mdns_clients.insert(
+                            info.get_fullname().to_owned(),
+                            MdnsClient {
+                                hostname: hostname.to_owned(),
+                                ip,
+                                next_report: Instant::now(),
+                            },
+                        );
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    self.mdns_clients.remove(&fullname);
+                }
+                _ => (),
+            }
+        }
+
+        let now = Instant::now();
+        let client = self
+            .mdns_clients
+            .values_mut()
+            .find(|client| client.next_report <= now)?;
+        client.next_report = now + MDNS_REPORT_INTERVAL;
+
+        Some((client.hostname.clone(), client.ip))
+    }
+
     // Returns: client IP, client hostname
     pub fn recv_non_blocking(&mut self) -> IntResult<(String, IpAddr)> {
+        if let Some(pair) = self.poll_mdns() {
+            return Ok(pair);
+        }
+
         let (size, address) = match self.socket.recv_from(&mut self.buffer) {
             Ok(pair) => pair,
             Err(e) => {
@@ -39,17 +121,8 @@ impl WelcomeSocket {
             && &self.buffer[..ALVR_NAME.len()] == ALVR_NAME.as_bytes()
             && self.buffer[ALVR_NAME.len()..16].iter().all(|b| *b == 0)
         {
-            let mut protocol_id_bytes = [0; 8];
-            protocol_id_bytes.copy_from_slice(&self.buffer[16..24]);
-            let received_protocol_id = u64::from_le_bytes(protocol_id_bytes);
-
-            if received_protocol_id != alvr_common::protocol_id() {
-                alvr_events::send_event(EventType::ClientFoundWrongVersion(format!(
-                    "Expected protocol ID {}, Found {received_protocol_id}",
-                    alvr_common::protocol_id()
-                )));
-            }
-
+            // Bytes 16..24 contain the protocol ID of the client. It is not checked: compatibility is
+            // negotiated on the control socket, where the supported versions are exchanged.
             let mut hostname_bytes = [0; 32];
             hostname_bytes.copy_from_slice(&self.buffer[24..56]);
             let hostname = std::str::from_utf8(&hostname_bytes)
@@ -57,7 +130,9 @@ impl WelcomeSocket {
                 .trim_end_matches('\x00')
                 .to_owned();
 
-            Ok((hostname, address.ip()))
+            register_peer_address(address);
+
+            Ok((hostname, canonical_ip(address.ip())))
         } else if &self.buffer[..16] == b"\x00\x00\x00\x00\x04\x00\x00\x00\x00\x00\x00\x00ALVR" {
             alvr_events::send_event(EventType::ClientFoundWrongVersion("v14 to v18".into()));
 
@@ -74,3 +149,11 @@ impl WelcomeSocket {
         }
     }
 }
+
+impl Drop for WelcomeSocket {
+    fn drop(&mut self) {
+        if let Some((daemon, _)) = &self.mdns {
+            daemon.shutdown().ok();
+        }
+    }
+}
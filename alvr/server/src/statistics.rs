@@ -47,11 +47,17 @@ pub struct StatisticsManager {
     fec_percentage: u32,
     battery_gauges: HashMap<u64, f32>,
     game_render_latency_average: SlidingWindowAverage<Duration>,
+    // Only the statistics of the primary client are shown on the dashboard
+    send_events: bool,
 }
 
 impl StatisticsManager {
     // history size used to calculate average total pipeline latency
-    pub fn new(history_size: usize, nominal_server_frame_interval: Duration) -> Self {
+    pub fn new(
+        history_size: usize,
+        nominal_server_frame_interval: Duration,
+        send_events: bool,
+    ) -> Self {
         Self {
             history_buffer: VecDeque::new(),
             max_history_size: history_size,
@@ -68,6 +74,7 @@ impl StatisticsManager {
             fec_percentage: 0,
             battery_gauges: HashMap::new(),
             game_render_latency_average: SlidingWindowAverage::new(history_size),
+            send_events,
         }
     }
 
@@ -187,41 +194,45 @@ impl StatisticsManager {
 
                 let interval_secs = FULL_REPORT_INTERVAL.as_secs_f32();
 
-                alvr_events::send_event(EventType::Statistics(Statistics {
-                    video_packets_total: self.video_packets_total,
-                    video_packets_per_sec: (self.video_packets_partial_sum as f32 / interval_secs)
-                        as _,
-                    video_mbytes_total: (self.video_bytes_total as f32 / 1e6) as usize,
-                    video_mbits_per_sec: self.video_bytes_partial_sum as f32 / interval_secs * 8.
-                        / 1e6,
-                    total_latency_ms: client_stats.total_pipeline_latency.as_secs_f32() * 1000.,
-                    network_latency_ms: network_latency.as_secs_f32() * 1000.,
-                    encode_latency_ms: encoder_latency.as_secs_f32() * 1000.,
-                    decode_latency_ms: client_stats.video_decode.as_secs_f32() * 1000.,
-                    fec_percentage: self.fec_percentage,
-                    fec_errors_total: self.fec_errors_total,
-                    fec_errors_per_sec: (self.fec_failures_partial_sum as f32 / interval_secs) as _,
-                    client_fps: (1. / client_stats.frame_interval.as_secs_f32()) as _,
-                    server_fps: (1. / self.last_frame_present_interval.as_secs_f32()) as _,
-                    battery_hmd: (self
-                        .battery_gauges
-                        .get(&HEAD_ID)
-                        .cloned()
-                        .unwrap_or_default()
-                        * 100.) as _,
-                    battery_left: (self
-                        .battery_gauges
-                        .get(&LEFT_HAND_ID)
-                        .cloned()
-                        .unwrap_or_default()
-                        * 100.) as _,
-                    battery_right: (self
-                        .battery_gauges
-                        .get(&RIGHT_HAND_ID)
-                        .cloned()
-                        .unwrap_or_default()
-                        * 100.) as _,
-                }));
+                if self.send_events {
+                    alvr_events::send_event(EventType::Statistics(Statistics {
+                        video_packets_total: self.video_packets_total,
+                        video_packets_per_sec: (self.video_packets_partial_sum as f32
+                            / interval_secs) as _,
+                        video_mbytes_total: (self.video_bytes_total as f32 / 1e6) as usize,
+                        video_mbits_per_sec: self.video_bytes_partial_sum as f32 / interval_secs
+                            * 8.
+                            / 1e6,
+                        total_latency_ms: client_stats.total_pipeline_latency.as_secs_f32() * 1000.,
+                        network_latency_ms: network_latency.as_secs_f32() * 1000.,
+                        encode_latency_ms: encoder_latency.as_secs_f32() * 1000.,
+                        decode_latency_ms: client_stats.video_decode.as_secs_f32() * 1000.,
+                        fec_percentage: self.fec_percentage,
+                        fec_errors_total: self.fec_errors_total,
+                        fec_errors_per_sec: (self.fec_failures_partial_sum as f32 / interval_secs)
+                            as _,
+                        client_fps: (1. / client_stats.frame_interval.as_secs_f32()) as _,
+                        server_fps: (1. / self.last_frame_present_interval.as_secs_f32()) as _,
+                        battery_hmd: (self
+                            .battery_gauges
+                            .get(&HEAD_ID)
+                            .cloned()
+                            .unwrap_or_default()
+                            * 100.) as _,
+                        battery_left: (self
+                            .battery_gauges
+                            .get(&LEFT_HAND_ID)
+                            .cloned()
+                            .unwrap_or_default()
+                            * 100.) as _,
+                        battery_right: (self
+                            .battery_gauges
+                            .get(&RIGHT_HAND_ID)
+                            .cloned()
+                            .unwrap_or_default()
+                            * 100.) as _,
+                    }));
+                }
 
                 self.video_packets_partial_sum = 0;
                 self.video_bytes_partial_sum = 0;
@@ -230,19 +241,21 @@ impl StatisticsManager {
 
             // todo: use target timestamp in nanoseconds. the dashboard needs to use the first
             // timestamp as the graph time origin.
-            alvr_events::send_event(EventType::GraphStatistics(GraphStatistics {
-                total_pipeline_latency_s: client_stats.total_pipeline_latency.as_secs_f32(),
-                game_time_s: game_time_latency.as_secs_f32(),
-                server_compositor_s: server_compositor_latency.as_secs_f32(),
-                encoder_s: encoder_latency.as_secs_f32(),
-                network_s: network_latency.as_secs_f32(),
-                decoder_s: client_stats.video_decode.as_secs_f32(),
-                decoder_queue_s: client_stats.video_decoder_queue.as_secs_f32(),
-                client_compositor_s: client_stats.rendering.as_secs_f32(),
-                vsync_queue_s: client_stats.vsync_queue.as_secs_f32(),
-                client_fps: 1. / client_stats.frame_interval.as_secs_f32(),
-                server_fps: 1. / self.last_frame_present_interval.as_secs_f32(),
-            }));
+            if self.send_events {
+                alvr_events::send_event(EventType::GraphStatistics(GraphStatistics {
+                    total_pipeline_latency_s: client_stats.total_pipeline_latency.as_secs_f32(),
+                    game_time_s: game_time_latency.as_secs_f32(),
+                    server_compositor_s: server_compositor_latency.as_secs_f32(),
+                    encoder_s: encoder_latency.as_secs_f32(),
+                    network_s: network_latency.as_secs_f32(),
+                    decoder_s: client_stats.video_decode.as_secs_f32(),
+                    decoder_queue_s: client_stats.video_decoder_queue.as_secs_f32(),
+                    client_compositor_s: client_stats.rendering.as_secs_f32(),
+                    vsync_queue_s: client_stats.vsync_queue.as_secs_f32(),
+                    client_fps: 1. / client_stats.frame_interval.as_secs_f32(),
+                    server_fps: 1. / self.last_frame_present_interval.as_secs_f32(),
+                }));
+            }
 
             network_latency
         } else {
@@ -256,3 +269,115 @@ impl StatisticsManager {
         self.game_render_latency_average.get_average() + self.nominal_server_frame_interval
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::parking_lot::Mutex;
+    use alvr_sockets::{
+        simulated_transport_pair, DelayDistribution, GilbertElliott, LinkImpairments, StreamSocket,
+        STATISTICS, TRACKING, VIDEO,
+    };
+    use std::sync::Arc;
+    use tokio::time;
+
+    const LINK_DELAY: Duration = Duration::from_millis(10);
+    const FRAMES_COUNT: u64 = 50;
+
+    // The client measures the latency from the input acquisition to the reception of the frame.
+    // What is left after subtracting the server and client stages is the network latency, which
+    // includes the delay of both directions of the link. Frames lost on the way are not reported.
+    #[tokio::test]
+    async fn network_latency_over_impaired_link() {
+        let impairments = LinkImpairments {
+            delay: DelayDistribution::Constant(LINK_DELAY),
+            loss: Some(GilbertElliott::uniform(0.1)),
+            ..Default::default()
+        };
+        let ((server_send, server_receive), (client_send, client_receive)) =
+            simulated_transport_pair(impairments.clone(), impairments, 0);
+        let server_socket = Arc::new(StreamSocket::new(server_send, server_receive, 1400));
+        let client_socket = Arc::new(StreamSocket::new(client_send, client_receive, 1400));
+        for socket in [&server_socket, &client_socket] {
+            let socket = Arc::clone(socket);
+            tokio::spawn(async move { socket.receive_loop().await });
+        }
+
+        let mut tracking_sender = client_socket.request_stream(TRACKING).await.unwrap();
+        let mut tracking_receiver = server_socket
+            .subscribe_to_stream::<Duration>(TRACKING)
+            .await
+            .unwrap();
+        let mut video_sender = server_socket.request_stream(VIDEO).await.unwrap();
+        let mut video_receiver = client_socket
+            .subscribe_to_stream::<Duration>(VIDEO)
+            .await
+            .unwrap();
+        let mut statistics_sender = client_socket.request_stream(STATISTICS).await.unwrap();
+        let mut statistics_receiver = server_socket
+            .subscribe_to_stream::<ClientStatistics>(STATISTICS)
+            .await
+            .unwrap();
+
+        let server_loop = tokio::spawn(async move {
+            let mut manager = StatisticsManager::new(100, Duration::from_millis(10), false);
+            let mut network_latencies = vec![];
+
+            loop {
+                tokio::select! {
+                    packet = tracking_receiver.recv() => {
+                        let target_timestamp = packet.unwrap().header;
+                        manager.report_tracking_received(target_timestamp);
+                        manager.report_frame_present(target_timestamp);
+                        manager.report_frame_composed(target_timestamp);
+                        manager.report_frame_encoded(target_timestamp);
+                        video_sender.send(&target_timestamp).await.unwrap();
+                    }
+                    packet = statistics_receiver.recv() => {
+                        network_latencies.push(manager.report_statistics(packet.unwrap().header));
+                    }
+                    _ = time::sleep(Duration::from_millis(200)) => break network_latencies,
+                }
+            }
+        });
+
+        let input_instants = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn({
+            let input_instants = Arc::clone(&input_instants);
+            async move {
+                while let Ok(packet) = video_receiver.recv().await {
+                    let target_timestamp = packet.header;
+                    let input_acquired = input_instants.lock()[&target_timestamp];
+
+                    statistics_sender
+                        .send(&ClientStatistics {
+                            target_timestamp,
+                            total_pipeline_latency: Instant::now() - input_acquired,
+                            ..Default::default()
+                        })
+                        .await
+                        .unwrap();
+                }
+            }
+        });
+
+        for frame_index in 0..FRAMES_COUNT {
+            let target_timestamp = Duration::from_millis(frame_index);
+            input_instants
+                .lock()
+                .insert(target_timestamp, Instant::now());
+            tracking_sender.send(&target_timestamp).await.unwrap();
+
+            time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let network_latencies = server_loop.await.unwrap();
+
+        // About 3 frames out of 4 make it through the three lossy links
+        assert!((25..FRAMES_COUNT as usize).contains(&network_latencies.len()));
+        for latency in network_latencies {
+            assert!(latency >= 2 * LINK_DELAY - Duration::from_millis(1));
+            assert!(latency < 2 * LINK_DELAY + Duration::from_millis(20));
+        }
+    }
+}
@@ -1,31 +1,647 @@
-use alvr_common::glam::Vec3;
+use crate::{hand_skeleton::BoneTransform, prediction::Predictor};
+use alvr_common::{
+    glam::{EulerRot, Quat, Vec3},
+    hash_string, HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID,
+};
 use alvr_session::HeadsetDesc;
 use alvr_sockets::DeviceMotion;
 use settings_schema::Switch;
+use std::{collections::HashSet, time::Duration};
+
+// The hand tracking reports the pose of the wrist bone, which is rotated and translated relative
+// to the pose of the controllers expected by SteamVR
+const LEFT_HAND_ROOT_ROTATION: Quat = Quat::from_xyzw(0.5, 0.5, -0.5, -0.5);
+const RIGHT_HAND_ROOT_ROTATION: Quat = Quat::from_xyzw(0.5, 0.5, 0.5, 0.5);
+const HAND_ROOT_POSITION: Vec3 = Vec3::new(0.0, 0.05, -0.05);
+
+// A step of the tracking pipeline. Each device has its list of stages, applied in order.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TransformStage {
+    // Rigid transform of the tracking space: rotates then translates the whole motion
+    Space { rotation: Quat, translation: Vec3 },
+    // Rigid offset expressed in the local space of the device. Velocities are not changed.
+    LocalOffset { rotation: Quat, translation: Vec3 },
+    // Keeps only the orientation
+    Force3dof,
+    // Scales the velocities down to zero at the cutoffs, to stop jitter when there is not a lot of
+    // movement. The cutoffs are in m/s and rad/s.
+    VelocityCutoff { linear: f32, angular: f32 },
+    // Extrapolates the pose forward in time using the velocities. The offset is in seconds.
+    Prediction(f32),
+}
+
+impl TransformStage {
+    pub fn apply(&self, motion: DeviceMotion) -> DeviceMotion {
+        match *self {
+            TransformStage::Space {
+                rotation,
+                translation,
+            } => DeviceMotion {
+                orientation: rotation * motion.orientation,
+                position: rotation * motion.position + translation,
+                linear_velocity: rotation * motion.linear_velocity,
+                angular_velocity: rotation * motion.angular_velocity,
+            },
+            TransformStage::LocalOffset {
+                rotation,
+                translation,
+            } => DeviceMotion {
+                orientation: motion.orientation * rotation,
+                position: motion.position + motion.orientation * translation,
+                ..motion
+            },
+            TransformStage::Force3dof => DeviceMotion {
+                position: Vec3::ZERO,
+                ..motion
+            },
+            TransformStage::VelocityCutoff { linear, angular } => DeviceMotion {
+                linear_velocity: motion.linear_velocity
+                    * cutoff_multiplier(motion.linear_velocity, linear),
+                angular_velocity: motion.angular_velocity
+                    * cutoff_multiplier(motion.angular_velocity, angular),
+                ..motion
+            },
+            TransformStage::Prediction(offset_s) => DeviceMotion {
+                orientation: (Quat::from_scaled_axis(motion.angular_velocity * offset_s)
+                    * motion.orientation)
+                    .normalize(),
+                position: motion.position + motion.linear_velocity * offset_s,
+                ..motion
+            },
+        }
+    }
+}
+
+fn cutoff_multiplier(velocity: Vec3, cutoff: f32) -> f32 {
+    let magnitude_squared = velocity.length_squared();
+    if magnitude_squared > cutoff * cutoff {
+        1.0 - cutoff * cutoff / magnitude_squared
+    } else {
+        0.0
+    }
+}
+
+pub fn apply_stages(stages: &[TransformStage], motion: DeviceMotion) -> DeviceMotion {
+    stages
+        .iter()
+        .fold(motion, |motion, stage| stage.apply(motion))
+}
+
+// Moves the origin of the playspace under the head and rotates it so the head faces forward
+pub fn recentering_stage(head_motion: &DeviceMotion) -> TransformStage {
+    let (yaw, _, _) = head_motion.orientation.to_euler(EulerRot::YXZ);
+    let rotation = Quat::from_rotation_y(-yaw);
+    let origin = Vec3::new(head_motion.position.x, 0.0, head_motion.position.z);
+
+    TransformStage::Space {
+        rotation,
+        translation: -(rotation * origin),
+    }
+}
+
+fn hand_root_stage(is_left: bool) -> TransformStage {
+    let rotation = if is_left {
+        LEFT_HAND_ROOT_ROTATION
+    } else {
+        RIGHT_HAND_ROOT_ROTATION
+    };
+
+    TransformStage::LocalOffset {
+        rotation,
+        translation: rotation * HAND_ROOT_POSITION,
+    }
+}
+
+// Pose of the wrist bone relative to the controller pose of a tracked hand, which is the inverse of
+// the hand root offset. The controller offsets set in the settings are not compensated.
+pub fn hand_skeleton_wrist(is_left: bool) -> BoneTransform {
+    let rotation = if is_left {
+        LEFT_HAND_ROOT_ROTATION
+    } else {
+        RIGHT_HAND_ROOT_ROTATION
+    };
+
+    BoneTransform {
+        orientation: rotation.inverse(),
+        position: -HAND_ROOT_POSITION,
+    }
+}
+
+// The offsets are set for the left controller and are mirrored for the right one. Like in the C++
+// driver, the Y, Z and X components of the rotation offset are applied as yaw, pitch and roll.
+fn controller_offset_stage(
+    position_offset_left: [f32; 3],
+    rotation_offset_left: [f32; 3],
+    is_left: bool,
+) -> TransformStage {
+    let [x, y, z] = rotation_offset_left.map(f32::to_radians);
+    let (y, z) = if is_left { (y, z) } else { (-y, -z) };
+    let rotation = Quat::from_euler(EulerRot::ZYX, y, z, x);
+
+    let mut translation = Vec3::from(position_offset_left);
+    if !is_left {
+        translation.x = -translation.x;
+    }
+
+    TransformStage::LocalOffset {
+        rotation,
+        translation: rotation * translation,
+    }
+}
+
+struct ControllerPipeline {
+    stages: Vec<TransformStage>,
+    // Applied after the stages
+    predictor: Predictor,
+}
 
 pub struct TrackingManager {
-    settings: HeadsetDesc,
+    head_stages: Vec<TransformStage>,
+    // Left and right. None if the controllers are disabled
+    controllers: Option<[ControllerPipeline; 2]>,
+    tracker_ids: HashSet<u64>,
+    recenter_on_connect: bool,
+    recentering: Option<TransformStage>,
 }
 
 impl TrackingManager {
     pub fn new(settings: HeadsetDesc) -> TrackingManager {
-        TrackingManager { settings }
+        let mut head_stages = vec![];
+        if settings.force_3dof {
+            head_stages.push(TransformStage::Force3dof);
+        }
+        head_stages.push(TransformStage::Space {
+            rotation: Quat::IDENTITY,
+            translation: Vec3::from(settings.position_offset),
+        });
+
+        let controllers = if let Switch::Enabled(controllers) = &settings.controllers {
+            let pipeline = |is_left| ControllerPipeline {
+                stages: vec![
+                    controller_offset_stage(
+                        controllers.position_offset_left,
+                        controllers.rotation_offset_left,
+                        is_left,
+                    ),
+                    TransformStage::VelocityCutoff {
+                        linear: controllers.linear_velocity_cutoff,
+                        angular: controllers.angular_velocity_cutoff.to_radians(),
+                    },
+                ],
+                predictor: Predictor::new(
+                    controllers.prediction_model,
+                    controllers.pose_time_offset_ms as f32 / 1000.,
+                ),
+            };
+
+            Some([pipeline(true), pipeline(false)])
+        } else {
+            None
+        };
+
+        let tracker_ids = if let Switch::Enabled(trackers) = &settings.trackers {
+            trackers.iter().map(|(path, _)| hash_string(path)).collect()
+        } else {
+            HashSet::new()
+        };
+
+        TrackingManager {
+            head_stages,
+            controllers,
+            tracker_ids,
+            recenter_on_connect: settings.recenter_on_connect,
+            recentering: None,
+        }
+    }
+
+    // The playspace is recentered on the first head pose received, if enabled
+    pub fn recenter(&mut self, head_motion: &DeviceMotion) {
+        if self.recenter_on_connect && self.recentering.is_none() {
+            self.recentering = Some(recentering_stage(head_motion));
+        }
+    }
+
+    // The companions send no head pose and use the recentering of the primary client
+    pub fn recentering(&self) -> Option<TransformStage> {
+        self.recentering
+    }
+
+    pub fn set_recentering(&mut self, recentering: Option<TransformStage>) {
+        self.recentering = recentering;
+    }
+
+    fn recentered(&self, motion: DeviceMotion) -> DeviceMotion {
+        if let Some(stage) = &self.recentering {
+            stage.apply(motion)
+        } else {
+            motion
+        }
     }
 
-    // todo: move here more modifiers from C++
     pub fn map_head(&self, device_motion: DeviceMotion) -> DeviceMotion {
+        apply_stages(&self.head_stages, self.recentered(device_motion))
+    }
+
+    // hand_tracked: the motion is the wrist pose from the hand tracking
+    // The angular velocity is returned in the local space of the device, before the controller
+    // offsets, as expected by SteamVR
+    pub fn map_controller(
+        &mut self,
+        path_id: u64,
+        timestamp: Duration,
+        device_motion: DeviceMotion,
+        hand_tracked: bool,
+    ) -> Option<DeviceMotion> {
+        let is_left = path_id == *LEFT_HAND_ID;
+
+        let mut motion = self.recentered(device_motion);
+        if hand_tracked {
+            motion = hand_root_stage(is_left).apply(motion);
+        }
+
+        let device_orientation = motion.orientation;
+
+        let [left, right] = self.controllers.as_mut()?;
+        let pipeline = if is_left { left } else { right };
+
+        let motion = apply_stages(&pipeline.stages, motion);
+        let motion = pipeline.predictor.predict(timestamp, motion);
+
+        Some(DeviceMotion {
+            angular_velocity: device_orientation.inverse() * motion.angular_velocity,
+            ..motion
+        })
+    }
+
+    // Motions of devices that are not configured as trackers are dropped, since SteamVR has no
+    // device to assign them to
+    pub fn map_tracker(&self, path_id: u64, device_motion: DeviceMotion) -> Option<DeviceMotion> {
+        self.tracker_ids
+            .contains(&path_id)
+            .then(|| self.recentered(device_motion))
+    }
+
+    // Trackers that are not in the settings, like the hands of a companion client
+    pub fn add_trackers(&mut self, path_ids: impl IntoIterator<Item = u64>) {
+        self.tracker_ids.extend(path_ids);
+    }
+
+    // Maps the motions of a tracking packet. The devices that are disabled or not configured as
+    // trackers are left out.
+    pub fn map_motions(
+        &mut self,
+        timestamp: Duration,
+        device_motions: Vec<(u64, DeviceMotion)>,
+        left_hand_tracked: bool,
+        right_hand_tracked: bool,
+    ) -> Vec<(u64, DeviceMotion)> {
+        if let Some((_, head_motion)) = device_motions.iter().find(|(id, _)| *id == *HEAD_ID) {
+            self.recenter(head_motion);
+        }
+
+        device_motions
+            .into_iter()
+            .filter_map(|(id, motion)| {
+                let motion = if id == *HEAD_ID {
+                    Some(self.map_head(motion))
+                } else if id == *LEFT_HAND_ID {
+                    self.map_controller(id, timestamp, motion, left_hand_tracked)
+                } else if id == *RIGHT_HAND_ID {
+                    self.map_controller(id, timestamp, motion, right_hand_tracked)
+                } else {
+                    self.map_tracker(id, motion)
+                };
+
+                Some((id, motion?))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alvr_common::{companion_device_path, LEFT_FOOT_PATH, LEFT_HAND_PATH, WAIST_PATH};
+    use alvr_session::PredictionModel;
+    use alvr_sockets::RecordedEvent;
+    use std::f32::consts::FRAC_PI_2;
+
+    const EPSILON: f32 = 1e-5;
+
+    // Tracking recording of a user walking and turning with two controllers and a waist tracker,
+    // plus a foot tracker that is not configured. It also contains a few button and haptics events.
+    const SESSION_RECORDING: &[u8] = include_bytes!("../resources/tracking_session.bin");
+    // Motions returned by session_manager() for each tracking packet of the recording
+    const SESSION_EXPECTED_MOTIONS: &str =
+        include_str!("../resources/tracking_session_expected.json");
+
+    fn motion(orientation: Quat, position: Vec3) -> DeviceMotion {
         DeviceMotion {
-            position: if !self.settings.force_3dof {
-                device_motion.position
-            } else {
-                Vec3::new(0.0, 0.0, 0.0)
+            orientation,
+            position,
+            ..Default::default()
+        }
+    }
+
+    fn tracker_manager(tracker_paths: &[&str]) -> TrackingManager {
+        TrackingManager {
+            head_stages: vec![],
+            controllers: None,
+            tracker_ids: tracker_paths.iter().map(|path| hash_string(path)).collect(),
+            recenter_on_connect: true,
+            recentering: None,
+        }
+    }
+
+    #[test]
+    fn space_rotates_and_translates() {
+        let stage = TransformStage::Space {
+            rotation: Quat::from_rotation_y(FRAC_PI_2),
+            translation: Vec3::new(0.0, 1.0, 0.0),
+        };
+        let res = stage.apply(DeviceMotion {
+            linear_velocity: Vec3::X,
+            ..motion(Quat::IDENTITY, Vec3::X)
+        });
+
+        assert!(res.position.abs_diff_eq(Vec3::new(0.0, 1.0, -1.0), EPSILON));
+        assert!(res.linear_velocity.abs_diff_eq(-Vec3::Z, EPSILON));
+        assert!(res
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2), EPSILON));
+    }
+
+    #[test]
+    fn local_offset_follows_orientation() {
+        let stage = TransformStage::LocalOffset {
+            rotation: Quat::IDENTITY,
+            translation: Vec3::new(0.0, 0.0, -0.1),
+        };
+        let res = stage.apply(motion(Quat::from_rotation_y(FRAC_PI_2), Vec3::ONE));
+
+        assert!(res.position.abs_diff_eq(Vec3::new(0.9, 1.0, 1.0), EPSILON));
+    }
+
+    #[test]
+    fn force_3dof_keeps_orientation() {
+        let orientation = Quat::from_rotation_x(0.3);
+        let res = TransformStage::Force3dof.apply(motion(orientation, Vec3::ONE));
+
+        assert_eq!(res.position, Vec3::ZERO);
+        assert_eq!(res.orientation, orientation);
+    }
+
+    #[test]
+    fn velocity_cutoff() {
+        let stage = TransformStage::VelocityCutoff {
+            linear: 0.1,
+            angular: 0.1,
+        };
+
+        let slow = stage.apply(DeviceMotion {
+            linear_velocity: Vec3::new(0.05, 0.0, 0.0),
+            angular_velocity: Vec3::new(0.0, 0.1, 0.0),
+            ..Default::default()
+        });
+        assert_eq!(slow.linear_velocity, Vec3::ZERO);
+        assert_eq!(slow.angular_velocity, Vec3::ZERO);
+
+        let fast = stage.apply(DeviceMotion {
+            linear_velocity: Vec3::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        });
+        assert!(fast
+            .linear_velocity
+            .abs_diff_eq(Vec3::new(0.99, 0.0, 0.0), EPSILON));
+    }
+
+    #[test]
+    fn prediction_extrapolates() {
+        let res = TransformStage::Prediction(0.5).apply(DeviceMotion {
+            linear_velocity: Vec3::new(0.0, 0.0, -2.0),
+            angular_velocity: Vec3::new(0.0, FRAC_PI_2, 0.0),
+            ..motion(Quat::IDENTITY, Vec3::ZERO)
+        });
+
+        assert!(res.position.abs_diff_eq(Vec3::new(0.0, 0.0, -1.0), EPSILON));
+        assert!(res
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2 / 2.0), EPSILON));
+    }
+
+    #[test]
+    fn recentering_moves_head_to_origin() {
+        let head = motion(
+            Quat::from_rotation_y(FRAC_PI_2) * Quat::from_rotation_x(0.2),
+            Vec3::new(1.0, 1.6, 2.0),
+        );
+        let res = recentering_stage(&head).apply(head);
+
+        assert!(res.position.abs_diff_eq(Vec3::new(0.0, 1.6, 0.0), EPSILON));
+        assert!(res
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_x(0.2), EPSILON));
+    }
+
+    #[test]
+    fn controller_offset_is_mirrored() {
+        let left = controller_offset_stage([0.1, 0.0, 0.0], [0.0, 0.0, 90.0], true);
+        let right = controller_offset_stage([0.1, 0.0, 0.0], [0.0, 0.0, 90.0], false);
+
+        let left_res = left.apply(motion(Quat::IDENTITY, Vec3::ZERO));
+        let right_res = right.apply(motion(Quat::IDENTITY, Vec3::ZERO));
+
+        assert!(left_res
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_y(FRAC_PI_2), EPSILON));
+        assert!(right_res
+            .orientation
+            .abs_diff_eq(Quat::from_rotation_y(-FRAC_PI_2), EPSILON));
+        assert!(left_res
+            .position
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -0.1), EPSILON));
+        assert!(right_res
+            .position
+            .abs_diff_eq(Vec3::new(0.0, 0.0, -0.1), EPSILON));
+    }
+
+    #[test]
+    fn stages_apply_in_order() {
+        let stages = [
+            TransformStage::Force3dof,
+            TransformStage::Space {
+                rotation: Quat::IDENTITY,
+                translation: Vec3::Y,
             },
-            ..device_motion
+        ];
+        let res = apply_stages(&stages, motion(Quat::IDENTITY, Vec3::ONE));
+
+        assert_eq!(res.position, Vec3::Y);
+    }
+
+    #[test]
+    fn hand_skeleton_wrist_cancels_hand_root() {
+        let wrist = motion(Quat::from_rotation_y(0.3), Vec3::new(0.1, 1.0, -0.2));
+
+        for is_left in [true, false] {
+            let root = hand_root_stage(is_left).apply(wrist.clone());
+            let bone = hand_skeleton_wrist(is_left);
+
+            assert!((root.orientation * bone.orientation).abs_diff_eq(wrist.orientation, EPSILON));
+            assert!((root.position + root.orientation * bone.position)
+                .abs_diff_eq(wrist.position, EPSILON));
         }
     }
 
-    // todo: move here more modifiers from C++
-    pub fn map_controller(&self, device_motion: DeviceMotion) -> Option<DeviceMotion> {
-        matches!(self.settings.controllers, Switch::Enabled(_)).then(|| device_motion)
+    #[test]
+    fn controller_angular_velocity_is_local() {
+        let pipeline = |is_left| ControllerPipeline {
+            stages: vec![controller_offset_stage([0.0; 3], [0.0, 45.0, 0.0], is_left)],
+            predictor: Predictor::new(PredictionModel::ConstantVelocity, 0.0),
+        };
+        let mut manager = TrackingManager {
+            head_stages: vec![],
+            controllers: Some([pipeline(true), pipeline(false)]),
+            tracker_ids: HashSet::new(),
+            recenter_on_connect: false,
+            recentering: None,
+        };
+
+        let res = manager
+            .map_controller(
+                *LEFT_HAND_ID,
+                Duration::ZERO,
+                DeviceMotion {
+                    angular_velocity: Vec3::X * 10.0,
+                    ..motion(Quat::from_rotation_y(FRAC_PI_2), Vec3::ZERO)
+                },
+                false,
+            )
+            .unwrap();
+
+        // The rotation offset of the controller is not applied to the velocity
+        assert!(res.angular_velocity.abs_diff_eq(Vec3::Z * 10.0, EPSILON));
+    }
+
+    #[test]
+    fn unconfigured_trackers_are_dropped() {
+        let manager = tracker_manager(&[WAIST_PATH]);
+        let tracker = motion(Quat::from_rotation_x(0.1), Vec3::new(0.0, 1.0, 0.0));
+
+        // Without recentering, the motion is left as it is
+        let res = manager
+            .map_tracker(hash_string(WAIST_PATH), tracker.clone())
+            .unwrap();
+        assert_eq!(res.position, tracker.position);
+        assert_eq!(res.orientation, tracker.orientation);
+
+        assert!(manager
+            .map_tracker(hash_string(LEFT_FOOT_PATH), tracker)
+            .is_none());
+    }
+
+    #[test]
+    fn added_trackers_are_forwarded() {
+        let mut manager = tracker_manager(&[WAIST_PATH]);
+        let companion_hand_id = hash_string(&companion_device_path("phone", LEFT_HAND_PATH));
+        assert!(manager
+            .map_tracker(companion_hand_id, DeviceMotion::default())
+            .is_none());
+
+        manager.add_trackers([companion_hand_id]);
+        assert!(manager
+            .map_tracker(companion_hand_id, DeviceMotion::default())
+            .is_some());
+        assert!(manager
+            .map_tracker(hash_string(WAIST_PATH), DeviceMotion::default())
+            .is_some());
+    }
+
+    #[test]
+    fn trackers_follow_recentering() {
+        let mut manager = tracker_manager(&[WAIST_PATH]);
+        manager.recenter(&motion(
+            Quat::from_rotation_y(FRAC_PI_2),
+            Vec3::new(1.0, 1.6, 2.0),
+        ));
+
+        // Under the head, one meter in front of it
+        let res = manager
+            .map_tracker(
+                hash_string(WAIST_PATH),
+                motion(Quat::from_rotation_y(FRAC_PI_2), Vec3::new(0.0, 1.0, 2.0)),
+            )
+            .unwrap();
+
+        assert!(res.position.abs_diff_eq(Vec3::new(0.0, 1.0, -1.0), EPSILON));
+        assert!(res.orientation.abs_diff_eq(Quat::IDENTITY, EPSILON));
+    }
+
+    // Recenters on connect and uses all the controller stages, with a fixed configuration so that
+    // the expected motions change only with the transforms
+    fn session_manager() -> TrackingManager {
+        let pipeline = |is_left| ControllerPipeline {
+            stages: vec![
+                controller_offset_stage([-0.01, 0.02, -0.05], [15.0, 5.0, 0.0], is_left),
+                TransformStage::VelocityCutoff {
+                    linear: 0.05,
+                    angular: 0.1,
+                },
+            ],
+            predictor: Predictor::new(PredictionModel::ConstantVelocity, 0.02),
+        };
+
+        TrackingManager {
+            head_stages: vec![TransformStage::Space {
+                rotation: Quat::IDENTITY,
+                translation: Vec3::new(0.0, 0.1, 0.0),
+            }],
+            controllers: Some([pipeline(true), pipeline(false)]),
+            tracker_ids: [hash_string(WAIST_PATH)].into_iter().collect(),
+            recenter_on_connect: true,
+            recentering: None,
+        }
+    }
+
+    #[test]
+    fn recorded_session_motions() {
+        let entries = alvr_sockets::parse_recording(SESSION_RECORDING).unwrap();
+        let expected: Vec<Vec<(u64, DeviceMotion)>> =
+            serde_json::from_str(SESSION_EXPECTED_MOTIONS).unwrap();
+
+        let mut manager = session_manager();
+        let motions = entries
+            .into_iter()
+            .filter_map(|entry| match entry.event {
+                RecordedEvent::Tracking(tracking) => Some(manager.map_motions(
+                    tracking.target_timestamp,
+                    tracking.device_motions,
+                    false,
+                    false,
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(motions.len(), expected.len());
+        for (packet, expected_packet) in motions.iter().zip(&expected) {
+            assert_eq!(packet.len(), expected_packet.len());
+            for ((id, motion), (expected_id, expected_motion)) in packet.iter().zip(expected_packet)
+            {
+                assert_eq!(id, expected_id);
+                assert!(motion
+                    .orientation
+                    .abs_diff_eq(expected_motion.orientation, EPSILON));
+                assert!(motion
+                    .position
+                    .abs_diff_eq(expected_motion.position, EPSILON));
+                assert!(motion
+                    .linear_velocity
+                    .abs_diff_eq(expected_motion.linear_velocity, EPSILON));
+                assert!(motion
+                    .angular_velocity
+                    .abs_diff_eq(expected_motion.angular_velocity, EPSILON));
+            }
+        }
     }
 }
@@ -0,0 +1,169 @@
+// Messages of the /api/video-mirror websocket, laid out to be fed to a WebCodecs VideoDecoder:
+// * Config: CONFIG_MESSAGE, the length of the codec string (u8), the codec string and the decoder
+//   description (avcC, hvcC or av1C record). It is sent before the first frame, when the decoder
+//   configuration changes, and to each client that joins while the stream is running.
+// * Frame: FRAME_MESSAGE, 1 if the frame is a keyframe else 0, the timestamp in microseconds (u64,
+//   little endian) and the frame data. H264 and HEVC frames use 4 byte NAL unit lengths, as
+//   required when a description is passed to the decoder; AV1 frames are left as they are.
+
+use crate::decoder_config::{
+    avc_configuration_record, hevc_configuration_record, write_length_prefixed,
+};
+use alvr_common::prelude::*;
+use alvr_session::CodecType;
+use std::{fmt::Write, time::Duration};
+
+pub const CONFIG_MESSAGE: u8 = 0;
+pub const FRAME_MESSAGE: u8 = 1;
+
+// Codec string as defined by ISO/IEC 14496-15 Annex E, with the values of the HEVC record
+fn hevc_codec_string(record: &[u8]) -> StrResult<String> {
+    let profile_tier_level = record.get(1..13).ok_or_else(enone!())?;
+
+    let profile_space = profile_tier_level[0] >> 6;
+    let tier = if profile_tier_level[0] & 0x20 != 0 {
+        'H'
+    } else {
+        'L'
+    };
+    let profile_idc = profile_tier_level[0] & 0x1F;
+    let compatibility_flags = u32::from_be_bytes(profile_tier_level[1..5].try_into().unwrap());
+    let level_idc = profile_tier_level[11];
+
+    let mut codec = format!(
+        "hvc1.{}{profile_idc}.{:X}.{tier}{level_idc}",
+        ["", "A", "B", "C"][profile_space as usize],
+        compatibility_flags.reverse_bits(),
+    );
+
+    // The trailing zero bytes of the constraint flags are omitted
+    let constraint_flags = &profile_tier_level[5..11];
+    let constraint_flags_count = constraint_flags
+        .iter()
+        .rposition(|byte| *byte != 0)
+        .map(|index| index + 1)
+        .unwrap_or(0);
+    for byte in &constraint_flags[..constraint_flags_count] {
+        write!(codec, ".{byte:X}").ok();
+    }
+
+    Ok(codec)
+}
+
+// Codec string as defined by the AV1 codec ISO media file format binding, from the av1C record
+fn av1_codec_string(record: &[u8]) -> StrResult<String> {
+    let (profile_level, flags) = match record {
+        [_, profile_level, flags, ..] => (*profile_level, *flags),
+        _ => return fmt_e!("Invalid AV1 codec configuration record"),
+    };
+
+    let bit_depth = if flags & 0x40 == 0 {
+        8
+    } else if flags & 0x20 == 0 {
+        10
+    } else {
+        12
+    };
+
+    Ok(format!(
+        "av01.{}.{:02}{}.{bit_depth:02}",
+        profile_level >> 5,
+        profile_level & 0x1F,
+        if flags & 0x80 != 0 { 'H' } else { 'M' },
+    ))
+}
+
+pub fn config_message(codec: CodecType, decoder_config: &[u8]) -> StrResult<Vec<u8>> {
+    let (codec_string, description) = match codec {
+        CodecType::H264 => {
+            let record = avc_configuration_record(decoder_config)?;
+            (
+                format!("avc1.{:02x}{:02x}{:02x}", record[1], record[2], record[3]),
+                record,
+            )
+        }
+        CodecType::HEVC => {
+            let record = hevc_configuration_record(decoder_config)?;
+            (hevc_codec_string(&record)?, record)
+        }
+        CodecType::AV1 => {
+            let record = alvr_sockets::av1_codec_configuration_record(decoder_config)?;
+            (av1_codec_string(&record)?, record)
+        }
+    };
+
+    let mut message = vec![CONFIG_MESSAGE, codec_string.len() as u8];
+    message.extend(codec_string.as_bytes());
+    message.extend(description);
+
+    Ok(message)
+}
+
+pub fn frame_message(codec: CodecType, timestamp: Duration, data: &[u8]) -> Vec<u8> {
+    let mut message = vec![FRAME_MESSAGE, alvr_encoder::is_keyframe(codec, data) as u8];
+    message.extend((timestamp.as_micros() as u64).to_le_bytes());
+
+    match codec {
+        CodecType::H264 | CodecType::HEVC => write_length_prefixed(data, &mut message),
+        CodecType::AV1 => message.extend_from_slice(data),
+    }
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn h264_messages() {
+        let config = [
+            0, 0, 0, 1, 0x67, 0x64, 0x00, 0x1F, 0xAC, // SPS
+            0, 0, 0, 1, 0x68, 0xEE, // PPS
+        ];
+
+        let message = config_message(CodecType::H264, &config).unwrap();
+        assert_eq!(message[..2], [CONFIG_MESSAGE, 11]);
+        assert_eq!(&message[2..13], b"avc1.64001f");
+        // The description is the avcC record
+        assert_eq!(message[13..19], [1, 0x64, 0x00, 0x1F, 0xFF, 0xE1]);
+
+        let message = frame_message(
+            CodecType::H264,
+            Duration::from_millis(20),
+            &[0, 0, 0, 1, 0x65, 1, 2],
+        );
+        assert_eq!(message[..2], [FRAME_MESSAGE, 1]);
+        assert_eq!(message[2..10], 20_000_u64.to_le_bytes());
+        assert_eq!(message[10..], [0, 0, 0, 3, 0x65, 1, 2]);
+
+        let message = frame_message(CodecType::H264, Duration::ZERO, &[0, 0, 1, 0x41, 1]);
+        assert_eq!(message[1], 0);
+        assert_eq!(message[10..], [0, 0, 0, 2, 0x41, 1]);
+    }
+
+    #[test]
+    fn hevc_codec_string_from_record() {
+        // Main profile, main tier, level 3.1
+        let record = [1, 0x01, 0x60, 0, 0, 0, 0x90, 0, 0, 0, 0, 0, 0x5D];
+        assert_eq!(hevc_codec_string(&record).unwrap(), "hvc1.1.6.L93.90");
+
+        // Main 10 profile, high tier, level 5.1, no constraint flags
+        let record = [1, 0x22, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0, 153];
+        assert_eq!(hevc_codec_string(&record).unwrap(), "hvc1.2.4.H153");
+    }
+
+    #[test]
+    fn av1_codec_string_from_record() {
+        // Main profile, level 4.0, main tier, 8 bit
+        assert_eq!(
+            av1_codec_string(&[0x81, 0x08, 0x0C, 0]).unwrap(),
+            "av01.0.08M.08"
+        );
+        // High tier, 10 bit
+        assert_eq!(
+            av1_codec_string(&[0x81, 0x0D, 0xCC, 0]).unwrap(),
+            "av01.0.13H.10"
+        );
+    }
+}
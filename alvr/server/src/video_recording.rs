@@ -0,0 +1,505 @@
+// Recordings of the video stream received by the primary client, used for bug reports. They are
+// Matroska files with the video track and optionally the game audio, as 16 bit PCM. The segment and
+// the clusters are written with an unknown size, so the file is playable even if the server stops
+// without closing it.
+
+use crate::decoder_config::{
+    avc_configuration_record, hevc_configuration_record, write_length_prefixed,
+};
+use alvr_common::{glam::UVec2, prelude::*, ALVR_VERSION};
+use alvr_session::CodecType;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+const VIDEO_TRACK: u8 = 1;
+const AUDIO_TRACK: u8 = 2;
+
+// Timestamps are in milliseconds
+const TIMESTAMP_SCALE_NS: u64 = 1_000_000;
+const MAX_CLUSTER_DURATION_MS: i64 = 5_000;
+
+const UNKNOWN_SIZE: [u8; 8] = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+
+const EBML: u32 = 0x1A45DFA3;
+const EBML_VERSION: u32 = 0x4286;
+const EBML_READ_VERSION: u32 = 0x42F7;
+const EBML_MAX_ID_LENGTH: u32 = 0x42F2;
+const EBML_MAX_SIZE_LENGTH: u32 = 0x42F3;
+const DOC_TYPE: u32 = 0x4282;
+const DOC_TYPE_VERSION: u32 = 0x4287;
+const DOC_TYPE_READ_VERSION: u32 = 0x4285;
+const SEGMENT: u32 = 0x18538067;
+const INFO: u32 = 0x1549A966;
+const TIMESTAMP_SCALE: u32 = 0x2AD7B1;
+const MUXING_APP: u32 = 0x4D80;
+const WRITING_APP: u32 = 0x5741;
+const TRACKS: u32 = 0x1654AE6B;
+const TRACK_ENTRY: u32 = 0xAE;
+const TRACK_NUMBER: u32 = 0xD7;
+const TRACK_UID: u32 = 0x73C5;
+const TRACK_TYPE: u32 = 0x83;
+const FLAG_LACING: u32 = 0x9C;
+const CODEC_ID: u32 = 0x86;
+const CODEC_PRIVATE: u32 = 0x63A2;
+const VIDEO: u32 = 0xE0;
+const PIXEL_WIDTH: u32 = 0xB0;
+const PIXEL_HEIGHT: u32 = 0xBA;
+const AUDIO: u32 = 0xE1;
+const SAMPLING_FREQUENCY: u32 = 0xB5;
+const CHANNELS: u32 = 0x9F;
+const BIT_DEPTH: u32 = 0x6264;
+const CLUSTER: u32 = 0x1F43B675;
+const TIMESTAMP: u32 = 0xE7;
+const SIMPLE_BLOCK: u32 = 0xA3;
+
+fn id_bytes(id: u32) -> Vec<u8> {
+    id.to_be_bytes()[id.leading_zeros() as usize / 8..].to_vec()
+}
+
+fn size_vint(size: usize) -> Vec<u8> {
+    // The value with all the bits set is reserved for unknown sizes
+    let mut length = 1;
+    while length < 8 && size as u64 >= (1 << (7 * length)) - 1 {
+        length += 1;
+    }
+
+    (size as u64 | 1 << (7 * length)).to_be_bytes()[8 - length..].to_vec()
+}
+
+fn element(id: u32, data: &[u8]) -> Vec<u8> {
+    let mut buffer = id_bytes(id);
+    buffer.extend(size_vint(data.len()));
+    buffer.extend_from_slice(data);
+
+    buffer
+}
+
+fn uint_element(id: u32, value: u64) -> Vec<u8> {
+    let skipped_bytes = (value.leading_zeros() as usize / 8).min(7);
+    element(id, &value.to_be_bytes()[skipped_bytes..])
+}
+
+fn float_element(id: u32, value: f64) -> Vec<u8> {
+    element(id, &value.to_be_bytes())
+}
+
+fn master_element(id: u32, children: &[Vec<u8>]) -> Vec<u8> {
+    element(id, &children.concat())
+}
+
+fn unknown_size_element(id: u32) -> Vec<u8> {
+    let mut buffer = id_bytes(id);
+    buffer.extend(UNKNOWN_SIZE);
+
+    buffer
+}
+
+struct AudioTrack {
+    sample_rate: u32,
+    channels_count: u16,
+    // Time of the first recorded samples, relative to the first video frame
+    start_time: Option<Duration>,
+    recorded_frames_count: u64,
+}
+
+pub struct VideoRecorder<W: Write = BufWriter<File>> {
+    writer: W,
+    codec: CodecType,
+    frame_size: UVec2,
+    audio: Option<AudioTrack>,
+    // When the first recorded frame was received, and its timestamp. Set when the header is written.
+    // The video is timed with the frame timestamps, the audio with the time elapsed since this
+    // instant, so both tracks start together.
+    start: Option<(Instant, Duration)>,
+    cluster_time_ms: Option<i64>,
+}
+
+impl VideoRecorder {
+    // `audio` is the sample rate and the channels count of the game audio, if it is recorded
+    pub fn new(
+        path: &Path,
+        codec: CodecType,
+        frame_size: UVec2,
+        audio: Option<(u32, u16)>,
+    ) -> StrResult<Self> {
+        let writer = BufWriter::new(File::create(path).map_err(err!())?);
+
+        Ok(Self::with_writer(writer, codec, frame_size, audio))
+    }
+}
+
+impl<W: Write> VideoRecorder<W> {
+    fn with_writer(
+        writer: W,
+        codec: CodecType,
+        frame_size: UVec2,
+        audio: Option<(u32, u16)>,
+    ) -> Self {
+        Self {
+            writer,
+            codec,
+            frame_size,
+            audio: audio.map(|(sample_rate, channels_count)| AudioTrack {
+                sample_rate,
+                channels_count,
+                start_time: None,
+                recorded_frames_count: 0,
+            }),
+            start: None,
+            cluster_time_ms: None,
+        }
+    }
+
+    fn write_header(&mut self, decoder_config: &[u8]) -> StrResult {
+        let (codec_id, codec_private) = match self.codec {
+            CodecType::H264 => ("V_MPEG4/ISO/AVC", avc_configuration_record(decoder_config)?),
+            CodecType::HEVC => (
+                "V_MPEGH/ISO/HEVC",
+                hevc_configuration_record(decoder_config)?,
+            ),
+            CodecType::AV1 => (
+                "V_AV1",
+                alvr_sockets::av1_codec_configuration_record(decoder_config)?,
+            ),
+        };
+
+        let mut tracks = vec![master_element(
+            TRACK_ENTRY,
+            &[
+                uint_element(TRACK_NUMBER, VIDEO_TRACK as _),
+                uint_element(TRACK_UID, VIDEO_TRACK as _),
+                uint_element(TRACK_TYPE, 1),
+                uint_element(FLAG_LACING, 0),
+                element(CODEC_ID, codec_id.as_bytes()),
+                element(CODEC_PRIVATE, &codec_private),
+                master_element(
+                    VIDEO,
+                    &[
+                        uint_element(PIXEL_WIDTH, self.frame_size.x as _),
+                        uint_element(PIXEL_HEIGHT, self.frame_size.y as _),
+                    ],
+                ),
+            ],
+        )];
+        if let Some(audio) = &self.audio {
+            tracks.push(master_element(
+                TRACK_ENTRY,
+                &[
+                    uint_element(TRACK_NUMBER, AUDIO_TRACK as _),
+                    uint_element(TRACK_UID, AUDIO_TRACK as _),
+                    uint_element(TRACK_TYPE, 2),
+                    uint_element(FLAG_LACING, 0),
+                    element(CODEC_ID, b"A_PCM/INT/LIT"),
+                    master_element(
+                        AUDIO,
+                        &[
+                            float_element(SAMPLING_FREQUENCY, audio.sample_rate as _),
+                            uint_element(CHANNELS, audio.channels_count as _),
+                            uint_element(BIT_DEPTH, 16),
+                        ],
+                    ),
+                ],
+            ));
+        }
+
+        let mut header = master_element(
+            EBML,
+            &[
+                uint_element(EBML_VERSION, 1),
+                uint_element(EBML_READ_VERSION, 1),
+                uint_element(EBML_MAX_ID_LENGTH, 4),
+                uint_element(EBML_MAX_SIZE_LENGTH, 8),
+                element(DOC_TYPE, b"matroska"),
+                uint_element(DOC_TYPE_VERSION, 4),
+                uint_element(DOC_TYPE_READ_VERSION, 2),
+            ],
+        );
+        header.extend(unknown_size_element(SEGMENT));
+        header.extend(master_element(
+            INFO,
+            &[
+                uint_element(TIMESTAMP_SCALE, TIMESTAMP_SCALE_NS),
+                element(MUXING_APP, b"ALVR"),
+                element(WRITING_APP, format!("ALVR {}", *ALVR_VERSION).as_bytes()),
+            ],
+        ));
+        header.extend(master_element(TRACKS, &tracks));
+
+        self.writer.write_all(&header).map_err(err!())
+    }
+
+    fn write_block(
+        &mut self,
+        track: u8,
+        time_ms: i64,
+        is_keyframe: bool,
+        data: &[u8],
+    ) -> StrResult {
+        // Video keyframes start a new cluster, to allow seeking
+        let cluster_time_ms = match self.cluster_time_ms {
+            Some(cluster_time_ms)
+                if !(track == VIDEO_TRACK && is_keyframe)
+                    && (i16::MIN as i64..=MAX_CLUSTER_DURATION_MS)
+                        .contains(&(time_ms - cluster_time_ms)) =>
+            {
+                cluster_time_ms
+            }
+            _ => {
+                let mut cluster = unknown_size_element(CLUSTER);
+                cluster.extend(uint_element(TIMESTAMP, time_ms as _));
+                self.writer.write_all(&cluster).map_err(err!())?;
+
+                self.cluster_time_ms = Some(time_ms);
+
+                time_ms
+            }
+        };
+
+        let mut block = vec![0x80 | track];
+        block.extend(((time_ms - cluster_time_ms) as i16).to_be_bytes());
+        block.push(if is_keyframe { 0x80 } else { 0 });
+        block.extend_from_slice(data);
+
+        self.writer
+            .write_all(&element(SIMPLE_BLOCK, &block))
+            .map_err(err!())
+    }
+
+    // The recording starts at the first keyframe, as the previous frames cannot be decoded. The
+    // decoder configuration is repeated before each keyframe, in case it changes. `timestamp` is the
+    // target timestamp of the frame, so the recording keeps the timing of the game.
+    pub fn record_video(
+        &mut self,
+        timestamp: Duration,
+        data: &[u8],
+        decoder_config: Option<&[u8]>,
+    ) -> StrResult {
+        self.record_video_at(Instant::now(), timestamp, data, decoder_config)
+    }
+
+    fn record_video_at(
+        &mut self,
+        now: Instant,
+        timestamp: Duration,
+        data: &[u8],
+        decoder_config: Option<&[u8]>,
+    ) -> StrResult {
+        let is_keyframe = alvr_encoder::is_keyframe(self.codec, data);
+        let decoder_config = decoder_config.filter(|_| is_keyframe);
+
+        let start_timestamp = match self.start {
+            Some((_, start_timestamp)) => start_timestamp,
+            None => {
+                if let Some(decoder_config) = decoder_config {
+                    self.write_header(decoder_config)?;
+                    self.start = Some((now, timestamp));
+
+                    timestamp
+                } else {
+                    return Ok(());
+                }
+            }
+        };
+
+        let mut block = vec![];
+        match self.codec {
+            CodecType::H264 | CodecType::HEVC => {
+                if let Some(decoder_config) = decoder_config {
+                    write_length_prefixed(decoder_config, &mut block);
+                }
+                write_length_prefixed(data, &mut block);
+            }
+            CodecType::AV1 => {
+                if let Some(decoder_config) = decoder_config {
+                    block.extend_from_slice(decoder_config);
+                }
+                block.extend_from_slice(data);
+            }
+        }
+
+        let time_ms = timestamp.saturating_sub(start_timestamp).as_millis() as _;
+        self.write_block(VIDEO_TRACK, time_ms, is_keyframe, &block)
+    }
+
+    // The audio timestamps are derived from the number of recorded samples, starting from when the
+    // first samples are received
+    pub fn record_audio(&mut self, data: &[u8]) -> StrResult {
+        self.record_audio_at(Instant::now(), data)
+    }
+
+    fn record_audio_at(&mut self, now: Instant, data: &[u8]) -> StrResult {
+        let (audio, start) = match (&mut self.audio, self.start) {
+            (Some(audio), Some((start, _))) => (audio, start),
+            _ => return Ok(()),
+        };
+
+        let start_time = *audio
+            .start_time
+            .get_or_insert_with(|| now.saturating_duration_since(start));
+        let time = start_time
+            + Duration::from_secs_f64(
+                audio.recorded_frames_count as f64 / audio.sample_rate as f64,
+            );
+        audio.recorded_frames_count += (data.len() / (2 * audio.channels_count as usize)) as u64;
+
+        self.write_block(AUDIO_TRACK, time.as_millis() as _, true, data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Returns the elements in order, entering the ones with an unknown size. Their content is empty.
+    fn flatten_elements(data: &[u8]) -> Vec<(u32, &[u8])> {
+        fn read_vint(data: &[u8], offset: &mut usize, keep_marker: bool) -> u64 {
+            let length = data[*offset].leading_zeros() as usize + 1;
+            let mut value = if keep_marker {
+                data[*offset] as u64
+            } else {
+                (data[*offset] & (0xFF >> length)) as u64
+            };
+            for byte in &data[*offset + 1..*offset + length] {
+                value = (value << 8) | *byte as u64;
+            }
+            *offset += length;
+
+            value
+        }
+
+        let mut elements = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let id = read_vint(data, &mut offset, true) as u32;
+            if data[offset..].starts_with(&UNKNOWN_SIZE) {
+                offset += UNKNOWN_SIZE.len();
+                elements.push((id, &[][..]));
+            } else {
+                let size = read_vint(data, &mut offset, false) as usize;
+                elements.push((id, &data[offset..offset + size]));
+                offset += size;
+            }
+        }
+
+        elements
+    }
+
+    #[test]
+    fn record_h264_with_audio() {
+        let config = [
+            0, 0, 0, 1, 0x67, 0x64, 0, 0x1F, 0xAC, // SPS
+            0, 0, 0, 1, 0x68, 0xEE, // PPS
+        ];
+        let mut recorder = VideoRecorder::with_writer(
+            vec![],
+            CodecType::H264,
+            UVec2::new(64, 32),
+            Some((48_000, 2)),
+        );
+        let epoch = Instant::now();
+        let at = |ms| epoch + Duration::from_millis(ms);
+        let ts = Duration::from_millis;
+
+        // Nothing is recorded before the first keyframe
+        recorder.record_audio_at(at(0), &[0; 8]).unwrap();
+        recorder
+            .record_video_at(at(0), ts(990), &[0, 0, 0, 1, 0x41, 1], Some(&config))
+            .unwrap();
+        assert!(recorder.writer.is_empty());
+
+        recorder
+            .record_video_at(at(10), ts(1000), &[0, 0, 0, 1, 0x65, 2, 3], Some(&config))
+            .unwrap();
+        recorder.record_audio_at(at(17), &[0; 192]).unwrap();
+        recorder
+            .record_video_at(at(30), ts(1016), &[0, 0, 1, 0x41, 4], Some(&config))
+            .unwrap();
+
+        let elements = flatten_elements(&recorder.writer);
+        assert_eq!(
+            elements.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            [
+                EBML,
+                SEGMENT,
+                INFO,
+                TRACKS,
+                CLUSTER,
+                TIMESTAMP,
+                SIMPLE_BLOCK,
+                SIMPLE_BLOCK,
+                SIMPLE_BLOCK
+            ]
+        );
+
+        // The AVCDecoderConfigurationRecord with one SPS
+        assert!(elements[3]
+            .1
+            .windows(6)
+            .any(|bytes| bytes == [1, 0x64, 0, 0x1F, 0xFF, 0xE1]));
+
+        // Video track, time 0 and keyframe flag, followed by the SPS, the PPS and the IDR slice
+        assert_eq!(
+            elements[6].1,
+            [
+                0x81, 0, 0, 0x80, 0, 0, 0, 5, 0x67, 0x64, 0, 0x1F, 0xAC, 0, 0, 0, 2, 0x68, 0xEE, 0,
+                0, 0, 3, 0x65, 2, 3
+            ]
+        );
+        // Audio track, timed from when the first keyframe was received
+        assert_eq!(elements[7].1[..4], [0x82, 0, 7, 0x80]);
+        assert_eq!(elements[7].1.len(), 4 + 192);
+        // The video is timed with the frame timestamps, not with when the frames are received
+        assert_eq!(elements[8].1, [0x81, 0, 16, 0, 0, 0, 0, 2, 0x41, 4]);
+    }
+
+    #[test]
+    fn record_av1() {
+        // Temporal delimiter and sequence header of a 64x32 stream, profile 0, level 2.0, 8 bit 4:2:0
+        let config = [
+            0x12, 0x00, // temporal delimiter
+            // sequence header
+            0x0A, 0x09, 0x00, 0x00, 0x00, 0x02, 0xA7, 0xFF, 0x00, 0x60, 0x04,
+        ];
+        // Frame OBU with show_existing_frame = 0 and frame_type = KEY_FRAME
+        let keyframe = [0x32, 0x02, 0x10, 0x00];
+        let inter_frame = [0x32, 0x02, 0x30, 0x00];
+
+        let mut recorder =
+            VideoRecorder::with_writer(vec![], CodecType::AV1, UVec2::new(64, 32), None);
+        let epoch = Instant::now();
+        let ts = Duration::from_millis;
+        recorder
+            .record_video_at(epoch, ts(489), &inter_frame, Some(&config))
+            .unwrap();
+        assert!(recorder.writer.is_empty());
+
+        recorder
+            .record_video_at(epoch, ts(500), &keyframe, Some(&config))
+            .unwrap();
+        recorder
+            .record_video_at(epoch, ts(511), &inter_frame, Some(&config))
+            .unwrap();
+
+        let elements = flatten_elements(&recorder.writer);
+        let tracks = flatten_elements(elements[3].1);
+        let track_entry = flatten_elements(tracks[0].1);
+        assert!(track_entry.contains(&(CODEC_ID, &b"V_AV1"[..])));
+        let codec_private = track_entry
+            .iter()
+            .find(|(id, _)| *id == CODEC_PRIVATE)
+            .unwrap()
+            .1;
+        // av1C marker and version, profile and level, then the 4:2:0 subsampling
+        assert_eq!(codec_private[..4], [0x81, 0, 0x0C, 0]);
+        assert_eq!(codec_private[4..], config[2..]);
+
+        // The keyframe is preceded by the sequence header, the frames are stored as OBUs
+        assert_eq!(elements[6].1[..4], [0x81, 0, 0, 0x80]);
+        assert_eq!(elements[6].1[4..], [&config[..], &keyframe[..]].concat());
+        assert_eq!(elements[7].1, [0x81, 0, 11, 0, 0x32, 0x02, 0x30, 0x00]);
+    }
+}
@@ -1,4 +1,5 @@
 use crate::{
+    backend::{CppBackend, ServerBackend},
     DISCONNECT_CLIENT_NOTIFIER, FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER, VIDEO_MIRROR_SENDER,
 };
 use alvr_common::{prelude::*, ALVR_VERSION};
@@ -44,9 +45,11 @@ async fn from_request_body<T: DeserializeOwned>(request: Request<Body>) -> StrRe
     .map_err(err!())
 }
 
+// `initial_data` is called after subscribing, its data is sent before the broadcast data
 async fn websocket<T: Clone + Send + 'static>(
     request: Request<Body>,
     sender: broadcast::Sender<T>,
+    initial_data: impl FnOnce() -> Vec<T> + Send + 'static,
     message_builder: impl Fn(T) -> protocol::Message + Send + Sync + 'static,
 ) -> StrResult<Response<Body>> {
     if let Some(key) = request.headers().typed_get::<headers::SecWebsocketKey>() {
@@ -59,6 +62,12 @@ async fn websocket<T: Clone + Send + 'static>(
                         WebSocketStream::from_raw_socket(upgraded, protocol::Role::Server, None)
                             .await;
 
+                    for data in initial_data() {
+                        if let Err(e) = ws.send(message_builder(data)).await {
+                            info!("Failed to send data with websocket: {e}");
+                        }
+                    }
+
                     loop {
                         match data_receiver.recv().await {
                             Ok(data) => {
@@ -145,12 +154,51 @@ async fn http_api(
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
-        "/api/log" => websocket(request, log_sender, protocol::Message::Text).await?,
+        "/api/input-profile/select" => {
+            if let Ok(name) = from_request_body::<String>(request).await {
+                let mut data_manager = SERVER_DATA_MANAGER.write();
+
+                // An empty name disables the remapping
+                if name.is_empty()
+                    || data_manager
+                        .session()
+                        .session_settings
+                        .headset
+                        .controllers
+                        .content
+                        .input_profiles
+                        .content
+                        .iter()
+                        .any(|(profile_name, _)| *profile_name == name)
+                {
+                    data_manager
+                        .session_mut()
+                        .session_settings
+                        .headset
+                        .controllers
+                        .content
+                        .active_input_profile = name;
+
+                    reply(StatusCode::OK)?
+                } else {
+                    reply(StatusCode::BAD_REQUEST)?
+                }
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
+        "/api/log" => websocket(request, log_sender, Vec::new, protocol::Message::Text).await?,
         "/api/events-legacy" => {
-            websocket(request, legacy_events_sender, protocol::Message::Text).await?
+            websocket(
+                request,
+                legacy_events_sender,
+                Vec::new,
+                protocol::Message::Text,
+            )
+            .await?
         }
         "/api/events" => {
-            websocket(request, events_sender, |e| {
+            websocket(request, events_sender, Vec::new, |e| {
                 protocol::Message::Binary(bincode::serialize(&e).unwrap())
             })
             .await?
@@ -168,7 +216,17 @@ async fn http_api(
                 }
             };
 
-            websocket(request, sender, protocol::Message::Binary).await?
+            // Clients that join while streaming need the decoder configuration and a keyframe
+            let initial_data = || {
+                let config_message = crate::video_mirror_config_message();
+                if config_message.is_some() {
+                    CppBackend.request_idr();
+                }
+
+                config_message.into_iter().collect()
+            };
+
+            websocket(request, sender, initial_data, protocol::Message::Binary).await?
         }
         "/api/driver/register" => {
             if alvr_commands::driver_registration(
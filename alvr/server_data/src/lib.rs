@@ -21,6 +21,7 @@ pub struct SessionLock<'a> {
     session_desc: &'a mut SessionDesc,
     session_path: &'a Path,
     settings: &'a mut Settings,
+    settings_revision: &'a mut u64,
 }
 
 impl Deref for SessionLock<'_> {
@@ -40,6 +41,7 @@ impl Drop for SessionLock<'_> {
     fn drop(&mut self) {
         save_session(self.session_desc, self.session_path).unwrap();
         *self.settings = self.session_desc.to_settings();
+        *self.settings_revision += 1;
         alvr_events::send_event(EventType::SessionUpdated); // deprecated
         alvr_events::send_event(EventType::Session(Box::new(self.session_desc.clone())));
     }
@@ -53,6 +55,8 @@ impl Drop for SessionLock<'_> {
 pub struct ServerDataManager {
     session: SessionDesc,
     settings: Settings,
+    // Incremented each time the settings are updated
+    settings_revision: u64,
     session_path: PathBuf,
     script_engine: rhai::Engine,
     gpu_adapters: Vec<Adapter>,
@@ -107,6 +111,7 @@ impl ServerDataManager {
         Self {
             session: session_desc.clone(),
             settings: session_desc.to_settings(),
+            settings_revision: 0,
             session_path: session_path.to_owned(),
             script_engine,
             gpu_adapters,
@@ -123,6 +128,7 @@ impl ServerDataManager {
             session_desc: &mut self.session,
             session_path: &self.session_path,
             settings: &mut self.settings,
+            settings_revision: &mut self.settings_revision,
         }
     }
 
@@ -130,6 +136,11 @@ impl ServerDataManager {
         &self.settings
     }
 
+    // Lets the users of the settings rebuild their state only when needed
+    pub fn settings_revision(&self) -> u64 {
+        self.settings_revision
+    }
+
     pub fn client_list(&self) -> &HashMap<String, ClientConnectionDesc> {
         &self.session.client_connections
     }
@@ -151,6 +162,7 @@ impl ServerDataManager {
         // session_json has been updated
         self.session = serde_json::from_value(session_json).map_err(err!())?;
         self.settings = self.session.to_settings();
+        self.settings_revision += 1;
 
         save_session(&self.session, &self.session_path).unwrap();
         alvr_events::send_event(EventType::Session(Box::new(self.session.clone())));
@@ -230,6 +242,7 @@ impl ServerDataManager {
                         current_ip: None,
                         manual_ips: HashSet::new(),
                         display_name: "Unknown".into(),
+                        certificate: None,
                     };
                     new_entry.insert(client_connection_desc);
 
@@ -280,6 +293,20 @@ impl ServerDataManager {
                     }
                 }
             }
+            ClientListAction::SetCertificate(certificate) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    entry.get_mut().certificate = Some(certificate);
+
+                    updated = true;
+                }
+            }
+            ClientListAction::RevokeTrust => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    entry.get_mut().trusted = false;
+
+                    updated = true;
+                }
+            }
         }
 
         if updated {
@@ -53,6 +53,7 @@ pub struct OpenvrConfig {
     pub rate_control_mode: u32,
     pub force_sw_encoding: bool,
     pub sw_thread_count: u32,
+    pub use_openh264: bool,
     pub encode_bitrate_mbs: u64,
     pub enable_adaptive_bitrate: bool,
     pub bitrate_maximum: u64,
@@ -76,15 +77,6 @@ pub struct OpenvrConfig {
     pub controllers_input_profile_path: String,
     pub controllers_mode_idx: i32,
     pub controllers_enabled: bool,
-    pub position_offset: [f32; 3],
-    pub linear_velocity_cutoff: f32,
-    pub angular_velocity_cutoff: f32,
-    pub position_offset_left: [f32; 3],
-    pub rotation_offset_left: [f32; 3],
-    pub override_trigger_threshold: bool,
-    pub trigger_threshold: f32,
-    pub override_grip_threshold: bool,
-    pub grip_threshold: f32,
     pub haptics_intensity: f32,
     pub haptics_amplitude_curve: f32,
     pub haptics_min_duration: f32,
@@ -104,7 +96,6 @@ pub struct OpenvrConfig {
     pub saturation: f32,
     pub gamma: f32,
     pub sharpening: f32,
-    pub enable_fec: bool,
     pub linux_async_reprojection: bool,
     pub nvenc_preset: i64,
     pub nvenc_refresh_rate: i64,
@@ -121,6 +112,20 @@ pub struct OpenvrConfig {
     pub rc_average_bitrate: i64,
     pub enable_aq: i64,
     pub capture_frame_dir: String,
+    pub generic_trackers: Vec<GenericTrackerConfig>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Default, Clone, Debug)]
+pub struct GenericTrackerConfig {
+    pub path: String,
+    pub serial_number: String,
+    pub tracking_system_name: String,
+    pub manufacturer_name: String,
+    pub model_number: String,
+    pub render_model_name: String,
+    pub registered_device_type: String,
+    pub controller_type: String,
+    pub input_profile_path: String,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -130,6 +135,8 @@ pub struct ClientConnectionDesc {
     pub current_ip: Option<IpAddr>,
     pub manual_ips: HashSet<IpAddr>,
     pub trusted: bool,
+    // DER encoded TLS certificate of the client, pinned when pairing
+    pub certificate: Option<Vec<u8>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -1,3 +1,4 @@
+use alvr_common::{LEFT_FOOT_PATH, RIGHT_FOOT_PATH, WAIST_PATH};
 use bytemuck::{Pod, Zeroable};
 use serde::{Deserialize, Serialize};
 use settings_schema::{DictionaryDefault, EntryData, SettingsSchema, Switch, SwitchDefault};
@@ -189,6 +190,8 @@ pub struct ColorCorrectionDesc {
 pub enum CodecType {
     H264,
     HEVC,
+    // Encoded on Linux only. The server falls back to HEVC or H264 when the client cannot decode it
+    AV1,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -236,6 +239,11 @@ pub struct VideoDesc {
     #[schema(advanced)]
     pub sw_thread_count: u32,
 
+    // Encodes with OpenH264 in the server instead of FFmpeg when software encoding is used. Only
+    // H264 is supported, on Linux.
+    #[schema(advanced)]
+    pub use_openh264: bool,
+
     #[schema(min = 1, max = 500)]
     pub encode_bitrate_mbs: u64,
 
@@ -346,6 +354,94 @@ pub struct ControllersGripOverrideDesc {
     pub grip_threshold: f32,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonMappingDesc {
+    // Path of the input that receives the value, like /user/hand/right/input/a/click
+    pub target: String,
+
+    // Used when a scalar input is mapped to a click or touch input
+    #[schema(min = 0., max = 1., step = 0.01)]
+    pub binary_threshold: f32,
+
+    // Negates the value, for the thumbstick axes
+    pub invert: bool,
+
+    // Smaller scalar values are set to zero, and the rest is rescaled to the full range
+    #[schema(min = 0., max = 1., step = 0.01)]
+    pub deadzone: f32,
+}
+
+// While both buttons are pressed, they are released and the target of the chord is pressed
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ButtonChordDesc {
+    pub first: String,
+    pub second: String,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct InputProfileDesc {
+    // Indexed by the path of the source input. The inputs without a mapping are not changed.
+    pub mappings: Vec<(String, ButtonMappingDesc)>,
+
+    // Indexed by the path of the target input
+    pub chords: Vec<(String, ButtonChordDesc)>,
+}
+
+// Distances are in meters, between the tips of the fingers or between the tips and the palm. Each
+// gesture goes from 0 at the start distance to 1 at the end distance.
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HandGesturesDesc {
+    // Thumb and index finger, for the trigger. Thumb and middle finger, for the menu button on the
+    // left hand and the A button on the right hand.
+    #[schema(min = 0., max = 0.1, step = 0.001)]
+    pub pinch_start_distance: f32,
+
+    #[schema(min = 0., max = 0.1, step = 0.001)]
+    pub pinch_end_distance: f32,
+
+    // Middle, ring and little fingers to the palm, for the grip
+    #[schema(min = 0., max = 0.15, step = 0.001)]
+    pub grab_start_distance: f32,
+
+    #[schema(min = 0., max = 0.15, step = 0.001)]
+    pub grab_end_distance: f32,
+
+    // The clicks are pressed above the press threshold and released below the release threshold
+    #[schema(advanced, min = 0., max = 1., step = 0.01)]
+    pub click_press_threshold: f32,
+
+    #[schema(advanced, min = 0., max = 1., step = 0.01)]
+    pub click_release_threshold: f32,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "camelCase", tag = "type", content = "content")]
+pub enum PredictionModel {
+    // Extrapolates the pose with the velocities reported by the client
+    ConstantVelocity,
+
+    // Also estimates the acceleration from the change of the velocities between packets
+    ConstantAcceleration,
+
+    // Smooths the pose with a One-Euro filter, then extrapolates it with the velocities. A lower
+    // min_cutoff removes more jitter when still, a higher beta reduces the lag when moving fast.
+    #[serde(rename_all = "camelCase")]
+    OneEuro {
+        #[schema(min = 0.01, max = 10., step = 0.01)]
+        min_cutoff: f32,
+
+        #[schema(min = 0., max = 20., step = 0.1)]
+        beta: f32,
+
+        #[schema(advanced, min = 0.1, max = 10., step = 0.1)]
+        derivative_cutoff: f32,
+    },
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ControllersDesc {
@@ -393,6 +489,8 @@ pub struct ControllersDesc {
     #[schema(min = -50, max = 50, step = 1)]
     pub pose_time_offset_ms: i64,
 
+    pub prediction_model: PredictionModel,
+
     #[schema(advanced, min = 0., max = 0.1, step = 0.001)]
     pub linear_velocity_cutoff: f32,
 
@@ -405,9 +503,12 @@ pub struct ControllersDesc {
     #[schema(advanced)]
     pub rotation_offset_left: [f32; 3],
 
+    // Sets the trigger click from its value, applied by the button remapping unless the input
+    // profile remaps the trigger
     #[schema(advanced)]
     pub override_trigger_threshold: Switch<ControllersTriggerOverrideDesc>,
 
+    // Same for the grip click
     #[schema(advanced)]
     pub override_grip_threshold: Switch<ControllersGripOverrideDesc>,
 
@@ -428,6 +529,48 @@ pub struct ControllersDesc {
 
     #[schema(advanced)]
     pub use_headset_tracking_system: bool,
+
+    // Emulates the buttons with hand gestures, for the clients that send the OpenXR hand
+    // skeletons. Replaces the emulation based on the finger rotations.
+    pub hand_gestures: Switch<HandGesturesDesc>,
+
+    // Remapping of the buttons of the client, indexed by profile name
+    pub input_profiles: Vec<(String, InputProfileDesc)>,
+
+    // Can be changed while streaming, for example by a script. An empty or unknown name disables
+    // the remapping.
+    pub active_input_profile: String,
+
+    // Profile names indexed by the executable name of a game, like vrchat.exe. The profile is used
+    // in place of the active one while the game is running.
+    pub game_input_profiles: Vec<(String, String)>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GenericTrackerDesc {
+    pub serial_number: String,
+
+    #[schema(advanced)]
+    pub tracking_system_name: String,
+
+    #[schema(advanced)]
+    pub manufacturer_name: String,
+
+    #[schema(advanced)]
+    pub model_number: String,
+
+    #[schema(advanced)]
+    pub render_model_name: String,
+
+    #[schema(advanced)]
+    pub registered_device_type: String,
+
+    // SteamVR assigns the tracker role from this, for example "vive_tracker_waist"
+    pub controller_type: String,
+
+    #[schema(advanced)]
+    pub input_profile_path: String,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
@@ -473,6 +616,10 @@ pub struct HeadsetDesc {
     #[schema(advanced)]
     pub position_offset: [f32; 3],
 
+    // The first head pose received after the connection becomes the origin of the playspace
+    #[schema(advanced)]
+    pub recenter_on_connect: bool,
+
     #[schema(advanced)]
     pub force_3dof: bool,
 
@@ -484,6 +631,11 @@ pub struct HeadsetDesc {
 
     pub controllers: Switch<ControllersDesc>,
 
+    // Indexed by the device path, for example "/user/waist". Each tracker is registered in SteamVR
+    // as a generic tracker and receives the motions that the client sends for its path. The hands
+    // of companion clients use paths like "/companion/<hostname>/user/hand/left".
+    pub trackers: Switch<Vec<(String, GenericTrackerDesc)>>,
+
     #[schema(advanced)]
     pub extra_latency_mode: bool,
 }
@@ -501,11 +653,16 @@ pub enum SocketProtocol {
     },
 
     Tcp,
+
+    // Video and audio are sent as QUIC datagrams, the other streams use reliable QUIC streams.
+    Quic,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct DiscoveryConfig {
+    // Pair new clients without asking to confirm the pairing code. Any device on the local network
+    // can impersonate a client that is not paired yet
     #[schema(advanced)]
     pub auto_trust_clients: bool,
 }
@@ -543,6 +700,21 @@ pub struct ConnectionDesc {
     #[schema(advanced)]
     pub stream_port: u16,
 
+    // Clients that connect while another one is streaming receive the same video. Each spectator
+    // uses the next free port after stream_port. When the streaming client disconnects, a spectator
+    // reconnects to take its place.
+    #[schema(min = 0, max = 16)]
+    pub max_spectators: u32,
+
+    // Clients that only send tracking and input, like a phone or a second headset. They also use
+    // the free ports after stream_port.
+    #[schema(min = 0, max = 16)]
+    pub max_companions: u32,
+
+    // Maximum size of a single network packet. Bigger payloads are split into multiple packets.
+    #[schema(advanced, min = 576, max = 65507)]
+    pub packet_size: u32,
+
     #[schema(advanced)]
     pub aggressive_keyframe_resend: bool,
 
@@ -555,6 +727,10 @@ pub struct ConnectionDesc {
     #[schema(advanced)]
     pub enable_fec: bool,
 
+    // Request again the video packets lost on the network, if they can arrive within a frame time
+    #[schema(advanced)]
+    pub enable_nack: bool,
+
     #[schema(advanced)]
     pub statistics_history_size: u64,
 }
@@ -584,6 +760,14 @@ pub enum LogLevel {
     Debug,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct VideoRecordingDesc {
+    // The log directory is used if empty
+    pub output_directory: String,
+    pub include_game_audio: bool,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ExtraDesc {
@@ -595,6 +779,17 @@ pub struct ExtraDesc {
     pub log_to_disk: bool,
 
     pub log_button_presses: bool,
+
+    // Record the tracking, the input and the haptics of the primary client, to be replayed later
+    // by a client without a headset. The recording is saved in the log directory and overwritten
+    // at every connection.
+    #[schema(advanced)]
+    pub record_tracking: bool,
+
+    // Save the video stream received by the primary client to a Matroska file, one per connection
+    #[schema(advanced)]
+    pub record_video: Switch<VideoRecordingDesc>,
+
     #[schema(advanced)]
     pub notification_level: LogLevel,
     #[schema(advanced)]
@@ -614,6 +809,25 @@ pub struct Settings {
     pub extra: ExtraDesc,
 }
 
+// Vive tracker identity used for the generic trackers. An empty serial number gives the template of
+// the new entries.
+fn vive_tracker_default(serial_number: &str, controller_type: &str) -> GenericTrackerDescDefault {
+    GenericTrackerDescDefault {
+        serial_number: serial_number.into(),
+        tracking_system_name: "oculus".into(),
+        manufacturer_name: "HTC".into(),
+        model_number: "Vive Tracker Pro MV".into(),
+        render_model_name: "{htc}vr_tracker_vive_1_0".into(),
+        registered_device_type: if serial_number.is_empty() {
+            "".into()
+        } else {
+            format!("htc/{serial_number}")
+        },
+        controller_type: controller_type.into(),
+        input_profile_path: "{htc}/input/vive_tracker_profile.json".into(),
+    }
+}
+
 pub fn session_settings_default() -> SettingsDefault {
     SettingsDefault {
         video: VideoDescDefault {
@@ -646,6 +860,7 @@ pub fn session_settings_default() -> SettingsDefault {
             use_10bit_encoder: false,
             force_sw_encoding: false,
             sw_thread_count: 0,
+            use_openh264: false,
             encode_bitrate_mbs: 30,
             adaptive_bitrate: SwitchDefault {
                 enabled: true,
@@ -792,6 +1007,7 @@ pub fn session_settings_default() -> SettingsDefault {
             render_model_name: "generic_hmd".into(),
             registered_device_type: "oculus/1WMGH000XX0000".into(),
             position_offset: [0., 0., 0.],
+            recenter_on_connect: false,
             force_3dof: false,
             tracking_ref_only: false,
             enable_vive_tracker_proxy: false,
@@ -810,6 +1026,14 @@ pub fn session_settings_default() -> SettingsDefault {
                     registered_device_type: "oculus/1WMGH000XX0000_Controller".into(),
                     input_profile_path: "{oculus}/input/touch_profile.json".into(),
                     pose_time_offset_ms: 20,
+                    prediction_model: PredictionModelDefault {
+                        variant: PredictionModelDefaultVariant::ConstantVelocity,
+                        OneEuro: PredictionModelOneEuroDefault {
+                            min_cutoff: 1.,
+                            beta: 5.,
+                            derivative_cutoff: 1.,
+                        },
+                    },
                     linear_velocity_cutoff: 0.01,
                     angular_velocity_cutoff: 10.,
                     position_offset_left: [-0.0065, 0.002, -0.051],
@@ -832,6 +1056,71 @@ pub fn session_settings_default() -> SettingsDefault {
                     haptics_low_duration_amplitude_multiplier: 2.5,
                     haptics_low_duration_range: 0.5,
                     use_headset_tracking_system: false,
+                    hand_gestures: SwitchDefault {
+                        enabled: false,
+                        content: HandGesturesDescDefault {
+                            pinch_start_distance: 0.05,
+                            pinch_end_distance: 0.015,
+                            grab_start_distance: 0.08,
+                            grab_end_distance: 0.04,
+                            click_press_threshold: 0.9,
+                            click_release_threshold: 0.7,
+                        },
+                    },
+                    input_profiles: DictionaryDefault {
+                        key: "".into(),
+                        value: InputProfileDescDefault {
+                            mappings: DictionaryDefault {
+                                key: "".into(),
+                                value: ButtonMappingDescDefault {
+                                    target: "".into(),
+                                    binary_threshold: 0.5,
+                                    invert: false,
+                                    deadzone: 0.,
+                                },
+                                content: vec![],
+                            },
+                            chords: DictionaryDefault {
+                                key: "".into(),
+                                value: ButtonChordDescDefault {
+                                    first: "".into(),
+                                    second: "".into(),
+                                },
+                                content: vec![],
+                            },
+                        },
+                        content: vec![],
+                    },
+                    active_input_profile: "".into(),
+                    game_input_profiles: DictionaryDefault {
+                        key: "".into(),
+                        value: "".into(),
+                        content: vec![],
+                    },
+                },
+            },
+            trackers: SwitchDefault {
+                enabled: false,
+                content: DictionaryDefault {
+                    key: "".into(),
+                    value: vive_tracker_default("", "vive_tracker"),
+                    content: vec![
+                        (
+                            WAIST_PATH.into(),
+                            vive_tracker_default("ALVR_Tracker_Waist", "vive_tracker_waist"),
+                        ),
+                        (
+                            LEFT_FOOT_PATH.into(),
+                            vive_tracker_default("ALVR_Tracker_LeftFoot", "vive_tracker_left_foot"),
+                        ),
+                        (
+                            RIGHT_FOOT_PATH.into(),
+                            vive_tracker_default(
+                                "ALVR_Tracker_RightFoot",
+                                "vive_tracker_right_foot",
+                            ),
+                        ),
+                    ],
                 },
             },
             extra_latency_mode: false,
@@ -871,10 +1160,14 @@ pub fn session_settings_default() -> SettingsDefault {
                 variant: SocketBufferSizeDefaultVariant::Maximum,
             },
             stream_port: 9944,
+            max_spectators: 4,
+            max_companions: 4,
+            packet_size: 1452,
             aggressive_keyframe_resend: false,
             on_connect_script: "".into(),
             on_disconnect_script: "".into(),
             enable_fec: true,
+            enable_nack: true,
             statistics_history_size: 256,
         },
         extra: ExtraDescDefault {
@@ -895,6 +1188,14 @@ pub fn session_settings_default() -> SettingsDefault {
             },
             log_to_disk: cfg!(debug_assertions),
             log_button_presses: false,
+            record_tracking: false,
+            record_video: SwitchDefault {
+                enabled: false,
+                content: VideoRecordingDescDefault {
+                    output_directory: "".into(),
+                    include_game_audio: true,
+                },
+            },
             notification_level: LogLevelDefault {
                 variant: if cfg!(debug_assertions) {
                     LogLevelDefaultVariant::Info
@@ -0,0 +1,117 @@
+// H264 and HEVC streams are sent in the Annex B format, where each NAL unit is preceded by a 3 or 4
+// byte start code. The parameter sets (SPS and PPS, plus VPS for HEVC) play the role of the AV1
+// sequence header: the server sends them with InitializeDecoder.
+
+use alvr_session::CodecType;
+
+// Splits an Annex B buffer into NAL units, each with its start code. Bytes before the first start
+// code are discarded.
+pub fn annex_b_nals(data: &[u8]) -> Vec<&[u8]> {
+    let mut starts = vec![];
+    let mut index = 0;
+    while index + 3 <= data.len() {
+        if data[index..index + 3] == [0, 0, 1] {
+            starts.push(if index > 0 && data[index - 1] == 0 {
+                index - 1
+            } else {
+                index
+            });
+            index += 3;
+        } else {
+            index += 1;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| &data[start..starts.get(i + 1).copied().unwrap_or(data.len())])
+        .collect()
+}
+
+// `nal` must start with a start code, like the units returned by annex_b_nals()
+pub fn strip_start_code(nal: &[u8]) -> &[u8] {
+    &nal[if nal[2] == 1 { 3 } else { 4 }..]
+}
+
+// Type read from the NAL unit header, after the start code
+pub fn nal_type(codec: CodecType, nal: &[u8]) -> Option<u8> {
+    let header = strip_start_code(nal).first()?;
+
+    match codec {
+        CodecType::H264 => Some(header & 0x1F),
+        CodecType::HEVC => Some((header >> 1) & 0x3F),
+        CodecType::AV1 => None,
+    }
+}
+
+// SPS and PPS, plus VPS for HEVC
+pub fn is_parameter_set(codec: CodecType, nal_type: u8) -> bool {
+    match codec {
+        CodecType::H264 => matches!(nal_type, 7 | 8),
+        CodecType::HEVC => matches!(nal_type, 32..=34),
+        CodecType::AV1 => false,
+    }
+}
+
+// For HEVC, any intra random access point
+pub fn is_idr(codec: CodecType, nal_type: u8) -> bool {
+    match codec {
+        CodecType::H264 => nal_type == 5,
+        CodecType::HEVC => matches!(nal_type, 16..=21),
+        CodecType::AV1 => false,
+    }
+}
+
+// SEI and access unit delimiters, which the decoders do not need
+pub fn is_sei_or_delimiter(codec: CodecType, nal_type: u8) -> bool {
+    match codec {
+        CodecType::H264 => matches!(nal_type, 6 | 9),
+        CodecType::HEVC => matches!(nal_type, 35 | 39),
+        CodecType::AV1 => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_nal_units() {
+        let data = [
+            0xFF, // garbage before the first start code
+            0, 0, 0, 1, 0x67, 0x64, // 4 byte start code
+            0, 0, 1, 0x68, 0xEE, // 3 byte start code
+            0, 0, 0, 1, 0x65,
+        ];
+
+        let nals = annex_b_nals(&data);
+        assert_eq!(
+            nals,
+            [
+                &[0, 0, 0, 1, 0x67, 0x64][..],
+                &[0, 0, 1, 0x68, 0xEE],
+                &[0, 0, 0, 1, 0x65]
+            ]
+        );
+        assert_eq!(strip_start_code(nals[1]), [0x68, 0xEE]);
+        assert_eq!(
+            nals.iter()
+                .filter_map(|nal| nal_type(CodecType::H264, nal))
+                .collect::<Vec<_>>(),
+            [7, 8, 5]
+        );
+    }
+
+    #[test]
+    fn hevc_nal_types() {
+        // VPS and CRA slice
+        assert_eq!(nal_type(CodecType::HEVC, &[0, 0, 1, 0x40, 0x01]), Some(32));
+        assert_eq!(nal_type(CodecType::HEVC, &[0, 0, 1, 0x2A, 0x01]), Some(21));
+        assert!(is_parameter_set(CodecType::HEVC, 32));
+        assert!(is_idr(CodecType::HEVC, 21));
+
+        // A start code at the end of the buffer has no header
+        assert_eq!(nal_type(CodecType::HEVC, &[0, 0, 1]), None);
+    }
+}
@@ -1,17 +1,85 @@
-use super::{Ldc, CONTROL_PORT, LOCAL_IP};
+use super::{
+    bind_dual_stack, canonical_ip, negotiate, peer_socket_addr, register_peer_address, Ldc,
+    NegotiatedProtocol, ProtocolHello, TlsIdentity, TLS_SERVER_NAME,
+};
 use alvr_common::prelude::*;
 use bytes::Bytes;
 use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    server::{ClientCertVerified, ClientCertVerifier},
+    Certificate, ClientConfig, ConnectionCommon, DistinguishedName, PrivateKey, ServerConfig,
+    ServerName,
+};
 use serde::{de::DeserializeOwned, Serialize};
-use std::{marker::PhantomData, net::IpAddr};
+use std::{marker::PhantomData, net::IpAddr, sync::Arc, time::SystemTime};
 use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::{TlsAcceptor, TlsConnector, TlsStream};
 use tokio_util::codec::Framed;
 
+const PAIRING_CODE_LABEL: &[u8] = b"ALVR pairing code";
+
+type TlsFramed = Framed<TlsStream<TcpStream>, Ldc>;
+
+// The peers do not know the certificate of each other before pairing, so any certificate is
+// accepted during the handshake (the possession of the private key is still verified). The caller
+// must check peer_certificate() against the pinned one.
+struct AnyCertificateVerifier;
+
+impl ServerCertVerifier for AnyCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+impl ClientCertVerifier for AnyCertificateVerifier {
+    fn client_auth_root_subjects(&self) -> &[DistinguishedName] {
+        &[]
+    }
+
+    fn verify_client_cert(
+        &self,
+        _: &Certificate,
+        _: &[Certificate],
+        _: SystemTime,
+    ) -> Result<ClientCertVerified, rustls::Error> {
+        Ok(ClientCertVerified::assertion())
+    }
+}
+
+// The pairing code is derived from the secrets of the TLS session, so the server and the client
+// show the same code only if there is no man in the middle
+fn pairing_code<D>(connection: &ConnectionCommon<D>) -> StrResult<String> {
+    let material = connection
+        .export_keying_material([0; 4], PAIRING_CODE_LABEL, None)
+        .map_err(err!())?;
+
+    Ok(format!("{:06}", u32::from_be_bytes(material) % 1_000_000))
+}
+
+// Both peers require a certificate, so it is always present after the handshake
+fn first_certificate<D>(connection: &ConnectionCommon<D>) -> StrResult<Vec<u8>> {
+    let certificate = connection
+        .peer_certificates()
+        .and_then(|certificates| certificates.first())
+        .ok_or_else(enone!())?;
+
+    Ok(certificate.0.clone())
+}
+
 pub struct ControlSocketSender<T> {
-    inner: SplitSink<Framed<TcpStream, Ldc>, Bytes>,
+    inner: SplitSink<TlsFramed, Bytes>,
     _phantom: PhantomData<T>,
 }
 
@@ -23,7 +91,7 @@ impl<S: Serialize> ControlSocketSender<S> {
 }
 
 pub struct ControlSocketReceiver<T> {
-    inner: SplitStream<Framed<TcpStream, Ldc>>,
+    inner: SplitStream<TlsFramed>,
     _phantom: PhantomData<T>,
 }
 
@@ -39,46 +107,122 @@ impl<R: DeserializeOwned> ControlSocketReceiver<R> {
     }
 }
 
-pub async fn get_server_listener() -> StrResult<TcpListener> {
-    TcpListener::bind((LOCAL_IP, CONTROL_PORT))
-        .await
-        .map_err(err!())
+// Clients listen on CONTROL_PORT
+pub async fn get_server_listener(port: u16) -> StrResult<TcpListener> {
+    let socket = bind_dual_stack(socket2::Type::STREAM, port)?;
+    socket.listen(128).map_err(err!())?;
+    socket.set_nonblocking(true).map_err(err!())?;
+
+    TcpListener::from_std(socket.into()).map_err(err!())
 }
 
 // Proto-control-socket that can send and receive any packet. After the split, only the packets of
 // the specified types can be exchanged
 pub struct ProtoControlSocket {
-    inner: Framed<TcpStream, Ldc>,
+    inner: TlsFramed,
+    peer_certificate: Vec<u8>,
+    pairing_code: String,
 }
 
+// The roles are inverted for TLS: the client is the TLS server, since it listens for the server.
+// Both peers present their own identity.
 pub enum PeerType<'a> {
-    AnyClient(Vec<IpAddr>),
-    Server(&'a TcpListener),
+    // IPs of the candidate clients, the port they listen on and the identity of the server
+    AnyClient(Vec<IpAddr>, u16, &'a TlsIdentity),
+    Server(&'a TcpListener, &'a TlsIdentity),
 }
 
 impl ProtoControlSocket {
     pub async fn connect_to(peer: PeerType<'_>) -> StrResult<(Self, IpAddr)> {
-        let socket = match peer {
-            PeerType::AnyClient(ips) => {
+        let (stream, peer_certificate, pairing_code): (TlsStream<_>, _, _) = match peer {
+            PeerType::AnyClient(ips, port, identity) => {
                 let client_addresses = ips
                     .iter()
-                    .map(|&ip| (ip, CONTROL_PORT).into())
+                    .map(|&ip| peer_socket_addr(ip, port))
                     .collect::<Vec<_>>();
-                TcpStream::connect(client_addresses.as_slice())
+                let socket = TcpStream::connect(client_addresses.as_slice())
+                    .await
+                    .map_err(err!())?;
+                socket.set_nodelay(true).map_err(err!())?;
+
+                let config = ClientConfig::builder()
+                    .with_safe_defaults()
+                    .with_custom_certificate_verifier(Arc::new(AnyCertificateVerifier))
+                    .with_client_auth_cert(
+                        vec![Certificate(identity.certificate.clone())],
+                        PrivateKey(identity.private_key.clone()),
+                    )
+                    .map_err(err!())?;
+                let server_name = ServerName::try_from(TLS_SERVER_NAME).map_err(err!())?;
+                let stream = TlsConnector::from(Arc::new(config))
+                    .connect(server_name, socket)
                     .await
-                    .map_err(err!())?
+                    .map_err(err!())?;
+
+                let (_, connection) = stream.get_ref();
+                let peer_certificate = first_certificate(connection)?;
+                let pairing_code = pairing_code(connection)?;
+
+                (stream.into(), peer_certificate, pairing_code)
             }
-            PeerType::Server(listener) => {
+            PeerType::Server(listener, identity) => {
                 let (socket, _) = listener.accept().await.map_err(err!())?;
-                socket
+                socket.set_nodelay(true).map_err(err!())?;
+
+                let config = ServerConfig::builder()
+                    .with_safe_defaults()
+                    .with_client_cert_verifier(Arc::new(AnyCertificateVerifier))
+                    .with_single_cert(
+                        vec![Certificate(identity.certificate.clone())],
+                        PrivateKey(identity.private_key.clone()),
+                    )
+                    .map_err(err!())?;
+                let stream = TlsAcceptor::from(Arc::new(config))
+                    .accept(socket)
+                    .await
+                    .map_err(err!())?;
+
+                let (_, connection) = stream.get_ref();
+                let peer_certificate = first_certificate(connection)?;
+                let pairing_code = pairing_code(connection)?;
+
+                (stream.into(), peer_certificate, pairing_code)
             }
         };
 
-        socket.set_nodelay(true).map_err(err!())?;
-        let peer_ip = socket.peer_addr().map_err(err!())?.ip();
-        let socket = Framed::new(socket, Ldc::new());
+        let peer_address = stream.get_ref().0.peer_addr().map_err(err!())?;
+        register_peer_address(peer_address);
+        let peer_ip = canonical_ip(peer_address.ip());
+
+        Ok((
+            Self {
+                inner: Framed::new(stream, Ldc::new()),
+                peer_certificate,
+                pairing_code,
+            },
+            peer_ip,
+        ))
+    }
+
+    // DER encoded certificate presented by the peer
+    pub fn peer_certificate(&self) -> &[u8] {
+        &self.peer_certificate
+    }
+
+    // Six digit code shown on the dashboard, that the user confirms on the headset
+    pub fn pairing_code(&self) -> &str {
+        &self.pairing_code
+    }
+
+    // Must be called by both peers right after connecting, before any other packet is exchanged
+    pub async fn negotiate_protocol(
+        &mut self,
+        local_hello: &ProtocolHello,
+    ) -> StrResult<NegotiatedProtocol> {
+        self.send(local_hello).await?;
+        let peer_hello = self.recv::<ProtocolHello>().await?;
 
-        Ok((Self { inner: socket }, peer_ip))
+        negotiate(local_hello, &peer_hello)
     }
 
     pub async fn send<S: Serialize>(&mut self, packet: &S) -> StrResult {
@@ -113,3 +257,44 @@ impl ProtoControlSocket {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn pairing_code_and_certificates_match() {
+        let client_identity = TlsIdentity::generate("client.alvr").unwrap();
+        let server_identity = TlsIdentity::generate("server.alvr").unwrap();
+        let listener = get_server_listener(0).await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let ((mut client_socket, server_ip), (mut server_socket, client_ip)) = tokio::try_join!(
+            ProtoControlSocket::connect_to(PeerType::Server(&listener, &client_identity)),
+            ProtoControlSocket::connect_to(PeerType::AnyClient(
+                vec![Ipv4Addr::LOCALHOST.into()],
+                port,
+                &server_identity,
+            )),
+        )
+        .unwrap();
+
+        // The listener is dual-stack, but IPv4 peers are reported with IPv4 addresses
+        assert_eq!(server_ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(client_ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(client_socket.pairing_code(), server_socket.pairing_code());
+        assert_eq!(client_socket.pairing_code().len(), 6);
+        assert_eq!(
+            server_socket.peer_certificate(),
+            client_identity.certificate
+        );
+        assert_eq!(
+            client_socket.peer_certificate(),
+            server_identity.certificate
+        );
+
+        server_socket.send(&"hello".to_owned()).await.unwrap();
+        assert_eq!(client_socket.recv::<String>().await.unwrap(), "hello");
+    }
+}
@@ -0,0 +1,143 @@
+// Sockets are bound on IPv6 with IPV6_V6ONLY disabled, so they can talk to both IPv4 and IPv6 peers.
+// On such sockets IPv4 peers appear as IPv4-mapped IPv6 addresses (::ffff:a.b.c.d). Addresses are
+// converted back to IPv4 before being reported, so the rest of the code sees the same addresses
+// on IPv4 and dual-stack systems.
+//
+// Link-local IPv6 addresses (fe80::/10) are valid only together with the index of the network
+// interface (scope ID), which IpAddr does not carry. The scope ID is remembered when a peer is seen
+// on a link-local address and applied when connecting back to it.
+
+use alvr_common::{once_cell::sync::Lazy, parking_lot::Mutex, prelude::*};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashMap,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+};
+
+static LINK_LOCAL_SCOPE_IDS: Lazy<Mutex<HashMap<Ipv6Addr, u32>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+// fe80::/10
+pub fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+// Bind on all interfaces, both IPv6 and IPv4. Falls back to IPv4 only if IPv6 is disabled on the
+// system. Stream sockets still need to listen().
+pub fn bind_dual_stack(ty: Type, port: u16) -> StrResult<Socket> {
+    let protocol = if ty == Type::STREAM {
+        Protocol::TCP
+    } else {
+        Protocol::UDP
+    };
+
+    let bind = |domain, ip: IpAddr| -> io::Result<Socket> {
+        let socket = Socket::new(domain, ty, Some(protocol))?;
+        if domain == Domain::IPV6 {
+            socket.set_only_v6(false)?;
+        }
+        // Same as std::net::TcpListener::bind()
+        #[cfg(not(windows))]
+        if ty == Type::STREAM {
+            socket.set_reuse_address(true)?;
+        }
+        socket.bind(&SocketAddr::new(ip, port).into())?;
+
+        Ok(socket)
+    };
+
+    bind(Domain::IPV6, Ipv6Addr::UNSPECIFIED.into())
+        .or_else(|e| {
+            warn!("IPv6 not available, binding port {port} on IPv4 only: {e}");
+            bind(Domain::IPV4, Ipv4Addr::UNSPECIFIED.into())
+        })
+        .map_err(err!())
+}
+
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => ipv6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ipv4 => ipv4,
+    }
+}
+
+// Remember the scope ID of a peer seen on a link-local address
+pub fn register_peer_address(address: SocketAddr) {
+    if let SocketAddr::V6(address) = address {
+        if is_link_local(address.ip()) && address.scope_id() != 0 {
+            LINK_LOCAL_SCOPE_IDS
+                .lock()
+                .insert(*address.ip(), address.scope_id());
+        }
+    }
+}
+
+// Address to connect to a peer, with the scope ID needed for link-local addresses
+pub fn peer_socket_addr(ip: IpAddr, port: u16) -> SocketAddr {
+    match ip {
+        IpAddr::V6(ipv6) if is_link_local(&ipv6) => {
+            let scope_id = LINK_LOCAL_SCOPE_IDS.lock().get(&ipv6).copied().unwrap_or(0);
+
+            SocketAddrV6::new(ipv6, port, 0, scope_id).into()
+        }
+        ip => SocketAddr::new(ip, port),
+    }
+}
+
+// IPv4 addresses must be mapped to IPv6 to be used with a dual-stack socket
+pub fn to_local_family(local_address: SocketAddr, address: SocketAddr) -> SocketAddr {
+    match (local_address, address) {
+        (SocketAddr::V6(_), SocketAddr::V4(address)) => {
+            SocketAddrV6::new(address.ip().to_ipv6_mapped(), address.port(), 0, 0).into()
+        }
+        (_, address) => address,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_addresses_are_reported_as_ipv4() {
+        let ipv4 = Ipv4Addr::new(192, 168, 1, 2);
+
+        assert_eq!(canonical_ip(ipv4.to_ipv6_mapped().into()), IpAddr::V4(ipv4));
+        assert_eq!(
+            canonical_ip(Ipv6Addr::LOCALHOST.into()),
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        );
+    }
+
+    #[test]
+    fn link_local_scope_is_restored() {
+        let ip = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0xabcd, 1);
+        register_peer_address(SocketAddrV6::new(ip, 1234, 0, 7).into());
+
+        assert_eq!(
+            peer_socket_addr(ip.into(), 9943),
+            SocketAddr::V6(SocketAddrV6::new(ip, 9943, 0, 7))
+        );
+    }
+
+    #[test]
+    fn dual_stack_socket_reaches_ipv4_peers() {
+        let socket = bind_dual_stack(Type::DGRAM, 0).unwrap();
+        let socket = std::net::UdpSocket::from(socket);
+        let peer = std::net::UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+
+        let local_address = socket.local_addr().unwrap();
+        let peer_address = to_local_family(local_address, peer.local_addr().unwrap());
+        socket.send_to(b"ping", peer_address).unwrap();
+
+        let mut buffer = [0; 4];
+        let (_, source) = peer.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"ping");
+
+        peer.send_to(b"pong", source).unwrap();
+        let (_, source) = socket.recv_from(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"pong");
+        assert_eq!(canonical_ip(source.ip()), IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+}
@@ -0,0 +1,218 @@
+// Conversion of the OpenXR hand skeletons to the legacy format of the Oculus SDK, sent to the peers
+// without HAND_TRACKING_OPENXR
+
+use crate::{DeviceMotion, HandSkeleton, Tracking};
+use alvr_common::{glam::Quat, LEFT_HAND_ID, RIGHT_HAND_ID};
+use std::f32::consts::FRAC_1_SQRT_2;
+
+pub const LEGACY_HAND_BONE_COUNT: usize = 19;
+
+// XrHandJointEXT indices
+const WRIST: usize = 1;
+const THUMB_METACARPAL: usize = 2;
+const THUMB_PROXIMAL: usize = 3;
+const THUMB_DISTAL: usize = 4;
+const INDEX_PROXIMAL: usize = 7;
+const INDEX_INTERMEDIATE: usize = 8;
+const INDEX_DISTAL: usize = 9;
+const MIDDLE_PROXIMAL: usize = 12;
+const MIDDLE_INTERMEDIATE: usize = 13;
+const MIDDLE_DISTAL: usize = 14;
+const RING_PROXIMAL: usize = 17;
+const RING_INTERMEDIATE: usize = 18;
+const RING_DISTAL: usize = 19;
+const LITTLE_METACARPAL: usize = 21;
+const LITTLE_PROXIMAL: usize = 22;
+const LITTLE_INTERMEDIATE: usize = 23;
+const LITTLE_DISTAL: usize = 24;
+
+// Legacy bone index, joint and parent joint. The wrist root, the forearm stub and the thumb
+// trapezium have no equivalent and are left to the identity.
+const LEGACY_BONES: [(usize, usize, usize); 16] = [
+    (3, THUMB_METACARPAL, WRIST),
+    (4, THUMB_PROXIMAL, THUMB_METACARPAL),
+    (5, THUMB_DISTAL, THUMB_PROXIMAL),
+    (6, INDEX_PROXIMAL, WRIST),
+    (7, INDEX_INTERMEDIATE, INDEX_PROXIMAL),
+    (8, INDEX_DISTAL, INDEX_INTERMEDIATE),
+    (9, MIDDLE_PROXIMAL, WRIST),
+    (10, MIDDLE_INTERMEDIATE, MIDDLE_PROXIMAL),
+    (11, MIDDLE_DISTAL, MIDDLE_INTERMEDIATE),
+    (12, RING_PROXIMAL, WRIST),
+    (13, RING_INTERMEDIATE, RING_PROXIMAL),
+    (14, RING_DISTAL, RING_INTERMEDIATE),
+    (15, LITTLE_METACARPAL, WRIST),
+    (16, LITTLE_PROXIMAL, LITTLE_METACARPAL),
+    (17, LITTLE_INTERMEDIATE, LITTLE_PROXIMAL),
+    (18, LITTLE_DISTAL, LITTLE_INTERMEDIATE),
+];
+
+// OpenXR joints point -Z towards the fingertip, with +Y out of the back of the hand. Oculus bones
+// point +X towards the fingertip on the left hand and -X on the right hand, and curl around +Z.
+const LEFT_BASIS: Quat = Quat::from_xyzw(FRAC_1_SQRT_2, 0.0, -FRAC_1_SQRT_2, 0.0);
+const RIGHT_BASIS: Quat = Quat::from_xyzw(0.0, -FRAC_1_SQRT_2, 0.0, FRAC_1_SQRT_2);
+
+impl HandSkeleton {
+    // Orientation of the hand root and local rotations of the bones in the Oculus SDK format. None
+    // if the wrist is not tracked.
+    pub fn to_legacy(&self, is_left: bool) -> Option<(Quat, [Quat; LEGACY_HAND_BONE_COUNT])> {
+        let basis = if is_left { LEFT_BASIS } else { RIGHT_BASIS };
+
+        let wrist = &self.joints[WRIST];
+        if !wrist.orientation_valid {
+            return None;
+        }
+
+        let mut bones = [Quat::IDENTITY; LEGACY_HAND_BONE_COUNT];
+        for (bone, joint, parent) in LEGACY_BONES {
+            let joint = &self.joints[joint];
+            let parent = &self.joints[parent];
+
+            if joint.orientation_valid && parent.orientation_valid {
+                let local = parent.orientation.inverse() * joint.orientation;
+                bones[bone] = (basis.inverse() * local * basis).normalize();
+            }
+        }
+
+        Some(((wrist.orientation * basis).normalize(), bones))
+    }
+}
+
+// Fills the legacy skeletons of the tracking packet. The motion of each tracked hand is replaced
+// by the pose of its wrist, converted to the legacy hand root.
+pub fn merge_legacy_hand_skeletons(
+    tracking: &mut Tracking,
+    left_hand: Option<&HandSkeleton>,
+    right_hand: Option<&HandSkeleton>,
+) {
+    for (device_id, skeleton, is_left) in [
+        (*LEFT_HAND_ID, left_hand, true),
+        (*RIGHT_HAND_ID, right_hand, false),
+    ] {
+        let skeleton = match skeleton {
+            Some(skeleton) => skeleton,
+            None => continue,
+        };
+        let (root_orientation, bones) = match skeleton.to_legacy(is_left) {
+            Some(res) => res,
+            None => continue,
+        };
+
+        if is_left {
+            tracking.left_hand_skeleton = Some(bones);
+        } else {
+            tracking.right_hand_skeleton = Some(bones);
+        }
+
+        let index = match tracking
+            .device_motions
+            .iter()
+            .position(|(id, _)| *id == device_id)
+        {
+            Some(index) => index,
+            None => {
+                tracking
+                    .device_motions
+                    .push((device_id, DeviceMotion::default()));
+                tracking.device_motions.len() - 1
+            }
+        };
+
+        let motion = &mut tracking.device_motions[index].1;
+        motion.orientation = root_orientation;
+        if skeleton.joints[WRIST].position_valid {
+            motion.position = skeleton.joints[WRIST].position;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{HandJoint, HAND_JOINT_COUNT};
+    use alvr_common::glam::Vec3;
+    use std::time::Duration;
+
+    // Flat hand with the palm facing down and the fingers pointing forward
+    fn open_hand() -> HandSkeleton {
+        HandSkeleton {
+            joints: [HandJoint {
+                orientation: Quat::IDENTITY,
+                position: Vec3::new(0.0, 1.0, -0.3),
+                radius: 0.01,
+                orientation_valid: true,
+                position_valid: true,
+            }; HAND_JOINT_COUNT],
+        }
+    }
+
+    fn curl_index(skeleton: &mut HandSkeleton, angle: f32) {
+        // The fingertip moves towards the palm
+        let curl = Quat::from_rotation_x(-angle);
+        for joint in [INDEX_PROXIMAL, INDEX_INTERMEDIATE, INDEX_DISTAL] {
+            skeleton.joints[joint].orientation = curl;
+        }
+    }
+
+    #[test]
+    fn open_hand_has_no_local_rotations() {
+        for is_left in [true, false] {
+            let (_, bones) = open_hand().to_legacy(is_left).unwrap();
+
+            for bone in bones {
+                assert!(bone.abs_diff_eq(Quat::IDENTITY, 1e-6));
+            }
+        }
+    }
+
+    #[test]
+    fn root_points_along_the_fingers() {
+        let (left_root, _) = open_hand().to_legacy(true).unwrap();
+        let (right_root, _) = open_hand().to_legacy(false).unwrap();
+
+        assert!((left_root * Vec3::X).abs_diff_eq(-Vec3::Z, 1e-6));
+        assert!((right_root * -Vec3::X).abs_diff_eq(-Vec3::Z, 1e-6));
+        // The curl axis is the same for both hands
+        assert!((left_root * Vec3::Z).abs_diff_eq(right_root * Vec3::Z, 1e-6));
+    }
+
+    // The server estimates the trigger value from the z component of the finger rotations
+    #[test]
+    fn curl_is_positive_around_z() {
+        for is_left in [true, false] {
+            let mut skeleton = open_hand();
+            curl_index(&mut skeleton, 0.5);
+
+            let (_, bones) = skeleton.to_legacy(is_left).unwrap();
+
+            let (axis, angle) = bones[6].to_axis_angle();
+            assert!(axis.abs_diff_eq(Vec3::Z, 1e-5));
+            assert!((angle - 0.5).abs() < 1e-5);
+            // Only the proximal phalanx is rotated relative to its parent
+            assert!(bones[7].abs_diff_eq(Quat::IDENTITY, 1e-6));
+        }
+    }
+
+    #[test]
+    fn untracked_wrist_is_skipped() {
+        let mut skeleton = open_hand();
+        skeleton.joints[WRIST].orientation_valid = false;
+
+        let mut tracking = Tracking {
+            target_timestamp: Duration::ZERO,
+            device_motions: vec![],
+            left_hand_skeleton: None,
+            right_hand_skeleton: None,
+        };
+        merge_legacy_hand_skeletons(&mut tracking, Some(&skeleton), Some(&open_hand()));
+
+        assert!(tracking.left_hand_skeleton.is_none());
+        assert!(tracking.right_hand_skeleton.is_some());
+        assert_eq!(tracking.device_motions.len(), 1);
+        assert_eq!(tracking.device_motions[0].0, *RIGHT_HAND_ID);
+        assert_eq!(
+            tracking.device_motions[0].1.position,
+            Vec3::new(0.0, 1.0, -0.3)
+        );
+    }
+}
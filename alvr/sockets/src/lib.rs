@@ -1,21 +1,45 @@
+mod annex_b;
 mod control_socket;
+mod dual_stack;
+mod hand_skeleton;
+mod obu;
 mod packets;
+mod protocol;
+mod recording;
 mod stream_socket;
 
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    time::Duration,
-};
+use std::{net::Ipv6Addr, time::Duration};
 
+pub use annex_b::*;
 pub use control_socket::*;
+pub use dual_stack::*;
+pub use hand_skeleton::*;
+pub use obu::*;
 pub use packets::*;
+pub use protocol::*;
+pub use recording::*;
 pub use stream_socket::*;
 
-pub const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
+pub use socket2;
+
 pub const CONTROL_PORT: u16 = 9943;
 pub const HANDSHAKE_PACKET_SIZE_BYTES: usize = 56; // this may change in future protocols
 pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
 
+// All-nodes link-local multicast group. The clients announce themselves to it on IPv6, where there
+// is no broadcast.
+pub const DISCOVERY_MULTICAST_IP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+
+// Service advertised by the clients with mDNS, on the control port. The TXT records contain the
+// hostname, the protocol ID and the comma separated features of the client.
+pub const MDNS_SERVICE_TYPE: &str = "_alvr._tcp.local.";
+pub const MDNS_HOSTNAME_KEY: &str = "hostname";
+pub const MDNS_PROTOCOL_KEY: &str = "protocol";
+pub const MDNS_FEATURES_KEY: &str = "features";
+
+// The client certificate is pinned, so this name is not checked
+const TLS_SERVER_NAME: &str = "client.alvr";
+
 type Ldc = tokio_util::codec::LengthDelimitedCodec;
 
 mod util {
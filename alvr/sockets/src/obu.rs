@@ -0,0 +1,462 @@
+// AV1 streams are made of OBUs (open bitstream units) in the low overhead format, where every OBU
+// has a size field. The sequence header OBU plays the role of the SPS and PPS of H264 and HEVC: the
+// server sends it with InitializeDecoder, and the client wraps it in an
+// AV1CodecConfigurationRecord, which is what the Android decoders expect as csd-0.
+
+use alvr_common::prelude::*;
+
+pub const OBU_SEQUENCE_HEADER: u8 = 1;
+pub const OBU_TEMPORAL_DELIMITER: u8 = 2;
+pub const OBU_FRAME_HEADER: u8 = 3;
+pub const OBU_FRAME: u8 = 6;
+pub const OBU_PADDING: u8 = 15;
+
+pub struct Obu<'a> {
+    pub obu_type: u8,
+    // The whole OBU, with its header
+    pub data: &'a [u8],
+    pub payload: &'a [u8],
+}
+
+fn read_leb128(data: &[u8], offset: &mut usize) -> StrResult<usize> {
+    // The value is accumulated on 64 bits, so that 8 bytes cannot overflow even on 32 bit targets
+    let mut value = 0_u64;
+    for index in 0..8 {
+        let byte = *data.get(*offset).ok_or_else(enone!())?;
+        *offset += 1;
+
+        value |= ((byte & 0x7F) as u64) << (index * 7);
+        if byte & 0x80 == 0 {
+            // The AV1 specification limits the values to 32 bits
+            return if value <= u32::MAX as u64 {
+                Ok(value as usize)
+            } else {
+                fmt_e!("LEB128 value too big: {value}")
+            };
+        }
+    }
+
+    fmt_e!("Invalid LEB128 value")
+}
+
+pub fn parse_obus(data: &[u8]) -> StrResult<Vec<Obu<'_>>> {
+    let mut obus = vec![];
+
+    let mut offset = 0;
+    while offset < data.len() {
+        let start = offset;
+
+        let header = data[offset];
+        if header & 0x80 != 0 {
+            return fmt_e!("Invalid OBU header at byte {offset}");
+        }
+        let obu_type = (header >> 3) & 0x0F;
+        let has_extension = header & 0x04 != 0;
+        let has_size = header & 0x02 != 0;
+        offset += if has_extension { 2 } else { 1 };
+
+        let payload_size = if has_size {
+            read_leb128(data, &mut offset)?
+        } else {
+            // Only the last OBU can omit its size
+            data.len().saturating_sub(offset)
+        };
+
+        let end = offset + payload_size;
+        if end > data.len() {
+            return fmt_e!("OBU at byte {start} is truncated");
+        }
+
+        obus.push(Obu {
+            obu_type,
+            data: &data[start..end],
+            payload: &data[offset..end],
+        });
+
+        offset = end;
+    }
+
+    Ok(obus)
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, bits: u32) -> StrResult<u32> {
+        let mut value = 0;
+        for _ in 0..bits {
+            let byte = self.data.get(self.position / 8).ok_or_else(enone!())?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = (value << 1) | bit as u32;
+            self.position += 1;
+        }
+
+        Ok(value)
+    }
+
+    fn read_flag(&mut self) -> StrResult<bool> {
+        Ok(self.read(1)? == 1)
+    }
+
+    fn skip(&mut self, bits: u32) -> StrResult {
+        self.read(bits).map(|_| ())
+    }
+
+    fn skip_uvlc(&mut self) -> StrResult {
+        let mut leading_zeros = 0;
+        while !self.read_flag()? {
+            leading_zeros += 1;
+        }
+        if leading_zeros < 32 {
+            self.skip(leading_zeros)?;
+        }
+
+        Ok(())
+    }
+}
+
+// Fields of the sequence header that are copied in the AV1CodecConfigurationRecord
+#[derive(Debug, PartialEq, Eq)]
+pub struct SequenceHeaderInfo {
+    pub seq_profile: u8,
+    pub seq_level_idx_0: u8,
+    pub seq_tier_0: bool,
+    pub high_bitdepth: bool,
+    pub twelve_bit: bool,
+    pub mono_chrome: bool,
+    pub chroma_subsampling_x: bool,
+    pub chroma_subsampling_y: bool,
+    pub chroma_sample_position: u8,
+}
+
+// Parses the payload of a sequence header OBU, up to the color configuration (AV1 spec 5.5)
+pub fn parse_sequence_header(payload: &[u8]) -> StrResult<SequenceHeaderInfo> {
+    let mut reader = BitReader {
+        data: payload,
+        position: 0,
+    };
+
+    let seq_profile = reader.read(3)? as u8;
+    let _still_picture = reader.read_flag()?;
+    let reduced_still_picture_header = reader.read_flag()?;
+
+    let seq_level_idx_0;
+    let mut seq_tier_0 = false;
+    let mut decoder_model_info_present = false;
+    if reduced_still_picture_header {
+        seq_level_idx_0 = reader.read(5)? as u8;
+    } else {
+        let mut buffer_delay_length = 0;
+        if reader.read_flag()? {
+            // timing_info
+            reader.skip(64)?;
+            if reader.read_flag()? {
+                reader.skip_uvlc()?;
+            }
+
+            decoder_model_info_present = reader.read_flag()?;
+            if decoder_model_info_present {
+                buffer_delay_length = reader.read(5)? + 1;
+                reader.skip(32 + 5 + 5)?;
+            }
+        }
+
+        let initial_display_delay_present = reader.read_flag()?;
+        let operating_points_count = reader.read(5)? + 1;
+
+        let mut first_level = None;
+        for _ in 0..operating_points_count {
+            reader.skip(12)?;
+            let seq_level_idx = reader.read(5)? as u8;
+            let seq_tier = seq_level_idx > 7 && reader.read_flag()?;
+            if first_level.is_none() {
+                first_level = Some((seq_level_idx, seq_tier));
+            }
+
+            if decoder_model_info_present && reader.read_flag()? {
+                reader.skip(2 * buffer_delay_length + 1)?;
+            }
+            if initial_display_delay_present && reader.read_flag()? {
+                reader.skip(4)?;
+            }
+        }
+        (seq_level_idx_0, seq_tier_0) = first_level.ok_or_else(enone!())?;
+    }
+
+    let frame_width_bits = reader.read(4)? + 1;
+    let frame_height_bits = reader.read(4)? + 1;
+    reader.skip(frame_width_bits + frame_height_bits)?;
+
+    if !reduced_still_picture_header && reader.read_flag()? {
+        // delta_frame_id_length_minus_2 and additional_frame_id_length_minus_1
+        reader.skip(4 + 3)?;
+    }
+
+    // use_128x128_superblock, enable_filter_intra, enable_intra_edge_filter
+    reader.skip(3)?;
+
+    if !reduced_still_picture_header {
+        // enable_interintra_compound, enable_masked_compound, enable_warped_motion,
+        // enable_dual_filter
+        reader.skip(4)?;
+
+        let enable_order_hint = reader.read_flag()?;
+        if enable_order_hint {
+            // enable_jnt_comp, enable_ref_frame_mvs
+            reader.skip(2)?;
+        }
+
+        let seq_force_screen_content_tools = if reader.read_flag()? {
+            2
+        } else {
+            reader.read(1)?
+        };
+        if seq_force_screen_content_tools > 0 && !reader.read_flag()? {
+            // seq_force_integer_mv
+            reader.skip(1)?;
+        }
+
+        if enable_order_hint {
+            // order_hint_bits_minus_1
+            reader.skip(3)?;
+        }
+    }
+
+    // enable_superres, enable_cdef, enable_restoration
+    reader.skip(3)?;
+
+    // color_config
+    let high_bitdepth = reader.read_flag()?;
+    let twelve_bit = seq_profile == 2 && high_bitdepth && reader.read_flag()?;
+    let mono_chrome = seq_profile != 1 && reader.read_flag()?;
+
+    let (mut color_primaries, mut transfer_characteristics, mut matrix_coefficients) = (2, 2, 2);
+    if reader.read_flag()? {
+        color_primaries = reader.read(8)?;
+        transfer_characteristics = reader.read(8)?;
+        matrix_coefficients = reader.read(8)?;
+    }
+
+    let (chroma_subsampling_x, chroma_subsampling_y);
+    let mut chroma_sample_position = 0;
+    if mono_chrome {
+        // color_range
+        reader.skip(1)?;
+        (chroma_subsampling_x, chroma_subsampling_y) = (true, true);
+    } else if color_primaries == 1 && transfer_characteristics == 13 && matrix_coefficients == 0 {
+        // sRGB
+        (chroma_subsampling_x, chroma_subsampling_y) = (false, false);
+    } else {
+        // color_range
+        reader.skip(1)?;
+
+        (chroma_subsampling_x, chroma_subsampling_y) = match seq_profile {
+            0 => (true, true),
+            1 => (false, false),
+            _ if twelve_bit => {
+                let subsampling_x = reader.read_flag()?;
+                (subsampling_x, subsampling_x && reader.read_flag()?)
+            }
+            _ => (true, false),
+        };
+
+        if chroma_subsampling_x && chroma_subsampling_y {
+            chroma_sample_position = reader.read(2)? as u8;
+        }
+    }
+
+    Ok(SequenceHeaderInfo {
+        seq_profile,
+        seq_level_idx_0,
+        seq_tier_0,
+        high_bitdepth,
+        twelve_bit,
+        mono_chrome,
+        chroma_subsampling_x,
+        chroma_subsampling_y,
+        chroma_sample_position,
+    })
+}
+
+// Builds the av1C box content from the buffer sent with InitializeDecoder. The configuration OBUs
+// are appended after the 4 byte header.
+pub fn av1_codec_configuration_record(config_buffer: &[u8]) -> StrResult<Vec<u8>> {
+    let obus = parse_obus(config_buffer)?;
+    let sequence_header = obus
+        .iter()
+        .find(|obu| obu.obu_type == OBU_SEQUENCE_HEADER)
+        .ok_or_else(enone!())?;
+    let info = parse_sequence_header(sequence_header.payload)?;
+
+    let mut record = vec![
+        // marker and version
+        0x81,
+        (info.seq_profile << 5) | info.seq_level_idx_0,
+        ((info.seq_tier_0 as u8) << 7)
+            | ((info.high_bitdepth as u8) << 6)
+            | ((info.twelve_bit as u8) << 5)
+            | ((info.mono_chrome as u8) << 4)
+            | ((info.chroma_subsampling_x as u8) << 3)
+            | ((info.chroma_subsampling_y as u8) << 2)
+            | info.chroma_sample_position,
+        // No initial presentation delay
+        0,
+    ];
+    record.extend_from_slice(sequence_header.data);
+
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct BitWriter {
+        data: Vec<u8>,
+        bits: usize,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u32) {
+            for index in (0..bits).rev() {
+                if self.bits % 8 == 0 {
+                    self.data.push(0);
+                }
+                let bit = ((value >> index) & 1) as u8;
+                *self.data.last_mut().unwrap() |= bit << (7 - self.bits % 8);
+                self.bits += 1;
+            }
+        }
+    }
+
+    // Sequence header of a 1920x1088 8 bit 4:2:0 stream, like the ones produced by the hardware
+    // encoders, with timing info and without decoder model
+    fn sequence_header_payload(seq_level_idx: u32, seq_tier: bool) -> Vec<u8> {
+        let mut writer = BitWriter::default();
+        writer.write(0, 3); // seq_profile
+        writer.write(0, 1); // still_picture
+        writer.write(0, 1); // reduced_still_picture_header
+        writer.write(1, 1); // timing_info_present_flag
+        writer.write(1, 32); // num_units_in_display_tick
+        writer.write(72, 32); // time_scale
+        writer.write(1, 1); // equal_picture_interval
+        writer.write(1, 1); // num_ticks_per_picture_minus_1 = 0 (uvlc)
+        writer.write(0, 1); // decoder_model_info_present_flag
+        writer.write(0, 1); // initial_display_delay_present_flag
+        writer.write(0, 5); // operating_points_cnt_minus_1
+        writer.write(0, 12); // operating_point_idc
+        writer.write(seq_level_idx, 5);
+        if seq_level_idx > 7 {
+            writer.write(seq_tier as u32, 1);
+        }
+        writer.write(10, 4); // frame_width_bits_minus_1
+        writer.write(10, 4); // frame_height_bits_minus_1
+        writer.write(1919, 11);
+        writer.write(1087, 11);
+        writer.write(0, 1); // frame_id_numbers_present_flag
+        writer.write(0, 3); // superblock and intra tools
+        writer.write(0, 4); // compound and filter tools
+        writer.write(1, 1); // enable_order_hint
+        writer.write(0, 2); // enable_jnt_comp, enable_ref_frame_mvs
+        writer.write(1, 1); // seq_choose_screen_content_tools
+        writer.write(1, 1); // seq_choose_integer_mv
+        writer.write(6, 3); // order_hint_bits_minus_1
+        writer.write(0, 3); // enable_superres, enable_cdef, enable_restoration
+        writer.write(0, 1); // high_bitdepth
+        writer.write(0, 1); // mono_chrome
+        writer.write(0, 1); // color_description_present_flag
+        writer.write(0, 1); // color_range
+        writer.write(1, 2); // chroma_sample_position
+        writer.write(0, 1); // film_grain_params_present
+        writer.write(1, 1); // trailing bit
+
+        writer.data
+    }
+
+    fn obu(obu_type: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = vec![(obu_type << 3) | 0x02];
+        let mut size = payload.len();
+        while size >= 0x80 {
+            data.push((size & 0x7F) as u8 | 0x80);
+            size >>= 7;
+        }
+        data.push(size as u8);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn split_temporal_unit() {
+        let sequence_header = obu(OBU_SEQUENCE_HEADER, &sequence_header_payload(8, true));
+        let frame = obu(6, &[1; 200]);
+
+        let mut data = obu(OBU_TEMPORAL_DELIMITER, &[]);
+        data.extend_from_slice(&sequence_header);
+        data.extend_from_slice(&frame);
+
+        let obus = parse_obus(&data).unwrap();
+        assert_eq!(
+            obus.iter().map(|obu| obu.obu_type).collect::<Vec<_>>(),
+            [OBU_TEMPORAL_DELIMITER, OBU_SEQUENCE_HEADER, 6]
+        );
+        assert_eq!(obus[1].data, sequence_header);
+        // The size of the frame OBU takes two bytes
+        assert_eq!(obus[2].payload.len(), 200);
+        assert_eq!(obus[2].data.len(), 203);
+    }
+
+    #[test]
+    fn reject_truncated_obu() {
+        let mut data = obu(6, &[1; 10]);
+        data.pop();
+
+        assert!(parse_obus(&data).is_err());
+    }
+
+    #[test]
+    fn reject_obu_size_above_32_bits() {
+        // Header with size field, then a size of 2^35
+        let data = [(6 << 3) | 0x02, 0x80, 0x80, 0x80, 0x80, 0x80, 0x01];
+        let mut offset = 1;
+        assert!(read_leb128(&data, &mut offset).is_err());
+        assert!(parse_obus(&data).is_err());
+
+        let mut offset = 0;
+        let max = [0xFF, 0xFF, 0xFF, 0xFF, 0x0F];
+        assert_eq!(read_leb128(&max, &mut offset).unwrap(), u32::MAX as usize);
+    }
+
+    #[test]
+    fn parse_sequence_header_fields() {
+        let info = parse_sequence_header(&sequence_header_payload(8, true)).unwrap();
+
+        assert_eq!(
+            info,
+            SequenceHeaderInfo {
+                seq_profile: 0,
+                seq_level_idx_0: 8,
+                seq_tier_0: true,
+                high_bitdepth: false,
+                twelve_bit: false,
+                mono_chrome: false,
+                chroma_subsampling_x: true,
+                chroma_subsampling_y: true,
+                chroma_sample_position: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn build_configuration_record() {
+        let sequence_header = obu(OBU_SEQUENCE_HEADER, &sequence_header_payload(5, false));
+
+        let record = av1_codec_configuration_record(&sequence_header).unwrap();
+
+        assert_eq!(record[..4], [0x81, 5, 0b0000_1101, 0]);
+        assert_eq!(record[4..], sequence_header);
+    }
+}
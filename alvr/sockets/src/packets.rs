@@ -9,6 +9,8 @@ pub const HAPTICS: u16 = 1;
 pub const AUDIO: u16 = 2;
 pub const VIDEO: u16 = 3;
 pub const STATISTICS: u16 = 4;
+// Used only with features::HAND_TRACKING_OPENXR
+pub const HAND_SKELETONS: u16 = 5;
 
 // Field of view in radians
 #[derive(Serialize, Deserialize, PartialEq, Default, Clone, Copy)]
@@ -26,6 +28,26 @@ pub struct VideoStreamingCapabilities {
     pub microphone_sample_rate: u32,
 }
 
+// Sent by the server right after the protocol negotiation. Unless the client is already paired,
+// both peers pin the certificate of the other one.
+#[derive(Serialize, Deserialize)]
+pub enum PairingRequest {
+    // The server has pinned the certificate of the client
+    Paired,
+    // The user must confirm on the headset that the pairing code is the one shown on the dashboard.
+    // The server disconnects once paired and connects again.
+    ConfirmCode,
+    // With auto_trust_clients, the server pins the certificate without confirmation
+    TrustOnFirstUse,
+}
+
+// Answer of the client to ConfirmCode and TrustOnFirstUse
+#[derive(Serialize, Deserialize)]
+pub enum PairingResponse {
+    Confirmed,
+    Rejected,
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum ClientConnectionResult {
     ConnectionAccepted {
@@ -114,7 +136,7 @@ pub struct DeviceMotion {
     pub angular_velocity: Vec3,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Tracking {
     pub target_timestamp: Duration,
     pub device_motions: Vec<(u64, DeviceMotion)>,
@@ -122,7 +144,33 @@ pub struct Tracking {
     pub right_hand_skeleton: Option<[Quat; 19]>, // legacy oculus hand
 }
 
-#[derive(Serialize, Deserialize)]
+pub const HAND_JOINT_COUNT: usize = 26;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default, Debug)]
+pub struct HandJoint {
+    pub orientation: Quat,
+    pub position: Vec3,
+    pub radius: f32,
+    pub orientation_valid: bool,
+    pub position_valid: bool,
+}
+
+// Joints indexed like XrHandJointEXT of XR_EXT_hand_tracking, in the same space as the device
+// motions
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HandSkeleton {
+    pub joints: [HandJoint; HAND_JOINT_COUNT],
+}
+
+// Sent together with the Tracking packet with the same target timestamp
+#[derive(Serialize, Deserialize, Clone)]
+pub struct HandSkeletons {
+    pub target_timestamp: Duration,
+    pub left_hand: Option<HandSkeleton>,
+    pub right_hand: Option<HandSkeleton>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Haptics {
     pub path: u64,
     pub duration: Duration,
@@ -157,6 +205,10 @@ pub enum ClientListAction {
     RemoveIp(IpAddr),
     RemoveEntry,
     UpdateCurrentIp(Option<IpAddr>),
+    SetCertificate(Vec<u8>),
+    // While pairing, the user gives back the trust after comparing the codes shown on the dashboard
+    // and on the headset
+    RevokeTrust,
 }
 
 #[derive(Serialize, Deserialize, Default, Clone)]
@@ -0,0 +1,149 @@
+// The first packet exchanged on the control socket is a ProtocolHello, sent by both peers. Its
+// layout must never change, so that any two versions can at least tell if they are compatible.
+//
+// Peers with the same major version understand each other's packets. Minor versions add features:
+// packets, stream IDs or fields that are used only if both peers advertise the corresponding
+// feature. New packet variants are appended at the end of their enum and sent only when the feature
+// has been negotiated, so the bincode encoding of the older variants does not change.
+
+use alvr_common::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Major versions this build can speak. The highest one supported by both peers is used
+pub const PROTOCOL_MAJOR_VERSIONS: &[u16] = &[1];
+pub const PROTOCOL_MINOR_VERSION: u16 = 2;
+
+pub mod features {
+    pub const CODEC_H264: &str = "codec.h264";
+    pub const CODEC_HEVC: &str = "codec.hevc";
+    // Advertised by clients with an AV1 hardware decoder. Minor version 2
+    pub const CODEC_AV1: &str = "codec.av1";
+    // Skeleton with 19 bone rotations, in the format of the Oculus SDK
+    pub const HAND_TRACKING_OCULUS: &str = "hand_tracking.oculus";
+    // 26 joints with positions and radii, in the format of XR_EXT_hand_tracking. Sent on the
+    // HAND_SKELETONS stream. Minor version 1
+    pub const HAND_TRACKING_OPENXR: &str = "hand_tracking.openxr";
+    pub const FEC: &str = "stream.fec";
+    pub const NACK: &str = "stream.nack";
+
+    // Supported by both the server and the client of this build. The features that depend on the
+    // hardware are added by each side.
+    pub const COMMON: &[&str] = &[
+        CODEC_H264,
+        CODEC_HEVC,
+        HAND_TRACKING_OCULUS,
+        HAND_TRACKING_OPENXR,
+        FEC,
+        NACK,
+    ];
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ProtocolHello {
+    pub major_versions: Vec<u16>,
+    pub minor_version: u16,
+    pub features: HashSet<String>,
+}
+
+impl ProtocolHello {
+    pub fn new(features: &[&str]) -> Self {
+        Self {
+            major_versions: PROTOCOL_MAJOR_VERSIONS.to_vec(),
+            minor_version: PROTOCOL_MINOR_VERSION,
+            features: features.iter().map(|&feature| feature.to_owned()).collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct NegotiatedProtocol {
+    pub major_version: u16,
+    pub peer_minor_version: u16,
+    pub features: HashSet<String>,
+}
+
+impl NegotiatedProtocol {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+// The result does not depend on which peer is local, so both ends agree without another round trip
+pub fn negotiate(local: &ProtocolHello, peer: &ProtocolHello) -> StrResult<NegotiatedProtocol> {
+    let major_version = local
+        .major_versions
+        .iter()
+        .filter(|version| peer.major_versions.contains(version))
+        .max()
+        .copied()
+        .ok_or_else(|| {
+            format!(
+                "No common protocol version. Local: {:?}, peer: {:?}",
+                local.major_versions, peer.major_versions
+            )
+        })?;
+
+    Ok(NegotiatedProtocol {
+        major_version,
+        peer_minor_version: peer.minor_version,
+        features: local
+            .features
+            .intersection(&peer.features)
+            .cloned()
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hello(major_versions: &[u16], minor_version: u16, features: &[&str]) -> ProtocolHello {
+        ProtocolHello {
+            major_versions: major_versions.to_vec(),
+            minor_version,
+            features: features.iter().map(|&feature| feature.to_owned()).collect(),
+        }
+    }
+
+    #[test]
+    fn agree_on_common_features() {
+        let server = hello(
+            &[1],
+            3,
+            &[features::CODEC_H264, features::CODEC_HEVC, features::FEC],
+        );
+        let client = hello(
+            &[1],
+            1,
+            &[features::CODEC_HEVC, features::FEC, "stream.future"],
+        );
+
+        let server_result = negotiate(&server, &client).unwrap();
+        let client_result = negotiate(&client, &server).unwrap();
+
+        assert_eq!(server_result.major_version, 1);
+        assert_eq!(server_result.peer_minor_version, 1);
+        assert_eq!(client_result.peer_minor_version, 3);
+        assert_eq!(server_result.features, client_result.features);
+        assert!(server_result.supports(features::FEC));
+        assert!(server_result.supports(features::CODEC_HEVC));
+        assert!(!server_result.supports(features::CODEC_H264));
+        assert!(!server_result.supports("stream.future"));
+    }
+
+    #[test]
+    fn pick_highest_common_major_version() {
+        let newer = hello(&[3, 2, 1], 0, &[]);
+        let older = hello(&[1, 2], 5, &[]);
+
+        assert_eq!(negotiate(&newer, &older).unwrap().major_version, 2);
+        assert_eq!(negotiate(&older, &newer).unwrap().major_version, 2);
+    }
+
+    #[test]
+    fn reject_incompatible_major_versions() {
+        assert!(negotiate(&hello(&[2], 0, &[]), &hello(&[1], 0, &[])).is_err());
+    }
+}
@@ -0,0 +1,189 @@
+// Tracking recordings store the input received from a client, to replay it later without a
+// headset. The file starts with RECORDING_MAGIC, followed by bincode encoded RecordedEntry values.
+
+use crate::{Haptics, Tracking};
+use alvr_common::prelude::*;
+use alvr_events::ButtonValue;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, ErrorKind, Write},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+// The last byte is the format version
+const RECORDING_MAGIC: &[u8; 8] = b"ALVRREC\x01";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum RecordedEvent {
+    Tracking(Box<Tracking>),
+    Button { path_id: u64, value: ButtonValue },
+    Haptics(Haptics),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct RecordedEntry {
+    // Time since the start of the recording
+    pub time: Duration,
+    pub event: RecordedEvent,
+}
+
+pub struct TrackingRecorder {
+    writer: BufWriter<File>,
+    start_instant: Instant,
+}
+
+impl TrackingRecorder {
+    pub fn new(path: &Path) -> StrResult<Self> {
+        let mut writer = BufWriter::new(File::create(path).map_err(err!())?);
+        writer.write_all(RECORDING_MAGIC).map_err(err!())?;
+
+        Ok(Self {
+            writer,
+            start_instant: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, event: RecordedEvent) -> StrResult {
+        let entry = RecordedEntry {
+            time: self.start_instant.elapsed(),
+            event,
+        };
+
+        bincode::serialize_into(&mut self.writer, &entry).map_err(err!())
+    }
+
+    pub fn flush(&mut self) -> StrResult {
+        self.writer.flush().map_err(err!())
+    }
+}
+
+pub fn read_recording(path: &Path) -> StrResult<Vec<RecordedEntry>> {
+    let reader = BufReader::new(File::open(path).map_err(err!())?);
+
+    parse_recording(reader).map_err(|e| format!("{}: {e}", path.display()))
+}
+
+// `reader` provides the content of a recording file
+pub fn parse_recording(mut reader: impl BufRead) -> StrResult<Vec<RecordedEntry>> {
+    let mut magic = [0; RECORDING_MAGIC.len()];
+    reader.read_exact(&mut magic).map_err(err!())?;
+    if &magic != RECORDING_MAGIC {
+        return fmt_e!("Not a tracking recording");
+    }
+
+    let mut entries = vec![];
+    while !reader.fill_buf().map_err(err!())?.is_empty() {
+        match bincode::deserialize_from(&mut reader) {
+            Ok(entry) => entries.push(entry),
+            // The last entry is truncated if the server was not closed cleanly
+            Err(e) => match *e {
+                bincode::ErrorKind::Io(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+                e => return fmt_e!("{e}"),
+            },
+        }
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DeviceMotion;
+    use alvr_common::glam::Vec3;
+    use std::{env, fs, process};
+
+    fn test_path(name: &str) -> std::path::PathBuf {
+        env::temp_dir().join(format!("alvr_{name}_{}.bin", process::id()))
+    }
+
+    #[test]
+    fn roundtrip() {
+        let path = test_path("roundtrip");
+
+        let mut recorder = TrackingRecorder::new(&path).unwrap();
+        recorder
+            .record(RecordedEvent::Tracking(Box::new(Tracking {
+                target_timestamp: Duration::from_millis(10),
+                device_motions: vec![(
+                    1,
+                    DeviceMotion {
+                        position: Vec3::new(0.0, 1.5, 0.0),
+                        ..Default::default()
+                    },
+                )],
+                left_hand_skeleton: None,
+                right_hand_skeleton: None,
+            })))
+            .unwrap();
+        recorder
+            .record(RecordedEvent::Button {
+                path_id: 2,
+                value: ButtonValue::Scalar(0.5),
+            })
+            .unwrap();
+        recorder.flush().unwrap();
+
+        let entries = read_recording(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].time <= entries[1].time);
+        match &entries[0].event {
+            RecordedEvent::Tracking(tracking) => {
+                assert_eq!(tracking.target_timestamp, Duration::from_millis(10));
+                assert_eq!(tracking.device_motions[0].1.position.y, 1.5);
+            }
+            _ => panic!("expected a tracking entry"),
+        }
+        assert!(matches!(
+            entries[1].event,
+            RecordedEvent::Button {
+                path_id: 2,
+                value: ButtonValue::Scalar(value),
+            } if value == 0.5
+        ));
+    }
+
+    #[test]
+    fn ignore_truncated_entry() {
+        let path = test_path("truncated");
+
+        let mut recorder = TrackingRecorder::new(&path).unwrap();
+        for path_id in 0..2 {
+            recorder
+                .record(RecordedEvent::Button {
+                    path_id,
+                    value: ButtonValue::Binary(true),
+                })
+                .unwrap();
+        }
+        recorder.flush().unwrap();
+
+        let len = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+
+        let entries = read_recording(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn reject_other_files() {
+        let path = test_path("invalid");
+        fs::write(&path, b"not a recording").unwrap();
+
+        let res = read_recording(&path);
+        fs::remove_file(&path).ok();
+
+        assert!(res.is_err());
+    }
+}
@@ -0,0 +1,327 @@
+// Reed-Solomon forward error correction over the shards of a single packet. Parity shards are sent
+// after the data shards, and any combination of shards as big as the data shards count is enough to
+// rebuild the packet. Redundancy is chosen by the sender and adapted to the loss rate reported by
+// the receiver.
+
+use alvr_common::prelude::*;
+use bytes::BytesMut;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// Codes over GF(2^8) support at most 256 shards. Bigger packets are sent without parity.
+const MAX_SHARDS: usize = 256;
+
+pub const MIN_REDUNDANCY: f32 = 0.05;
+const MAX_REDUNDANCY: f32 = 1.0;
+
+pub const FEEDBACK_INTERVAL: Duration = Duration::from_millis(500);
+
+// Sent by the receiver of a stream that uses FEC
+#[derive(Serialize, Deserialize)]
+pub struct FecFeedback {
+    pub stream_id: u16,
+    pub shard_loss_rate: f32,
+}
+
+// Parity can recover twice the measured loss, to account for bursts
+pub fn redundancy_for_loss_rate(shard_loss_rate: f32) -> f32 {
+    (MIN_REDUNDANCY + 2.0 * shard_loss_rate).clamp(MIN_REDUNDANCY, MAX_REDUNDANCY)
+}
+
+// At least one parity shard is used, so even single shard packets are protected
+pub fn parity_shards_count(data_shards_count: usize, redundancy: f32) -> usize {
+    let count = (data_shards_count as f32 * redundancy).ceil() as usize;
+
+    usize::min(count.max(1), MAX_SHARDS.saturating_sub(data_shards_count))
+}
+
+// Checks the counts and the payload size read from a shard, before anything is allocated for its
+// packet. Packets without parity can have more shards than the code supports.
+pub fn is_valid_layout(
+    data_shards_count: usize,
+    parity_shards_count: usize,
+    payload_size: usize,
+    max_shard_size: usize,
+) -> bool {
+    data_shards_count >= 1
+        && (parity_shards_count == 0 || data_shards_count + parity_shards_count <= MAX_SHARDS)
+        && payload_size <= data_shards_count * max_shard_size
+}
+
+// All data shards must have the same size
+pub fn encode(data_shards: &[&[u8]], parity_shards_count: usize) -> StrResult<Vec<Vec<u8>>> {
+    let shard_size = data_shards.first().ok_or_else(enone!())?.len();
+    let mut parity_shards = vec![vec![0; shard_size]; parity_shards_count];
+
+    ReedSolomon::new(data_shards.len(), parity_shards_count)
+        .map_err(err!())?
+        .encode_sep(data_shards, &mut parity_shards)
+        .map_err(err!())?;
+
+    Ok(parity_shards)
+}
+
+pub struct PartialPacket {
+    shards: Vec<Option<BytesMut>>,
+    data_shards_count: usize,
+    payload_size: usize,
+    received_shards: usize,
+    last_shard_index: usize,
+}
+
+impl PartialPacket {
+    pub fn new(data_shards_count: usize, parity_shards_count: usize, payload_size: usize) -> Self {
+        Self {
+            shards: vec![None; data_shards_count + parity_shards_count],
+            data_shards_count,
+            payload_size,
+            received_shards: 0,
+            last_shard_index: 0,
+        }
+    }
+
+    // Shards with an invalid index are ignored
+    pub fn push(&mut self, shard_index: usize, shard: BytesMut) {
+        if let Some(slot @ None) = self.shards.get_mut(shard_index) {
+            *slot = Some(shard);
+            self.received_shards += 1;
+            self.last_shard_index = usize::max(self.last_shard_index, shard_index);
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.received_shards >= self.data_shards_count
+    }
+
+    pub fn received_shards(&self) -> usize {
+        self.received_shards
+    }
+
+    pub fn shards_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn missing_data_shards(&self) -> Vec<u16> {
+        self.shards[..self.data_shards_count]
+            .iter()
+            .enumerate()
+            .filter(|(_, shard)| shard.is_none())
+            .map(|(index, _)| index as u16)
+            .collect()
+    }
+
+    pub fn has_parity(&self) -> bool {
+        self.shards.len() > self.data_shards_count
+    }
+
+    // Shards are sent in order, so the ones before the last received shard that are still missing
+    // are considered lost.
+    pub fn lost_shards(&self) -> usize {
+        (self.last_shard_index + 1).saturating_sub(self.received_shards)
+    }
+
+    // Concatenate the data shards, rebuilding the missing ones from parity. The packet must be
+    // complete.
+    pub fn into_payload(self) -> StrResult<BytesMut> {
+        let mut payload = BytesMut::with_capacity(self.payload_size);
+
+        if self.shards[..self.data_shards_count]
+            .iter()
+            .all(Option::is_some)
+        {
+            for shard in self.shards.iter().take(self.data_shards_count).flatten() {
+                payload.extend_from_slice(shard);
+            }
+        } else {
+            let parity_shards_count = self.shards.len() - self.data_shards_count;
+            let mut shards = self
+                .shards
+                .into_iter()
+                .map(|maybe_shard| maybe_shard.map(|shard| shard.to_vec()))
+                .collect::<Vec<_>>();
+
+            ReedSolomon::new(self.data_shards_count, parity_shards_count)
+                .map_err(err!())?
+                .reconstruct_data(&mut shards)
+                .map_err(err!())?;
+
+            for shard in shards.iter().take(self.data_shards_count).flatten() {
+                payload.extend_from_slice(shard);
+            }
+        }
+
+        // Remove the padding of the last data shard
+        payload.truncate(self.payload_size);
+
+        Ok(payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHARD_SIZE: usize = 100;
+
+    fn test_payload(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 31 % 251) as u8).collect()
+    }
+
+    // Split and encode like StreamSender does
+    fn make_shards(payload: &[u8], redundancy: f32) -> (usize, Vec<Vec<u8>>) {
+        let mut data_shards = payload
+            .chunks(SHARD_SIZE)
+            .map(|shard| shard.to_vec())
+            .collect::<Vec<_>>();
+        data_shards.last_mut().unwrap().resize(SHARD_SIZE, 0);
+
+        let data_shards_count = data_shards.len();
+        let parity_shards_count = parity_shards_count(data_shards_count, redundancy);
+        let parity_shards = encode(
+            &data_shards.iter().map(Vec::as_slice).collect::<Vec<_>>(),
+            parity_shards_count,
+        )
+        .unwrap();
+
+        data_shards.extend(parity_shards);
+
+        (data_shards_count, data_shards)
+    }
+
+    // Feed the shards for which `is_lost` returns false
+    fn receive(
+        payload_size: usize,
+        data_shards_count: usize,
+        shards: &[Vec<u8>],
+        is_lost: impl Fn(usize) -> bool,
+    ) -> PartialPacket {
+        let mut packet = PartialPacket::new(
+            data_shards_count,
+            shards.len() - data_shards_count,
+            payload_size,
+        );
+        for (index, shard) in shards.iter().enumerate() {
+            if !is_lost(index) {
+                packet.push(index, BytesMut::from(&shard[..]));
+            }
+        }
+
+        packet
+    }
+
+    #[test]
+    fn no_loss() {
+        let payload = test_payload(1050);
+        let (data_shards_count, shards) = make_shards(&payload, 0.2);
+        assert_eq!(data_shards_count, 11);
+        assert_eq!(shards.len(), 14);
+
+        let packet = receive(payload.len(), data_shards_count, &shards, |_| false);
+        assert!(packet.is_complete());
+        assert_eq!(packet.lost_shards(), 0);
+        assert_eq!(&packet.into_payload().unwrap()[..], &payload[..]);
+    }
+
+    #[test]
+    fn recover_from_any_loss_pattern_within_parity() {
+        let payload = test_payload(2345);
+        let (data_shards_count, shards) = make_shards(&payload, 0.25);
+        let parity_shards_count = shards.len() - data_shards_count;
+
+        // Burst losses at every offset, and sparse losses with every stride
+        let mut patterns = vec![];
+        for start in 0..shards.len() {
+            patterns.push(
+                (start..start + parity_shards_count)
+                    .map(|index| index % shards.len())
+                    .collect::<Vec<_>>(),
+            );
+        }
+        for stride in 2..shards.len() {
+            patterns.push(
+                (0..shards.len())
+                    .step_by(stride)
+                    .take(parity_shards_count)
+                    .collect(),
+            );
+        }
+
+        for lost in patterns {
+            let packet = receive(payload.len(), data_shards_count, &shards, |index| {
+                lost.contains(&index)
+            });
+            assert!(packet.is_complete(), "lost: {lost:?}");
+            assert_eq!(
+                &packet.into_payload().unwrap()[..],
+                &payload[..],
+                "lost: {lost:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn too_many_losses() {
+        let payload = test_payload(1000);
+        let (data_shards_count, shards) = make_shards(&payload, 0.2);
+        let parity_shards_count = shards.len() - data_shards_count;
+
+        let packet = receive(payload.len(), data_shards_count, &shards, |index| {
+            index <= parity_shards_count
+        });
+        assert!(!packet.is_complete());
+        assert_eq!(packet.lost_shards(), parity_shards_count + 1);
+    }
+
+    #[test]
+    fn single_shard() {
+        let payload = test_payload(40);
+        let (data_shards_count, shards) = make_shards(&payload, MIN_REDUNDANCY);
+        assert_eq!(shards.len(), 2);
+
+        let packet = receive(payload.len(), data_shards_count, &shards, |index| {
+            index == 0
+        });
+        assert_eq!(&packet.into_payload().unwrap()[..], &payload[..]);
+    }
+
+    #[test]
+    fn parity_limits() {
+        assert_eq!(parity_shards_count(10, 0.0), 1);
+        assert_eq!(parity_shards_count(10, 0.25), 3);
+        assert_eq!(parity_shards_count(250, 0.5), 6);
+        assert_eq!(parity_shards_count(256, 0.5), 0);
+    }
+
+    #[test]
+    fn valid_layouts() {
+        assert!(is_valid_layout(1, 1, 0, SHARD_SIZE));
+        assert!(is_valid_layout(250, 6, 250 * SHARD_SIZE, SHARD_SIZE));
+        assert!(is_valid_layout(1000, 0, 1000 * SHARD_SIZE, SHARD_SIZE));
+    }
+
+    #[test]
+    fn no_data_shards_rejected() {
+        assert!(!is_valid_layout(0, 0, 0, SHARD_SIZE));
+        assert!(!is_valid_layout(0, 10, 0, SHARD_SIZE));
+    }
+
+    #[test]
+    fn too_many_shards_rejected() {
+        assert!(!is_valid_layout(250, 7, SHARD_SIZE, SHARD_SIZE));
+        assert!(!is_valid_layout(300, 300, 0, SHARD_SIZE));
+    }
+
+    #[test]
+    fn payload_bigger_than_shards_rejected() {
+        assert!(!is_valid_layout(2, 1, 2 * SHARD_SIZE + 1, SHARD_SIZE));
+        assert!(!is_valid_layout(1, 0, u32::MAX as _, SHARD_SIZE));
+    }
+
+    #[test]
+    fn redundancy_adapts_to_loss() {
+        assert_eq!(redundancy_for_loss_rate(0.0), MIN_REDUNDANCY);
+        assert!(redundancy_for_loss_rate(0.1) > redundancy_for_loss_rate(0.01));
+        assert_eq!(redundancy_for_loss_rate(0.9), MAX_REDUNDANCY);
+    }
+}
@@ -3,40 +3,113 @@
 //
 // StreamSender and StreamReceiver endpoints allow for convenient conversion of the header to/from
 // bytes while still handling the additional byte buffer with zero copies and extra allocations.
-
+//
+// Packets bigger than the maximum packet size are split into shards by StreamSender and reassembled
+// by StreamReceiver. Every shard carries the stream ID, the packet index, the shard index, the data
+// and parity shards counts and the payload size, so the transports never see payloads bigger than
+// the maximum packet size. When FEC is enabled for a stream, parity shards are added (see fec.rs).
+// When NACK is enabled, missing shards are requested again (see nack.rs).
+
+mod fec;
+mod nack;
+mod quic;
+mod simulated;
 mod tcp;
 mod throttled_udp;
 mod udp;
 
+pub use quic::TlsIdentity;
+pub use simulated::{
+    simulated_link, simulated_transport_pair, BandwidthLimit, DelayDistribution, GilbertElliott,
+    LinkImpairments, LinkStatistics, SimulatedStreamPeer, SimulatedStreamReceiveSocket,
+    SimulatedStreamSendSocket,
+};
+
 use alvr_common::prelude::*;
 use alvr_session::{SocketBufferSize, SocketProtocol};
-use bytes::{Buf, BufMut, BytesMut};
-use futures::SinkExt;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use fec::{FecFeedback, PartialPacket};
+use futures::future::BoxFuture;
+use nack::{Nack, SendHistory};
+use quic::{QuicStreamConnectSocket, QuicStreamListenSocket};
 use serde::{de::DeserializeOwned, Serialize};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     marker::PhantomData,
+    mem,
     net::IpAddr,
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::Duration,
+};
+use tcp::{TcpStreamConnectSocket, TcpStreamListenSocket};
+use throttled_udp::{ThrottledUdpStreamConnectSocket, ThrottledUdpStreamListenSocket};
+use tokio::{
+    sync::{mpsc, Mutex},
+    time::{self, Instant},
 };
-use tcp::{TcpStreamReceiveSocket, TcpStreamSendSocket};
-use throttled_udp::{ThrottledUdpStreamReceiveSocket, ThrottledUdpStreamSendSocket};
-use tokio::net;
-use tokio::sync::{mpsc, Mutex};
-use udp::{UdpStreamReceiveSocket, UdpStreamSendSocket};
+use udp::{UdpStreamConnectSocket, UdpStreamListenSocket};
 
-#[derive(Clone)]
-enum StreamSendSocket {
-    Udp(UdpStreamSendSocket),
-    ThrottledUdp(ThrottledUdpStreamSendSocket),
-    Tcp(TcpStreamSendSocket),
+// stream ID (2 bytes), packet index (4 bytes), shard index (2 bytes), data shards count (2 bytes),
+// parity shards count (2 bytes), payload size (4 bytes)
+const PACKET_PREFIX_SIZE: usize = 16;
+
+// Reserved streams used by receivers to report the loss rate to the senders that use FEC, and to
+// request retransmissions
+const FEC_FEEDBACK_STREAM: u16 = u16::MAX;
+const NACK_STREAM: u16 = u16::MAX - 1;
+
+// Incomplete packets kept for reassembly. If exceeded, the oldest packet is discarded.
+const MAX_PARTIAL_PACKETS: usize = 32;
+
+type PacketQueues = Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>;
+
+#[derive(Default)]
+struct StreamSendState {
+    fec_redundancy: Option<f32>,
+    history: Option<SendHistory>,
 }
 
-enum StreamReceiveSocket {
-    Udp(UdpStreamReceiveSocket),
-    ThrottledUdp(ThrottledUdpStreamReceiveSocket),
-    Tcp(TcpStreamReceiveSocket),
+type StreamSendStates = Arc<Mutex<HashMap<u16, StreamSendState>>>;
+
+// Sending half of a transport. Each call must deliver the whole packet (stream ID, packet index,
+// header and buffer) as a single unit, or not deliver it at all.
+pub trait StreamSendTransport: Send + Sync {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult>;
+
+    // Limit of the transport for the packets of this stream, if smaller than the configured maximum
+    // packet size. It can change during the connection.
+    fn max_packet_size(&self, _stream_id: u16) -> Option<usize> {
+        None
+    }
+}
+
+// Receiving half of a transport. Returns None when the peer closed the connection.
+pub trait StreamReceiveTransport: Send {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>>;
+}
+
+pub type StreamTransportPair = (
+    Arc<dyn StreamSendTransport>,
+    Box<dyn StreamReceiveTransport>,
+);
+
+// Bound socket waiting for the server connection (client side)
+pub trait StreamListenTransport: Send {
+    fn accept_from_server(
+        self: Box<Self>,
+        server_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>>;
+}
+
+// Bound socket connecting to the client (server side)
+pub trait StreamConnectTransport: Send {
+    fn connect_to_client(
+        self: Box<Self>,
+        client_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>>;
 }
 
 pub struct SendBufferLock<'a> {
@@ -85,7 +158,9 @@ impl<T> SenderBuffer<T> {
 #[derive(Clone)]
 pub struct StreamSender<T> {
     stream_id: u16,
-    socket: StreamSendSocket,
+    socket: Arc<dyn StreamSendTransport>,
+    max_packet_size: usize,
+    send_states: StreamSendStates,
     // if the packet index overflows the worst that happens is a false positive packet loss
     next_packet_index: u32,
     _phantom: PhantomData<T>,
@@ -93,29 +168,122 @@ pub struct StreamSender<T> {
 
 impl<T> StreamSender<T> {
     // The buffer is moved into the method. There is no way of reusing the same buffer twice without
-    // extra copies/allocations
-    pub async fn send_buffer(&mut self, mut buffer: SenderBuffer<T>) -> StrResult {
-        buffer.inner[2..6].copy_from_slice(&self.next_packet_index.to_be_bytes());
-        self.next_packet_index += 1;
-
-        match &self.socket {
-            StreamSendSocket::Udp(socket) => socket
-                .inner
-                .lock()
-                .await
-                .send((buffer.inner.freeze(), socket.peer_addr))
-                .await
-                .map_err(err!()),
-            StreamSendSocket::Tcp(socket) => socket
-                .lock()
-                .await
-                .send(buffer.inner.freeze())
-                .await
-                .map_err(err!()),
-            StreamSendSocket::ThrottledUdp(socket) => {
-                socket.send(buffer.inner.freeze()).await.map_err(err!())
+    // extra copies/allocations. Only packets that need to be split into shards are copied.
+    pub async fn send_buffer(&mut self, buffer: SenderBuffer<T>) -> StrResult {
+        let packet_index = self.next_packet_index;
+        self.next_packet_index = self.next_packet_index.wrapping_add(1);
+
+        let mut packet = buffer.inner;
+
+        let max_packet_size = match self.socket.max_packet_size(self.stream_id) {
+            Some(size) => usize::min(size, self.max_packet_size).max(PACKET_PREFIX_SIZE + 1),
+            None => self.max_packet_size,
+        };
+        let max_shard_size = max_packet_size - PACKET_PREFIX_SIZE;
+        let payload_size = packet.len() - PACKET_PREFIX_SIZE;
+        let data_shards_count = usize::max((payload_size + max_shard_size - 1) / max_shard_size, 1);
+
+        let maybe_redundancy = self
+            .send_states
+            .lock()
+            .await
+            .get(&self.stream_id)
+            .and_then(|state| state.fec_redundancy);
+        let parity_shards_count = match maybe_redundancy {
+            Some(redundancy) if payload_size > 0 => {
+                fec::parity_shards_count(data_shards_count, redundancy)
             }
+            _ => 0,
+        };
+
+        if data_shards_count + parity_shards_count > u16::MAX as usize {
+            return fmt_e!("Packet too big: {payload_size} bytes");
         }
+
+        let shards = if data_shards_count == 1 && parity_shards_count == 0 {
+            let mut prefix = &mut packet[2..PACKET_PREFIX_SIZE];
+            prefix.put_u32(packet_index);
+            prefix.put_u16(0);
+            prefix.put_u16(1);
+            prefix.put_u16(0);
+            prefix.put_u32(payload_size as u32);
+
+            vec![packet.freeze()]
+        } else {
+            self.split_into_shards(
+                packet_index,
+                packet.split_off(PACKET_PREFIX_SIZE),
+                max_shard_size,
+                data_shards_count,
+                parity_shards_count,
+            )?
+        };
+
+        for shard in &shards {
+            self.socket.send(shard.clone()).await?;
+        }
+
+        if let Some(history) = self
+            .send_states
+            .lock()
+            .await
+            .get_mut(&self.stream_id)
+            .and_then(|state| state.history.as_mut())
+        {
+            history.push(packet_index, shards);
+        }
+
+        Ok(())
+    }
+
+    fn split_into_shards(
+        &self,
+        packet_index: u32,
+        payload: BytesMut,
+        max_shard_size: usize,
+        data_shards_count: usize,
+        parity_shards_count: usize,
+    ) -> StrResult<Vec<Bytes>> {
+        let payload_size = payload.len();
+
+        // With FEC, data shards must have the same size. The last one is padded.
+        let shard_size = if parity_shards_count > 0 {
+            (payload_size + data_shards_count - 1) / data_shards_count
+        } else {
+            max_shard_size
+        };
+        let mut data_shards = payload.chunks(shard_size).collect::<Vec<_>>();
+
+        let padded_last_shard;
+        let parity_shards = if parity_shards_count > 0 {
+            let last_shard = data_shards.pop().ok_or_else(enone!())?;
+            padded_last_shard = [last_shard, &vec![0; shard_size - last_shard.len()]].concat();
+            data_shards.push(&padded_last_shard);
+
+            fec::encode(&data_shards, parity_shards_count)?
+        } else {
+            vec![]
+        };
+
+        let shards = data_shards
+            .into_iter()
+            .chain(parity_shards.iter().map(Vec::as_slice))
+            .enumerate()
+            .map(|(shard_index, shard_payload)| {
+                let mut shard = BytesMut::with_capacity(PACKET_PREFIX_SIZE + shard_payload.len());
+                shard.put_u16(self.stream_id);
+                shard.put_u32(packet_index);
+                shard.put_u16(shard_index as u16);
+                shard.put_u16(data_shards_count as u16);
+                shard.put_u16(parity_shards_count as u16);
+                shard.put_u32(payload_size as u32);
+                shard.extend_from_slice(shard_payload);
+
+                shard.freeze()
+            })
+            .collect();
+
+        Ok(shards)
     }
 }
 
@@ -126,15 +294,14 @@ impl<T: Serialize> StreamSender<T> {
         preferred_max_buffer_size: usize,
     ) -> StrResult<SenderBuffer<T>> {
         let header_size = bincode::serialized_size(header).map_err(err!())?;
-        // the first two bytes are for the stream ID
-        let offset = 2 + 4 + header_size as usize;
+        let offset = PACKET_PREFIX_SIZE + header_size as usize;
 
         let mut buffer = BytesMut::with_capacity(offset + preferred_max_buffer_size);
 
         buffer.put_u16(self.stream_id);
 
-        // make space for the packet index
-        buffer.put_u32(0);
+        // make space for the rest of the prefix
+        buffer.put_bytes(0, PACKET_PREFIX_SIZE - 2);
 
         let mut buffer_writer = buffer.writer();
         bincode::serialize_into(&mut buffer_writer, header).map_err(err!())?;
@@ -154,30 +321,298 @@ impl<T: Serialize> StreamSender<T> {
 
 enum StreamReceiverType {
     Queue(mpsc::UnboundedReceiver<BytesMut>),
-    // QuicReliable(...)
+}
+
+// A packet that was discarded because too few of its shards arrived
+pub struct PartialPacketLoss {
+    pub packet_index: u32,
+    pub received_shards: u16,
+    pub shards_count: u16,
 }
 
 pub struct ReceivedPacket<T> {
     pub header: T,
     pub buffer: BytesMut,
+    // Packets before this one were lost, either completely or in part
     pub had_packet_loss: bool,
+    // Packets discarded since the previous received packet, that arrived only in part
+    pub partial_losses: Vec<PartialPacketLoss>,
 }
 
 pub struct StreamReceiver<T> {
+    stream_id: u16,
     receiver: StreamReceiverType,
+    feedback_socket: Arc<dyn StreamSendTransport>,
     next_packet_index: u32,
+    partial_packets: BTreeMap<u32, PartialPacket>,
+    // Packets ready to be returned, with the instant they were completed
+    completed_packets: BTreeMap<u32, (Instant, BytesMut)>,
+    partial_losses: Vec<PartialPacketLoss>,
+    // Max time a completed packet is held back waiting for older missing packets
+    nack_max_delay: Option<Duration>,
+    nacked_until: u32,
+    // Upper bound of the payload of the shards sent by the peer
+    max_shard_size: usize,
+    // shards counters for the FEC feedback
+    received_shards: usize,
+    lost_shards: usize,
+    peer_uses_fec: bool,
+    last_feedback_instant: Instant,
     _phantom: PhantomData<T>,
 }
 
+impl<T> StreamReceiver<T> {
+    // Request missing packets to the sender, which must have enabled NACK for this stream too.
+    // max_delay should be the time after which a packet is not useful anymore.
+    pub fn enable_nack(&mut self, max_delay: Duration) {
+        self.nack_max_delay = Some(max_delay);
+    }
+
+    fn wait_deadline(&self) -> Option<Instant> {
+        let max_delay = self.nack_max_delay?;
+        let (completion_instant, _) = self.completed_packets.values().next()?;
+
+        Some(*completion_instant + max_delay)
+    }
+
+    // Completed packets are returned in order. Older missing packets are waited for only until the
+    // deadline of the oldest completed packet.
+    fn pop_completed_packet(&mut self) -> Option<(u32, BytesMut)> {
+        let packet_index = *self.completed_packets.keys().next()?;
+
+        let expired = self
+            .wait_deadline()
+            .map(|deadline| Instant::now() >= deadline)
+            .unwrap_or(true);
+
+        if packet_index == self.next_packet_index || expired {
+            self.completed_packets
+                .remove(&packet_index)
+                .map(|(_, payload)| (packet_index, payload))
+        } else {
+            None
+        }
+    }
+
+    fn discard_partial_packet(&mut self, packet_index: u32, packet: PartialPacket) {
+        self.received_shards += packet.received_shards();
+        self.lost_shards += packet.shards_count() - packet.received_shards();
+
+        self.partial_losses.push(PartialPacketLoss {
+            packet_index,
+            received_shards: packet.received_shards() as u16,
+            shards_count: packet.shards_count() as u16,
+        });
+    }
+
+    // Returns the packet once enough shards have been received
+    fn push_shard(
+        &mut self,
+        packet_index: u32,
+        shard_index: u16,
+        data_shards_count: u16,
+        parity_shards_count: u16,
+        payload_size: u32,
+        shard: BytesMut,
+    ) -> Option<PartialPacket> {
+        let partial_packet = self.partial_packets.entry(packet_index).or_insert_with(|| {
+            PartialPacket::new(
+                data_shards_count as usize,
+                parity_shards_count as usize,
+                payload_size as usize,
+            )
+        });
+        partial_packet.push(shard_index as usize, shard);
+
+        if partial_packet.is_complete() {
+            self.partial_packets.remove(&packet_index)
+        } else {
+            if self.partial_packets.len() > MAX_PARTIAL_PACKETS {
+                let oldest_index = *self.partial_packets.keys().next()?;
+                let oldest_packet = self.partial_packets.remove(&oldest_index)?;
+                self.discard_partial_packet(oldest_index, oldest_packet);
+            }
+
+            None
+        }
+    }
+
+    // Request the packets older than the newest completed one that are still missing. Every packet
+    // is requested only once.
+    async fn send_nack(&mut self) -> StrResult {
+        let newest_index = if let Some(index) = self.completed_packets.keys().next_back() {
+            *index
+        } else {
+            return Ok(());
+        };
+
+        let mut packet_index =
+            if self.nacked_until.wrapping_sub(self.next_packet_index) < u32::MAX / 2 {
+                self.nacked_until
+            } else {
+                self.next_packet_index
+            };
+
+        let mut packets = vec![];
+        while packet_index != newest_index && packets.len() < nack::MAX_NACK_PACKETS {
+            if !self.completed_packets.contains_key(&packet_index) {
+                let missing_shards = self
+                    .partial_packets
+                    .get(&packet_index)
+                    .map(PartialPacket::missing_data_shards)
+                    .unwrap_or_default();
+
+                packets.push((packet_index, missing_shards));
+            }
+
+            packet_index = packet_index.wrapping_add(1);
+        }
+        self.nacked_until = packet_index;
+
+        if packets.is_empty() {
+            return Ok(());
+        }
+
+        let nack = Nack {
+            stream_id: self.stream_id,
+            packets,
+        };
+
+        let mut packet = BytesMut::new();
+        packet.put_u16(NACK_STREAM);
+        let mut packet_writer = packet.writer();
+        bincode::serialize_into(&mut packet_writer, &nack).map_err(err!())?;
+
+        self.feedback_socket
+            .send(packet_writer.into_inner().freeze())
+            .await
+    }
+
+    async fn send_fec_feedback(&mut self) -> StrResult {
+        let total_shards = self.received_shards + self.lost_shards;
+        let feedback = FecFeedback {
+            stream_id: self.stream_id,
+            shard_loss_rate: if total_shards > 0 {
+                self.lost_shards as f32 / total_shards as f32
+            } else {
+                0.0
+            },
+        };
+        self.received_shards = 0;
+        self.lost_shards = 0;
+        self.last_feedback_instant = Instant::now();
+
+        let mut packet = BytesMut::new();
+        packet.put_u16(FEC_FEEDBACK_STREAM);
+        let mut packet_writer = packet.writer();
+        bincode::serialize_into(&mut packet_writer, &feedback).map_err(err!())?;
+
+        self.feedback_socket
+            .send(packet_writer.into_inner().freeze())
+            .await
+    }
+}
+
 impl<T: DeserializeOwned> StreamReceiver<T> {
     pub async fn recv(&mut self) -> StrResult<ReceivedPacket<T>> {
-        let mut bytes = match &mut self.receiver {
-            StreamReceiverType::Queue(receiver) => receiver.recv().await.ok_or_else(enone!())?,
+        let (packet_index, bytes) = loop {
+            if let Some(packet) = self.pop_completed_packet() {
+                break packet;
+            }
+
+            let maybe_deadline = self.wait_deadline();
+            let maybe_shard = match &mut self.receiver {
+                StreamReceiverType::Queue(receiver) => {
+                    if let Some(deadline) = maybe_deadline {
+                        time::timeout_at(deadline, receiver.recv()).await.ok()
+                    } else {
+                        Some(receiver.recv().await)
+                    }
+                }
+            };
+            let mut shard = if let Some(maybe_shard) = maybe_shard {
+                maybe_shard.ok_or_else(enone!())?
+            } else {
+                // Stop waiting for the missing packets
+                continue;
+            };
+
+            // Malformed or truncated shards are dropped
+            if shard.remaining() < PACKET_PREFIX_SIZE - 2 {
+                continue;
+            }
+
+            let packet_index = shard.get_u32();
+            let shard_index = shard.get_u16();
+            let data_shards_count = shard.get_u16();
+            let parity_shards_count = shard.get_u16();
+            let payload_size = shard.get_u32();
+
+            if !fec::is_valid_layout(
+                data_shards_count as usize,
+                parity_shards_count as usize,
+                payload_size as usize,
+                self.max_shard_size,
+            ) {
+                continue;
+            }
+
+            // Shards of packets older than the last received one arrived too late, or they are
+            // parity shards that were not needed.
+            if packet_index.wrapping_sub(self.next_packet_index) > u32::MAX / 2
+                || self.completed_packets.contains_key(&packet_index)
+            {
+                continue;
+            }
+
+            let payload = if data_shards_count == 1 && parity_shards_count == 0 {
+                self.received_shards += 1;
+                self.peer_uses_fec = false;
+
+                shard
+            } else if let Some(packet) = self.push_shard(
+                packet_index,
+                shard_index,
+                data_shards_count,
+                parity_shards_count,
+                payload_size,
+                shard,
+            ) {
+                self.received_shards += packet.received_shards();
+                self.lost_shards += packet.lost_shards();
+                self.peer_uses_fec = packet.has_parity();
+
+                match packet.into_payload() {
+                    Ok(payload) => payload,
+                    Err(e) => {
+                        debug!("Failed to rebuild packet {packet_index}: {e}");
+                        continue;
+                    }
+                }
+            } else {
+                continue;
+            };
+
+            self.completed_packets
+                .insert(packet_index, (Instant::now(), payload));
+
+            if self.nack_max_delay.is_some() {
+                self.send_nack().await?;
+            }
         };
 
-        let packet_index = bytes.get_u32();
+        // Older packets cannot be completed anymore
+        let newer_packets = self.partial_packets.split_off(&packet_index);
+        for (index, partial_packet) in mem::replace(&mut self.partial_packets, newer_packets) {
+            self.discard_partial_packet(index, partial_packet);
+        }
+
         let had_packet_loss = packet_index != self.next_packet_index;
-        self.next_packet_index = packet_index + 1;
+        self.next_packet_index = packet_index.wrapping_add(1);
+
+        if self.peer_uses_fec && self.last_feedback_instant.elapsed() > fec::FEEDBACK_INTERVAL {
+            self.send_fec_feedback().await?;
+        }
 
         let mut bytes_reader = bytes.reader();
         let header = bincode::deserialize_from(&mut bytes_reader).map_err(err!())?;
@@ -188,160 +623,270 @@ impl<T: DeserializeOwned> StreamReceiver<T> {
             header,
             buffer,
             had_packet_loss,
+            partial_losses: mem::take(&mut self.partial_losses),
         })
     }
 }
 
-pub enum StreamSocketBuilder {
-    Tcp(net::TcpListener),
-    Udp(net::UdpSocket),
-    ThrottledUdp(net::UdpSocket),
-}
+pub struct StreamSocketBuilder(Box<dyn StreamListenTransport>);
 
 impl StreamSocketBuilder {
+    // Use a custom transport. The peer must use a compatible transport too.
+    pub fn new(listen_socket: Box<dyn StreamListenTransport>) -> Self {
+        Self(listen_socket)
+    }
+
     pub async fn listen_for_server(
         port: u16,
         stream_socket_config: SocketProtocol,
+        identity: &TlsIdentity,
         send_buffer_bytes: SocketBufferSize,
         recv_buffer_bytes: SocketBufferSize,
     ) -> StrResult<Self> {
-        Ok(match stream_socket_config {
-            SocketProtocol::Udp => StreamSocketBuilder::Udp(
+        Ok(Self(match stream_socket_config {
+            SocketProtocol::Udp => Box::new(UdpStreamListenSocket(
                 udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
-            ),
-            SocketProtocol::Tcp => StreamSocketBuilder::Tcp(
+            )),
+            SocketProtocol::Tcp => Box::new(TcpStreamListenSocket(
                 tcp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
-            ),
-            SocketProtocol::ThrottledUdp { .. } => StreamSocketBuilder::ThrottledUdp(
+            )),
+            SocketProtocol::ThrottledUdp { .. } => Box::new(ThrottledUdpStreamListenSocket(
                 udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
-            ),
-        })
+            )),
+            SocketProtocol::Quic => Box::new(QuicStreamListenSocket(
+                quic::bind(port, identity, send_buffer_bytes, recv_buffer_bytes).await?,
+            )),
+        }))
     }
 
-    pub async fn accept_from_server(self, server_ip: IpAddr, port: u16) -> StrResult<StreamSocket> {
-        let (send_socket, receive_socket) = match self {
-            StreamSocketBuilder::Udp(socket) => {
-                let (send_socket, receive_socket) = udp::connect(socket, server_ip, port).await?;
-                (
-                    StreamSendSocket::Udp(send_socket),
-                    StreamReceiveSocket::Udp(receive_socket),
-                )
-            }
-            StreamSocketBuilder::Tcp(listener) => {
-                let (send_socket, receive_socket) =
-                    tcp::accept_from_server(listener, server_ip).await?;
-                (
-                    StreamSendSocket::Tcp(send_socket),
-                    StreamReceiveSocket::Tcp(receive_socket),
-                )
-            }
-            StreamSocketBuilder::ThrottledUdp(socket) => {
-                let (send_socket, receive_socket) =
-                    throttled_udp::accept_from_server(socket, server_ip, port).await?;
-                (
-                    StreamSendSocket::ThrottledUdp(send_socket),
-                    StreamReceiveSocket::ThrottledUdp(receive_socket),
-                )
-            }
-        };
+    pub async fn accept_from_server(
+        self,
+        server_ip: IpAddr,
+        port: u16,
+        max_packet_size: usize,
+    ) -> StrResult<StreamSocket> {
+        let (send_socket, receive_socket) = self.0.accept_from_server(server_ip, port).await?;
 
-        Ok(StreamSocket {
+        Ok(StreamSocket::new(
             send_socket,
-            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
-            packet_queues: Arc::new(Mutex::new(HashMap::new())),
-        })
+            receive_socket,
+            max_packet_size,
+        ))
     }
+}
 
-    pub async fn connect_to_client(
-        client_ip: IpAddr,
+pub struct StreamSocketConnector(Box<dyn StreamConnectTransport>);
+
+impl StreamSocketConnector {
+    // Use a custom transport. The peer must use a compatible transport too.
+    pub fn new(connect_socket: Box<dyn StreamConnectTransport>) -> Self {
+        Self(connect_socket)
+    }
+
+    pub async fn bind_for_client(
         port: u16,
         protocol: SocketProtocol,
+        client_certificate: Option<Vec<u8>>,
         video_byterate: u32,
         send_buffer_bytes: SocketBufferSize,
         recv_buffer_bytes: SocketBufferSize,
-    ) -> StrResult<StreamSocket> {
-        let (send_socket, receive_socket) = match protocol {
-            SocketProtocol::Udp => {
-                let socket = udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?;
-                let (send_socket, receive_socket) = udp::connect(socket, client_ip, port).await?;
-                (
-                    StreamSendSocket::Udp(send_socket),
-                    StreamReceiveSocket::Udp(receive_socket),
-                )
-            }
-            SocketProtocol::Tcp => {
-                let (send_socket, receive_socket) =
-                    tcp::connect_to_client(client_ip, port, send_buffer_bytes, recv_buffer_bytes)
-                        .await?;
-                (
-                    StreamSendSocket::Tcp(send_socket),
-                    StreamReceiveSocket::Tcp(receive_socket),
-                )
-            }
+    ) -> StrResult<Self> {
+        Ok(Self(match protocol {
+            SocketProtocol::Udp => Box::new(UdpStreamConnectSocket(
+                udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
+            )),
+            SocketProtocol::Tcp => Box::new(TcpStreamConnectSocket {
+                send_buffer_bytes,
+                recv_buffer_bytes,
+            }),
             SocketProtocol::ThrottledUdp { bitrate_multiplier } => {
-                let socket = udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?;
-
-                let (send_socket, receive_socket) = throttled_udp::connect_to_client(
-                    socket,
-                    client_ip,
-                    port,
+                Box::new(ThrottledUdpStreamConnectSocket {
+                    socket: udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
                     video_byterate,
                     bitrate_multiplier,
-                )
-                .await?;
-                (
-                    StreamSendSocket::ThrottledUdp(send_socket),
-                    StreamReceiveSocket::ThrottledUdp(receive_socket),
-                )
+                })
             }
-        };
+            SocketProtocol::Quic => {
+                let client_certificate = if let Some(certificate) = client_certificate {
+                    certificate
+                } else {
+                    return fmt_e!("No certificate pinned for this client");
+                };
+
+                Box::new(QuicStreamConnectSocket {
+                    socket: udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?,
+                    client_certificate,
+                })
+            }
+        }))
+    }
 
-        Ok(StreamSocket {
+    pub async fn connect_to_client(
+        self,
+        client_ip: IpAddr,
+        port: u16,
+        max_packet_size: usize,
+    ) -> StrResult<StreamSocket> {
+        let (send_socket, receive_socket) = self.0.connect_to_client(client_ip, port).await?;
+
+        Ok(StreamSocket::new(
             send_socket,
-            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
-            packet_queues: Arc::new(Mutex::new(HashMap::new())),
-        })
+            receive_socket,
+            max_packet_size,
+        ))
     }
 }
 
 pub struct StreamSocket {
-    send_socket: StreamSendSocket,
-    receive_socket: Arc<Mutex<Option<StreamReceiveSocket>>>,
-    packet_queues: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
+    send_socket: Arc<dyn StreamSendTransport>,
+    receive_socket: Arc<Mutex<Option<Box<dyn StreamReceiveTransport>>>>,
+    max_packet_size: usize,
+    packet_queues: PacketQueues,
+    send_states: StreamSendStates,
 }
 
 impl StreamSocket {
+    // Build a socket from an already connected transport. This can be used on both sides.
+    // max_packet_size includes the packet prefix and must be bigger than it.
+    pub fn new(
+        send_socket: Arc<dyn StreamSendTransport>,
+        receive_socket: Box<dyn StreamReceiveTransport>,
+        max_packet_size: usize,
+    ) -> Self {
+        assert!(max_packet_size > PACKET_PREFIX_SIZE);
+
+        Self {
+            send_socket,
+            receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
+            max_packet_size,
+            packet_queues: Arc::new(Mutex::new(HashMap::new())),
+            send_states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
     pub async fn request_stream<T>(&self, stream_id: u16) -> StrResult<StreamSender<T>> {
         Ok(StreamSender {
             stream_id,
-            socket: self.send_socket.clone(),
+            socket: Arc::clone(&self.send_socket),
+            max_packet_size: self.max_packet_size,
+            send_states: Arc::clone(&self.send_states),
             next_packet_index: 0,
             _phantom: PhantomData,
         })
     }
 
+    // Add parity shards to the packets sent on this stream. The amount of parity is adapted to the
+    // loss rate reported by the peer.
+    pub async fn enable_fec(&self, stream_id: u16) {
+        self.send_states
+            .lock()
+            .await
+            .entry(stream_id)
+            .or_default()
+            .fec_redundancy = Some(fec::MIN_REDUNDANCY);
+    }
+
+    // Keep the packets sent on this stream for max_delay, to resend them if requested by the peer
+    pub async fn enable_nack(&self, stream_id: u16, max_delay: Duration) {
+        self.send_states
+            .lock()
+            .await
+            .entry(stream_id)
+            .or_default()
+            .history = Some(SendHistory::new(max_delay));
+    }
+
     pub async fn subscribe_to_stream<T>(&self, stream_id: u16) -> StrResult<StreamReceiver<T>> {
         let (enqueuer, dequeuer) = mpsc::unbounded_channel();
         self.packet_queues.lock().await.insert(stream_id, enqueuer);
 
         Ok(StreamReceiver {
+            stream_id,
             receiver: StreamReceiverType::Queue(dequeuer),
+            feedback_socket: Arc::clone(&self.send_socket),
             next_packet_index: 0,
+            partial_packets: BTreeMap::new(),
+            completed_packets: BTreeMap::new(),
+            partial_losses: vec![],
+            nack_max_delay: None,
+            nacked_until: 0,
+            max_shard_size: self.max_packet_size - PACKET_PREFIX_SIZE,
+            received_shards: 0,
+            lost_shards: 0,
+            peer_uses_fec: false,
+            last_feedback_instant: Instant::now(),
             _phantom: PhantomData,
         })
     }
 
     pub async fn receive_loop(&self) -> StrResult {
-        match self.receive_socket.lock().await.take().unwrap() {
-            StreamReceiveSocket::Udp(socket) => {
-                udp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+        let res = self.dispatch_packets().await;
+
+        // The receivers of the subscribed streams return an error from now on
+        self.packet_queues.lock().await.clear();
+
+        res
+    }
+
+    async fn dispatch_packets(&self) -> StrResult {
+        let mut socket = self.receive_socket.lock().await.take().unwrap();
+
+        while let Some(mut packet) = socket.recv().await? {
+            if packet.remaining() < 2 {
+                continue;
             }
-            StreamReceiveSocket::Tcp(socket) => {
-                tcp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+            let stream_id = packet.get_u16();
+
+            if stream_id == FEC_FEEDBACK_STREAM {
+                let feedback: FecFeedback = match bincode::deserialize(&packet) {
+                    Ok(feedback) => feedback,
+                    Err(e) => {
+                        debug!("Malformed FEC feedback: {e}");
+                        continue;
+                    }
+                };
+                if let Some(redundancy) = self
+                    .send_states
+                    .lock()
+                    .await
+                    .get_mut(&feedback.stream_id)
+                    .and_then(|state| state.fec_redundancy.as_mut())
+                {
+                    *redundancy = fec::redundancy_for_loss_rate(feedback.shard_loss_rate);
+                }
+
+                continue;
             }
-            StreamReceiveSocket::ThrottledUdp(socket) => {
-                throttled_udp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+
+            if stream_id == NACK_STREAM {
+                let nack: Nack = match bincode::deserialize(&packet) {
+                    Ok(nack) => nack,
+                    Err(e) => {
+                        debug!("Malformed NACK: {e}");
+                        continue;
+                    }
+                };
+                let shards = self
+                    .send_states
+                    .lock()
+                    .await
+                    .get(&nack.stream_id)
+                    .and_then(|state| state.history.as_ref())
+                    .map(|history| history.shards_to_resend(&nack))
+                    .unwrap_or_default();
+
+                for shard in shards {
+                    self.send_socket.send(shard).await?;
+                }
+
+                continue;
+            }
+
+            if let Some(enqueuer) = self.packet_queues.lock().await.get_mut(&stream_id) {
+                enqueuer.send(packet).map_err(err!())?;
             }
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,172 @@
+// Selective retransmission. When a packet completes while older ones are still missing, the
+// receiver sends a NACK listing what it is waiting for and holds the newer packets back for a
+// limited time. The sender keeps the shards it sent recently and resends the requested ones, unless
+// they are too old to be useful anymore.
+
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+use tokio::time::Instant;
+
+const MAX_HISTORY_PACKETS: usize = 1024;
+
+// Max packets listed in a single NACK
+pub const MAX_NACK_PACKETS: usize = 64;
+
+#[derive(Serialize, Deserialize)]
+pub struct Nack {
+    pub stream_id: u16,
+    // Packet index and missing shard indices. An empty shards list requests the whole packet.
+    pub packets: Vec<(u32, Vec<u16>)>,
+}
+
+struct SentPacket {
+    packet_index: u32,
+    send_instant: Instant,
+    shards: Vec<Bytes>,
+}
+
+pub struct SendHistory {
+    max_delay: Duration,
+    packets: VecDeque<SentPacket>,
+}
+
+impl SendHistory {
+    // Packets older than max_delay are not resent
+    pub fn new(max_delay: Duration) -> Self {
+        Self {
+            max_delay,
+            packets: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, packet_index: u32, shards: Vec<Bytes>) {
+        let now = Instant::now();
+
+        while let Some(packet) = self.packets.front() {
+            if self.packets.len() >= MAX_HISTORY_PACKETS
+                || now - packet.send_instant > self.max_delay
+            {
+                self.packets.pop_front();
+            } else {
+                break;
+            }
+        }
+
+        self.packets.push_back(SentPacket {
+            packet_index,
+            send_instant: now,
+            shards,
+        });
+    }
+
+    pub fn shards_to_resend(&self, nack: &Nack) -> Vec<Bytes> {
+        let now = Instant::now();
+
+        let first_index = if let Some(packet) = self.packets.front() {
+            packet.packet_index
+        } else {
+            return vec![];
+        };
+
+        let mut shards = vec![];
+        for (packet_index, shard_indices) in &nack.packets {
+            // Packets are pushed with consecutive indices
+            let maybe_packet = self
+                .packets
+                .get(packet_index.wrapping_sub(first_index) as usize)
+                .filter(|packet| {
+                    packet.packet_index == *packet_index
+                        && now - packet.send_instant <= self.max_delay
+                });
+
+            if let Some(packet) = maybe_packet {
+                if shard_indices.is_empty() {
+                    shards.extend(packet.shards.iter().cloned());
+                } else {
+                    shards.extend(
+                        shard_indices
+                            .iter()
+                            .filter_map(|index| packet.shards.get(*index as usize).cloned()),
+                    );
+                }
+            }
+        }
+
+        shards
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn shards(packet_index: u32, count: u8) -> Vec<Bytes> {
+        (0..count)
+            .map(|shard_index| Bytes::from(vec![packet_index as u8, shard_index]))
+            .collect()
+    }
+
+    fn resend(history: &SendHistory, packets: Vec<(u32, Vec<u16>)>) -> Vec<Vec<u8>> {
+        let nack = Nack {
+            stream_id: 0,
+            packets,
+        };
+
+        history
+            .shards_to_resend(&nack)
+            .iter()
+            .map(|shard| shard.to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn resend_requested_shards() {
+        let mut history = SendHistory::new(Duration::from_secs(1));
+        for packet_index in 10..14 {
+            history.push(packet_index, shards(packet_index, 3));
+        }
+
+        assert_eq!(
+            resend(
+                &history,
+                vec![(11, vec![]), (12, vec![0, 2, 5]), (20, vec![])]
+            ),
+            [[11, 0], [11, 1], [11, 2], [12, 0], [12, 2]]
+        );
+    }
+
+    #[test]
+    fn expired_packets_are_not_resent() {
+        let mut history = SendHistory::new(Duration::from_millis(20));
+        history.push(0, shards(0, 1));
+        thread::sleep(Duration::from_millis(30));
+        history.push(1, shards(1, 1));
+
+        assert_eq!(resend(&history, vec![(0, vec![]), (1, vec![])]), [[1, 0]]);
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = SendHistory::new(Duration::from_secs(1));
+        for packet_index in 0..MAX_HISTORY_PACKETS as u32 + 10 {
+            history.push(packet_index, shards(packet_index, 1));
+        }
+        assert_eq!(history.packets.len(), MAX_HISTORY_PACKETS);
+
+        assert_eq!(resend(&history, vec![(5, vec![]), (10, vec![])]), [[10, 0]]);
+    }
+
+    #[test]
+    fn packet_index_wraps_around() {
+        let mut history = SendHistory::new(Duration::from_secs(1));
+        history.push(u32::MAX, shards(7, 1));
+        history.push(0, shards(8, 1));
+
+        assert_eq!(
+            resend(&history, vec![(0, vec![]), (u32::MAX, vec![])]),
+            [[8, 0], [7, 0]]
+        );
+    }
+}
@@ -0,0 +1,393 @@
+use super::{
+    udp, StreamConnectTransport, StreamListenTransport, StreamReceiveTransport,
+    StreamSendTransport, StreamTransportPair,
+};
+use crate::{
+    canonical_ip, peer_socket_addr, Ldc, AUDIO, KEEPALIVE_INTERVAL, TLS_SERVER_NAME, VIDEO,
+};
+use alvr_common::prelude::*;
+use alvr_session::SocketBufferSize;
+use bytes::{Bytes, BytesMut};
+use futures::{future::BoxFuture, stream::SelectAll, FutureExt, SinkExt, StreamExt};
+use quinn::{
+    ClientConfig, Connection, ConnectionError, Endpoint, EndpointConfig, RecvStream, SendStream,
+    ServerConfig, TokioRuntime, TransportConfig,
+};
+use rustls::{
+    client::{ServerCertVerified, ServerCertVerifier},
+    Certificate, CertificateError, PrivateKey, ServerName,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::SystemTime};
+use tokio::{net::UdpSocket, sync::Mutex};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+// Packets of these streams are sent as datagrams, which are not retransmitted if lost. All other
+// streams get their own reliable QUIC stream, so they don't block each other.
+const UNRELIABLE_STREAMS: [u16; 2] = [AUDIO, VIDEO];
+
+// Self-signed certificate and private key of the client, both DER encoded. The default value is empty
+// and must be replaced with a generated identity before use.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct TlsIdentity {
+    pub certificate: Vec<u8>,
+    pub private_key: Vec<u8>,
+}
+
+impl TlsIdentity {
+    pub fn generate(hostname: &str) -> StrResult<Self> {
+        let certificate =
+            rcgen::generate_simple_self_signed([hostname.to_owned()]).map_err(err!())?;
+
+        Ok(Self {
+            certificate: certificate.serialize_der().map_err(err!())?,
+            private_key: certificate.serialize_private_key_der(),
+        })
+    }
+}
+
+// The client certificate is self-signed, so there is no chain to validate. It is accepted only if
+// it matches the certificate pinned for this client.
+struct PinnedCertificateVerifier(Certificate);
+
+impl ServerCertVerifier for PinnedCertificateVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _: &[Certificate],
+        _: &ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        if *end_entity == self.0 {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::InvalidCertificate(
+                CertificateError::ApplicationVerificationFailure,
+            ))
+        }
+    }
+}
+
+type QuicReliableSendStream = Arc<Mutex<FramedWrite<SendStream, Ldc>>>;
+
+pub struct QuicStreamSendSocket {
+    connection: Connection,
+    reliable_streams: Mutex<HashMap<u16, QuicReliableSendStream>>,
+}
+
+impl QuicStreamSendSocket {
+    async fn reliable_stream(&self, stream_id: u16) -> StrResult<QuicReliableSendStream> {
+        let mut streams = self.reliable_streams.lock().await;
+
+        if let Some(stream) = streams.get(&stream_id) {
+            Ok(Arc::clone(stream))
+        } else {
+            let stream = self.connection.open_uni().await.map_err(err!())?;
+            let stream = Arc::new(Mutex::new(FramedWrite::new(stream, Ldc::new())));
+            streams.insert(stream_id, Arc::clone(&stream));
+
+            Ok(stream)
+        }
+    }
+}
+
+impl StreamSendTransport for QuicStreamSendSocket {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult> {
+        async move {
+            let stream_id = u16::from_be_bytes([packet[0], packet[1]]);
+
+            // Shards are sized to fit a datagram (see max_packet_size()), but the path MTU can
+            // shrink in the meantime. Those packets fall back to the reliable channel.
+            if UNRELIABLE_STREAMS.contains(&stream_id)
+                && matches!(self.connection.max_datagram_size(), Some(size) if packet.len() <= size)
+            {
+                self.connection.send_datagram(packet).map_err(err!())
+            } else {
+                let stream = self.reliable_stream(stream_id).await?;
+                let mut stream = stream.lock().await;
+
+                stream.send(packet).await.map_err(err!())
+            }
+        }
+        .boxed()
+    }
+
+    fn max_packet_size(&self, stream_id: u16) -> Option<usize> {
+        if UNRELIABLE_STREAMS.contains(&stream_id) {
+            self.connection.max_datagram_size()
+        } else {
+            None
+        }
+    }
+}
+
+pub struct QuicStreamReceiveSocket {
+    connection: Connection,
+    reliable_streams: SelectAll<FramedRead<RecvStream, Ldc>>,
+}
+
+impl StreamReceiveTransport for QuicStreamReceiveSocket {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>> {
+        async move {
+            loop {
+                tokio::select! {
+                    res = self.connection.read_datagram() => {
+                        return match res {
+                            Ok(packet) => Ok(Some(BytesMut::from(&packet[..]))),
+                            Err(e) => on_connection_error(e),
+                        };
+                    }
+                    res = self.connection.accept_uni() => match res {
+                        Ok(stream) => self
+                            .reliable_streams
+                            .push(FramedRead::new(stream, Ldc::new())),
+                        Err(e) => return on_connection_error(e),
+                    },
+                    Some(res) = self.reliable_streams.next() => {
+                        return res.map(Some).map_err(err!());
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+fn on_connection_error(error: ConnectionError) -> StrResult<Option<BytesMut>> {
+    match error {
+        ConnectionError::ApplicationClosed(_) | ConnectionError::LocallyClosed => Ok(None),
+        e => fmt_e!("QUIC connection lost: {e}"),
+    }
+}
+
+pub struct QuicStreamListenSocket(pub Endpoint);
+
+impl StreamListenTransport for QuicStreamListenSocket {
+    fn accept_from_server(
+        self: Box<Self>,
+        server_ip: IpAddr,
+        _: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let connection = self
+                .0
+                .accept()
+                .await
+                .ok_or_else(enone!())?
+                .await
+                .map_err(err!())?;
+
+            let server_address = connection.remote_address();
+            if canonical_ip(server_address.ip()) != server_ip {
+                return fmt_e!("Connected to wrong server: {server_address} != {server_ip}");
+            }
+
+            Ok(split(connection))
+        }
+        .boxed()
+    }
+}
+
+// The client certificate is pinned, the connection fails if the client presents another one
+pub struct QuicStreamConnectSocket {
+    pub socket: UdpSocket,
+    pub client_certificate: Vec<u8>,
+}
+
+impl StreamConnectTransport for QuicStreamConnectSocket {
+    fn connect_to_client(
+        self: Box<Self>,
+        client_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let connection =
+                connect_to_client(self.socket, client_ip, port, self.client_certificate).await?;
+
+            Ok(split(connection))
+        }
+        .boxed()
+    }
+}
+
+fn transport_config() -> Arc<TransportConfig> {
+    let mut config = TransportConfig::default();
+    config.keep_alive_interval(Some(KEEPALIVE_INTERVAL));
+
+    Arc::new(config)
+}
+
+fn endpoint(socket: UdpSocket, server_config: Option<ServerConfig>) -> StrResult<Endpoint> {
+    Endpoint::new(
+        EndpointConfig::default(),
+        server_config,
+        socket.into_std().map_err(err!())?,
+        Arc::new(TokioRuntime),
+    )
+    .map_err(err!())
+}
+
+fn split(connection: Connection) -> StreamTransportPair {
+    (
+        Arc::new(QuicStreamSendSocket {
+            connection: connection.clone(),
+            reliable_streams: Mutex::new(HashMap::new()),
+        }),
+        Box::new(QuicStreamReceiveSocket {
+            connection,
+            reliable_streams: SelectAll::new(),
+        }),
+    )
+}
+
+pub async fn bind(
+    port: u16,
+    identity: &TlsIdentity,
+    send_buffer_bytes: SocketBufferSize,
+    recv_buffer_bytes: SocketBufferSize,
+) -> StrResult<Endpoint> {
+    let socket = udp::bind(port, send_buffer_bytes, recv_buffer_bytes).await?;
+
+    let mut server_config = ServerConfig::with_single_cert(
+        vec![Certificate(identity.certificate.clone())],
+        PrivateKey(identity.private_key.clone()),
+    )
+    .map_err(err!())?;
+    server_config.transport_config(transport_config());
+
+    endpoint(socket, Some(server_config))
+}
+
+pub async fn connect_to_client(
+    socket: UdpSocket,
+    client_ip: IpAddr,
+    port: u16,
+    client_certificate: Vec<u8>,
+) -> StrResult<Connection> {
+    let crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(PinnedCertificateVerifier(Certificate(
+            client_certificate,
+        ))))
+        .with_no_client_auth();
+    let mut client_config = ClientConfig::new(Arc::new(crypto));
+    client_config.transport_config(transport_config());
+
+    endpoint(socket, None)?
+        .connect_with(
+            client_config,
+            peer_socket_addr(client_ip, port),
+            TLS_SERVER_NAME,
+        )
+        .map_err(err!())?
+        .await
+        .map_err(err!())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StreamSocket, HAPTICS};
+    use std::{net::Ipv4Addr, time::Duration};
+    use tokio::time;
+
+    // Default packet size of the settings, bigger than the datagrams allowed before MTU discovery
+    const PACKET_SIZE: usize = 1452;
+
+    // Returns the server connection and the client socket
+    async fn connect(pinned_certificate: Option<Vec<u8>>) -> StrResult<(Connection, StreamSocket)> {
+        let identity = TlsIdentity::generate("client.alvr")?;
+        let pinned_certificate = pinned_certificate.unwrap_or_else(|| identity.certificate.clone());
+
+        let listen_endpoint = bind(
+            0,
+            &identity,
+            SocketBufferSize::Default,
+            SocketBufferSize::Default,
+        )
+        .await?;
+        let client_port = listen_endpoint.local_addr().map_err(err!())?.port();
+        let server_socket =
+            udp::bind(0, SocketBufferSize::Default, SocketBufferSize::Default).await?;
+        let server_port = server_socket.local_addr().map_err(err!())?.port();
+
+        let (server_connection, (client_send, client_receive)) = tokio::try_join!(
+            connect_to_client(
+                server_socket,
+                Ipv4Addr::LOCALHOST.into(),
+                client_port,
+                pinned_certificate
+            ),
+            Box::new(QuicStreamListenSocket(listen_endpoint))
+                .accept_from_server(Ipv4Addr::LOCALHOST.into(), server_port),
+        )?;
+
+        Ok((
+            server_connection,
+            StreamSocket::new(client_send, client_receive, PACKET_SIZE),
+        ))
+    }
+
+    #[tokio::test]
+    async fn unpinned_certificate_is_rejected() {
+        let other_identity = TlsIdentity::generate("other.client.alvr").unwrap();
+
+        let res = time::timeout(
+            Duration::from_secs(5),
+            connect(Some(other_identity.certificate)),
+        )
+        .await
+        .unwrap();
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn video_shards_fit_datagrams() {
+        let (server_connection, client_socket) = connect(None).await.unwrap();
+        let (server_send, server_receive) = split(server_connection.clone());
+        let server_socket = StreamSocket::new(server_send, server_receive, PACKET_SIZE);
+        let client_socket = Arc::new(client_socket);
+        tokio::spawn({
+            let client_socket = Arc::clone(&client_socket);
+            async move { client_socket.receive_loop().await }
+        });
+
+        let mut video_sender = server_socket.request_stream::<u32>(VIDEO).await.unwrap();
+        let mut video_receiver = client_socket
+            .subscribe_to_stream::<u32>(VIDEO)
+            .await
+            .unwrap();
+        let mut haptics_sender = server_socket.request_stream::<u32>(HAPTICS).await.unwrap();
+        let mut haptics_receiver = client_socket
+            .subscribe_to_stream::<u32>(HAPTICS)
+            .await
+            .unwrap();
+
+        let payload = (0..20_000).map(|i| i as u8).collect::<Vec<_>>();
+        let mut buffer = video_sender.new_buffer(&7, payload.len()).unwrap();
+        buffer.get_mut().extend_from_slice(&payload);
+        video_sender.send_buffer(buffer).await.unwrap();
+
+        let packet = time::timeout(Duration::from_secs(5), video_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.header, 7);
+        assert_eq!(packet.buffer[..], payload[..]);
+
+        // All video shards were sent as datagrams, none fell back to a reliable stream
+        let frames = server_connection.stats().frame_tx;
+        assert!(frames.datagram >= (payload.len() / PACKET_SIZE) as u64);
+        assert_eq!(frames.stream, 0);
+
+        haptics_sender.send(&8).await.unwrap();
+        let packet = time::timeout(Duration::from_secs(5), haptics_receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.header, 8);
+        assert!(server_connection.stats().frame_tx.stream > 0);
+    }
+}
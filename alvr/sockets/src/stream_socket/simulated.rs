@@ -0,0 +1,705 @@
+// In-process network link with configurable impairments, used to reproduce bad network conditions
+// in tests. Every direction of the link applies in order: loss (Gilbert-Elliott model), bandwidth
+// limit (token bucket, packets that would wait too long in the queue are dropped), delay and
+// reordering. All random choices come from a seeded generator, so a test sees the same sequence of
+// impairments on every run, given the same sequence of packets.
+
+use super::{
+    StreamConnectTransport, StreamListenTransport, StreamReceiveTransport, StreamSendTransport,
+    StreamTransportPair,
+};
+use alvr_common::{parking_lot::Mutex, prelude::*};
+use bytes::{Bytes, BytesMut};
+use futures::{future::BoxFuture, FutureExt};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::{Distribution, Normal};
+use std::{collections::BTreeMap, future, net::IpAddr, sync::Arc, time::Duration};
+use tokio::{
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+#[derive(Clone, Debug)]
+pub enum DelayDistribution {
+    Constant(Duration),
+    Uniform { min: Duration, max: Duration },
+    // Negative samples are clamped to zero
+    Normal { mean: Duration, std_dev: Duration },
+}
+
+impl Default for DelayDistribution {
+    fn default() -> Self {
+        Self::Constant(Duration::ZERO)
+    }
+}
+
+impl DelayDistribution {
+    fn sample(&self, rng: &mut StdRng) -> Duration {
+        match self {
+            Self::Constant(delay) => *delay,
+            Self::Uniform { min, max } => {
+                if min < max {
+                    rng.gen_range(*min..=*max)
+                } else {
+                    *min
+                }
+            }
+            Self::Normal { mean, std_dev } => {
+                let delay_s = Normal::new(mean.as_secs_f64(), std_dev.as_secs_f64())
+                    .map(|distribution| distribution.sample(rng))
+                    .unwrap_or(mean.as_secs_f64());
+
+                Duration::from_secs_f64(delay_s.max(0.0))
+            }
+        }
+    }
+}
+
+// Two state Markov chain. Losses in the bad state come in bursts, whose average length is
+// 1 / bad_to_good packets.
+#[derive(Clone, Debug)]
+pub struct GilbertElliott {
+    pub good_to_bad: f32,
+    pub bad_to_good: f32,
+    pub good_loss_rate: f32,
+    pub bad_loss_rate: f32,
+}
+
+impl GilbertElliott {
+    // Independent losses
+    pub fn uniform(loss_rate: f32) -> Self {
+        Self {
+            good_to_bad: 0.0,
+            bad_to_good: 1.0,
+            good_loss_rate: loss_rate,
+            bad_loss_rate: loss_rate,
+        }
+    }
+
+    // Bursts of consecutive losses, with the given average length, amounting to loss_rate overall
+    pub fn bursts(loss_rate: f32, average_burst_length: f32) -> Self {
+        let bad_to_good = 1.0 / average_burst_length.max(1.0);
+
+        Self {
+            good_to_bad: bad_to_good * loss_rate / (1.0 - loss_rate).max(f32::EPSILON),
+            bad_to_good,
+            good_loss_rate: 0.0,
+            bad_loss_rate: 1.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct BandwidthLimit {
+    pub bytes_per_second: f64,
+    // Bucket size, the amount of bytes that can be sent at once after the link has been idle
+    pub burst_bytes: usize,
+    // Packets that would need to wait longer than this are dropped
+    pub max_queue_delay: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct LinkImpairments {
+    pub delay: DelayDistribution,
+    pub loss: Option<GilbertElliott>,
+    pub bandwidth: Option<BandwidthLimit>,
+    // Probability of holding a packet back for reorder_delay, so that later packets overtake it
+    pub reorder_rate: f32,
+    pub reorder_delay: Duration,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct LinkStatistics {
+    pub sent_packets: usize,
+    pub lost_packets: usize,
+    pub queue_dropped_packets: usize,
+    pub reordered_packets: usize,
+}
+
+struct LinkState {
+    rng: StdRng,
+    bad_state: bool,
+    bucket_tokens: f64,
+    bucket_update_instant: Instant,
+    next_sequence: u64,
+    statistics: LinkStatistics,
+}
+
+// Packets are sent with their delivery instant. The sequence number keeps the sending order among
+// packets with the same delivery instant.
+type ScheduledPacket = (Instant, u64, Bytes);
+
+pub struct SimulatedStreamSendSocket {
+    impairments: LinkImpairments,
+    state: Mutex<LinkState>,
+    sender: mpsc::UnboundedSender<ScheduledPacket>,
+}
+
+impl SimulatedStreamSendSocket {
+    pub fn statistics(&self) -> LinkStatistics {
+        self.state.lock().statistics
+    }
+
+    // Returns the delivery instant, or None if the packet is dropped
+    fn schedule(&self, state: &mut LinkState, packet_size: usize) -> Option<Instant> {
+        let now = Instant::now();
+
+        if let Some(model) = &self.impairments.loss {
+            let transition_rate = if state.bad_state {
+                model.bad_to_good
+            } else {
+                model.good_to_bad
+            };
+            if state.rng.gen::<f32>() < transition_rate {
+                state.bad_state = !state.bad_state;
+            }
+
+            let loss_rate = if state.bad_state {
+                model.bad_loss_rate
+            } else {
+                model.good_loss_rate
+            };
+            if state.rng.gen::<f32>() < loss_rate {
+                state.statistics.lost_packets += 1;
+                return None;
+            }
+        }
+
+        let mut departure_instant = now;
+        if let Some(limit) = &self.impairments.bandwidth {
+            let elapsed = (now - state.bucket_update_instant).as_secs_f64();
+            state.bucket_tokens = f64::min(
+                state.bucket_tokens + elapsed * limit.bytes_per_second,
+                limit.burst_bytes as f64,
+            );
+            state.bucket_update_instant = now;
+
+            // Negative tokens represent the bytes queued before this packet
+            let tokens = state.bucket_tokens - packet_size as f64;
+            let queue_delay =
+                Duration::from_secs_f64(f64::max(-tokens, 0.0) / limit.bytes_per_second);
+            if queue_delay > limit.max_queue_delay {
+                state.statistics.queue_dropped_packets += 1;
+                return None;
+            }
+
+            state.bucket_tokens = tokens;
+            departure_instant += queue_delay;
+        }
+
+        let mut delivery_instant =
+            departure_instant + self.impairments.delay.sample(&mut state.rng);
+        if state.rng.gen::<f32>() < self.impairments.reorder_rate {
+            state.statistics.reordered_packets += 1;
+            delivery_instant += self.impairments.reorder_delay;
+        }
+
+        Some(delivery_instant)
+    }
+}
+
+impl StreamSendTransport for SimulatedStreamSendSocket {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult> {
+        let res = {
+            let mut state = self.state.lock();
+            state.statistics.sent_packets += 1;
+
+            if let Some(delivery_instant) = self.schedule(&mut state, packet.len()) {
+                let sequence = state.next_sequence;
+                state.next_sequence += 1;
+
+                self.sender
+                    .send((delivery_instant, sequence, packet))
+                    .map_err(err!())
+            } else {
+                Ok(())
+            }
+        };
+
+        async move { res }.boxed()
+    }
+}
+
+pub struct SimulatedStreamReceiveSocket {
+    receiver: mpsc::UnboundedReceiver<ScheduledPacket>,
+    in_flight: BTreeMap<(Instant, u64), Bytes>,
+    closed: bool,
+}
+
+impl StreamReceiveTransport for SimulatedStreamReceiveSocket {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>> {
+        async move {
+            loop {
+                // Packets already sent might be due before the ones already received
+                while let Ok((instant, sequence, packet)) = self.receiver.try_recv() {
+                    self.in_flight.insert((instant, sequence), packet);
+                }
+
+                if let Some(&(delivery_instant, sequence)) = self.in_flight.keys().next() {
+                    if delivery_instant <= Instant::now() {
+                        let packet = self
+                            .in_flight
+                            .remove(&(delivery_instant, sequence))
+                            .ok_or_else(enone!())?;
+
+                        return Ok(Some(BytesMut::from(&packet[..])));
+                    }
+
+                    if self.closed {
+                        time::sleep_until(delivery_instant).await;
+                        continue;
+                    }
+
+                    tokio::select! {
+                        maybe_packet = self.receiver.recv() => match maybe_packet {
+                            Some((instant, sequence, packet)) => {
+                                self.in_flight.insert((instant, sequence), packet);
+                            }
+                            None => self.closed = true,
+                        },
+                        _ = time::sleep_until(delivery_instant) => (),
+                    }
+                } else if self.closed {
+                    return Ok(None);
+                } else {
+                    match self.receiver.recv().await {
+                        Some((instant, sequence, packet)) => {
+                            self.in_flight.insert((instant, sequence), packet);
+                        }
+                        None => self.closed = true,
+                    }
+                }
+            }
+        }
+        .boxed()
+    }
+}
+
+// One direction of the link. The send socket can be queried for statistics.
+pub fn simulated_link(
+    impairments: LinkImpairments,
+    seed: u64,
+) -> (Arc<SimulatedStreamSendSocket>, SimulatedStreamReceiveSocket) {
+    let (sender, receiver) = mpsc::unbounded_channel();
+
+    let bucket_tokens = impairments
+        .bandwidth
+        .as_ref()
+        .map(|limit| limit.burst_bytes as f64)
+        .unwrap_or_default();
+
+    (
+        Arc::new(SimulatedStreamSendSocket {
+            impairments,
+            state: Mutex::new(LinkState {
+                rng: StdRng::seed_from_u64(seed),
+                bad_state: false,
+                bucket_tokens,
+                bucket_update_instant: Instant::now(),
+                next_sequence: 0,
+                statistics: LinkStatistics::default(),
+            }),
+            sender,
+        }),
+        SimulatedStreamReceiveSocket {
+            receiver,
+            in_flight: BTreeMap::new(),
+            closed: false,
+        },
+    )
+}
+
+// Returns the transports of the server and of the client. Use with StreamSocket::new().
+pub fn simulated_transport_pair(
+    server_to_client: LinkImpairments,
+    client_to_server: LinkImpairments,
+    seed: u64,
+) -> (StreamTransportPair, StreamTransportPair) {
+    let (server_send, client_receive) = simulated_link(server_to_client, seed);
+    let (client_send, server_receive) = simulated_link(client_to_server, seed.wrapping_add(1));
+
+    (
+        (server_send, Box::new(server_receive)),
+        (client_send, Box::new(client_receive)),
+    )
+}
+
+// Transport already connected to its peer, to run the pipelines over a simulated link. Use with
+// StreamSocketBuilder::new() on the client and StreamSocketConnector::new() on the server.
+pub struct SimulatedStreamPeer(pub StreamTransportPair);
+
+impl StreamListenTransport for SimulatedStreamPeer {
+    fn accept_from_server(
+        self: Box<Self>,
+        _: IpAddr,
+        _: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        future::ready(Ok(self.0)).boxed()
+    }
+}
+
+impl StreamConnectTransport for SimulatedStreamPeer {
+    fn connect_to_client(
+        self: Box<Self>,
+        _: IpAddr,
+        _: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        future::ready(Ok(self.0)).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StreamReceiver, StreamSocket, StreamSocketBuilder, AUDIO, VIDEO};
+    use std::net::Ipv4Addr;
+
+    const PACKET_SIZE: usize = 1000;
+
+    fn packet(index: u32) -> Bytes {
+        let mut packet = vec![0; PACKET_SIZE];
+        packet[..4].copy_from_slice(&index.to_be_bytes());
+
+        packet.into()
+    }
+
+    // Send the packets with the given interval, and return the indices of the delivered ones in
+    // order of arrival
+    async fn transmit(
+        impairments: LinkImpairments,
+        seed: u64,
+        count: u32,
+        interval: Duration,
+    ) -> (LinkStatistics, Vec<u32>) {
+        let (send_socket, mut receive_socket) = simulated_link(impairments, seed);
+
+        for index in 0..count {
+            send_socket.send(packet(index)).await.unwrap();
+            if !interval.is_zero() {
+                time::sleep(interval).await;
+            }
+        }
+        let statistics = send_socket.statistics();
+        drop(send_socket);
+
+        let mut received = vec![];
+        while let Some(packet) = receive_socket.recv().await.unwrap() {
+            received.push(u32::from_be_bytes(packet[..4].try_into().unwrap()));
+        }
+
+        (statistics, received)
+    }
+
+    #[tokio::test]
+    async fn burst_losses_are_deterministic() {
+        let impairments = LinkImpairments {
+            loss: Some(GilbertElliott::bursts(0.1, 4.0)),
+            ..Default::default()
+        };
+
+        let (statistics, received) = transmit(impairments.clone(), 42, 5000, Duration::ZERO).await;
+        assert_eq!(
+            transmit(impairments, 42, 5000, Duration::ZERO).await,
+            (statistics, received.clone())
+        );
+
+        assert_eq!(statistics.lost_packets, 5000 - received.len());
+        let loss_rate = statistics.lost_packets as f32 / 5000.0;
+        assert!((0.07..0.13).contains(&loss_rate), "loss rate: {loss_rate}");
+
+        let bursts_count = received
+            .windows(2)
+            .filter(|pair| pair[1] != pair[0] + 1)
+            .count();
+        let average_burst_length = statistics.lost_packets as f32 / bursts_count as f32;
+        assert!(
+            (3.0..5.0).contains(&average_burst_length),
+            "average burst length: {average_burst_length}"
+        );
+    }
+
+    #[tokio::test]
+    async fn bandwidth_limit_delays_and_drops() {
+        let impairments = LinkImpairments {
+            bandwidth: Some(BandwidthLimit {
+                bytes_per_second: 100_000.0,
+                burst_bytes: 10 * PACKET_SIZE,
+                max_queue_delay: Duration::from_millis(200),
+            }),
+            ..Default::default()
+        };
+
+        let start_instant = Instant::now();
+        let (statistics, received) = transmit(impairments, 0, 100, Duration::ZERO).await;
+
+        // 10 packets fit the bucket, 20 more fit the queue
+        assert_eq!(received, (0..30).collect::<Vec<_>>());
+        assert_eq!(statistics.queue_dropped_packets, 70);
+        assert!(start_instant.elapsed() >= Duration::from_millis(190));
+    }
+
+    #[tokio::test]
+    async fn reordering() {
+        let impairments = LinkImpairments {
+            reorder_rate: 0.2,
+            reorder_delay: Duration::from_millis(5),
+            ..Default::default()
+        };
+
+        let (statistics, mut received) =
+            transmit(impairments, 0, 200, Duration::from_millis(1)).await;
+        assert!(statistics.reordered_packets > 0);
+        assert!(received.windows(2).any(|pair| pair[1] < pair[0]));
+
+        received.sort_unstable();
+        assert_eq!(received, (0..200).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn stream_socket_reports_packet_loss() {
+        let (server_send_socket, client_receive_socket) = simulated_link(
+            LinkImpairments {
+                loss: Some(GilbertElliott::bursts(0.05, 3.0)),
+                ..Default::default()
+            },
+            0,
+        );
+        let (client_send_socket, server_receive_socket) =
+            simulated_link(LinkImpairments::default(), 1);
+
+        let server_socket = StreamSocket::new(
+            Arc::clone(&server_send_socket) as _,
+            Box::new(server_receive_socket),
+            PACKET_SIZE,
+        );
+        let client_socket = Arc::new(StreamSocket::new(
+            client_send_socket,
+            Box::new(client_receive_socket),
+            PACKET_SIZE,
+        ));
+        tokio::spawn({
+            let client_socket = Arc::clone(&client_socket);
+            async move { client_socket.receive_loop().await }
+        });
+
+        let mut sender = server_socket.request_stream::<u32>(AUDIO).await.unwrap();
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(AUDIO)
+            .await
+            .unwrap();
+
+        for index in 0..500 {
+            sender.send(&index).await.unwrap();
+        }
+
+        let mut received_headers = vec![];
+        let mut loss_events = 0;
+        while let Ok(packet) = time::timeout(Duration::from_millis(100), receiver.recv()).await {
+            let packet = packet.unwrap();
+
+            if packet.had_packet_loss {
+                loss_events += 1;
+            }
+            received_headers.push(packet.header);
+        }
+
+        let lost_packets = server_send_socket.statistics().lost_packets;
+        assert!(lost_packets > 0);
+        assert_eq!(received_headers.len(), 500 - lost_packets);
+        assert!(received_headers.windows(2).all(|pair| pair[0] < pair[1]));
+
+        let expected_loss_events = received_headers
+            .iter()
+            .enumerate()
+            .filter(|(index, header)| {
+                let previous = index.checked_sub(1).map(|i| received_headers[i] + 1);
+                **header != previous.unwrap_or(0)
+            })
+            .count();
+        assert_eq!(loss_events, expected_loss_events);
+    }
+
+    #[tokio::test]
+    async fn truncated_packets_are_dropped() {
+        let ((server_send_socket, _), (client_send_socket, client_receive_socket)) =
+            simulated_transport_pair(LinkImpairments::default(), LinkImpairments::default(), 0);
+
+        let client_socket = Arc::new(StreamSocket::new(
+            client_send_socket,
+            client_receive_socket,
+            PACKET_SIZE,
+        ));
+        tokio::spawn({
+            let client_socket = Arc::clone(&client_socket);
+            async move { client_socket.receive_loop().await }
+        });
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(AUDIO)
+            .await
+            .unwrap();
+
+        // Shorter than the stream ID, and shorter than the shard prefix
+        server_send_socket
+            .send(Bytes::from_static(&[0]))
+            .await
+            .unwrap();
+        let mut packet = AUDIO.to_be_bytes().to_vec();
+        packet.extend([0; 5]);
+        server_send_socket.send(packet.into()).await.unwrap();
+
+        let server_socket = StreamSocket::new(
+            server_send_socket,
+            Box::new(simulated_link(LinkImpairments::default(), 1).1),
+            PACKET_SIZE,
+        );
+        let mut sender = server_socket.request_stream::<u32>(AUDIO).await.unwrap();
+        sender.send(&7).await.unwrap();
+
+        let packet = time::timeout(Duration::from_millis(100), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.header, 7);
+    }
+
+    #[tokio::test]
+    async fn receivers_fail_once_the_peer_disconnects() {
+        let ((server_send_socket, _), client_transport) =
+            simulated_transport_pair(LinkImpairments::default(), LinkImpairments::default(), 0);
+
+        let client_socket =
+            StreamSocketBuilder::new(Box::new(SimulatedStreamPeer(client_transport)))
+                .accept_from_server(Ipv4Addr::LOCALHOST.into(), 0, PACKET_SIZE)
+                .await
+                .unwrap();
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(AUDIO)
+            .await
+            .unwrap();
+
+        let server_socket = StreamSocket::new(
+            server_send_socket,
+            Box::new(simulated_link(LinkImpairments::default(), 1).1),
+            PACKET_SIZE,
+        );
+        let mut sender = server_socket.request_stream::<u32>(AUDIO).await.unwrap();
+        sender.send(&7).await.unwrap();
+        drop(sender);
+        drop(server_socket);
+
+        let (loop_res, packets) = tokio::join!(client_socket.receive_loop(), async {
+            let first = receiver.recv().await.map(|packet| packet.header);
+            (first, receiver.recv().await.is_err())
+        });
+        assert!(loop_res.is_ok());
+        assert_eq!(packets, (Ok(7), true));
+    }
+
+    // Returns the server and client sockets, with their receive loops running
+    fn stream_socket_pair(
+        server_to_client: LinkImpairments,
+        seed: u64,
+    ) -> (Arc<StreamSocket>, Arc<StreamSocket>) {
+        let ((server_send, server_receive), (client_send, client_receive)) =
+            simulated_transport_pair(server_to_client, LinkImpairments::default(), seed);
+
+        let server_socket = Arc::new(StreamSocket::new(server_send, server_receive, PACKET_SIZE));
+        let client_socket = Arc::new(StreamSocket::new(client_send, client_receive, PACKET_SIZE));
+        for socket in [&server_socket, &client_socket] {
+            let socket = Arc::clone(socket);
+            tokio::spawn(async move { socket.receive_loop().await });
+        }
+
+        (server_socket, client_socket)
+    }
+
+    // Returns the headers of the received packets and the number of loss events
+    async fn receive_all(
+        receiver: &mut StreamReceiver<u32>,
+        payload_size: usize,
+    ) -> (Vec<u32>, usize) {
+        let mut received_headers = vec![];
+        let mut loss_events = 0;
+        while let Ok(packet) = time::timeout(Duration::from_millis(200), receiver.recv()).await {
+            let packet = packet.unwrap();
+            assert_eq!(packet.buffer.len(), payload_size);
+
+            if packet.had_packet_loss {
+                loss_events += 1;
+            }
+            received_headers.push(packet.header);
+        }
+
+        (received_headers, loss_events)
+    }
+
+    #[tokio::test]
+    async fn nack_recovers_lost_shards() {
+        let (server_socket, client_socket) = stream_socket_pair(
+            LinkImpairments {
+                loss: Some(GilbertElliott::uniform(0.05)),
+                ..Default::default()
+            },
+            0,
+        );
+        server_socket
+            .enable_nack(VIDEO, Duration::from_millis(100))
+            .await;
+
+        let mut sender = server_socket.request_stream::<u32>(VIDEO).await.unwrap();
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(VIDEO)
+            .await
+            .unwrap();
+        receiver.enable_nack(Duration::from_millis(100));
+
+        // 3 shards per packet
+        let payload_size = 2 * PACKET_SIZE;
+        tokio::spawn(async move {
+            for index in 0..300 {
+                let mut buffer = sender.new_buffer(&index, payload_size).unwrap();
+                buffer.get_mut().resize(payload_size, index as u8);
+                sender.send_buffer(buffer).await.unwrap();
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+
+        let (received_headers, _) = receive_all(&mut receiver, payload_size).await;
+
+        // Without NACK, about 40 packets would miss at least one shard. Only a resent shard that is
+        // lost again, or the last packets, can still be missing.
+        assert!(received_headers.len() >= 296, "{}", received_headers.len());
+        assert!(received_headers.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[tokio::test]
+    async fn out_of_order_packets_are_held_back() {
+        let (server_socket, client_socket) = stream_socket_pair(
+            LinkImpairments {
+                reorder_rate: 0.2,
+                reorder_delay: Duration::from_millis(5),
+                ..Default::default()
+            },
+            0,
+        );
+
+        let mut sender = server_socket.request_stream::<u32>(VIDEO).await.unwrap();
+        let mut receiver = client_socket
+            .subscribe_to_stream::<u32>(VIDEO)
+            .await
+            .unwrap();
+        receiver.enable_nack(Duration::from_millis(50));
+
+        tokio::spawn(async move {
+            for index in 0..200 {
+                sender.send(&index).await.unwrap();
+                time::sleep(Duration::from_millis(1)).await;
+            }
+        });
+
+        // The overtaken packets arrive before the deadline of the newer ones
+        let (received_headers, loss_events) = receive_all(&mut receiver, 0).await;
+        assert_eq!(received_headers, (0..200).collect::<Vec<_>>());
+        assert_eq!(loss_events, 0);
+    }
+}
@@ -1,21 +1,88 @@
-use crate::{Ldc, LOCAL_IP};
+use super::{
+    StreamConnectTransport, StreamListenTransport, StreamReceiveTransport, StreamSendTransport,
+    StreamTransportPair,
+};
+use crate::{bind_dual_stack, canonical_ip, peer_socket_addr, Ldc};
 use alvr_common::prelude::*;
 use alvr_session::SocketBufferSize;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 use futures::{
+    future::BoxFuture,
     stream::{SplitSink, SplitStream},
-    StreamExt,
+    FutureExt, SinkExt, StreamExt,
 };
-use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use std::{net::IpAddr, sync::Arc};
 use tokio::{
     net::{TcpListener, TcpStream},
-    sync::{mpsc, Mutex},
+    sync::Mutex,
 };
 use tokio_util::codec::Framed;
 
 pub type TcpStreamSendSocket = Arc<Mutex<SplitSink<Framed<TcpStream, Ldc>, Bytes>>>;
 pub type TcpStreamReceiveSocket = SplitStream<Framed<TcpStream, Ldc>>;
 
+impl StreamSendTransport for TcpStreamSendSocket {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult> {
+        async move { self.lock().await.send(packet).await.map_err(err!()) }.boxed()
+    }
+}
+
+impl StreamReceiveTransport for TcpStreamReceiveSocket {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>> {
+        async move { self.next().await.transpose().map_err(err!()) }.boxed()
+    }
+}
+
+pub struct TcpStreamListenSocket(pub TcpListener);
+
+impl StreamListenTransport for TcpStreamListenSocket {
+    fn accept_from_server(
+        self: Box<Self>,
+        server_ip: IpAddr,
+        _: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = accept_from_server(self.0, server_ip).await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
+// The TCP connection is initiated by the server, the socket is created when connecting
+pub struct TcpStreamConnectSocket {
+    pub send_buffer_bytes: SocketBufferSize,
+    pub recv_buffer_bytes: SocketBufferSize,
+}
+
+impl StreamConnectTransport for TcpStreamConnectSocket {
+    fn connect_to_client(
+        self: Box<Self>,
+        client_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = connect_to_client(
+                client_ip,
+                port,
+                self.send_buffer_bytes,
+                self.recv_buffer_bytes,
+            )
+            .await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
 pub fn set_socket_buffers(
     socket: &socket2::Socket,
     send_buffer_bytes: SocketBufferSize,
@@ -67,11 +134,13 @@ pub async fn bind(
     send_buffer_bytes: SocketBufferSize,
     recv_buffer_bytes: SocketBufferSize,
 ) -> StrResult<TcpListener> {
-    let socket = TcpListener::bind((LOCAL_IP, port)).await.map_err(err!())?;
-    let socket = socket2::Socket::from(socket.into_std().map_err(err!())?);
+    let socket = bind_dual_stack(socket2::Type::STREAM, port)?;
 
     set_socket_buffers(&socket, send_buffer_bytes, recv_buffer_bytes)?;
 
+    socket.listen(128).map_err(err!())?;
+    socket.set_nonblocking(true).map_err(err!())?;
+
     TcpListener::from_std(socket.into()).map_err(err!())
 }
 
@@ -81,7 +150,7 @@ pub async fn accept_from_server(
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
     let (socket, server_address) = listener.accept().await.map_err(err!())?;
 
-    if server_address.ip() != server_ip {
+    if canonical_ip(server_address.ip()) != server_ip {
         return fmt_e!("Connected to wrong client: {server_address} != {server_ip}");
     }
 
@@ -98,7 +167,7 @@ pub async fn connect_to_client(
     send_buffer_bytes: SocketBufferSize,
     recv_buffer_bytes: SocketBufferSize,
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
-    let socket = TcpStream::connect((client_ip, port))
+    let socket = TcpStream::connect(peer_socket_addr(client_ip, port))
         .await
         .map_err(err!())?;
     let socket = socket2::Socket::from(socket.into_std().map_err(err!())?);
@@ -112,19 +181,3 @@ pub async fn connect_to_client(
 
     Ok((Arc::new(Mutex::new(send_socket)), receive_socket))
 }
-
-pub async fn receive_loop(
-    mut socket: TcpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
-) -> StrResult {
-    while let Some(maybe_packet) = socket.next().await {
-        let mut packet = maybe_packet.map_err(err!())?;
-
-        let stream_id = packet.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet).map_err(err!())?;
-        }
-    }
-
-    Ok(())
-}
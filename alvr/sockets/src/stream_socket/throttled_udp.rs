@@ -1,6 +1,11 @@
+use super::{
+    StreamConnectTransport, StreamListenTransport, StreamReceiveTransport, StreamSendTransport,
+    StreamTransportPair,
+};
+use crate::{peer_socket_addr, to_local_family};
 use alvr_common::prelude::*;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures::{Stream, StreamExt};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{future::BoxFuture, FutureExt, Stream, StreamExt};
 use governor::{
     clock,
     state::{InMemoryState, NotKeyed},
@@ -8,7 +13,6 @@ use governor::{
 };
 use nonzero_ext::NonZero;
 use std::{
-    collections::HashMap,
     io,
     mem::MaybeUninit,
     net::{IpAddr, SocketAddr},
@@ -16,11 +20,7 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use tokio::{
-    io::ReadBuf,
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{io::ReadBuf, net::UdpSocket};
 
 const INITIAL_RD_CAPACITY: usize = 64 * 1024;
 
@@ -82,6 +82,80 @@ impl Stream for ThrottledUdpStreamReceiveSocket {
     }
 }
 
+impl StreamSendTransport for ThrottledUdpStreamSendSocket {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult> {
+        async move {
+            ThrottledUdpStreamSendSocket::send(self, packet)
+                .await
+                .map_err(err!())
+        }
+        .boxed()
+    }
+}
+
+impl StreamReceiveTransport for ThrottledUdpStreamReceiveSocket {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>> {
+        async move {
+            match self.next().await {
+                Some(maybe_packet) => Ok(Some(maybe_packet.map_err(err!())?.0)),
+                None => Ok(None),
+            }
+        }
+        .boxed()
+    }
+}
+
+pub struct ThrottledUdpStreamListenSocket(pub UdpSocket);
+
+impl StreamListenTransport for ThrottledUdpStreamListenSocket {
+    fn accept_from_server(
+        self: Box<Self>,
+        server_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = accept_from_server(self.0, server_ip, port).await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
+pub struct ThrottledUdpStreamConnectSocket {
+    pub socket: UdpSocket,
+    pub video_byterate: u32,
+    pub bitrate_multiplier: f32,
+}
+
+impl StreamConnectTransport for ThrottledUdpStreamConnectSocket {
+    fn connect_to_client(
+        self: Box<Self>,
+        client_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = connect_to_client(
+                self.socket,
+                client_ip,
+                port,
+                self.video_byterate,
+                self.bitrate_multiplier,
+            )
+            .await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
 pub async fn connect_to_client(
     socket: UdpSocket,
     client_ip: IpAddr,
@@ -92,7 +166,10 @@ pub async fn connect_to_client(
     ThrottledUdpStreamSendSocket,
     ThrottledUdpStreamReceiveSocket,
 )> {
-    let client_addr: SocketAddr = (client_ip, port).into();
+    let client_addr = to_local_family(
+        socket.local_addr().map_err(err!())?,
+        peer_socket_addr(client_ip, port),
+    );
     socket.connect(client_addr).await.map_err(err!())?;
 
     let rx = Arc::new(socket);
@@ -129,7 +206,10 @@ pub async fn accept_from_server(
     ThrottledUdpStreamSendSocket,
     ThrottledUdpStreamReceiveSocket,
 )> {
-    let server_addr: SocketAddr = (server_ip, port).into();
+    let server_addr = to_local_family(
+        socket.local_addr().map_err(err!())?,
+        peer_socket_addr(server_ip, port),
+    );
     socket.connect(server_addr).await.map_err(err!())?;
 
     let rx = Arc::new(socket);
@@ -146,19 +226,3 @@ pub async fn accept_from_server(
         },
     ))
 }
-
-pub async fn receive_loop(
-    mut socket: ThrottledUdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
-) -> StrResult {
-    while let Some(maybe_packet) = socket.next().await {
-        let (mut packet_bytes, _) = maybe_packet.map_err(err!())?;
-
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet_bytes).map_err(err!())?;
-        }
-    }
-
-    Ok(())
-}
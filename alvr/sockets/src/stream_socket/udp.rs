@@ -1,20 +1,21 @@
-use crate::{Ldc, LOCAL_IP};
+use super::{
+    StreamConnectTransport, StreamListenTransport, StreamReceiveTransport, StreamSendTransport,
+    StreamTransportPair,
+};
+use crate::{bind_dual_stack, peer_socket_addr, to_local_family, Ldc};
 use alvr_common::prelude::*;
 use alvr_session::SocketBufferSize;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::{Bytes, BytesMut};
 use futures::{
+    future::BoxFuture,
     stream::{SplitSink, SplitStream},
-    StreamExt,
+    FutureExt, SinkExt, StreamExt,
 };
 use std::{
-    collections::HashMap,
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
-use tokio::{
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{net::UdpSocket, sync::Mutex};
 use tokio_util::udp::UdpFramed;
 
 #[allow(clippy::type_complexity)]
@@ -31,15 +32,85 @@ pub struct UdpStreamReceiveSocket {
     pub inner: SplitStream<UdpFramed<Ldc>>,
 }
 
-// Create tokio socket, convert to socket2, apply settings, convert back to tokio. This is done to
-// let tokio set all the internal parameters it needs from the start.
+impl StreamSendTransport for UdpStreamSendSocket {
+    fn send(&self, packet: Bytes) -> BoxFuture<'_, StrResult> {
+        async move {
+            self.inner
+                .lock()
+                .await
+                .send((packet, self.peer_addr))
+                .await
+                .map_err(err!())
+        }
+        .boxed()
+    }
+}
+
+impl StreamReceiveTransport for UdpStreamReceiveSocket {
+    fn recv(&mut self) -> BoxFuture<'_, StrResult<Option<BytesMut>>> {
+        async move {
+            while let Some(maybe_packet) = self.inner.next().await {
+                let (packet_bytes, address) = maybe_packet.map_err(err!())?;
+
+                if address == self.peer_addr {
+                    return Ok(Some(packet_bytes));
+                }
+            }
+
+            Ok(None)
+        }
+        .boxed()
+    }
+}
+
+pub struct UdpStreamListenSocket(pub UdpSocket);
+
+impl StreamListenTransport for UdpStreamListenSocket {
+    fn accept_from_server(
+        self: Box<Self>,
+        server_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = connect(self.0, server_ip, port).await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
+pub struct UdpStreamConnectSocket(pub UdpSocket);
+
+impl StreamConnectTransport for UdpStreamConnectSocket {
+    fn connect_to_client(
+        self: Box<Self>,
+        client_ip: IpAddr,
+        port: u16,
+    ) -> BoxFuture<'static, StrResult<StreamTransportPair>> {
+        async move {
+            let (send_socket, receive_socket) = connect(self.0, client_ip, port).await?;
+
+            Ok((
+                Arc::new(send_socket) as Arc<dyn StreamSendTransport>,
+                Box::new(receive_socket) as Box<dyn StreamReceiveTransport>,
+            ))
+        }
+        .boxed()
+    }
+}
+
+// Create a dual-stack socket2 socket, apply settings, convert to tokio
 pub async fn bind(
     port: u16,
     send_buffer_bytes: SocketBufferSize,
     recv_buffer_bytes: SocketBufferSize,
 ) -> StrResult<UdpSocket> {
-    let socket = UdpSocket::bind((LOCAL_IP, port)).await.map_err(err!())?;
-    let socket = socket2::Socket::from(socket.into_std().map_err(err!())?);
+    let socket = bind_dual_stack(socket2::Type::DGRAM, port)?;
+    socket.set_nonblocking(true).map_err(err!())?;
 
     info!(
         "Initial UDP buffer size: send: {}B, recv: {}B",
@@ -87,7 +158,10 @@ pub async fn connect(
     peer_ip: IpAddr,
     port: u16,
 ) -> StrResult<(UdpStreamSendSocket, UdpStreamReceiveSocket)> {
-    let peer_addr = (peer_ip, port).into();
+    let peer_addr = to_local_family(
+        socket.local_addr().map_err(err!())?,
+        peer_socket_addr(peer_ip, port),
+    );
     let socket = UdpFramed::new(socket, Ldc::new());
     let (send_socket, receive_socket) = socket.split();
 
@@ -102,23 +176,3 @@ pub async fn connect(
         },
     ))
 }
-
-pub async fn receive_loop(
-    mut socket: UdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
-) -> StrResult {
-    while let Some(maybe_packet) = socket.inner.next().await {
-        let (mut packet_bytes, address) = maybe_packet.map_err(err!())?;
-
-        if address != socket.peer_addr {
-            continue;
-        }
-
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet_bytes).map_err(err!())?;
-        }
-    }
-
-    Ok(())
-}
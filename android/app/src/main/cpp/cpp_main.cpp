@@ -106,6 +106,8 @@ public:
 
     bool running = false;
     bool streaming = false;
+    // The pairing code is shown on the HUD and waits for an answer
+    bool pairingRequested = false;
     std::thread eventsThread;
 
     uint32_t recommendedViewWidth = 1;
@@ -402,6 +404,36 @@ void updateButtons() {
     }
 }
 
+// A confirms the pairing code, B rejects it. Returns 1 if confirmed, 0 if rejected, -1 if no
+// answer was given yet.
+int pollPairingAnswer() {
+    ovrInputCapabilityHeader capabilitiesHeader;
+    uint32_t deviceIndex = 0;
+    while (vrapi_EnumerateInputDevices(CTX.ovrContext, deviceIndex, &capabilitiesHeader) >= 0) {
+        deviceIndex++;
+
+        if (capabilitiesHeader.Type != ovrControllerType_TrackedRemote) {
+            continue;
+        }
+
+        ovrInputStateTrackedRemote inputState = {};
+        inputState.Header.ControllerType = capabilitiesHeader.Type;
+        if (vrapi_GetCurrentInputState(CTX.ovrContext,
+                                       capabilitiesHeader.DeviceID,
+                                       &inputState.Header) != ovrSuccess) {
+            continue;
+        }
+
+        if (inputState.Buttons & ovrButton_A) {
+            return 1;
+        } else if (inputState.Buttons & ovrButton_B) {
+            return 0;
+        }
+    }
+
+    return -1;
+}
+
 // return fov in OpenXR convention
 EyeFov getFov(ovrTracking2 tracking, int eye) {
     // ovrTracking2 tracking = vrapi_GetPredictedTracking2(CTX.ovrContext, 0.0);
@@ -744,6 +776,16 @@ void eventsThread() {
                 java.Env->CallVoidMethod(java.ActivityObject, onStreamStartMethod);
             } else if (event.tag == ALVR_EVENT_STREAMING_STOPPED) {
                 java.Env->CallVoidMethod(java.ActivityObject, onStreamStopMethod);
+            } else if (event.tag == ALVR_EVENT_PAIRING_REQUESTED) {
+                CTX.pairingRequested = true;
+            }
+        }
+
+        if (CTX.pairingRequested) {
+            int answer = pollPairingAnswer();
+            if (answer >= 0) {
+                alvr_confirm_pairing(answer == 1);
+                CTX.pairingRequested = false;
             }
         }
 
@@ -72,6 +72,9 @@ define({
             "HEVC is preferred to achieve better visual quality on lower bitrates. AMD video cards work best with HEVC.",
         "_root_video_codec_H264-choice-.name": "h264",
         "_root_video_codec_HEVC-choice-.name": "HEVC (h265)",
+        "_root_video_codec_AV1-choice-.name": "AV1",
+        "_root_video_codec_AV1-choice-.description":
+            "Linux only. Needs a headset with an AV1 decoder, otherwise HEVC or h264 is used.",
         "_root_video_use10bitEncoder.name": "Reduce color banding",
         "_root_video_use10bitEncoder.description":
             "This increases visual quality by streaming 10 bits per color channel instead of 8. Works on all supported platforms except nvidia on linux.",
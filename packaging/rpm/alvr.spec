@@ -100,8 +100,8 @@ rm -rf '%{buildroot}'
 %postun
 if [ "${1}" = 0 ]; then
     # Unlabel ports
-    semanage port -d -p tcp 9943-9944
-    semanage port -d -p udp 9943-9944
+    semanage port -d -p tcp 9943-9976
+    semanage port -d -p udp 9943-9976
     # Unload SELinux policy
     semodule -nr '%{name}' >/dev/null
 fi
@@ -118,9 +118,12 @@ if selinuxenabled; then
     load_policy
     # Restore contexts
     restorecon -FR '%{_bindir}/%{name}_launcher' '%{_libdir}/'{'%{name}','lib%{name}_vulkan_layer.so'} '%{_libexecdir}/%{name}'
-    # Label ports if they're unlabeled
-    if ! semanage port -l | grep -P '%{name}_port_t.*9943-9944' >/dev/null 2>&1; then
-        semanage port -a -t '%{name}_port_t' -p tcp 9943-9944
-        semanage port -a -t '%{name}_port_t' -p udp 9943-9944
+    # Label ports if they're unlabeled. Previous versions only labeled the control and stream ports,
+    # the spectators and companions use the next ones
+    if ! semanage port -l | grep -P '%{name}_port_t.*9943-9976' >/dev/null 2>&1; then
+        semanage port -d -p tcp 9943-9944 >/dev/null 2>&1
+        semanage port -d -p udp 9943-9944 >/dev/null 2>&1
+        semanage port -a -t '%{name}_port_t' -p tcp 9943-9976
+        semanage port -a -t '%{name}_port_t' -p udp 9943-9976
     fi
 fi